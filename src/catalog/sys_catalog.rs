@@ -1,4 +1,5 @@
-use std::sync::{OnceLock};
+use std::sync::{Mutex, OnceLock};
+use std::path::PathBuf;
 use std::time;
 use std::collections::HashMap;
 
@@ -15,12 +16,16 @@ use crate::config::{
     MAX_COL_NAME_SIZE, 
     MAX_USERNAME_SIZE, 
     MAX_TABLE_NAME_SIZE, 
+    MAX_COMMENT_SIZE,
+    MAX_CHECKS_SIZE,
+    MAX_DEFAULT_SIZE,
+    MAX_PRIMARY_KEY_SIZE,
     DEFAULT_PASSWORD,
     DEFAULT_USERNAME,
 };
 use crate::common::PrivilegeConn;
 
-use super::table_schema::TableSchema;
+use super::table_schema::{CheckConstraint, Collation, TableSchema, parse_stored_expr};
 
 pub const SYS_TABLE_ID: u64 = 0;
 pub const SYS_COLUMN_ID: u64 = 1;
@@ -61,10 +66,122 @@ fn sys_table_schema() -> TableSchema {
             index: false,
             is_dropped: false,
         },
+        TableColumn { // JSON array of the CHECK expressions
+            name: "check_constraints".to_string(),
+            data_type: super::table_schema::ColType::VarChar(MAX_CHECKS_SIZE),
+            pk: false,
+            nullable: true,
+            unique: false,
+            index: false,
+            is_dropped: false,
+        },
+        TableColumn { // JSON array of the columns of a multi-column primary key in key order
+            name: "primary_key".to_string(),
+            data_type: super::table_schema::ColType::VarChar(MAX_PRIMARY_KEY_SIZE),
+            pk: false,
+            nullable: true,
+            unique: false,
+            index: false,
+            is_dropped: false,
+        },
     ];
     TableSchema::new(columns).unwrap()
 }
 
+/// The sys_table item holding the CHECK constraints of a table, null without any
+fn checks_to_item(checks: &[CheckConstraint]) -> RsqlResult<DataItem> {
+    if checks.is_empty() {
+        return Ok(DataItem::NullVarChar);
+    }
+    let sqls: Vec<&str> = checks.iter().map(|check| check.sql.as_str()).collect();
+    let value = serde_json::to_string(&sqls)
+        .map_err(|e| RsqlError::Unknown(format!("Failed to encode CHECK constraints: {}", e)))?;
+    if value.len() > MAX_CHECKS_SIZE {
+        return Err(RsqlError::InvalidInput(format!("CHECK constraints are longer than {} bytes", MAX_CHECKS_SIZE)));
+    }
+    Ok(DataItem::VarChar {
+        head: crate::common::VarCharHead {
+            max_len: MAX_CHECKS_SIZE as u64,
+            len: value.len() as u64,
+            page_ptr: None,
+        },
+        value,
+    })
+}
+
+/// The sys_column item holding the collation of a column, null for BINARY
+fn collation_to_item(collation: Collation) -> DataItem {
+    if collation == Collation::Binary {
+        return DataItem::NullVarChar;
+    }
+    let value = collation.name().to_string();
+    DataItem::VarChar {
+        head: crate::common::VarCharHead {
+            max_len: MAX_COL_NAME_SIZE as u64,
+            len: value.len() as u64,
+            page_ptr: None,
+        },
+        value,
+    }
+}
+
+/// The sys_column item holding the DEFAULT expression of a column, null without one
+fn default_to_item(default: Option<&sqlparser::ast::Expr>) -> RsqlResult<DataItem> {
+    let Some(default) = default else {
+        return Ok(DataItem::NullVarChar);
+    };
+    let value = default.to_string();
+    if value.len() > MAX_DEFAULT_SIZE {
+        return Err(RsqlError::InvalidInput(format!("DEFAULT {} is longer than {} bytes", value, MAX_DEFAULT_SIZE)));
+    }
+    Ok(DataItem::VarChar {
+        head: crate::common::VarCharHead {
+            max_len: MAX_DEFAULT_SIZE as u64,
+            len: value.len() as u64,
+            page_ptr: None,
+        },
+        value,
+    })
+}
+
+/// The sys_table item holding the key order of a multi-column primary key, null for other tables
+fn primary_key_to_item(schema: &TableSchema) -> RsqlResult<DataItem> {
+    if schema.get_primary_key().len() < 2 {
+        return Ok(DataItem::NullVarChar);
+    }
+    let value = serde_json::to_string(schema.get_primary_key())
+        .map_err(|e| RsqlError::Unknown(format!("Failed to encode primary key: {}", e)))?;
+    if value.len() > MAX_PRIMARY_KEY_SIZE {
+        return Err(RsqlError::InvalidInput(format!("Primary key columns are longer than {} bytes", MAX_PRIMARY_KEY_SIZE)));
+    }
+    Ok(DataItem::VarChar {
+        head: crate::common::VarCharHead {
+            max_len: MAX_PRIMARY_KEY_SIZE as u64,
+            len: value.len() as u64,
+            page_ptr: None,
+        },
+        value,
+    })
+}
+
+fn primary_key_from_item(item: &DataItem) -> RsqlResult<Option<Vec<String>>> {
+    let DataItem::VarChar { value, .. } = item else {
+        return Ok(None);
+    };
+    serde_json::from_str(value)
+        .map(Some)
+        .map_err(|e| RsqlError::StorageError(format!("Invalid primary key in sys_table: {}", e)))
+}
+
+fn checks_from_item(item: &DataItem) -> RsqlResult<Vec<CheckConstraint>> {
+    let DataItem::VarChar { value, .. } = item else {
+        return Ok(vec![]);
+    };
+    let sqls: Vec<String> = serde_json::from_str(value)
+        .map_err(|e| RsqlError::StorageError(format!("Invalid CHECK constraints in sys_table: {}", e)))?;
+    sqls.iter().map(|sql| CheckConstraint::parse(sql)).collect()
+}
+
 fn sys_column_schema() -> TableSchema {
     let columns = vec![
         TableColumn {
@@ -157,6 +274,33 @@ fn sys_column_schema() -> TableSchema {
             index: false,
             is_dropped: false,
         },
+        TableColumn { // set by COMMENT ON COLUMN
+            name: "comment".to_string(),
+            data_type: super::table_schema::ColType::VarChar(MAX_COMMENT_SIZE),
+            pk: false,
+            nullable: true,
+            unique: false,
+            index: false,
+            is_dropped: false,
+        },
+        TableColumn { // SQL text of the DEFAULT expression
+            name: "default_value".to_string(),
+            data_type: super::table_schema::ColType::VarChar(MAX_DEFAULT_SIZE),
+            pk: false,
+            nullable: true,
+            unique: false,
+            index: false,
+            is_dropped: false,
+        },
+        TableColumn { // set by COLLATE, null for BINARY
+            name: "collation".to_string(),
+            data_type: super::table_schema::ColType::VarChar(MAX_COL_NAME_SIZE),
+            pk: false,
+            nullable: true,
+            unique: false,
+            index: false,
+            is_dropped: false,
+        },
     ];
     TableSchema::new(columns).unwrap()
 }
@@ -256,7 +400,19 @@ static SYS_TABLE_INSTANCE: OnceLock<SysCatalog> = OnceLock::new();
 /// System Catalog
 /// Some special tables to store metadata about database objects
 /// Singleton struct
-pub struct SysCatalog {} // Sys tables are all processed as common tables
+pub struct SysCatalog { // Sys tables are all processed as common tables
+    cache: Mutex<CatalogCache>,
+}
+
+/// Table names and schemas already read from the sys tables.
+/// Cleared by every write to sys_table, sys_column or sys_index and by rollbacks;
+/// lookups still take the read locks, so an uncommitted entry is only seen by its own transaction
+#[derive(Default)]
+struct CatalogCache {
+    sys_path: PathBuf, // sys_table file the entries were read from
+    table_ids: HashMap<String, u64>,
+    schemas: HashMap<u64, TableSchema>,
+}
 
 
 impl SysCatalog {
@@ -271,6 +427,7 @@ impl SysCatalog {
             return Ok(());
         };
         info!("First time starting database, initializing system catalog...");
+        Self::global().clear_cache();
         let tnx_id = TnxManager::global()
             .begin_transaction(PrivilegeConn::INIT);
         let table_ids = vec![
@@ -309,6 +466,8 @@ impl SysCatalog {
                         value: table_name.to_string(), 
                     },
                     DataItem::Integer(created_at as i64),
+                    DataItem::NullVarChar,
+                    DataItem::NullVarChar,
                 ],
                 tnx_id,
             )?;
@@ -357,6 +516,9 @@ impl SysCatalog {
                         DataItem::Bool(col.index),
                         DataItem::Bool(col.unique),
                         DataItem::Bool(col.is_dropped),
+                        DataItem::NullVarChar,
+                        DataItem::NullVarChar,
+                        DataItem::NullVarChar,
                     ],
                     tnx_id,
                 )?;
@@ -418,7 +580,10 @@ impl SysCatalog {
             ],
             tnx_id,
         )?;
-        WAL::global().commit_tnx(tnx_id)?;
+        // the row counts are written to the headers of the closed tables
+        drop((table, column, sequence, user));
+        Table::commit_row_counts(tnx_id)?;
+        WAL::global()?.commit_tnx(tnx_id)?;
         TnxManager::global().end_transaction(0);
         info!("System catalog initialized successfully!");
         Ok(())
@@ -426,16 +591,36 @@ impl SysCatalog {
     /// Construct syscatalog
     /// This method can only called when the database has been initialized
     fn new() -> Self {
-        SysCatalog {}
+        SysCatalog {
+            cache: Mutex::new(CatalogCache::default()),
+        }
+    }
+    /// Forget cached names and schemas, the next lookups read the sys tables again
+    pub fn clear_cache(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.table_ids.clear();
+        cache.schemas.clear();
+    }
+    /// The cache, emptied first if it was filled from the sys tables of another directory
+    fn cache(&self) -> std::sync::MutexGuard<'_, CatalogCache> {
+        let sys_path = table::get_table_path(SYS_TABLE_ID, true);
+        let mut cache = self.cache.lock().unwrap();
+        if cache.sys_path != sys_path {
+            *cache = CatalogCache { sys_path, ..Default::default() };
+        }
+        cache
     }
 
     /// Query the table schema from system catalog
     /// Input a table id, return the TableSchema of the table
     pub fn get_table_schema(&self, tnx_id: u64, table_id: u64) -> RsqlResult<TableSchema> {
-        let read_table = vec![SYS_COLUMN_ID];
+        let read_table = vec![SYS_TABLE_ID, SYS_COLUMN_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
+        if let Some(schema) = self.cache().schemas.get(&table_id) {
+            return Ok(schema.clone());
+        }
         // query sys_column to get columns
-        let column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
+        let column = Table::from_shared(SYS_COLUMN_ID, sys_column_schema(), true)?;
         let pk = DataItem::Integer(table_id as i64);
         let pk_opt = Some(pk.clone());
         let column_rows = column
@@ -445,6 +630,8 @@ impl SysCatalog {
                 &pk_opt,
             ).unwrap();
         let mut columns = vec![];
+        let mut defaults = HashMap::new();
+        let mut collations = HashMap::new();
         for row in column_rows {
             let row = row.unwrap();
             let DataItem::Chars{ len: _, value: name} = &row[2] else {
@@ -479,8 +666,17 @@ impl SysCatalog {
             let DataItem::Bool(is_dropped) = &row[9] else {
                 panic!("is_dropped column is not Bool");
             };
+            let DataItem::Integer(column_id) = &row[0] else {
+                panic!("column_id column is not Integer");
+            };
+            if let DataItem::VarChar { value: default, .. } = &row[11] && !*is_dropped {
+                defaults.insert(name.clone(), parse_stored_expr(default)?);
+            }
+            if let DataItem::VarChar { value: collation, .. } = &row[12] && !*is_dropped {
+                collations.insert(name.clone(), Collation::from_name(collation)?);
+            }
             
-            columns.push(TableColumn {
+            columns.push((*column_id, TableColumn {
                 name: name.clone(),
                 data_type,
                 pk: *pk,
@@ -488,15 +684,30 @@ impl SysCatalog {
                 unique: *unique,
                 index: *index,
                 is_dropped: *is_dropped,
-            });
+            }));
+        };
+        // updated column records are reinserted into the index, column ids keep the declared order
+        columns.sort_by_key(|(column_id, _)| *column_id);
+        let schema = TableSchema::new(columns.into_iter().map(|(_, col)| col).collect()).unwrap();
+        // query sys_table to get the check constraints
+        let table = Table::from_shared(SYS_TABLE_ID, sys_table_schema(), true)?;
+        let (checks, primary_key) = match table.get_row_by_pk(&pk)? {
+            Some(table_row) => (checks_from_item(&table_row[3])?, primary_key_from_item(&table_row[4])?),
+            None => (vec![], None),
         };
-        Ok(TableSchema::new(columns).unwrap())
+        let schema = match primary_key {
+            Some(primary_key) => schema.with_primary_key(primary_key)?,
+            None => schema,
+        };
+        let schema = schema.with_checks(checks).with_defaults(defaults).with_collations(collations);
+        self.cache().schemas.insert(table_id, schema.clone());
+        Ok(schema)
     }
     pub fn get_table_name(&self, table_id: u64, tnx_id: u64) -> RsqlResult<Option<String>> {
         let read_table = vec![SYS_TABLE_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
         // query sys_table to get table name
-        let table = Table::from(SYS_TABLE_ID, sys_table_schema(), true)?;
+        let table = Table::from_shared(SYS_TABLE_ID, sys_table_schema(), true)?;
         let pk = DataItem::Integer(table_id as i64);
         let table_row = match table.get_row_by_pk(&pk).unwrap() {
             Some(row) => row,
@@ -510,8 +721,11 @@ impl SysCatalog {
     pub fn get_table_id(&self, tnx_id: u64, table_name: &str) -> RsqlResult<Option<u64>> {
         let read_table = vec![SYS_TABLE_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
+        if let Some(table_id) = self.cache().table_ids.get(table_name) {
+            return Ok(Some(*table_id));
+        }
         // query sys_table to get table id
-        let table = Table::from(SYS_TABLE_ID, sys_table_schema(), true)?;
+        let table = Table::from_shared(SYS_TABLE_ID, sys_table_schema(), true)?;
         let index = DataItem::Chars {
             len: MAX_TABLE_NAME_SIZE as u64,
             value: table_name.to_string(),
@@ -529,6 +743,7 @@ impl SysCatalog {
         let DataItem::Integer(table_id) = &table_row[0] else {
             panic!("table_id column is not Integer");
         };
+        self.cache().table_ids.insert(table_name.to_string(), *table_id as u64);
         Ok(Some(*table_id as u64))
     }
     fn get_autoincrement(&self, tnx_id: u64, sequence_name: &str) -> RsqlResult<Option<u64>> {
@@ -575,6 +790,7 @@ impl SysCatalog {
     ) -> RsqlResult<u64> {
         let write_table = vec![SYS_TABLE_ID, SYS_INDEX_ID, SYS_COLUMN_ID];
         TnxManager::global().acquire_write_locks(tnx_id, &write_table)?;
+        self.clear_cache();
         let mut table = Table::from(SYS_TABLE_ID, sys_table_schema(), true)?;
         let mut column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
         // get table id
@@ -596,6 +812,8 @@ impl SysCatalog {
                     value: table_name.to_string(),
                 },
                 DataItem::Integer(created_at as i64),
+                checks_to_item(schema.get_checks())?,
+                primary_key_to_item(schema)?,
             ],
             tnx_id,
         )?;
@@ -628,6 +846,9 @@ impl SysCatalog {
                     DataItem::Bool(col.index),
                     DataItem::Bool(col.unique),
                     DataItem::Bool(col.is_dropped),
+                    DataItem::NullVarChar,
+                    default_to_item(schema.get_default(&col.name))?,
+                    collation_to_item(schema.get_collation(&col.name)),
                 ],
                 tnx_id,
             )?;
@@ -662,6 +883,7 @@ impl SysCatalog {
     ) -> RsqlResult<()> {
         let write_table = vec![SYS_TABLE_ID, SYS_COLUMN_ID];
         TnxManager::global().acquire_write_locks(tnx_id, &write_table)?;
+        self.clear_cache();
         let mut table = Table::from(SYS_TABLE_ID, sys_table_schema(), true)?;
         let mut column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
         // delete from sys_table
@@ -692,6 +914,9 @@ impl SysCatalog {
         }
         Ok(())
     }
+    /// Table files are named by id, so a rename only rewrites the catalog row.
+    /// The row and its name index entry are updated in the caller's transaction, a rollback or
+    /// recovery never leaves both names or neither pointing to the table
     pub fn rename_table(
         &self,
         tnx_id: u64,
@@ -700,6 +925,7 @@ impl SysCatalog {
     ) -> RsqlResult<()> {
         let write_table = vec![SYS_TABLE_ID];
         TnxManager::global().acquire_write_locks(tnx_id, &write_table)?;
+        self.clear_cache();
         let mut table = Table::from(SYS_TABLE_ID, sys_table_schema(), true)?;
         let pk = DataItem::Integer(table_id as i64);
         let table_row = table.get_row_by_pk(&pk)?.ok_or(
@@ -714,6 +940,8 @@ impl SysCatalog {
                     value: new_table_name.to_string(),
                 },
                 table_row[2].clone(),
+                table_row[3].clone(),
+                table_row[4].clone(),
             ],
             tnx_id,
         )?;
@@ -722,6 +950,7 @@ impl SysCatalog {
     pub fn rename_column(&self, tnx_id: u64, table_id: u64, old_col_name: &str, new_col_name: &str) -> RsqlResult<()> {
         // 1. lock and open sys_column table
         TnxManager::global().acquire_write_locks(tnx_id, &[SYS_COLUMN_ID])?;
+        self.clear_cache();
         let mut sys_column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
         
         // 2. find the corresponding column record
@@ -744,6 +973,10 @@ impl SysCatalog {
                     if is_indexed {
                         return Err(RsqlError::InvalidInput(format!("Cannot rename indexed column: {}", old_col_name)));
                     }
+                    // the columns of a multi-column primary key are named in sys_table
+                    if let DataItem::Bool(true) = &row[5] {
+                        return Err(RsqlError::InvalidInput(format!("Cannot rename primary key column: {}", old_col_name)));
+                    }
                     
                     // 4. update column name
                     if new_col_name.len() > MAX_COL_NAME_SIZE {
@@ -767,7 +1000,7 @@ impl SysCatalog {
     pub fn get_all_table_ids(&self, tnx_id: u64) -> RsqlResult<Vec<u64>> {
         let read_table = vec![SYS_TABLE_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
-        let table = Table::from(SYS_TABLE_ID, sys_table_schema(), true)?;
+        let table = Table::from_shared(SYS_TABLE_ID, sys_table_schema(), true)?;
         let mut table_ids = vec![];
         let table_rows = table.get_all_rows()?;
         for row in table_rows {
@@ -790,6 +1023,7 @@ impl SysCatalog {
     ) -> RsqlResult<()> {
         let write_table = vec![SYS_COLUMN_ID];
         TnxManager::global().acquire_write_locks(tnx_id, &write_table)?;
+        self.clear_cache();
         // register to column table
         let mut column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
         let pk = DataItem::Integer(table_id as i64);
@@ -818,6 +1052,9 @@ impl SysCatalog {
                         DataItem::Bool(true), // set is_indexed to true
                         DataItem::Bool(unique), // set is_unique
                         row[9].clone(),
+                        row[10].clone(),
+                        row[11].clone(),
+                        row[12].clone(),
                     ],
                     tnx_id,
                 )?;
@@ -852,7 +1089,7 @@ impl SysCatalog {
     pub fn get_index_name(&self, tnx_id: u64, table_id: u64, column_name: &str) -> RsqlResult<Option<String>> {
         let read_table = vec![SYS_INDEX_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
-        let index = Table::from(SYS_INDEX_ID, sys_index_schema(), true)?;
+        let index = Table::from_shared(SYS_INDEX_ID, sys_index_schema(), true)?;
         let mut index_name_opt = None;
         let table_id_item = DataItem::Integer(table_id as i64);
         let key_start = Some(table_id_item.clone());
@@ -878,7 +1115,7 @@ impl SysCatalog {
     pub fn get_index_id(&self, tnx_id: u64, index_name: &str) -> RsqlResult<Option<u64>> {
         let read_table = vec![SYS_INDEX_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
-        let index = Table::from(SYS_INDEX_ID, sys_index_schema(), true)?;
+        let index = Table::from_shared(SYS_INDEX_ID, sys_index_schema(), true)?;
         let index_item = DataItem::Chars { 
             len: MAX_COL_NAME_SIZE as u64, 
             value: index_name.to_string(), 
@@ -905,7 +1142,7 @@ impl SysCatalog {
     ) -> RsqlResult<bool> {
         let read_table = vec![SYS_USER_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
-        let user = Table::from(SYS_USER_ID, sys_user_schema(), true)?;
+        let user = Table::from_shared(SYS_USER_ID, sys_user_schema(), true)?;
         let index = DataItem::Chars { 
             len: MAX_USERNAME_SIZE as u64, 
             value: username.to_string(), 
@@ -950,7 +1187,7 @@ impl SysCatalog {
     ) -> RsqlResult<bool> {
         let read_table = vec![SYS_USER_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
-        let user = Table::from(SYS_USER_ID, sys_user_schema(), true)?;
+        let user = Table::from_shared(SYS_USER_ID, sys_user_schema(), true)?;
         let index = DataItem::Chars { 
             len: MAX_USERNAME_SIZE as u64, 
             value: username.to_string(), 
@@ -1095,7 +1332,7 @@ impl SysCatalog {
     pub fn get_all_users(&self, tnx_id: u64) -> RsqlResult<Vec<String>> {
         let read_table = vec![SYS_USER_ID];
         TnxManager::global().acquire_read_locks(tnx_id, &read_table)?;
-        let user = Table::from(SYS_USER_ID, sys_user_schema(), true)?;
+        let user = Table::from_shared(SYS_USER_ID, sys_user_schema(), true)?;
         let mut usernames = vec![];
         let user_rows = user.get_all_rows()?;
         for row in user_rows {
@@ -1143,6 +1380,7 @@ impl SysCatalog {
     pub fn drop_column(&self, tnx_id: u64, table_id: u64, col_name: &str) -> RsqlResult<()> {
         // 1. lock and open sys_column table
         TnxManager::global().acquire_write_locks(tnx_id, &[SYS_COLUMN_ID])?;
+        self.clear_cache();
         let mut sys_column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
         
         // 2. find the corresponding column record
@@ -1156,6 +1394,9 @@ impl SysCatalog {
                     // found the target column
                     let pk = &row[0];
                     let mut new_row = row.clone();
+                    if let DataItem::Bool(true) = &row[5] {
+                        return Err(RsqlError::InvalidInput(format!("Cannot drop primary key column: {}", col_name)));
+                    }
                     
                     // 3. update is_dropped
                     // Ensure row has enough columns (migrating from old schema)
@@ -1173,6 +1414,127 @@ impl SysCatalog {
         
         Err(RsqlError::ExecutionError(format!("Column {} not found in table", col_name)))
     }
+    /// Change the recorded data type of a column, the stored values are not touched
+    pub fn set_column_type(&self, tnx_id: u64, table_id: u64, col_name: &str, col_type: &super::table_schema::ColType) -> RsqlResult<()> {
+        // 1. lock and open sys_column table
+        TnxManager::global().acquire_write_locks(tnx_id, &[SYS_COLUMN_ID])?;
+        self.clear_cache();
+        let mut sys_column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
+
+        // 2. find the corresponding live column record
+        let tid_data = DataItem::Integer(table_id as i64);
+        let key = Some(tid_data.clone());
+        let iter = sys_column.get_rows_by_range_indexed_col("table_id", &key, &key)?.collect::<RsqlResult<Vec<_>>>()?;
+
+        for row in iter.into_iter() {
+            if matches!(row.get(9), Some(DataItem::Bool(true))) {
+                continue;
+            }
+            if let DataItem::Chars { value: name, .. } = &row[2]
+                && name == col_name {
+                // 3. update data type and its size
+                let (data_type, extra) = match col_type {
+                    super::table_schema::ColType::Integer => (0, 0),
+                    super::table_schema::ColType::Float => (1, 0),
+                    super::table_schema::ColType::Chars(size) => (2, *size as i64),
+                    super::table_schema::ColType::VarChar(size) => (3, *size as i64),
+                    super::table_schema::ColType::Bool => (4, 0),
+                };
+                let pk = &row[0];
+                let mut new_row = row.clone();
+                new_row[3] = DataItem::Integer(data_type);
+                new_row[4] = DataItem::Integer(extra);
+                // only string columns have a collation
+                if data_type != 2 && data_type != 3 {
+                    new_row[12] = DataItem::NullVarChar;
+                }
+                sys_column.update_row(pk, new_row, tnx_id)?;
+                return Ok(());
+            }
+        }
+
+        Err(RsqlError::ExecutionError(format!("Column {} not found in table", col_name)))
+    }
+    /// Mark a live column unique, and with `primary` also the primary key, which is never null.
+    /// The column must already be registered as indexed
+    pub fn set_column_key(&self, tnx_id: u64, table_id: u64, col_name: &str, primary: bool) -> RsqlResult<()> {
+        TnxManager::global().acquire_write_locks(tnx_id, &[SYS_COLUMN_ID])?;
+        self.clear_cache();
+        let mut sys_column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
+        let key = Some(DataItem::Integer(table_id as i64));
+        let rows = sys_column.get_rows_by_range_indexed_col("table_id", &key, &key)?.collect::<RsqlResult<Vec<_>>>()?;
+        for row in rows {
+            if matches!(row.get(9), Some(DataItem::Bool(true))) {
+                continue;
+            }
+            if let DataItem::Chars { value: name, .. } = &row[2]
+                && name == col_name {
+                let pk = &row[0];
+                let mut new_row = row.clone();
+                if primary {
+                    new_row[5] = DataItem::Bool(true);
+                    new_row[6] = DataItem::Bool(false);
+                }
+                new_row[8] = DataItem::Bool(true);
+                sys_column.update_row(pk, new_row, tnx_id)?;
+                return Ok(());
+            }
+        }
+        Err(RsqlError::ExecutionError(format!("Column {} not found in table", col_name)))
+    }
+    /// Set or, with None, remove the comment of a column
+    pub fn set_column_comment(&self, tnx_id: u64, table_id: u64, col_name: &str, comment: Option<&str>) -> RsqlResult<()> {
+        if let Some(comment) = comment
+            && comment.len() > MAX_COMMENT_SIZE {
+            return Err(RsqlError::InvalidInput(format!("Comment is longer than {} bytes", MAX_COMMENT_SIZE)));
+        }
+        TnxManager::global().acquire_write_locks(tnx_id, &[SYS_COLUMN_ID])?;
+        self.clear_cache();
+        let mut sys_column = Table::from(SYS_COLUMN_ID, sys_column_schema(), true)?;
+        let key = Some(DataItem::Integer(table_id as i64));
+        let rows = sys_column.get_rows_by_range_indexed_col("table_id", &key, &key)?.collect::<RsqlResult<Vec<_>>>()?;
+        for row in rows {
+            if matches!(row.get(9), Some(DataItem::Bool(true))) {
+                continue;
+            }
+            if let DataItem::Chars { value: name, .. } = &row[2]
+                && name == col_name {
+                let pk = &row[0];
+                let mut new_row = row.clone();
+                new_row[10] = match comment {
+                    Some(comment) => DataItem::VarChar {
+                        head: crate::common::VarCharHead {
+                            max_len: MAX_COMMENT_SIZE as u64,
+                            len: comment.len() as u64,
+                            page_ptr: None,
+                        },
+                        value: comment.to_string(),
+                    },
+                    None => DataItem::NullVarChar,
+                };
+                sys_column.update_row(pk, new_row, tnx_id)?;
+                return Ok(());
+            }
+        }
+        Err(RsqlError::ExecutionError(format!("Column {} not found in table", col_name)))
+    }
+    /// Comments of the live columns of a table, by column name
+    pub fn get_column_comments(&self, tnx_id: u64, table_id: u64) -> RsqlResult<HashMap<String, String>> {
+        TnxManager::global().acquire_read_locks(tnx_id, &[SYS_COLUMN_ID])?;
+        let sys_column = Table::from_shared(SYS_COLUMN_ID, sys_column_schema(), true)?;
+        let key = Some(DataItem::Integer(table_id as i64));
+        let mut comments = HashMap::new();
+        for row in sys_column.get_rows_by_range_indexed_col("table_id", &key, &key)? {
+            let row = row?;
+            if matches!(row[9], DataItem::Bool(true)) {
+                continue;
+            }
+            if let (DataItem::Chars { value: name, .. }, DataItem::VarChar { value: comment, .. }) = (&row[2], &row[10]) {
+                comments.insert(name.clone(), comment.clone());
+            }
+        }
+        Ok(comments)
+    }
 }
 
 #[cfg(test)]
@@ -1241,6 +1603,42 @@ mod tests {
         TnxManager::global().end_transaction(1);
     }
 
+    #[test]
+    #[serial]
+    fn test_catalog_cache() {
+        use crate::storage::ConsistStorageEngine;
+        let catalog = setup_test_catalog();
+        let tnx_id = TnxManager::global().begin_transaction(5);
+        let columns = vec![TableColumn {
+            name: "id".to_string(),
+            data_type: ColType::Integer,
+            pk: true,
+            nullable: false,
+            unique: true,
+            index: true,
+            is_dropped: false,
+        }];
+        let schema = TableSchema::new(columns).unwrap();
+        let table_id = catalog.register_table(tnx_id, "cached_table", &schema).unwrap();
+        assert_eq!(catalog.get_table_id(tnx_id, "cached_table").unwrap(), Some(table_id));
+        catalog.get_table_schema(tnx_id, table_id).unwrap();
+
+        // repeated lookups don't read the sys tables again
+        let before = ConsistStorageEngine::thread_read_count();
+        for _ in 0..3 {
+            assert_eq!(catalog.get_table_id(tnx_id, "cached_table").unwrap(), Some(table_id));
+            assert_eq!(catalog.get_table_schema(tnx_id, table_id).unwrap().get_columns().len(), 1);
+        }
+        assert_eq!(ConsistStorageEngine::thread_read_count(), before);
+
+        // a rename is seen by the next lookup
+        catalog.rename_table(tnx_id, table_id, "renamed_table").unwrap();
+        assert_eq!(catalog.get_table_id(tnx_id, "cached_table").unwrap(), None);
+        assert_eq!(catalog.get_table_id(tnx_id, "renamed_table").unwrap(), Some(table_id));
+        catalog.unregister_table(tnx_id, table_id).unwrap();
+        TnxManager::global().end_transaction(5);
+    }
+
     #[test]
     #[serial]
     fn test_user_management() {
@@ -1264,6 +1662,29 @@ mod tests {
         TnxManager::global().end_transaction(2);
     }
 
+    #[test]
+    #[serial]
+    fn test_drop_user_clears_privileges() {
+        let catalog = setup_test_catalog();
+        let tnx_id = TnxManager::global().begin_transaction(4);
+        let username = "grantee";
+
+        catalog.register_user(tnx_id, username, "pw").unwrap();
+        catalog.set_user_table_privilege(tnx_id, username, "t1", Some("W")).unwrap();
+        catalog.set_user_permission(tnx_id, username, Some("R")).unwrap();
+        assert!(catalog.check_user_privilege(tnx_id, username, Some("t1"), "W").unwrap());
+
+        // drop and re-create the user with the same name
+        catalog.unregister_user(tnx_id, username).unwrap();
+        catalog.register_user(tnx_id, username, "pw").unwrap();
+        assert!(!catalog.check_user_privilege(tnx_id, username, Some("t1"), "W").unwrap());
+        assert!(!catalog.check_user_privilege(tnx_id, username, Some("t1"), "R").unwrap());
+        assert!(!catalog.check_user_privilege(tnx_id, username, None, "R").unwrap());
+
+        catalog.unregister_user(tnx_id, username).unwrap();
+        TnxManager::global().end_transaction(4);
+    }
+
     #[test]
     #[serial]
     fn test_system_tables_init() {
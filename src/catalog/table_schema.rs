@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
+use sqlparser::ast::Expr;
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
 use crate::common::{RsqlError, RsqlResult};
 use crate::common::DataItem;
-use super::super::config::{MAX_VARCHAR_SIZE, MAX_COL_NAME_SIZE};
+use super::super::config::{MAX_VARCHAR_SIZE, MAX_COL_NAME_SIZE, COMPOSITE_PK_INDEX};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ColType {
@@ -12,6 +16,41 @@ pub enum ColType {
     Bool
 }
 
+/// How the values of a string column compare in its indexes and sorts, set by COLLATE
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collation {
+    #[default]
+    Binary, // byte-wise
+    NoCase, // ASCII letters compare without case
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> RsqlResult<Self> {
+        match name.to_uppercase().as_str() {
+            "BINARY" => Ok(Collation::Binary),
+            "NOCASE" => Ok(Collation::NoCase),
+            _ => Err(RsqlError::InvalidInput(format!("Unknown collation {}", name))),
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Collation::Binary => "BINARY",
+            Collation::NoCase => "NOCASE",
+        }
+    }
+    /// The value `item` compares as, values equal under the collation have the same key
+    pub fn key(&self, item: &DataItem) -> DataItem {
+        match (self, item) {
+            (Collation::NoCase, DataItem::Chars { len, value }) => DataItem::Chars { len: *len, value: value.to_ascii_lowercase() },
+            (Collation::NoCase, DataItem::VarChar { head, value }) => DataItem::VarChar { head: head.clone(), value: value.to_ascii_lowercase() },
+            _ => item.clone(),
+        }
+    }
+    pub fn compare(&self, a: &DataItem, b: &DataItem) -> Option<std::cmp::Ordering> {
+        self.key(a).partial_cmp(&self.key(b))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TableColumn {
     pub name: String, // fix to MAX_COL_NAME_SIZE bytes
@@ -23,9 +62,38 @@ pub struct TableColumn {
     pub is_dropped: bool,
 }
 
+/// A CHECK constraint of a table, the SQL text is what the catalog stores
+#[derive(Clone, Debug)]
+pub struct CheckConstraint {
+    pub sql: String,
+    pub expr: Expr,
+}
+
+impl CheckConstraint {
+    pub fn new(expr: Expr) -> Self {
+        CheckConstraint { sql: expr.to_string(), expr }
+    }
+    /// Parse a check expression read back from the catalog
+    pub fn parse(sql: &str) -> RsqlResult<Self> {
+        Ok(CheckConstraint { sql: sql.to_string(), expr: parse_stored_expr(sql)? })
+    }
+}
+
+/// Parse an expression the catalog keeps as SQL text
+pub fn parse_stored_expr(sql: &str) -> RsqlResult<Expr> {
+    Parser::new(&GenericDialect {})
+        .try_with_sql(sql)
+        .and_then(|mut parser| parser.parse_expr())
+        .map_err(|e| RsqlError::StorageError(format!("Invalid stored expression {}: {}", sql, e)))
+}
+
 #[derive(Clone, Debug)]
 pub struct TableSchema {
     columns: Vec<TableColumn>,
+    checks: Vec<CheckConstraint>,
+    defaults: HashMap<String, Expr>, // column name -> DEFAULT expression
+    primary_key: Vec<String>, // primary key column names in key order
+    collations: HashMap<String, Collation>, // column name -> collation, BINARY columns are left out
 }
 
 impl TableSchema {
@@ -76,7 +144,8 @@ impl TableSchema {
                 is_dropped,
             });
         }
-        Ok((TableSchema { columns }, schema_length))
+        let primary_key = columns.iter().filter(|col| col.pk).map(|col| col.name.clone()).collect();
+        Ok((TableSchema { columns, checks: vec![], defaults: HashMap::new(), primary_key, collations: HashMap::new() }, schema_length))
     }
     fn to_bytes(&self) -> Vec<u8> {
         let mut buf = vec![0u8; 8];
@@ -220,9 +289,18 @@ impl TableSchema {
                 return Err(RsqlError::InvalidInput(format!("Unique column {} must be indexed", col.name)));
             }
         }
-        // check if primary key columns are indexed and not null and unique
+        // check if primary key columns are indexed and not null and unique,
+        // the columns of a multi-column key share one index and are unique together only
+        let pk_count = columns.iter().filter(|col| col.pk).count();
         for col in &columns {
-            if col.pk {
+            if col.pk && pk_count > 1 {
+                if col.nullable {
+                    return Err(RsqlError::InvalidInput(format!("Primary key column {} cannot be nullable", col.name)));
+                }
+                if let ColType::VarChar(_) = col.data_type {
+                    return Err(RsqlError::InvalidInput(format!("VarChar column {} cannot be part of a primary key", col.name)));
+                }
+            } else if col.pk {
                 if !col.index {
                     return Err(RsqlError::InvalidInput(format!("Primary key column {} must be indexed", col.name)));
                 }
@@ -248,12 +326,36 @@ impl TableSchema {
             }
             name_set.insert(col.name.clone());
         }
-        // check if there are multiple primary key columns
-        let pk_count = columns.iter().filter(|col| col.pk).count();
-        if pk_count > 1 {
-            return Err(RsqlError::InvalidInput("Multiple primary key columns found".to_string()));
+        let primary_key = columns.iter().filter(|col| col.pk).map(|col| col.name.clone()).collect();
+        Ok(Self { columns, checks: vec![], defaults: HashMap::new(), primary_key, collations: HashMap::new() })
+    }
+    /// Order the primary key columns as `names`, e.g. for `PRIMARY KEY (b, a)`.
+    /// The names must be the primary key columns of the schema
+    pub fn with_primary_key(mut self, names: Vec<String>) -> RsqlResult<Self> {
+        let mut sorted = names.clone();
+        sorted.sort();
+        let mut pk_cols = self.primary_key.clone();
+        pk_cols.sort();
+        if sorted != pk_cols {
+            return Err(RsqlError::InvalidInput(format!(
+                "Primary key ({}) does not match the primary key columns ({})", names.join(", "), self.primary_key.join(", ")
+            )));
         }
-        Ok(Self { columns })
+        self.primary_key = names;
+        Ok(self)
+    }
+    pub fn with_checks(mut self, checks: Vec<CheckConstraint>) -> Self {
+        self.checks = checks;
+        self
+    }
+    pub fn with_defaults(mut self, defaults: HashMap<String, Expr>) -> Self {
+        self.defaults = defaults;
+        self
+    }
+    pub fn with_collations(mut self, collations: HashMap<String, Collation>) -> Self {
+        self.collations = collations;
+        self.collations.retain(|_, collation| *collation != Collation::Binary);
+        self
     }
     pub fn get_sizes(&self) -> Vec<usize> {
         let mut sizes = vec![];
@@ -271,4 +373,114 @@ impl TableSchema {
     pub fn get_columns(&self) -> &Vec<TableColumn> {
         &self.columns
     }
+    pub fn get_checks(&self) -> &Vec<CheckConstraint> {
+        &self.checks
+    }
+    pub fn get_default(&self, col_name: &str) -> Option<&Expr> {
+        self.defaults.get(col_name)
+    }
+    pub fn get_collations(&self) -> &HashMap<String, Collation> {
+        &self.collations
+    }
+    pub fn get_collation(&self, col_name: &str) -> Collation {
+        self.collations.get(col_name).copied().unwrap_or_default()
+    }
+    /// The key a value of column `col_name` is indexed and sorted by under the column collation
+    pub fn collation_key(&self, col_name: &str, item: &DataItem) -> DataItem {
+        self.get_collation(col_name).key(item)
+    }
+    /// Primary key column names in key order, empty without a primary key
+    pub fn get_primary_key(&self) -> &[String] {
+        &self.primary_key
+    }
+    /// Name of the index of the primary key: the column for a single-column key,
+    /// COMPOSITE_PK_INDEX for a multi-column one
+    pub fn pk_index_name(&self) -> Option<&str> {
+        match self.primary_key.as_slice() {
+            [] => None,
+            [col] => Some(col),
+            _ => Some(COMPOSITE_PK_INDEX),
+        }
+    }
+    /// Names of all indexes the table file keeps for this schema
+    pub fn index_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.columns.iter()
+            .filter(|col| col.index)
+            .map(|col| col.name.clone())
+            .collect();
+        if self.primary_key.len() > 1 {
+            names.push(COMPOSITE_PK_INDEX.to_string());
+        }
+        names
+    }
+    /// Type of the primary key index keys, CHAR of the encoded width for a multi-column key
+    pub fn pk_key_type(&self) -> Option<ColType> {
+        let pk_cols = self.pk_columns();
+        match pk_cols.as_slice() {
+            [] => None,
+            [col] => Some(col.data_type.clone()),
+            _ => Some(ColType::Chars(pk_cols.iter().map(|col| key_part_width(&col.data_type)).sum())),
+        }
+    }
+    /// Primary key index key of a row of the live columns.
+    /// A multi-column key is its values encoded to fixed-width, order-preserving text and concatenated,
+    /// so the keys sort like the tuples of values
+    pub fn pk_key(&self, row: &[DataItem]) -> RsqlResult<DataItem> {
+        let live: Vec<&TableColumn> = self.columns.iter().filter(|col| !col.is_dropped).collect();
+        let mut parts = vec![];
+        for name in &self.primary_key {
+            let idx = live.iter().position(|col| &col.name == name)
+                .ok_or(RsqlError::InvalidInput(format!("Primary key column {} is not a live column", name)))?;
+            let item = row.get(idx)
+                .ok_or(RsqlError::InvalidInput(format!("Row has no value for primary key column {}", name)))?;
+            parts.push((self.collation_key(name, item), &live[idx].data_type));
+        }
+        match parts.as_slice() {
+            [] => Err(RsqlError::InvalidInput("Table has no primary key".to_string())),
+            [(item, _)] => Ok(item.clone()),
+            _ => {
+                let mut value = String::new();
+                let mut len = 0;
+                for (item, col_type) in parts {
+                    value.push_str(&encode_key_part(&item, col_type)?);
+                    len += key_part_width(col_type);
+                }
+                Ok(DataItem::Chars { len: len as u64, value })
+            },
+        }
+    }
+    fn pk_columns(&self) -> Vec<&TableColumn> {
+        self.primary_key.iter()
+            .filter_map(|name| self.columns.iter().find(|col| &col.name == name))
+            .collect()
+    }
+}
+
+/// Bytes a value of `col_type` takes in a multi-column primary key
+fn key_part_width(col_type: &ColType) -> usize {
+    match col_type {
+        ColType::Integer | ColType::Float => 16,
+        ColType::Bool => 1,
+        ColType::Chars(size) | ColType::VarChar(size) => *size,
+    }
+}
+
+/// One value of a multi-column primary key as text of key_part_width bytes that sorts like the value.
+/// Numbers are hex of their bits with the order of signed values, CHAR is padded with \x01 since
+/// stored keys lose trailing \0
+fn encode_key_part(item: &DataItem, col_type: &ColType) -> RsqlResult<String> {
+    let part = match (item, col_type) {
+        (DataItem::Integer(i), ColType::Integer) => format!("{:016x}", (*i as u64) ^ (1 << 63)),
+        (DataItem::Float(f), ColType::Float) => {
+            let bits = f.to_bits();
+            let ordered = if bits >> 63 == 1 { !bits } else { bits | (1 << 63) };
+            format!("{:016x}", ordered)
+        },
+        (DataItem::Bool(b), ColType::Bool) => if *b { "1" } else { "0" }.to_string(),
+        (DataItem::Chars { value, .. }, ColType::Chars(size)) if value.len() <= *size => {
+            format!("{}{}", value, "\x01".repeat(size - value.len()))
+        },
+        _ => return Err(RsqlError::InvalidInput(format!("Invalid primary key value {:?} for type {:?}", item, col_type))),
+    };
+    Ok(part)
 }
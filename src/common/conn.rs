@@ -2,4 +2,5 @@ pub struct PrivilegeConn;
 impl PrivilegeConn {
     pub const INIT: u64 = 0;
     pub const USER_VALIDATE: u64 = 1;
+    pub const ANALYZE: u64 = 2;
 }
@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::common::{RsqlError, RsqlResult};
 use crate::catalog::table_schema;
+use crate::config;
 
 /// Data item representation in one block in table.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,6 +60,29 @@ impl DataItem {
             table_schema::ColType::Bool => 1 + 1,
         }
     }
+    /// Text of a Chars or VarChar item, trailing padding of Chars is trimmed
+    fn text_value(&self) -> Option<&str> {
+        match self {
+            DataItem::Chars { value, .. } => Some(value.trim_end_matches([' ', '\0'])),
+            DataItem::VarChar { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+    /// SQL-style rendering for clients: floats with a fixed precision, CHAR without its padding,
+    /// booleans as true/false and NULL as config::NULL_DISPLAY
+    pub fn display(&self) -> String {
+        match self {
+            DataItem::Integer(v) => v.to_string(),
+            DataItem::Float(v) => format!("{:.*}", config::FLOAT_DISPLAY_PRECISION, v),
+            DataItem::Chars { .. } | DataItem::VarChar { .. } => self.text_value().unwrap().to_string(),
+            DataItem::Bool(v) => v.to_string(),
+            DataItem::NullInt
+            | DataItem::NullFloat
+            | DataItem::NullChars { .. }
+            | DataItem::NullVarChar
+            | DataItem::NullBool => config::NULL_DISPLAY.to_string(),
+        }
+    }
     fn tag_to_byte(&self) -> u8 {
         match self {
             DataItem::Integer(_) => 1,
@@ -73,6 +97,8 @@ impl DataItem {
             DataItem::NullBool => 10,
         }
     }
+    /// A null keeps the width of its type with zeroed data, the type byte is what marks it null,
+    /// so stored nulls are never confused with zero or empty values
     pub fn to_bytes(&self) -> RsqlResult<(Vec<u8>, Option<Vec<u8>>)> {
         // the bytes include [data type(1 byte), data/data_head]
         match self {
@@ -152,6 +178,30 @@ impl DataItem {
             },
         }
     }
+    /// Decode the items of a stored entry in one pass, `layout` holds the (offset, size) of each item in `bytes`.
+    /// Fixed width items are read in place, CHAR and VARCHAR heads go through `from_bytes`
+    pub fn decode_row(bytes: &[u8], layout: &[(usize, usize)]) -> RsqlResult<Vec<Self>> {
+        let mut row = Vec::with_capacity(layout.len());
+        for &(offset, size) in layout {
+            let item_bytes = bytes.get(offset..offset + size).ok_or_else(|| RsqlError::Unknown(format!(
+                "Item at offset {} of size {} exceeds the entry of {} bytes", offset, size, bytes.len()
+            )))?;
+            // the size is checked with the tag, so the 8 bytes after the tag are there
+            let word = || item_bytes[1..9].try_into().unwrap();
+            let item = match (item_bytes[0], size) {
+                (1, 9) => DataItem::Integer(i64::from_le_bytes(word())),
+                (2, 9) => DataItem::Float(f64::from_le_bytes(word())),
+                (5, 2) if item_bytes[1] <= 1 => DataItem::Bool(item_bytes[1] == 1),
+                (6, 9) => DataItem::NullInt,
+                (7, 9) => DataItem::NullFloat,
+                (9, 25) => DataItem::NullVarChar,
+                (10, 2) => DataItem::NullBool,
+                _ => DataItem::from_bytes(item_bytes, None)?,
+            };
+            row.push(item);
+        }
+        Ok(row)
+    }
     pub fn from_bytes(head_bytes: &[u8], body_bytes: Option<&[u8]>) -> RsqlResult<Self> {
         if head_bytes.len() == 0 {
             return Err(RsqlError::Unknown("Cannot parse DataItem from empty bytes".to_string()));
@@ -188,7 +238,7 @@ impl DataItem {
                 // truncate trailing zeros
                 let value = String::from_utf8(head_bytes[9..expected_len].to_vec())
                     .map_err(|e| RsqlError::ParserError(e.to_string()))?
-                    .trim_matches('\0')
+                    .trim_end_matches('\0')
                     .to_string();
                 Ok(DataItem::Chars {len, value})
             },
@@ -280,6 +330,9 @@ impl PartialOrd for DataItem {
             (DataItem::Float(_) | DataItem::NullFloat, DataItem::Float(_) | DataItem::NullFloat) => true,
             (DataItem::Chars{..} | DataItem::NullChars{..}, DataItem::Chars{..} | DataItem::NullChars{..}) => true,
             (DataItem::VarChar{..} | DataItem::NullVarChar, DataItem::VarChar{..} | DataItem::NullVarChar) => true,
+            // Chars against VarChar compares the text
+            (DataItem::Chars{..} | DataItem::NullChars{..}, DataItem::VarChar{..} | DataItem::NullVarChar) => true,
+            (DataItem::VarChar{..} | DataItem::NullVarChar, DataItem::Chars{..} | DataItem::NullChars{..}) => true,
             (DataItem::Bool(_) | DataItem::NullBool, DataItem::Bool(_) | DataItem::NullBool) => true,
             _ => panic!("Comparing different data item types: {:?} vs {:?}", self, other),
         };
@@ -315,7 +368,15 @@ impl PartialOrd for DataItem {
             (DataItem::NullVarChar, DataItem::NullVarChar) => Some(Ordering::Equal),
             (DataItem::VarChar {value: v1, ..}, DataItem::VarChar {value: v2, ..}) => Some(v1.cmp(v2)),
 
-            // Bool Group
+            // Mixed Chars / VarChar, nulls sort first like in the groups above
+            (DataItem::NullChars{..} | DataItem::NullVarChar, DataItem::NullChars{..} | DataItem::NullVarChar) => Some(Ordering::Equal),
+            (DataItem::NullChars{..} | DataItem::NullVarChar, _) => Some(Ordering::Less),
+            (_, DataItem::NullChars{..} | DataItem::NullVarChar) => Some(Ordering::Greater),
+            (DataItem::Chars{..}, DataItem::VarChar{..}) | (DataItem::VarChar{..}, DataItem::Chars{..}) => {
+                Some(self.text_value()?.cmp(other.text_value()?))
+            },
+
+            // Bool Group, NULL < false < true, the key order of bool indexes
             (DataItem::NullBool, DataItem::Bool(_)) => Some(Ordering::Less),
             (DataItem::Bool(_), DataItem::NullBool) => Some(Ordering::Greater),
             (DataItem::NullBool, DataItem::NullBool) => Some(Ordering::Equal),
@@ -344,6 +405,12 @@ impl PartialEq for DataItem {
             // VarChar comparisons - only compare values, ignore page_ptr and other metadata
             (DataItem::VarChar { value: v1, .. }, DataItem::VarChar { value: v2, .. }) => v1 == v2,
             (DataItem::NullVarChar, DataItem::NullVarChar) => true,
+
+            // Chars against VarChar compares the text, the padding of Chars is ignored
+            (DataItem::Chars { .. }, DataItem::VarChar { .. }) | (DataItem::VarChar { .. }, DataItem::Chars { .. }) => {
+                self.text_value() == other.text_value()
+            },
+            (DataItem::NullChars { .. }, DataItem::NullVarChar) | (DataItem::NullVarChar, DataItem::NullChars { .. }) => true,
             
             // Bool comparisons
             (DataItem::Bool(b1), DataItem::Bool(b2)) => b1 == b2,
@@ -354,3 +421,106 @@ impl PartialEq for DataItem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chars_varchar_comparison() {
+        let chars = DataItem::Chars { len: 8, value: "alice   ".to_string() };
+        let varchar = |value: &str| DataItem::VarChar {
+            head: VarCharHead { max_len: value.len() as u64, len: value.len() as u64, page_ptr: None },
+            value: value.to_string(),
+        };
+        assert_eq!(chars, varchar("alice"));
+        assert_eq!(varchar("alice"), chars);
+        assert_ne!(chars, varchar("alicia"));
+        assert_eq!(chars.partial_cmp(&varchar("alice")), Some(Ordering::Equal));
+        assert_eq!(chars.partial_cmp(&varchar("bob")), Some(Ordering::Less));
+        assert_eq!(varchar("bob").partial_cmp(&chars), Some(Ordering::Greater));
+        assert_eq!(DataItem::NullVarChar.partial_cmp(&chars), Some(Ordering::Less));
+        assert_eq!(DataItem::NullChars { len: 8 }, DataItem::NullVarChar);
+    }
+
+    /// Entry bytes of a row and the layout of its items
+    fn encode_row(row: &[DataItem]) -> (Vec<u8>, Vec<(usize, usize)>) {
+        let mut bytes = vec![];
+        let mut layout = vec![];
+        for item in row {
+            let (head, _) = item.to_bytes().unwrap();
+            layout.push((bytes.len(), head.len()));
+            bytes.extend(head);
+        }
+        (bytes, layout)
+    }
+
+    /// Decode item by item, the way rows were read before `decode_row`
+    fn decode_per_item(bytes: &[u8], layout: &[(usize, usize)]) -> Vec<DataItem> {
+        layout.iter().map(|&(offset, size)| DataItem::from_bytes(&bytes[offset..offset + size], None).unwrap()).collect()
+    }
+
+    fn wide_row(i: i64) -> Vec<DataItem> {
+        vec![
+            DataItem::Integer(i),
+            DataItem::Float(i as f64 / 3.0),
+            DataItem::Chars { len: 12, value: format!("name{}", i) },
+            DataItem::VarChar { head: VarCharHead { max_len: 64, len: 5, page_ptr: Some(i as u64 + 1) }, value: "hello".to_string() },
+            DataItem::Bool(i % 2 == 0),
+            DataItem::NullInt,
+            DataItem::NullFloat,
+            DataItem::NullChars { len: 4 },
+            DataItem::NullVarChar,
+            DataItem::NullBool,
+            DataItem::Integer(-i),
+            DataItem::Float(f64::MAX),
+        ]
+    }
+
+    #[test]
+    fn test_decode_row_matches_per_item() {
+        let (bytes, layout) = encode_row(&wide_row(7));
+        let decoded = DataItem::decode_row(&bytes, &layout).unwrap();
+        assert_eq!(decoded, decode_per_item(&bytes, &layout));
+        // padding and pointers are kept as stored
+        assert!(matches!(&decoded[2], DataItem::Chars { len: 12, value } if value == "name7"));
+        assert!(matches!(&decoded[3], DataItem::VarChar { head, .. } if head.page_ptr == Some(8) && head.len == 5));
+        // a layout running past the entry, or a corrupted bool, fails
+        assert!(DataItem::decode_row(&bytes[..bytes.len() - 1], &layout).is_err());
+        let mut corrupted = bytes.clone();
+        corrupted[layout[4].0 + 1] = 7;
+        assert!(DataItem::decode_row(&corrupted, &layout).is_err());
+    }
+
+    #[test]
+    fn test_decode_many_rows() {
+        let rows: Vec<_> = (0..20_000).map(|i| encode_row(&wide_row(i))).collect();
+        let start = std::time::Instant::now();
+        let batch: Vec<_> = rows.iter().map(|(bytes, layout)| DataItem::decode_row(bytes, layout).unwrap()).collect();
+        let batch_time = start.elapsed();
+        let start = std::time::Instant::now();
+        let per_item: Vec<_> = rows.iter().map(|(bytes, layout)| decode_per_item(bytes, layout)).collect();
+        let per_item_time = start.elapsed();
+        println!("decoded {} rows: batch {:?}, per item {:?}", rows.len(), batch_time, per_item_time);
+        assert_eq!(batch, per_item);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(DataItem::Integer(-42).display(), "-42");
+        assert_eq!(DataItem::Float(1.5).display(), "1.500000");
+        assert_eq!(DataItem::Float(2.0 / 3.0).display(), "0.666667");
+        assert_eq!(DataItem::Chars { len: 8, value: "ab\0\0\0\0\0\0".to_string() }.display(), "ab");
+        assert_eq!(DataItem::Chars { len: 8, value: "a b     ".to_string() }.display(), "a b");
+        let varchar = DataItem::VarChar {
+            head: VarCharHead { max_len: 16, len: 7, page_ptr: None },
+            value: "padded ".to_string(),
+        };
+        assert_eq!(varchar.display(), "padded ");
+        assert_eq!(DataItem::Bool(true).display(), "true");
+        assert_eq!(DataItem::Bool(false).display(), "false");
+        for null in [DataItem::NullInt, DataItem::NullFloat, DataItem::NullChars { len: 8 }, DataItem::NullVarChar, DataItem::NullBool] {
+            assert_eq!(null.display(), "NULL");
+        }
+    }
+}
@@ -23,6 +23,15 @@ pub enum RsqlError {
     #[error("Failed to acquire lock: {0}")]
     LockError(String),
 
+    #[error("Deadlock detected: {0}")]
+    Deadlock(String),
+
+    #[error("Constraint violation: {0}")]
+    ConstraintViolation(String),
+
+    #[error("Statement cancelled: {0}")]
+    Cancelled(String),
+
     #[error("Unknown Error: {0}")]
     Unknown(String),
 }
@@ -2,12 +2,23 @@
 //! Caution: if you changed anyone below, make sure you have deleted
 //! old database files before starting rsql, or it may cause some 
 //! unexpected errors.
+//! The settings that can change per instance are also part of `Config`,
+//! which takes the constants below as its defaults.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::common::{RsqlError, RsqlResult};
 
 pub const _NAME: &str = "rsql";
 pub const _VERSION: &str = "0.1.0";
 
 // Network configuration
 pub const PORT: u16 = 4456;
+pub const EMBEDDED_CONNECTION_BASE: u64 = 1 << 48; // first id of connections opened through the library, above the clock based ids of websockets
+pub const MAX_CONNECTIONS: usize = 128; // default of the `max_connections` setting, connections beyond it are refused
+pub const IDLE_TIMEOUT_SECS: u64 = 30 * 60; // connections without a request for this long are closed and their transaction rolled back, 0 keeps them
+pub const IDLE_CHECK_INTERVAL_SECS: u64 = 10; // how often a connection checks its idle time
 
 // Logging configuration
 pub const LOG_LEVEL: &str = "info";
@@ -16,18 +27,45 @@ pub const LOG_PATH: &str = "./logs/rsql.log";
 // Performance configuration
 pub const THREAD_MAXNUM: usize = 0;
 pub const MAX_PAGE_CACHE_BYTES: usize = 100 * 1024 * 1024; // 100 MB
+pub const OPEN_TABLE_CACHE_SIZE: usize = 64; // closed tables whose parsed header and open file are kept for reuse
 
 // Database configuration
 pub const DB_DIR: &str = "./data";
 pub const SINGLE_FILE_MODE: bool = false; // unstable feature
 pub const PAGE_SIZE_BYTES: usize = 16 * 1024; // 16 KB
 pub const MAX_WAL_SIZE: u64 = 10 * 1024 * 1024; // 10 MB
+pub const MAX_WAL_SIZE_CEIL: u64 = 8 * MAX_WAL_SIZE; // checkpoint threshold is raised up to this when checkpoints reclaim little
+pub const MIN_CHECKPOINT_RECLAIM_RATIO: f64 = 0.25; // checkpoints reclaiming less than this raise the threshold
+pub const WAL_BUFFER_SIZE: usize = 64 * 1024; // 64 KB, appended log entries are written to the file in batches up to this size
+pub const PAGE_COMPACT_THRESHOLD: f64 = 0.5; // data pages whose free slots before the last row exceed this ratio are compacted
+pub const MAX_JOIN_ROWS: u64 = 10_000_000; // joins whose cross product has more rows are refused
+pub const INDEX_LOOKUP_COST: f64 = 4.0; // cost of reading a row through a secondary index, relative to reading it in a full scan
+pub const STATS_REFRESH_RATIO: f64 = 0.1; // column stats are recounted once the row count drifts by more than this ratio
+pub const SECURE_DROP: bool = false; // dropped tables are overwritten with zeros on disk before their file is truncated
+pub const FLOAT_EPSILON: f64 = 0.0; // float = and <> in filters match values within this distance, 0 is strict equality
+
+// SQL parsing
+pub const STATEMENT_TERMINATOR: char = ';'; // separates the statements of one request
+
+// Statement logging
+pub const STATEMENT_LOG: bool = true; // log every statement with its duration
+pub const SLOW_STATEMENT_MS: u64 = 1000; // statements slower than this are logged at warn level
+
+// Result display
+pub const FLOAT_DISPLAY_PRECISION: usize = 6; // digits after the decimal point of displayed floats
+pub const NULL_DISPLAY: &str = "NULL";
 
 // Limits
 pub const MAX_VARCHAR_SIZE: usize = 65535; // 64 KB
+pub const TRUNCATE_LONG_STRINGS: bool = false; // strings longer than their CHAR(n)/VARCHAR(n) column are truncated instead of rejected
 pub const MAX_COL_NAME_SIZE: usize = 64; // 64 bytes
 pub const MAX_USERNAME_SIZE: usize = 64; // 64 bytes
 pub const MAX_TABLE_NAME_SIZE: usize = 64; // 64 bytes
+pub const MAX_COMMENT_SIZE: usize = 1024; // 1 KB, comments attached to columns
+pub const MAX_CHECKS_SIZE: usize = 1024; // 1 KB, the CHECK constraints of a table
+pub const MAX_DEFAULT_SIZE: usize = 256; // the DEFAULT expression of a column
+pub const MAX_PRIMARY_KEY_SIZE: usize = 1024; // the column names of a multi-column primary key
+pub const COMPOSITE_PK_INDEX: &str = "(primary key)"; // index name of a multi-column primary key, never a column name
 
 // User configuration
 pub const DEFAULT_USERNAME: &str = "root";
@@ -41,4 +79,157 @@ pub const BACKUP_INTERVAL_SECS: u64 = 60 * 60; // 1 hour
 pub const CHECKPOINT_INTERVAL_SECS: u64 = 60; // 1 minute
 
 pub const LOCK_TIMEOUT_MS: u64 = 5000; // 5 seconds
-pub const LOCK_MAX_RETRY: u32 = 3; // Maximum number of retries for acquiring a lock
\ No newline at end of file
+pub const LOCK_MAX_RETRY: u32 = 3; // Maximum number of retries for acquiring a lock
+pub const TRANSACTION_MAX_RETRY: u32 = 5; // times a transaction aborted by a lock conflict is run again
+pub const TRANSACTION_RETRY_BACKOFF_MS: u64 = 20; // a retried transaction first waits up to this, doubled on each retry
+pub const WAL_OPEN_MAX_RETRY: u32 = 3; // times opening the WAL file is retried before the error is returned
+pub const WAL_OPEN_RETRY_MS: u64 = 100; // wait between two attempts to open the WAL file
+
+/// Settings of a database instance, built from the defaults above with the `with_*` methods,
+/// or loaded at startup from a config file and the environment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub db_dir: PathBuf,
+    pub log_path: PathBuf,
+    pub log_level: String,
+    pub max_wal_size: u64,
+    pub single_file_mode: bool,
+    pub max_join_rows: u64,
+    pub float_epsilon: f64,
+    pub secure_drop: bool,
+    pub idle_timeout_secs: u64,
+    pub max_connections: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            db_dir: PathBuf::from(DB_DIR),
+            log_path: PathBuf::from(LOG_PATH),
+            log_level: LOG_LEVEL.to_string(),
+            max_wal_size: MAX_WAL_SIZE,
+            single_file_mode: SINGLE_FILE_MODE,
+            max_join_rows: MAX_JOIN_ROWS,
+            float_epsilon: FLOAT_EPSILON,
+            secure_drop: SECURE_DROP,
+            idle_timeout_secs: IDLE_TIMEOUT_SECS,
+            max_connections: MAX_CONNECTIONS,
+        }
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_db_dir(mut self, db_dir: impl Into<PathBuf>) -> Self {
+        self.db_dir = db_dir.into();
+        self
+    }
+    pub fn with_log_path(mut self, log_path: impl Into<PathBuf>) -> Self {
+        self.log_path = log_path.into();
+        self
+    }
+    pub fn with_log_level(mut self, log_level: &str) -> Self {
+        self.log_level = log_level.to_string();
+        self
+    }
+    pub fn with_max_wal_size(mut self, max_wal_size: u64) -> Self {
+        self.max_wal_size = max_wal_size;
+        self
+    }
+    pub fn with_single_file_mode(mut self, single_file_mode: bool) -> Self {
+        self.single_file_mode = single_file_mode;
+        self
+    }
+    pub fn with_max_join_rows(mut self, max_join_rows: u64) -> Self {
+        self.max_join_rows = max_join_rows;
+        self
+    }
+    pub fn with_float_epsilon(mut self, float_epsilon: f64) -> Self {
+        self.float_epsilon = float_epsilon;
+        self
+    }
+    pub fn with_secure_drop(mut self, secure_drop: bool) -> Self {
+        self.secure_drop = secure_drop;
+        self
+    }
+    pub fn with_idle_timeout_secs(mut self, idle_timeout_secs: u64) -> Self {
+        self.idle_timeout_secs = idle_timeout_secs;
+        self
+    }
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+    /// The defaults, overridden by the `key = value` lines of the file named by `RSQL_CONFIG`,
+    /// then by the `RSQL_<KEY>` environment variables (e.g. `RSQL_DB_DIR`)
+    pub fn load() -> RsqlResult<Self> {
+        let mut config = Config::default();
+        if let Ok(path) = std::env::var("RSQL_CONFIG") {
+            config = config.with_file(Path::new(&path))?;
+        }
+        for key in ["db_dir", "log_path", "log_level", "max_wal_size", "single_file_mode", "max_join_rows", "float_epsilon", "secure_drop", "idle_timeout_secs", "max_connections"] {
+            if let Ok(value) = std::env::var(format!("RSQL_{}", key.to_uppercase())) {
+                config.set(key, &value)?;
+            }
+        }
+        Ok(config)
+    }
+    /// Override the settings with the `key = value` lines of a file, `#` starts a comment
+    pub fn with_file(mut self, path: &Path) -> RsqlResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(RsqlError::InvalidInput(format!("Invalid config line: {}", line)));
+            };
+            self.set(key.trim(), value.trim())?;
+        }
+        Ok(self)
+    }
+    fn set(&mut self, key: &str, value: &str) -> RsqlResult<()> {
+        let invalid = || RsqlError::InvalidInput(format!("Invalid value for config {}: {}", key, value));
+        match key {
+            "db_dir" => self.db_dir = PathBuf::from(value),
+            "log_path" => self.log_path = PathBuf::from(value),
+            "log_level" => self.log_level = value.to_string(),
+            "max_wal_size" => self.max_wal_size = value.parse().map_err(|_| invalid())?,
+            "single_file_mode" => self.single_file_mode = value.parse().map_err(|_| invalid())?,
+            "max_join_rows" => self.max_join_rows = value.parse().map_err(|_| invalid())?,
+            "float_epsilon" => match value.parse::<f64>() {
+                Ok(epsilon) if epsilon.is_finite() && epsilon >= 0.0 => self.float_epsilon = epsilon,
+                _ => return Err(invalid()),
+            },
+            "secure_drop" => self.secure_drop = value.parse().map_err(|_| invalid())?,
+            "idle_timeout_secs" => self.idle_timeout_secs = value.parse().map_err(|_| invalid())?,
+            "max_connections" => self.max_connections = value.parse().map_err(|_| invalid())?,
+            _ => return Err(RsqlError::InvalidInput(format!("Unknown config key: {}", key))),
+        }
+        Ok(())
+    }
+    pub fn sys_dir(&self) -> PathBuf {
+        self.db_dir.join("sys")
+    }
+    pub fn tables_dir(&self) -> PathBuf {
+        self.db_dir.join("tables")
+    }
+    pub fn wal_path(&self) -> PathBuf {
+        self.db_dir.join("wal.log")
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Use `config` for this process, fails once a configuration is in use
+pub fn install(config: Config) -> RsqlResult<()> {
+    CONFIG.set(config).map_err(|_| RsqlError::InvalidInput("A configuration is already in use".to_string()))
+}
+
+/// The configuration in use, the defaults when none was installed
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
@@ -0,0 +1,340 @@
+//! Validation of SQL against the catalog without executing it, for editors and linters.
+
+use std::collections::HashMap;
+use sqlparser::ast::{Expr, FunctionArg, FunctionArgExpr, FunctionArguments};
+
+use crate::catalog::SysCatalog;
+use crate::common::{RsqlResult, RsqlError, PrivilegeConn};
+use crate::sql::Plan;
+use crate::sql::plan::{PlanItem, PlanNode, DdlOperation};
+use crate::transaction::TnxManager;
+
+/// What the analysis knows about a table name
+#[derive(Clone)]
+enum TableState {
+    Missing,
+    Columns(Vec<String>),
+    /// created by CREATE TABLE ... AS SELECT earlier in the SQL, its columns are not checked
+    Inferred,
+}
+
+/// Build the plan of `sql` and check that the tables and columns it names exist.
+/// Nothing is executed, a table created or dropped earlier in `sql` counts as such.
+pub fn analyze(sql: &str) -> RsqlResult<Plan> {
+    let plan = Plan::build_plan(sql)?;
+    let tnx_id = TnxManager::global().begin_transaction(PrivilegeConn::ANALYZE);
+    let mut analyzer = Analyzer { tnx_id, tables: HashMap::new() };
+    let checked = plan.items.iter().try_for_each(|item| match item {
+        PlanItem::DDL(node) | PlanItem::DML(node) => analyzer.check_node(node),
+        _ => Ok(()),
+    });
+    TnxManager::global().end_transaction(PrivilegeConn::ANALYZE);
+    checked.map(|_| plan)
+}
+
+struct Analyzer {
+    tnx_id: u64,
+    tables: HashMap<String, TableState>,
+}
+
+impl Analyzer {
+    fn lookup(&mut self, table_name: &str) -> RsqlResult<TableState> {
+        if let Some(state) = self.tables.get(table_name) {
+            return Ok(state.clone());
+        }
+        let catalog = SysCatalog::global();
+        let state = match catalog.get_table_id(self.tnx_id, table_name)? {
+            Some(table_id) => TableState::Columns(catalog.get_table_schema(self.tnx_id, table_id)?
+                .get_columns().iter()
+                .filter(|col| !col.is_dropped)
+                .map(|col| col.name.clone())
+                .collect()),
+            None => TableState::Missing,
+        };
+        self.tables.insert(table_name.to_string(), state.clone());
+        Ok(state)
+    }
+
+    fn exists(&mut self, table_name: &str) -> RsqlResult<bool> {
+        Ok(!matches!(self.lookup(table_name)?, TableState::Missing))
+    }
+
+    /// The columns of the table, None when they are not known
+    fn columns(&mut self, table_name: &str) -> RsqlResult<Option<Vec<String>>> {
+        match self.lookup(table_name)? {
+            TableState::Missing => Err(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name))),
+            TableState::Columns(cols) => Ok(Some(cols)),
+            TableState::Inferred => Ok(None),
+        }
+    }
+
+    fn check_column(&mut self, table_name: &str, column: &str) -> RsqlResult<()> {
+        match self.columns(table_name)? {
+            Some(cols) if !cols.iter().any(|col| col == column) =>
+                Err(RsqlError::InvalidInput(format!("Column {} does not exist in table {}.", column, table_name))),
+            _ => Ok(()),
+        }
+    }
+
+    fn update_columns(&mut self, table_name: &str, f: impl FnOnce(&mut Vec<String>)) {
+        if let Some(TableState::Columns(cols)) = self.tables.get_mut(table_name) {
+            f(cols);
+        }
+    }
+
+    fn check_node(&mut self, node: &PlanNode) -> RsqlResult<()> {
+        match node {
+            PlanNode::DDL { op } => self.check_ddl(op),
+            _ => self.check_statement(node),
+        }
+    }
+
+    fn check_ddl(&mut self, op: &DdlOperation) -> RsqlResult<()> {
+        match op {
+            DdlOperation::CreateTable { table_name, schema, if_not_exists } => {
+                if self.exists(table_name)? {
+                    if !*if_not_exists {
+                        return Err(RsqlError::ExecutionError(format!("Table {} already exists.", table_name)));
+                    }
+                    return Ok(());
+                }
+                let cols = schema.get_columns().iter().map(|col| col.name.clone()).collect();
+                self.tables.insert(table_name.clone(), TableState::Columns(cols));
+            }
+            DdlOperation::CreateTableAs { table_name, input, if_not_exists } => {
+                self.check_statement(input)?;
+                if self.exists(table_name)? {
+                    if !*if_not_exists {
+                        return Err(RsqlError::ExecutionError(format!("Table {} already exists.", table_name)));
+                    }
+                    return Ok(());
+                }
+                self.tables.insert(table_name.clone(), TableState::Inferred);
+            }
+            DdlOperation::DropTable { table_name, if_exists } => {
+                if !*if_exists {
+                    self.columns(table_name)?;
+                }
+                self.tables.insert(table_name.clone(), TableState::Missing);
+            }
+            DdlOperation::RenameTable { old_name, new_name, if_exists } => {
+                if !self.exists(old_name)? {
+                    if !*if_exists {
+                        return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", old_name)));
+                    }
+                    return Ok(());
+                }
+                let state = self.lookup(old_name)?;
+                self.tables.insert(old_name.clone(), TableState::Missing);
+                self.tables.insert(new_name.clone(), state);
+            }
+            DdlOperation::RenameColumn { table_name, old_name, new_name } => {
+                self.check_column(table_name, old_name)?;
+                self.update_columns(table_name, |cols| for col in cols.iter_mut().filter(|col| *col == old_name) {
+                    *col = new_name.clone();
+                });
+            }
+            DdlOperation::DropColumn { table_name, column_name, if_exists } => {
+                if !*if_exists {
+                    self.check_column(table_name, column_name)?;
+                }
+                self.columns(table_name)?;
+                self.update_columns(table_name, |cols| cols.retain(|col| col != column_name));
+            }
+            DdlOperation::CreateIndex { table_name, column, .. }
+            | DdlOperation::DebugIndex { table_name, column }
+            | DdlOperation::AlterColumnType { table_name, column_name: column, .. }
+            | DdlOperation::AddKey { table_name, column_name: column, .. }
+            | DdlOperation::CommentOnColumn { table_name, column_name: column, .. } => {
+                self.check_column(table_name, column)?;
+            }
+            DdlOperation::Reindex { table_name, column } => {
+                self.columns(table_name)?;
+                if let Some(column) = column {
+                    self.check_column(table_name, column)?;
+                }
+            }
+            DdlOperation::Describe { table_name } | DdlOperation::IndexStats { table_name } => {
+                self.columns(table_name)?;
+            }
+            DdlOperation::DryRun { op } => {
+                // a dry run leaves the tables as they were for the statements after it
+                let tables = self.tables.clone();
+                let checked = self.check_ddl(op);
+                self.tables = tables;
+                checked?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A query or DML statement: every scanned table must exist, and every column
+    /// named in its expressions must belong to one of them
+    fn check_statement(&mut self, node: &PlanNode) -> RsqlResult<()> {
+        let mut tables = vec![];
+        let mut exprs = vec![];
+        collect(node, &mut tables, &mut exprs);
+        let mut known = vec![];
+        let mut opaque = false;
+        for table in &tables {
+            match table {
+                Some(table) => match self.columns(table)? {
+                    Some(cols) => known.extend(cols),
+                    None => opaque = true,
+                },
+                None => opaque = true,
+            }
+        }
+        // a column may come from a source whose columns are not known
+        if opaque {
+            return Ok(());
+        }
+        if let PlanNode::Insert { table_name, columns, .. } = node {
+            let Some(target_cols) = self.columns(table_name)? else {
+                return Ok(());
+            };
+            for column in columns.iter().flatten() {
+                self.check_column(table_name, column)?;
+            }
+            known.extend(target_cols);
+        }
+        let mut names = vec![];
+        if let PlanNode::Update { assignments, .. } = node {
+            names.extend(assignments.iter().map(|(column, _)| column.clone()));
+        }
+        for expr in exprs {
+            expr_columns(expr, &mut names);
+        }
+        match names.into_iter().find(|name| !known.contains(name)) {
+            Some(name) => Err(RsqlError::InvalidInput(format!("Column {} does not exist.", name))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The scanned tables of a plan and the expressions whose column names are checked,
+/// a None table is a WITH query or VALUES list whose columns are not known.
+/// ORDER BY keys are left out, they may name select list aliases
+fn collect<'a>(node: &'a PlanNode, tables: &mut Vec<Option<String>>, exprs: &mut Vec<&'a Expr>) {
+    match node {
+        PlanNode::TableScan { table, .. } => tables.push(Some(table.clone())),
+        PlanNode::CteScan { .. } | PlanNode::Values { .. } => tables.push(None),
+        PlanNode::With { ctes, input } => {
+            for (_, cte) in ctes {
+                collect(cte, tables, exprs);
+            }
+            collect(input, tables, exprs);
+        }
+        PlanNode::Subquery { subquery, .. } => collect(subquery, tables, exprs),
+        PlanNode::Apply { input, subquery, .. } => {
+            collect(input, tables, exprs);
+            collect(subquery, tables, exprs);
+        }
+        PlanNode::Filter { predicate, input } => {
+            exprs.push(predicate);
+            collect(input, tables, exprs);
+        }
+        PlanNode::Aggregate { group_by, aggr_exprs, input } => {
+            exprs.extend(group_by.iter().chain(aggr_exprs));
+            collect(input, tables, exprs);
+        }
+        PlanNode::Projection { exprs: projection, input } => {
+            exprs.extend(projection);
+            collect(input, tables, exprs);
+        }
+        PlanNode::Sort { input, .. } | PlanNode::Limit { input, .. } => collect(input, tables, exprs),
+        PlanNode::Join { left, right, on, .. } => {
+            exprs.extend(on);
+            collect(left, tables, exprs);
+            collect(right, tables, exprs);
+        }
+        PlanNode::Insert { input, returning, .. } => {
+            exprs.extend(returning.iter().flatten());
+            if let Some(input) = input {
+                collect(input, tables, exprs);
+            }
+        }
+        PlanNode::Delete { input, returning } => {
+            exprs.extend(returning.iter().flatten());
+            collect(input, tables, exprs);
+        }
+        PlanNode::Update { input, assignments, returning } => {
+            exprs.extend(returning.iter().flatten());
+            exprs.extend(assignments.iter().map(|(_, value)| value));
+            collect(input, tables, exprs);
+        }
+        _ => {}
+    }
+}
+
+/// Column names an expression refers to, the last part of a qualified name.
+/// Subquery bodies are planned as their own nodes and skipped here
+fn expr_columns(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::Identifier(ident) if ident.value != "*" => names.push(ident.value.clone()),
+        Expr::CompoundIdentifier(idents) => names.extend(idents.last().map(|ident| ident.value.clone())),
+        Expr::BinaryOp { left, right, .. } | Expr::IsDistinctFrom(left, right) | Expr::IsNotDistinctFrom(left, right) => {
+            expr_columns(left, names);
+            expr_columns(right, names);
+        }
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::IsTrue(expr)
+        | Expr::IsFalse(expr)
+        | Expr::Cast { expr, .. }
+        | Expr::Named { expr, .. }
+        | Expr::InSubquery { expr, .. } => expr_columns(expr, names),
+        Expr::Between { expr, low, high, .. } => {
+            for e in [expr, low, high] {
+                expr_columns(e, names);
+            }
+        }
+        Expr::InList { expr, list, .. } => {
+            expr_columns(expr, names);
+            for e in list {
+                expr_columns(e, names);
+            }
+        }
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => {
+            expr_columns(expr, names);
+            expr_columns(pattern, names);
+        }
+        Expr::Function(func) => {
+            if let FunctionArguments::List(args) = &func.args {
+                for arg in &args.args {
+                    if let FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) = arg {
+                        expr_columns(e, names);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::execute;
+    use crate::execution::test_utils::TestConn;
+
+    #[test]
+    #[serial_test::serial]
+    fn test_analyze_checks_catalog() {
+        let test_conn = TestConn::setup(&["analyzed", "no_such_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE analyzed (id INTEGER PRIMARY KEY, name CHAR(8))", conn).unwrap();
+
+        assert!(analyze("SELECT id, name FROM analyzed WHERE id > 1").is_ok());
+        assert!(analyze("SELECT a.name, COUNT(*) FROM analyzed a JOIN analyzed b ON a.id = b.id GROUP BY a.name").is_ok());
+        assert!(analyze("SELECT id FROM no_such_table").is_err());
+        assert!(analyze("SELECT missing FROM analyzed").is_err());
+        assert!(analyze("UPDATE analyzed SET name = 'x' WHERE missing = 1").is_err());
+        // a table created earlier in the same SQL exists for the statements after it
+        assert!(analyze("CREATE TABLE no_such_table (k INTEGER PRIMARY KEY); INSERT INTO no_such_table (k) VALUES (1)").is_ok());
+        // nothing was executed
+        assert!(execute("SELECT * FROM no_such_table", conn).is_err());
+    }
+}
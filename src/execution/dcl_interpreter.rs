@@ -1,8 +1,12 @@
 use crate::catalog::SysCatalog;
+use crate::catalog::table_schema::ColType;
 use crate::common::{RsqlResult, RsqlError};
+use crate::common::data_item::{DataItem, VarCharHead};
 use crate::sql::plan::{PlanNode};
 use crate::config::DEFAULT_PASSWORD;
 use crate::server::conncetion_user_map::ConnectionUserMap;
+use crate::storage::WAL;
+use crate::transaction::TnxManager;
 use super::result::{ExecutionResult::{self, Dcl}};
 use tracing::info;
 
@@ -50,7 +54,21 @@ pub fn execute_dcl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
                     return Err(RsqlError::ExecutionError(format!("User {} does not exist.", user_name)));
                 }
             }
+            // privileges are stored in the user row, so they are removed together with it
             SysCatalog::global().unregister_user(tnx_id, user_name)?;
+            // close the open connections of the dropped user: a websocket stops and rolls back its transaction,
+            // other connections are marked and disconnected once their running statement finished.
+            // This statement's connection commits it first
+            let closed = ConnectionUserMap::global().user_connections(user_name);
+            let reason = format!("User {} was dropped", user_name);
+            for conn_id in closed.iter().copied() {
+                if !ConnectionUserMap::global().close_connection(conn_id, &reason) && conn_id != connection_id {
+                    ConnectionUserMap::global().mark_terminated(conn_id);
+                }
+            }
+            if !closed.is_empty() {
+                info!("Closed {} connection(s) of dropped user {}.", closed.len(), user_name);
+            }
             Ok(Dcl(format!("User {} dropped successfully.", user_name)))
         },
         // only support write permission for now
@@ -106,8 +124,146 @@ pub fn execute_dcl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
                 }
             }
         },
+        PlanNode::Kill { tnx_id: target } => {
+            // verify permision
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, &username)?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} does not have permission to kill transactions.", username)));
+            }
+            if !TnxManager::global().kill(*target) {
+                return Err(RsqlError::ExecutionError(format!("Transaction {} is not active.", target)));
+            }
+            Ok(Dcl(format!("Transaction {} is being cancelled.", target)))
+        },
+        PlanNode::DebugWal => {
+            // verify permision
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, &username)?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} does not have permission to debug the WAL.", username)));
+            }
+            let summaries = WAL::global()?.entry_summaries()?;
+            let varchar = |value: String, max_len: usize| DataItem::VarChar {
+                head: VarCharHead { max_len: max_len as u64, len: value.len() as u64, page_ptr: None },
+                value,
+            };
+            let int = |value: Option<u64>| value.map_or(DataItem::NullInt, |v| DataItem::Integer(v as i64));
+            let type_width = summaries.iter().map(|s| s.entry_type.len()).max().unwrap_or(0);
+            let detail_width = summaries.iter().map(|s| s.detail.len()).max().unwrap_or(0);
+            let rows = summaries.into_iter().map(|s| vec![
+                varchar(s.entry_type.to_string(), type_width),
+                int(s.tnx_id),
+                int(s.table_id),
+                int(s.page_id),
+                varchar(s.detail, detail_width),
+            ]).collect();
+            Ok(ExecutionResult::Query {
+                cols: (
+                    ["entry_type", "tnx_id", "table_id", "page_id", "detail"].map(String::from).to_vec(),
+                    vec![ColType::VarChar(type_width), ColType::Integer, ColType::Integer, ColType::Integer, ColType::VarChar(detail_width)],
+                ),
+                rows,
+            })
+        },
         _ => {
             panic!("Unsupported DCL operation")
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::executor::execute;
+    use crate::execution::test_utils::{query_rows, TestConn};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    #[serial_test::serial]
+    fn test_kill_running_query() {
+        let test_conns = [TestConn::setup(&["kill_table"]), TestConn::new()];
+        let (conn, killer) = (test_conns[0].id, test_conns[1].id);
+        execute("CREATE TABLE kill_table (id INTEGER PRIMARY KEY, v INTEGER)", conn).unwrap();
+        for i in 0..200 {
+            execute(&format!("INSERT INTO kill_table VALUES ({}, {})", i, i), conn).unwrap();
+        }
+        assert!(execute("KILL 999999", killer).is_err());
+
+        execute("BEGIN", conn).unwrap();
+        let tnx_id = TnxManager::global().get_transaction_id(conn).unwrap();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            execute(&format!("KILL {}", tnx_id), killer).unwrap();
+        });
+        // keep scanning until the kill lands, mid-scan or before the next statement starts
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let err = loop {
+            match execute("SELECT * FROM kill_table WHERE v >= 0", conn) {
+                Ok(_) => assert!(Instant::now() < deadline, "the scan was never cancelled"),
+                Err(e) => break e,
+            }
+        };
+        handle.join().unwrap();
+        assert!(matches!(err, RsqlError::Cancelled(_)), "{:?}", err);
+        // the killed transaction is rolled back and the connection can go on
+        assert!(TnxManager::global().get_transaction_id(conn).is_none());
+        let res = execute("SELECT id FROM kill_table", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1.len(), 200);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_debug_wal() {
+        let test_conn = TestConn::setup(&["wal_dump"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE wal_dump (id INTEGER PRIMARY KEY, v INTEGER)", conn).unwrap();
+        let run_tnx = |sql: &str, end: &str| {
+            execute("BEGIN", conn).unwrap();
+            let tnx_id = TnxManager::global().get_transaction_id(conn).unwrap();
+            execute(sql, conn).unwrap();
+            execute(end, conn).unwrap();
+            tnx_id
+        };
+        let committed = run_tnx("INSERT INTO wal_dump VALUES (1, 10)", "COMMIT");
+        let rolled_back = run_tnx("UPDATE wal_dump SET v = 20 WHERE id = 1", "ROLLBACK");
+
+        let res = execute("DEBUG WAL", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["entry_type", "tnx_id", "table_id", "page_id", "detail"]);
+        // other tests may share the log, only look at the entries of these transactions
+        let types_of = |tnx_id: u64| -> Vec<String> {
+            rows.iter()
+                .filter(|row| row[1] == DataItem::Integer(tnx_id as i64))
+                .map(|row| match &row[0] {
+                    DataItem::VarChar { value, .. } => value.clone(),
+                    other => panic!("Expected an entry type, got {:?}", other),
+                })
+                .collect()
+        };
+        for (tnx_id, end) in [(committed, "CommitTnx"), (rolled_back, "RollbackTnx")] {
+            let types = types_of(tnx_id);
+            let (last, pages) = types.split_last().unwrap();
+            assert_eq!(last, end);
+            assert!(!pages.is_empty() && pages.iter().all(|t| t.ends_with("Page")), "{:?}", types);
+        }
+        assert!(execute("DEBUG WAL extra", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_drop_user_detaches_connections() {
+        let test_conn = TestConn::setup(&[]);
+        let conn = test_conn.id;
+        execute("DROP USER IF EXISTS dropped_user", conn).unwrap();
+        execute("CREATE USER dropped_user PASSWORD 'pw'", conn).unwrap();
+        let test_user_conn = TestConn::as_user("dropped_user");
+        let user_conn = test_user_conn.id;
+        execute("BEGIN", user_conn).unwrap();
+        assert!(TnxManager::global().get_transaction_id(user_conn).is_some());
+
+        // a connection without a websocket is removed and rolled back once DROP USER finished
+        execute("DROP USER dropped_user", conn).unwrap();
+        assert!(ConnectionUserMap::global().get_username(user_conn).is_none());
+        assert!(TnxManager::global().get_transaction_id(user_conn).is_none());
+    }
+}
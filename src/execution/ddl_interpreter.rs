@@ -1,12 +1,125 @@
+use std::collections::HashSet;
 use crate::catalog::{SysCatalog, sys_catalog};
+use crate::catalog::table_schema::{TableSchema, TableColumn, ColType};
 use crate::common::{RsqlResult, RsqlError};
+use crate::common::data_item::{DataItem, VarCharHead};
 use crate::sql::plan::{PlanNode};
 use crate::sql::plan::DdlOperation;
 use crate::server::conncetion_user_map::ConnectionUserMap;
 use crate::storage::Table;
-use super::result::{ExecutionResult::{self, Ddl}};
+use crate::transaction::TnxManager;
+use crate::config::{MAX_COL_NAME_SIZE, MAX_COMMENT_SIZE};
+use super::result::{ExecutionResult::{self, Ddl}, MiddleResult};
+use super::dml_interpreter::execute_dml_plan_node;
+use super::expr_interpreter::{check_row, default_row, is_null_item, null_of_type};
 use tracing::info;
 
+/// Name of the primary key added to CREATE TABLE ... AS SELECT tables without a usable key column
+const ROWID_COL: &str = "rowid";
+
+/// Convert a stored value to `tar_type` for ALTER COLUMN ... TYPE.
+/// Returns None when the value has no representation in the new type.
+fn convert_column_item(item: &DataItem, tar_type: &ColType) -> Option<DataItem> {
+    let text = |value: String| match tar_type {
+        ColType::Chars(size) if value.len() <= *size => Some(DataItem::Chars { len: *size as u64, value }),
+        ColType::VarChar(size) if value.len() <= *size => Some(DataItem::VarChar {
+            head: VarCharHead { max_len: *size as u64, len: value.len() as u64, page_ptr: None },
+            value,
+        }),
+        _ => None,
+    };
+    if is_null_item(item) {
+        return Some(match tar_type {
+            ColType::Integer => DataItem::NullInt,
+            ColType::Float => DataItem::NullFloat,
+            ColType::Bool => DataItem::NullBool,
+            ColType::Chars(size) => DataItem::NullChars { len: *size as u64 },
+            ColType::VarChar(_) => DataItem::NullVarChar,
+        });
+    }
+    match (item, tar_type) {
+        (DataItem::Integer(i), ColType::Integer) => Some(DataItem::Integer(*i)),
+        (DataItem::Integer(i), ColType::Float) => Some(DataItem::Float(*i as f64)),
+        (DataItem::Integer(i), ColType::Chars(_) | ColType::VarChar(_)) => text(i.to_string()),
+        (DataItem::Float(f), ColType::Float) => Some(DataItem::Float(*f)),
+        (DataItem::Float(f), ColType::Integer) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => Some(DataItem::Integer(*f as i64)),
+        (DataItem::Float(f), ColType::Chars(_) | ColType::VarChar(_)) => text(f.to_string()),
+        (DataItem::Bool(b), ColType::Bool) => Some(DataItem::Bool(*b)),
+        (DataItem::Bool(b), ColType::Chars(_) | ColType::VarChar(_)) => text(b.to_string()),
+        (DataItem::Chars { value, .. } | DataItem::VarChar { value, .. }, _) => match tar_type {
+            ColType::Integer => value.trim().parse::<i64>().ok().map(DataItem::Integer),
+            ColType::Float => value.trim().parse::<f64>().ok().filter(|f| f.is_finite()).map(DataItem::Float),
+            ColType::Bool => value.trim().parse::<bool>().ok().map(DataItem::Bool),
+            ColType::Chars(_) | ColType::VarChar(_) => text(value.clone()),
+        },
+        _ => None,
+    }
+}
+
+/// SQL name of a column type as shown by DESCRIBE
+fn col_type_name(col_type: &ColType) -> String {
+    match col_type {
+        ColType::Integer => "INTEGER".to_string(),
+        ColType::Float => "FLOAT".to_string(),
+        ColType::Chars(size) => format!("CHAR({})", size),
+        ColType::VarChar(size) => format!("VARCHAR({})", size),
+        ColType::Bool => "BOOLEAN".to_string(),
+    }
+}
+
+/// Infer the schema of a CREATE TABLE ... AS SELECT table from the query output.
+/// The first integer or chars column holding distinct non-null values becomes the primary key,
+/// otherwise a synthetic `rowid` primary key is put in front.
+/// Returns the schema and whether the rowid column was added.
+fn infer_ctas_schema(cols: &(Vec<String>, Vec<ColType>), rows: &[Vec<DataItem>]) -> RsqlResult<(TableSchema, bool)> {
+    let mut names = HashSet::new();
+    for name in &cols.0 {
+        if !names.insert(name) {
+            return Err(RsqlError::ExecutionError(format!("Duplicate column {} in CREATE TABLE ... AS SELECT, use aliases", name)));
+        }
+    }
+    let pk_idx = cols.1.iter().enumerate().position(|(idx, col_type)| {
+        if !matches!(col_type, ColType::Integer | ColType::Chars(_)) {
+            return false;
+        }
+        let mut seen = HashSet::new();
+        rows.iter().all(|row| !is_null_item(&row[idx]) && seen.insert(row[idx].clone()))
+    });
+    let mut columns: Vec<TableColumn> = cols.0.iter().zip(cols.1.iter()).enumerate().map(|(idx, (name, col_type))| {
+        let pk = Some(idx) == pk_idx;
+        TableColumn {
+            name: name.clone(),
+            data_type: col_type.clone(),
+            pk,
+            nullable: !pk,
+            index: pk,
+            unique: pk,
+            is_dropped: false,
+        }
+    }).collect();
+    let add_rowid = pk_idx.is_none();
+    if add_rowid {
+        if names.contains(&ROWID_COL.to_string()) {
+            return Err(RsqlError::ExecutionError(format!("Column {} is reserved for the generated primary key", ROWID_COL)));
+        }
+        columns.insert(0, TableColumn {
+            name: ROWID_COL.to_string(),
+            data_type: ColType::Integer,
+            pk: true,
+            nullable: false,
+            index: true,
+            unique: true,
+            is_dropped: false,
+        });
+    }
+    Ok((TableSchema::new(columns)?, add_rowid))
+}
+
+/// Result of an IF [NOT] EXISTS statement that had nothing to do, with a warning saying why
+fn skipped(message: String, warning: String) -> RsqlResult<ExecutionResult> {
+    Ok(Ddl(message).with_warnings(vec![warning]))
+}
+
 /// table and index relevant sql statements
 pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -> RsqlResult<ExecutionResult> {
     let PlanNode::DDL { op } = node else {
@@ -15,10 +128,15 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
     let username = ConnectionUserMap::global()
         .get_username(connection_id)
         .ok_or(RsqlError::ExecutionError("Failed to get username from connection ID".to_string()))?;
+    execute_ddl_op(op, tnx_id, connection_id, &username, false)
+}
+
+/// Run `op`, a dry run stops after the checks and reports what would be done
+fn execute_ddl_op(op: &DdlOperation, tnx_id: u64, connection_id: u64, username: &str, dry_run: bool) -> RsqlResult<ExecutionResult> {
     match op {
         DdlOperation::CreateTable { table_name, schema, if_not_exists} => {
             // verify permision
-            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, &username)?;
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
             if !has_permission {
                 return Err(RsqlError::ExecutionError(format!("User {} does not have permission to create table.", username)));
             }
@@ -27,11 +145,21 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             if table_id.is_some() {
                 if *if_not_exists {
                     info!("Table {} already exists, skipping create table.", table_name);
-                    return Ok(Ddl(format!("Table {} already exists, skipping create table.", table_name)));
+                    return skipped(format!("Table {} already exists, skipping create table.", table_name), format!("Table {} already exists", table_name));
                 } else {
                     return Err(RsqlError::ExecutionError(format!("Table {} already exists.", table_name)));
                 }
             }
+            // a CHECK must name existing columns and compare values of the same kind
+            let visible_cols = schema.get_columns().iter().filter(|col| !col.is_dropped);
+            let cols: (Vec<String>, Vec<ColType>) = visible_cols.map(|col| (col.name.clone(), col.data_type.clone())).unzip();
+            let null_row: Vec<DataItem> = cols.1.iter().map(null_of_type).collect();
+            check_row(schema.get_checks(), &cols, &null_row)?;
+            // a DEFAULT must be a constant of its column type
+            default_row(schema)?;
+            if dry_run {
+                return Ok(Ddl(format!("Dry run: table {} would be created.", table_name)));
+            }
             // register table in sys catalog
             let table_id = SysCatalog::global().register_table(tnx_id, table_name, &schema)?;
             // create table object
@@ -40,7 +168,7 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
         },
         DdlOperation::RenameTable {old_name, new_name, if_exists} => {
             // verify permision
-            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, &username)?;
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
             if !has_permission {
                 return Err(RsqlError::ExecutionError(format!("User {} does not have permission to rename table.", username)));
             }
@@ -48,7 +176,7 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             let table_id = SysCatalog::global().get_table_id(tnx_id, old_name)?;
             if table_id.is_none() {
                 if *if_exists {
-                    return Ok(Ddl(format!("Table {} does not exist, skipping rename table.", old_name)));
+                    return skipped(format!("Table {} does not exist, skipping rename table.", old_name), format!("Table {} did not exist", old_name));
                 } else {
                     return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", old_name)));
                 }
@@ -63,13 +191,16 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             if new_table_id.is_some() {
                 return Err(RsqlError::ExecutionError(format!("Table {} already exists.", new_name)));
             }
+            if dry_run {
+                return Ok(Ddl(format!("Dry run: table {} would be renamed to {}.", old_name, new_name)));
+            }
             // rename table in sys catalog
             SysCatalog::global().rename_table(tnx_id, table_id, new_name)?;
             Ok(Ddl(format!("Table {} renamed to {} successfully.", old_name, new_name)))
         },
         DdlOperation::RenameColumn { table_name, old_name, new_name } => {
             // verify permision
-            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, &username)?;
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
             if !has_permission {
                 return Err(RsqlError::ExecutionError(format!("User {} does not have permission to rename column.", username)));
             }
@@ -83,13 +214,26 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             if sys_catalog::is_sys_table(table_id) {
                 return Err(RsqlError::ExecutionError(format!("System table {} cannot be modified.", table_name)));
             }
+            if dry_run {
+                let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+                let col = table_schema.get_columns().iter().find(|col| !col.is_dropped && &col.name == old_name)
+                    .ok_or(RsqlError::InvalidInput(format!("Column not found: {}", old_name)))?;
+                if col.index {
+                    return Err(RsqlError::InvalidInput(format!("Cannot rename indexed column: {}", old_name)));
+                }
+                if new_name.len() > MAX_COL_NAME_SIZE {
+                    return Err(RsqlError::InvalidInput(format!("New column name too long: {}", new_name)));
+                }
+                return Ok(Ddl(format!("Dry run: column {} of table {} would be renamed to {}.", old_name, table_name, new_name)));
+            }
             // rename column in sys catalog
             SysCatalog::global().rename_column(tnx_id, table_id, old_name, new_name)?;
+            Table::evict_cached(table_id);
             Ok(Ddl(format!("Column {} renamed to {} in table {} successfully.", old_name, new_name, table_name)))
         },
         DdlOperation::DropTable { table_name, if_exists} => {
             // verify permision
-            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, &username)?;
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
             if !has_permission {
                 return Err(RsqlError::ExecutionError(format!("User {} does not have permission to drop table.", username)));
             }
@@ -97,7 +241,7 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             let table_id = SysCatalog::global().get_table_id(tnx_id, table_name)?;
             if table_id.is_none() {
                 if *if_exists {
-                    return Ok(Ddl(format!("Table {} does not exist, skipping drop table.", table_name)));
+                    return skipped(format!("Table {} does not exist, skipping drop table.", table_name), format!("Table {} did not exist", table_name));
                 } else {
                     return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name)));
                 }
@@ -107,10 +251,19 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             if sys_catalog::is_sys_table(table_id) {
                 return Err(RsqlError::ExecutionError(format!("System table {} cannot be dropped.", table_name)));
             }
+            if dry_run {
+                let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+                let row_count = Table::from_shared(table_id, table_schema, false)?.row_count();
+                return Ok(Ddl(format!("Dry run: table {} would be dropped with {} rows.", table_name, row_count)));
+            }
             // drop table itself first
             let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
             let table = Table::from(table_id, table_schema, false)?;
-            table.drop(tnx_id)?;
+            if crate::config::get().secure_drop {
+                table.drop_zeroed(tnx_id)?;
+            } else {
+                table.drop(tnx_id)?;
+            }
             // unregister table from sys catalog
             SysCatalog::global().unregister_table(tnx_id, table_id)?;
             Ok(Ddl(format!("Table {} dropped successfully.", table_name)))
@@ -123,7 +276,7 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             if_not_exists,
         } => {
             // verify permision
-            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, &username)?;
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
             if !has_permission {
                 return Err(RsqlError::ExecutionError(format!("User {} does not have permission to create index.", username)));
             }
@@ -131,7 +284,7 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             let index_id = SysCatalog::global().get_index_id(tnx_id, index_name)?;
             if index_id.is_some() {
                 if *if_not_exists {
-                    return Ok(Ddl(format!("Index {} already exists, skipping create index.", index_name)));
+                    return skipped(format!("Index {} already exists, skipping create index.", index_name), format!("Index {} already exists", index_name));
                 } else {
                     return Err(RsqlError::ExecutionError(format!("Index {} already exists.", index_name)));
                 }
@@ -148,6 +301,18 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             // get table object
             let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
             let mut table = Table::from(table_id, table_schema, false)?;
+            // a second B-tree on the same column would not be maintained
+            if table.has_index(column) {
+                if *if_not_exists {
+                    info!("Column {} of table {} is already indexed, skipping create index.", column, table_name);
+                    return skipped(
+                        format!("Column {} of table {} is already indexed, skipping create index.", column, table_name),
+                        format!("Column {} of table {} is already indexed", column, table_name),
+                    );
+                } else {
+                    return Err(RsqlError::ExecutionError(format!("Column {} of table {} is already indexed.", column, table_name)));
+                }
+            }
             // check if unique constraint is violated
             if *unique {
                 // find column index first
@@ -175,6 +340,12 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
                     value_set.insert(value.clone());
                 }
             }
+            if dry_run {
+                if !table.get_schema().get_columns().iter().any(|col| !col.is_dropped && &col.name == column) {
+                    return Err(RsqlError::InvalidInput(format!("Column {} does not exist in table {}.", column, table_name)));
+                }
+                return Ok(Ddl(format!("Dry run: index {} would be created on column {} of table {}.", index_name, column, table_name)));
+            }
             // create index on table
             table.create_index(column, tnx_id)?;
             // register index in sys catalog
@@ -185,11 +356,14 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
                 index_name,
                 *unique,
             )?;
+            // the table is cached on close, evict it once closed so the next open follows the new schema
+            drop(table);
+            Table::evict_cached(table_id);
             Ok(Ddl(format!("Index {} created successfully on table {}.", index_name, table_name)))
         },
         DdlOperation::DropColumn { table_name, column_name, if_exists } => {
             // verify permision
-            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, &username)?;
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
             if !has_permission {
                 return Err(RsqlError::ExecutionError(format!("User {} does not have permission to drop column.", username)));
             }
@@ -203,17 +377,609 @@ pub fn execute_ddl_plan_node(node: &PlanNode, tnx_id: u64, connection_id: u64) -
             if sys_catalog::is_sys_table(table_id) {
                 return Err(RsqlError::ExecutionError(format!("System table {} cannot be modified.", table_name)));
             }
+            if dry_run {
+                let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+                if !table_schema.get_columns().iter().any(|col| !col.is_dropped && &col.name == column_name) {
+                    if *if_exists {
+                        return skipped(format!("Column {} does not exist, skipping drop column.", column_name), format!("Column {} did not exist", column_name));
+                    }
+                    return Err(RsqlError::ExecutionError(format!("Column {} not found in table", column_name)));
+                }
+                return Ok(Ddl(format!("Dry run: column {} would be dropped from table {}.", column_name, table_name)));
+            }
             // drop column in sys catalog
             match SysCatalog::global().drop_column(tnx_id, table_id, column_name) {
-                Ok(_) => Ok(Ddl(format!("Column {} dropped from table {} successfully.", column_name, table_name))),
+                Ok(_) => {
+                    Table::evict_cached(table_id);
+                    Ok(Ddl(format!("Column {} dropped from table {} successfully.", column_name, table_name)))
+                },
                 Err(e) => {
                     if *if_exists {
-                         Ok(Ddl(format!("Column {} does not exist, skipping drop column.", column_name)))
+                         skipped(format!("Column {} does not exist, skipping drop column.", column_name), format!("Column {} did not exist", column_name))
                     } else {
                          Err(e)
                     }
                 }
             }
         },
+        DdlOperation::AlterColumnType { table_name, column_name, data_type } => {
+            // verify permision
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} does not have permission to alter table.", username)));
+            }
+            // check if table exists
+            let table_id = SysCatalog::global().get_table_id(tnx_id, table_name)?;
+            if table_id.is_none() {
+                return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name)));
+            }
+            let table_id = table_id.unwrap();
+            // check if table is system table
+            if sys_catalog::is_sys_table(table_id) {
+                return Err(RsqlError::ExecutionError(format!("System table {} cannot be modified.", table_name)));
+            }
+            // block readers and writers of the table until the rewrite commits
+            TnxManager::global().acquire_write_locks(tnx_id, &[table_id])?;
+            let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+            // build the new schema, the entry size may change so the table is rewritten
+            let mut new_columns = table_schema.get_columns().clone();
+            let col = new_columns.iter_mut().find(|col| !col.is_dropped && &col.name == column_name)
+                .ok_or(RsqlError::InvalidInput(format!("Column {} does not exist in table {}.", column_name, table_name)))?;
+            col.data_type = data_type.clone();
+            // only string columns keep a collation
+            let mut collations = table_schema.get_collations().clone();
+            if !matches!(data_type, ColType::Chars(_) | ColType::VarChar(_)) {
+                collations.remove(column_name);
+            }
+            let new_schema = TableSchema::new(new_columns)?
+                .with_primary_key(table_schema.get_primary_key().to_vec())?
+                .with_collations(collations);
+            let col_idx = table_schema.get_columns().iter().filter(|col| !col.is_dropped)
+                .position(|col| &col.name == column_name)
+                .unwrap();
+            // convert every value first, any failure aborts before the table is touched
+            let table = Table::from(table_id, table_schema, false)?;
+            let mut rows = Vec::new();
+            for row in table.get_all_rows()? {
+                let mut row = row?;
+                row[col_idx] = convert_column_item(&row[col_idx], data_type).ok_or(RsqlError::ExecutionError(format!(
+                    "Cannot convert value {:?} of column {} to {:?}.", row[col_idx], column_name, data_type
+                )))?;
+                rows.push(row);
+            }
+            if dry_run {
+                return Ok(Ddl(format!(
+                    "Dry run: {} rows of column {} in table {} would be converted to {:?}.", rows.len(), column_name, table_name, data_type
+                )));
+            }
+            // rewrite the table under the new schema, indexes are rebuilt by the inserts
+            table.drop(tnx_id)?;
+            SysCatalog::global().set_column_type(tnx_id, table_id, column_name, data_type)?;
+            let mut table = Table::create(table_id, new_schema, tnx_id, false)?;
+            let row_count = rows.len();
+            for row in rows {
+                table.insert_row(row, tnx_id)?;
+            }
+            info!("Converted {} rows of column {} in table {} to {:?}.", row_count, column_name, table_name, data_type);
+            Ok(Ddl(format!("Column {} of table {} altered successfully.", column_name, table_name)))
+        },
+        DdlOperation::AddKey { table_name, column_name, primary } => {
+            // verify permision
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} does not have permission to alter table.", username)));
+            }
+            let table_id = SysCatalog::global().get_table_id(tnx_id, table_name)?
+                .ok_or(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name)))?;
+            if sys_catalog::is_sys_table(table_id) {
+                return Err(RsqlError::ExecutionError(format!("System table {} cannot be modified.", table_name)));
+            }
+            // block readers and writers of the table until the rewrite commits
+            TnxManager::global().acquire_write_locks(tnx_id, &[table_id])?;
+            let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+            if *primary && table_schema.get_columns().iter().any(|col| col.pk && !col.is_dropped) {
+                return Err(RsqlError::ExecutionError(format!("Table {} already has a primary key.", table_name)));
+            }
+            let visible_cols: Vec<&TableColumn> = table_schema.get_columns().iter().filter(|col| !col.is_dropped).collect();
+            let col_idx = visible_cols.iter().position(|col| &col.name == column_name)
+                .ok_or(RsqlError::InvalidInput(format!("Column {} does not exist in table {}.", column_name, table_name)))?;
+            let was_indexed = visible_cols[col_idx].index;
+            if visible_cols[col_idx].unique && !*primary {
+                return Err(RsqlError::ExecutionError(format!("Column {} of table {} is already unique.", column_name, table_name)));
+            }
+            // build the new schema first, it rejects keys the storage cannot index
+            let mut new_columns = table_schema.get_columns().clone();
+            let col = new_columns.iter_mut().find(|col| !col.is_dropped && &col.name == column_name).unwrap();
+            col.index = true;
+            col.unique = true;
+            if *primary {
+                col.pk = true;
+                col.nullable = false;
+            }
+            let mut new_schema = TableSchema::new(new_columns)?
+                .with_checks(table_schema.get_checks().clone())
+                .with_collations(table_schema.get_collations().clone());
+            if !*primary {
+                new_schema = new_schema.with_primary_key(table_schema.get_primary_key().to_vec())?;
+            }
+            // without a primary key the rows are read through another index, or from the data pages
+            let table = Table::from(table_id, table_schema, false)?;
+            let rows = match table.get_schema().get_indexed_col().first() {
+                Some(indexed_col) => table.get_rows_by_range_indexed_col(indexed_col, &None, &None)?.collect::<RsqlResult<Vec<_>>>()?,
+                None => table.get_rows_by_pages()?,
+            };
+            // the existing values must already satisfy the key
+            let mut values = HashSet::new();
+            for row in rows.iter() {
+                let value = &row[col_idx];
+                if *primary && is_null_item(value) {
+                    return Err(RsqlError::ExecutionError(format!("Column {} of table {} has null values.", column_name, table_name)));
+                }
+                if !values.insert(value.clone()) {
+                    return Err(RsqlError::ExecutionError(format!(
+                        "Column {} of table {} has duplicate values, cannot add the key.", column_name, table_name
+                    )));
+                }
+            }
+            if dry_run {
+                let key = if *primary { "Primary key" } else { "Unique constraint" };
+                return Ok(Ddl(format!(
+                    "Dry run: {} on column {} would be added to table {} with {} rows.", key, column_name, table_name, rows.len()
+                )));
+            }
+            // rewrite the table under the new schema, the header gets the new index
+            table.drop(tnx_id)?;
+            if !was_indexed {
+                let index_name = format!("IDX_{}_{}", table_name, column_name);
+                SysCatalog::global().register_index(tnx_id, table_id, column_name, &index_name, true)?;
+            }
+            SysCatalog::global().set_column_key(tnx_id, table_id, column_name, *primary)?;
+            let mut table = Table::create(table_id, new_schema, tnx_id, false)?;
+            for row in rows {
+                table.insert_row(row, tnx_id)?;
+            }
+            let key = if *primary { "Primary key" } else { "Unique constraint" };
+            Ok(Ddl(format!("{} on column {} added to table {}.", key, column_name, table_name)))
+        },
+        DdlOperation::CreateTableAs { table_name, input, if_not_exists } => {
+            // verify permision
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} does not have permission to create table.", username)));
+            }
+            // check if table exists
+            if SysCatalog::global().get_table_id(tnx_id, table_name)?.is_some() {
+                if *if_not_exists {
+                    info!("Table {} already exists, skipping create table.", table_name);
+                    return skipped(format!("Table {} already exists, skipping create table.", table_name), format!("Table {} already exists", table_name));
+                } else {
+                    return Err(RsqlError::ExecutionError(format!("Table {} already exists.", table_name)));
+                }
+            }
+            // run the select, its output decides the schema
+            let (cols, rows) = match execute_dml_plan_node(input, tnx_id, true, connection_id)? {
+                MiddleResult::Query { cols, rows } | MiddleResult::TempTable { cols, rows, .. } => (cols, rows),
+                _ => return Err(RsqlError::ExecutionError("CREATE TABLE ... AS SELECT input must be a query".to_string())),
+            };
+            let (schema, add_rowid) = infer_ctas_schema(&cols, &rows)?;
+            if dry_run {
+                return Ok(Ddl(format!("Dry run: table {} would be created with {} rows.", table_name, rows.len())));
+            }
+            // register and fill the table in the current transaction
+            let table_id = SysCatalog::global().register_table(tnx_id, table_name, &schema)?;
+            let mut table = Table::create(table_id, schema, tnx_id, false)?;
+            let row_count = rows.len();
+            for (idx, row) in rows.into_iter().enumerate() {
+                let row = if add_rowid {
+                    let mut with_rowid = Vec::with_capacity(row.len() + 1);
+                    with_rowid.push(DataItem::Integer(idx as i64 + 1));
+                    with_rowid.extend(row);
+                    with_rowid
+                } else {
+                    row
+                };
+                table.insert_row(row, tnx_id)?;
+            }
+            Ok(Ddl(format!("Table {} created successfully with {} rows.", table_name, row_count)))
+        },
+        DdlOperation::Reindex { table_name, column } => {
+            // verify permision
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} does not have permission to reindex table.", username)));
+            }
+            // check if table exists
+            let table_id = SysCatalog::global().get_table_id(tnx_id, table_name)?;
+            if table_id.is_none() {
+                return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name)));
+            }
+            let table_id = table_id.unwrap();
+            // check if table is system table
+            if sys_catalog::is_sys_table(table_id) {
+                return Err(RsqlError::ExecutionError(format!("System table {} cannot be reindexed.", table_name)));
+            }
+            // block readers and writers of the table until the rebuild commits
+            TnxManager::global().acquire_write_locks(tnx_id, &[table_id])?;
+            // rebuild indexes from live rows
+            let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+            let mut table = Table::from(table_id, table_schema, false)?;
+            match column {
+                Some(column) => {
+                    table.rebuild_index(column, tnx_id)?;
+                    info!("Rebuilt index on column {} of table {}.", column, table_name);
+                    Ok(Ddl(format!("Index on column {} of table {} rebuilt successfully.", column, table_name)))
+                },
+                None => {
+                    table.rebuild_indexes(tnx_id)?;
+                    info!("Rebuilt all indexes of table {}.", table_name);
+                    Ok(Ddl(format!("Indexes of table {} rebuilt successfully.", table_name)))
+                },
+            }
+        },
+        DdlOperation::DebugIndex { table_name, column } => {
+            let Some(table_id) = SysCatalog::global().get_table_id(tnx_id, table_name)? else {
+                return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name)));
+            };
+            let has_permission = SysCatalog::global().check_user_privilege(tnx_id, username, Some(table_name), "R")?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} has no permission to read table {}.", username, table_name)));
+            }
+            TnxManager::global().acquire_read_locks(tnx_id, &[table_id])?;
+            let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+            let table = Table::from_shared(table_id, table_schema, sys_catalog::is_sys_table(table_id))?;
+            let dump = table.debug_index(column)?;
+            // one row per line of the dump
+            let width = dump.lines().map(|line| line.len()).max().unwrap_or(0);
+            let rows = dump.lines().map(|line| vec![DataItem::VarChar {
+                head: VarCharHead { max_len: width as u64, len: line.len() as u64, page_ptr: None },
+                value: line.to_string(),
+            }]).collect();
+            Ok(ExecutionResult::Query {
+                cols: (vec!["btree".to_string()], vec![ColType::VarChar(width)]),
+                rows,
+            })
+        },
+        DdlOperation::CommentOnColumn { table_name, column_name, comment } => {
+            // verify permision
+            let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} does not have permission to comment on columns.", username)));
+            }
+            let Some(table_id) = SysCatalog::global().get_table_id(tnx_id, table_name)? else {
+                return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name)));
+            };
+            if sys_catalog::is_sys_table(table_id) {
+                return Err(RsqlError::ExecutionError(format!("System table {} cannot be modified.", table_name)));
+            }
+            SysCatalog::global().set_column_comment(tnx_id, table_id, column_name, comment.as_deref())?;
+            Ok(Ddl(format!("Comment on column {}.{} updated successfully.", table_name, column_name)))
+        },
+        DdlOperation::Describe { table_name } => {
+            let Some(table_id) = SysCatalog::global().get_table_id(tnx_id, table_name)? else {
+                return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name)));
+            };
+            let has_permission = SysCatalog::global().check_user_privilege(tnx_id, username, Some(table_name), "R")?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} has no permission to read table {}.", username, table_name)));
+            }
+            let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+            let comments = SysCatalog::global().get_column_comments(tnx_id, table_id)?;
+            let varchar = |value: &str| DataItem::VarChar {
+                head: VarCharHead { max_len: MAX_COMMENT_SIZE as u64, len: value.len() as u64, page_ptr: None },
+                value: value.to_string(),
+            };
+            let rows = table_schema.get_columns().iter().filter(|col| !col.is_dropped).map(|col| vec![
+                varchar(&col.name),
+                varchar(&col_type_name(&col.data_type)),
+                DataItem::Bool(col.nullable),
+                DataItem::Bool(col.pk),
+                comments.get(&col.name).map_or(DataItem::NullVarChar, |comment| varchar(comment)),
+            ]).collect();
+            Ok(ExecutionResult::Query {
+                cols: (
+                    ["column_name", "data_type", "nullable", "primary_key", "comment"].map(String::from).to_vec(),
+                    vec![
+                        ColType::VarChar(MAX_COL_NAME_SIZE),
+                        ColType::VarChar(MAX_COL_NAME_SIZE),
+                        ColType::Bool,
+                        ColType::Bool,
+                        ColType::VarChar(MAX_COMMENT_SIZE),
+                    ],
+                ),
+                rows,
+            })
+        },
+        DdlOperation::IndexStats { table_name } => {
+            let Some(table_id) = SysCatalog::global().get_table_id(tnx_id, table_name)? else {
+                return Err(RsqlError::ExecutionError(format!("Table {} does not exist.", table_name)));
+            };
+            let has_permission = SysCatalog::global().check_user_privilege(tnx_id, username, Some(table_name), "R")?;
+            if !has_permission {
+                return Err(RsqlError::ExecutionError(format!("User {} has no permission to read table {}.", username, table_name)));
+            }
+            let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+            let rows = table_schema.get_indexed_col().into_iter().map(|col| {
+                let uses = Table::index_use_count(table_id, &col);
+                vec![
+                    DataItem::VarChar {
+                        head: VarCharHead { max_len: MAX_COL_NAME_SIZE as u64, len: col.len() as u64, page_ptr: None },
+                        value: col,
+                    },
+                    DataItem::Integer(uses as i64),
+                ]
+            }).collect();
+            Ok(ExecutionResult::Query {
+                cols: (
+                    vec!["column_name".to_string(), "uses".to_string()],
+                    vec![ColType::VarChar(MAX_COL_NAME_SIZE), ColType::Integer],
+                ),
+                rows,
+            })
+        },
+        DdlOperation::DryRun { op } => match **op {
+            DdlOperation::DryRun { .. }
+            | DdlOperation::IndexStats { .. }
+            | DdlOperation::Reindex { .. }
+            | DdlOperation::DebugIndex { .. }
+            | DdlOperation::CommentOnColumn { .. }
+            | DdlOperation::Describe { .. } => Err(RsqlError::ExecutionError("Only schema changes can be dry run.".to_string())),
+            _ => execute_ddl_op(op, tnx_id, connection_id, username, true),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::executor::execute;
+    use crate::execution::test_utils::{query_rows, TestConn};
+
+    #[test]
+    #[serial_test::serial]
+    fn test_create_table_as_select() {
+        let test_conn = TestConn::setup(&["ctas_src", "ctas_adults", "ctas_names"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE ctas_src (id INTEGER PRIMARY KEY, name CHAR(16), age INTEGER)", conn).unwrap();
+        for (id, name, age) in [(1, "alice", 30), (2, "bob", 17), (3, "carol", 42), (4, "bob", 65)] {
+            execute(&format!("INSERT INTO ctas_src VALUES ({}, '{}', {})", id, name, age), conn).unwrap();
+        }
+
+        // the id column keeps being the primary key
+        execute("CREATE TABLE ctas_adults AS SELECT id, name FROM ctas_src WHERE age >= 18", conn).unwrap();
+        let schema = {
+            let tnx_id = TnxManager::global().begin_transaction(conn);
+            let table_id = SysCatalog::global().get_table_id(tnx_id, "ctas_adults").unwrap().unwrap();
+            let schema = SysCatalog::global().get_table_schema(tnx_id, table_id).unwrap();
+            TnxManager::global().end_transaction(conn);
+            schema
+        };
+        let columns = schema.get_columns();
+        assert_eq!(columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["id", "name"]);
+        assert!(columns[0].pk && matches!(columns[0].data_type, ColType::Integer));
+        assert!(matches!(columns[1].data_type, ColType::Chars(16)));
+        let res = execute("SELECT * FROM ctas_adults", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        let ids: Vec<_> = rows.iter().map(|r| r[0].clone()).collect();
+        assert_eq!(ids, vec![DataItem::Integer(1), DataItem::Integer(3), DataItem::Integer(4)]);
+
+        // no distinct column, a rowid primary key is generated
+        execute("CREATE TABLE ctas_names AS SELECT name FROM ctas_src WHERE age > 10", conn).unwrap();
+        let res = execute("SELECT * FROM ctas_names", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, vec!["rowid".to_string(), "name".to_string()]);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[3], vec![DataItem::Integer(4), DataItem::Chars { len: 16, value: "bob".to_string() }]);
+
+        // existing tables are not overwritten
+        assert!(execute("CREATE TABLE ctas_names AS SELECT name FROM ctas_src", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_create_duplicate_index() {
+        let test_conn = TestConn::setup(&["dup_index"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE dup_index (id INTEGER PRIMARY KEY, score INTEGER)", conn).unwrap();
+        execute("INSERT INTO dup_index VALUES (1, 10)", conn).unwrap();
+
+        // the primary key is already indexed
+        assert!(execute("CREATE INDEX dup_index_id ON dup_index (id)", conn).is_err());
+        execute("CREATE INDEX IF NOT EXISTS dup_index_id ON dup_index (id)", conn).unwrap();
+
+        execute("CREATE INDEX dup_index_score ON dup_index (score)", conn).unwrap();
+        assert!(execute("CREATE INDEX dup_index_score2 ON dup_index (score)", conn).is_err());
+        execute("CREATE INDEX IF NOT EXISTS dup_index_score2 ON dup_index (score)", conn).unwrap();
+        let tnx_id = TnxManager::global().begin_transaction(conn);
+        assert!(SysCatalog::global().get_index_id(tnx_id, "dup_index_id").unwrap().is_none());
+        assert!(SysCatalog::global().get_index_id(tnx_id, "dup_index_score2").unwrap().is_none());
+        TnxManager::global().end_transaction(conn);
+
+        // the single index keeps being maintained
+        execute("INSERT INTO dup_index VALUES (2, 20)", conn).unwrap();
+        let res = execute("SELECT id FROM dup_index WHERE score = 20", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(2)]]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_column_comment_in_describe() {
+        let test_conn = TestConn::setup(&["commented"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE commented (id INTEGER PRIMARY KEY, price FLOAT)", conn).unwrap();
+        execute("COMMENT ON COLUMN commented.price IS 'unit price, it''s in cents'", conn).unwrap();
+        let comments = || {
+            let res = execute("DESCRIBE commented", conn).unwrap();
+            let (cols, rows) = query_rows(&res[0]);
+            assert_eq!(cols, ["column_name", "data_type", "nullable", "primary_key", "comment"]);
+            rows.into_iter().map(|row| match &row[4] {
+                DataItem::VarChar { value, .. } => Some(value.clone()),
+                _ => None,
+            }).collect::<Vec<_>>()
+        };
+        assert_eq!(comments(), [None, Some("unit price, it's in cents".to_string())]);
+
+        execute("COMMENT ON COLUMN commented.price IS NULL", conn).unwrap();
+        assert_eq!(comments(), [None, None]);
+        assert!(execute("COMMENT ON COLUMN commented.missing IS 'x'", conn).is_err());
+        assert!(execute("COMMENT ON COLUMN commented.price 'x'", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_add_key_to_populated_table() {
+        let test_conn = TestConn::setup(&["keyless"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE keyless (code INTEGER, grp INTEGER, name CHAR(8))", conn).unwrap();
+        for (code, grp) in [(30, 1), (10, 2), (20, 1)] {
+            execute(&format!("INSERT INTO keyless VALUES ({}, {}, 'n{}')", code, grp, code), conn).unwrap();
+        }
+
+        // grp repeats, so it cannot become the key
+        let err = execute("ALTER TABLE keyless ADD PRIMARY KEY (grp)", conn).unwrap_err();
+        assert!(err.to_string().contains("duplicate values"), "{err}");
+        execute("ALTER TABLE keyless ADD PRIMARY KEY (code)", conn).unwrap();
+        let res = execute("SELECT code, name FROM keyless", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        assert_eq!(rows.iter().map(|row| row[0].clone()).collect::<Vec<_>>(),
+            [10, 20, 30].map(DataItem::Integer));
+        assert!(execute("INSERT INTO keyless VALUES (NULL, 3, 'none')", conn).is_err());
+        assert!(execute("ALTER TABLE keyless ADD PRIMARY KEY (grp)", conn).unwrap_err().to_string().contains("already has a primary key"));
+
+        // a unique constraint is enforced from then on
+        let err = execute("ALTER TABLE keyless ADD UNIQUE (grp)", conn).unwrap_err();
+        assert!(err.to_string().contains("duplicate values"), "{err}");
+        execute("ALTER TABLE keyless ADD UNIQUE (name)", conn).unwrap();
+        assert!(execute("INSERT INTO keyless VALUES (40, 4, 'n10')", conn).is_err());
+        execute("INSERT INTO keyless VALUES (40, 4, 'n40')", conn).unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_explain_ddl_dry_run() {
+        let test_conn = TestConn::setup(&["dry_run"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE dry_run (code INTEGER UNIQUE, grp INTEGER)", conn).unwrap();
+        for (code, grp) in [(10, 1), (20, 2), (30, 1)] {
+            execute(&format!("INSERT INTO dry_run VALUES ({}, {})", code, grp), conn).unwrap();
+        }
+        let describe = || query_rows(&execute("DESCRIBE dry_run", conn).unwrap()[0]).1;
+        let before = describe();
+
+        // the duplicate groups are reported, the table is left without a key
+        let err = execute("EXPLAIN ALTER TABLE dry_run ADD PRIMARY KEY (grp)", conn).unwrap_err();
+        assert!(err.to_string().contains("duplicate values"), "{err}");
+        assert_eq!(describe(), before);
+
+        // a valid change is described but not applied
+        let res = execute("EXPLAIN ALTER TABLE dry_run ADD PRIMARY KEY (code)", conn).unwrap();
+        assert!(matches!(&res[0], ExecutionResult::Ddl(msg) if msg.starts_with("Dry run: Primary key on column code")), "{:?}", res[0]);
+        let res = execute("EXPLAIN DROP TABLE dry_run", conn).unwrap();
+        assert!(matches!(&res[0], ExecutionResult::Ddl(msg) if msg.contains("would be dropped with 3 rows")), "{:?}", res[0]);
+        execute("EXPLAIN ALTER TABLE dry_run RENAME COLUMN grp TO kind", conn).unwrap();
+        assert!(execute("EXPLAIN ALTER TABLE dry_run RENAME COLUMN missing TO kind", conn).is_err());
+        assert_eq!(describe(), before);
+        let res = execute("SELECT grp FROM dry_run WHERE code = 30", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(1)]]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_rename_table() {
+        let test_conn = TestConn::setup(&["rename_old", "rename_new"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE rename_old (id INTEGER PRIMARY KEY)", conn).unwrap();
+        execute("INSERT INTO rename_old VALUES (1)", conn).unwrap();
+
+        // a rolled back rename leaves the old name in place
+        execute("BEGIN", conn).unwrap();
+        execute("ALTER TABLE rename_old RENAME TO rename_new", conn).unwrap();
+        execute("ROLLBACK", conn).unwrap();
+        assert!(execute("SELECT id FROM rename_new", conn).is_err());
+        assert_eq!(query_rows(&execute("SELECT id FROM rename_old", conn).unwrap()[0]).1, vec![vec![DataItem::Integer(1)]]);
+
+        execute("ALTER TABLE rename_old RENAME TO rename_new", conn).unwrap();
+        assert_eq!(query_rows(&execute("SELECT id FROM rename_new", conn).unwrap()[0]).1, vec![vec![DataItem::Integer(1)]]);
+        assert!(execute("SELECT id FROM rename_old", conn).is_err());
+        execute("DROP TABLE rename_new", conn).unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_show_index_stats() {
+        let test_conn = TestConn::setup(&["index_stats"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE index_stats (id INTEGER PRIMARY KEY, code INTEGER UNIQUE, note CHAR(8))", conn).unwrap();
+        for id in 0..20 {
+            execute(&format!("INSERT INTO index_stats VALUES ({}, {}, 'n{}')", id, id * 10, id), conn).unwrap();
+        }
+        let uses = || {
+            let res = execute("SHOW INDEX STATS FOR index_stats", conn).unwrap();
+            let (cols, rows) = query_rows(&res[0]);
+            assert_eq!(cols, ["column_name", "uses"]);
+            rows.iter().map(|row| (row[0].display(), row[1].clone())).collect::<Vec<_>>()
+        };
+        let before = uses();
+        assert_eq!(before.iter().map(|(col, _)| col.as_str()).collect::<Vec<_>>(), ["id", "code"]);
+
+        execute("SELECT * FROM index_stats WHERE id = 3", conn).unwrap();
+        execute("SELECT * FROM index_stats WHERE id = 4", conn).unwrap();
+        execute("SELECT * FROM index_stats WHERE code = 50", conn).unwrap();
+        // a scan on a column without an index reads no index
+        execute("SELECT * FROM index_stats WHERE note = 'n1'", conn).unwrap();
+        let count = |stats: &[(String, DataItem)], col: &str| match stats.iter().find(|(c, _)| c == col) {
+            Some((_, DataItem::Integer(n))) => *n,
+            other => panic!("no count for {col}: {:?}", other),
+        };
+        let after = uses();
+        assert_eq!(count(&after, "id") - count(&before, "id"), 2);
+        assert_eq!(count(&after, "code") - count(&before, "code"), 1);
+
+        assert!(execute("SHOW INDEX STATS index_stats", conn).is_err());
+        assert!(execute("SHOW INDEX STATS FOR index_stats_missing", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_if_exists_warning() {
+        let test_conn = TestConn::setup(&["nonexistent"]);
+        let conn = test_conn.id;
+        let res = execute("DROP TABLE IF EXISTS nonexistent", conn).unwrap();
+        assert!(matches!(res[0].result(), ExecutionResult::Ddl(_)), "{:?}", res[0]);
+        assert_eq!(res[0].warnings(), ["Table nonexistent did not exist".to_string()]);
+
+        execute("CREATE TABLE nonexistent (id INTEGER PRIMARY KEY)", conn).unwrap();
+        let res = execute("CREATE TABLE IF NOT EXISTS nonexistent (id INTEGER PRIMARY KEY)", conn).unwrap();
+        assert_eq!(res[0].warnings(), ["Table nonexistent already exists".to_string()]);
+        let res = execute("DROP TABLE IF EXISTS nonexistent", conn).unwrap();
+        assert!(res[0].warnings().is_empty());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_alter_column_type() {
+        let test_conn = TestConn::setup(&["alter_type"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE alter_type (id INTEGER PRIMARY KEY, code INTEGER, note CHAR(8))", conn).unwrap();
+        execute("INSERT INTO alter_type VALUES (1, 12, 'abc')", conn).unwrap();
+        execute("INSERT INTO alter_type VALUES (2, 345, '7')", conn).unwrap();
+        let text = |item: &DataItem| match item {
+            DataItem::VarChar { value, .. } | DataItem::Chars { value, .. } => Some(value.clone()),
+            _ => None,
+        };
+
+        // widening INT to VARCHAR keeps every value
+        execute("ALTER TABLE alter_type ALTER COLUMN code TYPE VARCHAR(16)", conn).unwrap();
+        let res = execute("SELECT code FROM alter_type", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        assert_eq!(text(&rows[0][0]), Some("12".to_string()));
+        assert_eq!(text(&rows[1][0]), Some("345".to_string()));
+        execute("INSERT INTO alter_type VALUES (3, 'x-1', 'z')", conn).unwrap();
+
+        // a value that cannot be converted aborts the whole statement
+        assert!(execute("ALTER TABLE alter_type ALTER COLUMN note TYPE INTEGER", conn).is_err());
+        let res = execute("SELECT note FROM alter_type WHERE id = 1", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        assert_eq!(text(&rows[0][0]), Some("abc".to_string()));
+        assert_eq!(execute("SELECT * FROM alter_type", conn).map(|res| query_rows(&res[0]).1.len()).unwrap(), 3);
     }
-}
\ No newline at end of file
+}
@@ -1,9 +1,9 @@
 use crate::catalog::sys_catalog::is_sys_table;
 use crate::catalog::{SysCatalog, sys_catalog};
 use crate::common::{RsqlResult, RsqlError};
-use crate::sql::plan::{PlanNode, JoinType};
+use crate::sql::plan::{PlanNode, JoinType, InsertConflict, ConflictAction};
 use crate::common::data_item::{DataItem};
-use crate::catalog::table_schema::{ColType};
+use crate::catalog::table_schema::{ColType, Collation};
 use crate::storage::table::{Table};
 use crate::transaction::TnxManager;
 use super::result::{MiddleResult::{self, Query, Mutation, TableObj, TableWithFilter, TempTable, AggrTable}, TableObject};
@@ -11,13 +11,59 @@ use super::expr_interpreter::{handle_on_expr,
     handle_table_obj_filter_expr, 
     handle_temp_table_filter_expr, 
     handle_insert_expr, 
+    handle_values,
+    handle_insert_rows,
     handle_update_expr,
-    handle_aggr_expr
+    handle_aggr_expr,
+    handle_subquery_apply,
+    handle_projection_exprs,
+    unalias,
+    index_range_bounds,
+    check_row,
+    default_row,
+    sort_rows,
+    unqualify_columns
 };
 use crate::server::conncetion_user_map::ConnectionUserMap;
 use tracing::info;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use sqlparser::ast::{Expr};
+use sqlparser::ast::{Expr, Ident};
+
+type CteRows = ((Vec<String>, Vec<ColType>), Vec<Vec<DataItem>>);
+
+thread_local! {
+    /// Rows of the WITH queries of the statement running on this thread, by name
+    static CTE_ROWS: RefCell<HashMap<String, CteRows>> = RefCell::new(HashMap::new());
+}
+
+/// Makes the rows of WITH queries readable by `CteScan` on this thread until dropped,
+/// queries of the same name bound by an enclosing WITH are restored then
+#[derive(Default)]
+struct CteScope {
+    shadowed: Vec<(String, Option<CteRows>)>,
+}
+
+impl CteScope {
+    fn bind(&mut self, name: &str, rows: CteRows) {
+        let prev = CTE_ROWS.with(|ctes| ctes.borrow_mut().insert(name.to_string(), rows));
+        self.shadowed.push((name.to_string(), prev));
+    }
+}
+
+impl Drop for CteScope {
+    fn drop(&mut self) {
+        CTE_ROWS.with(|ctes| {
+            let mut ctes = ctes.borrow_mut();
+            for (name, prev) in self.shadowed.drain(..).rev() {
+                match prev {
+                    Some(rows) => ctes.insert(name, rows),
+                    None => ctes.remove(&name),
+                };
+            }
+        });
+    }
+}
 
 fn get_table_object (table_name: &str, read_only: bool, tnx_id: u64) -> RsqlResult<TableObject> {
     let Some(table_id) = SysCatalog::global().get_table_id(tnx_id, table_name)? else {
@@ -31,25 +77,26 @@ fn get_table_object (table_name: &str, read_only: bool, tnx_id: u64) -> RsqlResu
     };
     // 1. get Table
     let table_schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
-    let table_obj = Table::from(table_id, table_schema.clone(), is_sys_table(table_id))?;
+    let table_obj = if read_only {
+        Table::from_shared(table_id, table_schema.clone(), is_sys_table(table_id))?
+    } else {
+        Table::from(table_id, table_schema.clone(), is_sys_table(table_id))?
+    };
     // 2. construct TableObject
     let mut map = HashMap::new();
     let mut cols_name = vec![];
     let mut cols_type = vec![];
-    let mut pk_col_name = String::new();
-    let mut pk_col_type = ColType::Integer;
     let mut visible_idx = 0;
     for col in table_schema.get_columns().iter() {
         if col.is_dropped { continue; }
         map.insert(col.name.clone(), visible_idx);
         cols_name.push(col.name.clone());
         cols_type.push(col.data_type.clone());
-        if col.pk {
-            pk_col_name = col.name.clone();
-            pk_col_type = col.data_type.clone();
-        }
         visible_idx += 1;
     }
+    // a multi-column primary key is named by its index, which is never a column
+    let pk_col_name = table_schema.pk_index_name().unwrap_or_default().to_string();
+    let pk_col_type = table_schema.pk_key_type().unwrap_or(ColType::Integer);
     let indexed_cols = table_obj.get_schema().get_indexed_col();
     let table_object = TableObject {
         table_obj,
@@ -64,7 +111,7 @@ fn get_table_object (table_name: &str, read_only: bool, tnx_id: u64) -> RsqlResu
 pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn_id: u64) -> RsqlResult<MiddleResult> {
     let username = ConnectionUserMap::global().get_username(conn_id).ok_or(RsqlError::ExecutionError(format!("cannot find user with connection id {conn_id}")))?;
     match node {
-        PlanNode::TableScan { table } => {
+        PlanNode::TableScan { table, .. } => {
             info!("Implement TableScan execution");
             let has_permission = SysCatalog::global().check_user_privilege(tnx_id, &username, Some(table), "R")?;
             if !has_permission {
@@ -77,11 +124,19 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
             info!("Implement Filter execution");
             let input_result = execute_dml_plan_node(input, tnx_id, read_only, conn_id)?;
             if let TableObj(table_obj) = input_result {
-                let filter_result = handle_table_obj_filter_expr(&table_obj, predicate)?;
+                let predicate = match &**input {
+                    PlanNode::TableScan { table, alias } => unqualify_columns(predicate, alias.as_ref().unwrap_or(table)),
+                    _ => predicate.clone(),
+                };
+                let filter_result = handle_table_obj_filter_expr(&table_obj, &predicate)?;
                 Ok(TableWithFilter { table_obj, rows: filter_result }) // get temp query result after filter
             }else {
                 if let TempTable{cols, rows, table_name} = input_result {
-                    let filter_result = handle_temp_table_filter_expr(&cols.0, &cols.1, &rows, predicate)?;
+                    let predicate = match &table_name {
+                        Some(table_name) => unqualify_columns(predicate, table_name),
+                        None => predicate.clone(),
+                    };
+                    let filter_result = handle_temp_table_filter_expr(&cols.0, &cols.1, &rows, &predicate)?;
                     Ok(TempTable{
                         cols,
                         rows: filter_result,
@@ -92,23 +147,28 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
                 }
             }
         },
-        PlanNode::Sort { columns, asc, input } => {
-            if columns.len() > 1 {
-                return Err(RsqlError::ExecutionError(format!("Sort by multiple columns is not supported")));
+        PlanNode::Sort { exprs, asc, input } => {
+            // a select without WHERE reads the table in primary key order, sorting by the key alone is not needed
+            if let ([Expr::Identifier(key)], [is_asc]) = (exprs.as_slice(), asc.as_slice())
+                && let PlanNode::Projection { exprs: proj_exprs, input: scan } = &**input
+                && let PlanNode::TableScan { .. } = &**scan
+                && proj_exprs.iter().any(|e| matches!(e, Expr::Identifier(i) if i.value == "*" || i.value == key.value))
+                && let TableObj(table_obj) = execute_dml_plan_node(scan, tnx_id, true, conn_id)? {
+                let in_pk_order = key.value == table_obj.pk_col.0;
+                let rows = table_obj.table_obj.get_all_rows()?.collect::<RsqlResult<Vec<_>>>()?;
+                let Query { cols, mut rows } = project_rows(proj_exprs, table_obj.cols, rows)? else {
+                    return Err(RsqlError::ExecutionError("Sort input must be a Query".to_string()));
+                };
+                if !in_pk_order {
+                    rows = sort_rows(exprs, asc, &cols, &output_collations(input, &cols.0, tnx_id)?, rows)?;
+                } else if !is_asc {
+                    rows.reverse();
+                }
+                return Ok(Query { cols, rows });
             }
             let input_result = execute_dml_plan_node(input, tnx_id, read_only, conn_id)?;
             if let Query { cols, rows } = input_result {
-                let sort_col_idx = cols.0.iter().position(|x| x == &columns[0]).unwrap();
-                let asc = asc[0];
-                let mut rows = rows;
-                rows.sort_by(|a, b| {
-                    let cmp = a[sort_col_idx].partial_cmp(&b[sort_col_idx]).unwrap_or(std::cmp::Ordering::Equal);
-                    if asc {
-                        cmp
-                    }else {
-                        cmp.reverse()
-                    }
-                });
+                let rows = sort_rows(exprs, asc, &cols, &output_collations(input, &cols.0, tnx_id)?, rows)?;
                 Ok(Query {
                     cols,
                     rows,
@@ -120,101 +180,36 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
         PlanNode::Projection { exprs, input } => {
             info!("Implement Projection execution");
             let input_result = execute_dml_plan_node(input, tnx_id, true, conn_id)?;
+            let input_cols = match &input_result {
+                TableWithFilter { table_obj, .. } | TableObj(table_obj) => &table_obj.cols.0[..],
+                TempTable { cols, .. } => &cols.0[..],
+                _ => &[],
+            };
+            let exprs = &expand_qualified_wildcards(exprs, input, input_cols, tnx_id)?;
             if let TableWithFilter {table_obj, rows: input_rows} = input_result {
-                // 0. handle * column
-                if let Expr::Identifier(ident) = &exprs[0] {
-                    if ident.value == "*" {
-                        return Ok(Query {
-                            cols: table_obj.cols,
-                            rows: input_rows,
-                        })
-                    }
-                }
-                // 1. get projection columns
-                let mut cols_name = vec![];
-                let mut cols_type = vec![];
-                for expr in exprs {
-                    match expr {
-                        Expr::Identifier(ident) => {
-                            let col_idx = table_obj.map.get(&ident.value).unwrap();
-                            cols_name.push(ident.value.clone());
-                            cols_type.push(table_obj.cols.1[*col_idx].clone());
-                        },
-                        _ => {
-                            return Err(RsqlError::ExecutionError(format!("Projection expr {:?} is not supported", expr)))
-                        }
-                    }
-                }
-                // 2. get projection rows
-                let mut rows = vec![];
-                for row in input_rows.iter() {
-                    let mut r = vec![];
-                    for col in cols_name.iter() {
-                        let col_idx = table_obj.map.get(col).unwrap();
-                        r.push(row[*col_idx].clone());
-                    }
-                    rows.push(r);
-                }
-                Ok(Query{
-                    cols: (cols_name, cols_type),
-                    rows,
-                }) // get final query result
+                project_rows(exprs, table_obj.cols, input_rows) // get final query result
             }else {
                 if let TempTable{cols: input_cols, rows: input_rows, table_name: _} = input_result {
-                    // 0. handle * column
-                    if let Expr::Identifier(ident) = &exprs[0] {
-                        if ident.value == "*" {
-                            return Ok(Query {
-                                cols: input_cols,
-                                rows: input_rows,
-                            })
-                        }
-                    }
-                    // 1. get projection columns
-                    let mut cols_name = vec![];
-                    let mut cols_type = vec![];
-                    for expr in exprs {
-                        match expr {
-                            Expr::Identifier(ident) => {
-                                let col_idx = input_cols.0.iter().position(|x| x == &ident.value);
-                                let col_idx = match col_idx {
-                                    Some(id) => id,
-                                    None => return Err(RsqlError::ExecutionError(format!("column {} is not found", &ident.value))),
-                                };
-                                cols_name.push(ident.value.clone());
-                                cols_type.push(input_cols.1[col_idx].clone());
-                            },
-                            _ => {
-                                return Err(RsqlError::ExecutionError(format!("Projection expr {:?} is not supported", expr)))
-                            }
-                        }
-                    }
-                    // 2. get projection rows
-                    let mut rows = vec![];
-                    for row in input_rows.iter() {
-                        let mut r = vec![];
-                        for col in cols_name.iter() {
-                            let col_idx = input_cols.0.iter().position(|x| x == col).unwrap();
-                            r.push(row[col_idx].clone());
-                        }
-                        rows.push(r);
-                    }
-                    Ok(Query {
-                        cols: (cols_name, cols_type),
-                        rows,
-                    }) // handle subquery
+                    project_rows(exprs, input_cols, input_rows) // handle subquery
                 }else {
                     if let AggrTable{cols: input_cols, rows: input_rows, aggr_cols} = input_result {
                         // 1. get projection columns
                         let mut cols_name = vec![];
                         let mut cols_type = vec![];
-                        for expr in exprs {
+                        // the group keys and aggregates keep their own names, aliases are not applied here
+                        for expr in exprs.iter().map(|expr| unalias(expr).0) {
                             match expr {
                                 Expr::Identifier(ident) => {
                                     let col_idx = input_cols.0.iter().position(|x| x == &ident.value).unwrap();
                                     cols_name.push(ident.value.clone());
                                     cols_type.push(input_cols.1[col_idx].clone());
                                 },
+                                // a computed group key, named by its expression
+                                _ if let Some(col_idx) = input_cols.0.iter().position(|x| *x == expr.to_string())
+                                    && !aggr_cols.contains(&input_cols.0[col_idx]) => {
+                                    cols_name.push(input_cols.0[col_idx].clone());
+                                    cols_type.push(input_cols.1[col_idx].clone());
+                                },
                                 _ => (), // skip aggr cols
                             }
                         }
@@ -240,47 +235,8 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
                     }else {
                         if let TableObj(table_obj) = input_result {
                             // -1. get rows from table_obj
-                            let input_rows_iter = table_obj.table_obj.get_all_rows()?;
-                            let mut input_rows = vec![];
-                            for row in input_rows_iter {
-                                let row = row?;
-                                input_rows.push(row);
-                            }
-                            // 0. handle * column
-                            if let Expr::Identifier(ident) = &exprs[0] {
-                                if ident.value == "*" {
-                                    return Ok(Query { cols: table_obj.cols, rows: input_rows})
-                                }
-                            }
-                            // 1. get project columns
-                            let mut cols_name = vec![];
-                            let mut cols_type = vec![];
-                            for expr in exprs {
-                                match expr {
-                                    Expr::Identifier(ident) => {
-                                        let col_idx = table_obj.map.get(&ident.value).unwrap();
-                                        cols_name.push(ident.value.clone());
-                                        cols_type.push(table_obj.cols.1[*col_idx].clone());
-                                    },
-                                    _ => {
-                                        return Err(RsqlError::ExecutionError(format!("Projection expr {:?} is not supported", expr)))
-                                    }
-                                }
-                            }
-                            // 2. get projection rows
-                            let mut rows = vec![];
-                            for row in input_rows.iter() {
-                                let mut r = vec![];
-                                for col in cols_name.iter() {
-                                    let col_idx = table_obj.map.get(col).unwrap();
-                                    r.push(row[*col_idx].clone());
-                                }
-                                rows.push(r);
-                            }
-                            Ok(Query {
-                                cols: (cols_name, cols_type),
-                                rows,
-                            }) // get projection query result without where clause
+                            let input_rows = table_obj.table_obj.get_all_rows()?.collect::<RsqlResult<Vec<_>>>()?;
+                            project_rows(exprs, table_obj.cols, input_rows) // get projection query result without where clause
                         }else {
                             Err(RsqlError::ExecutionError(format!("Projection input must be a TableWithFilter, TempTable, AggrTable or TableObj")))
                         }
@@ -288,14 +244,37 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
                 }
             }
         },
+        PlanNode::Limit { limit, offset, input } => {
+            let (skip, take) = (*offset as usize, usize::try_from(*limit).unwrap_or(usize::MAX));
+            // a select without WHERE stops reading the table once it has enough rows
+            if let PlanNode::Projection { exprs, input: scan } = &**input
+                && let PlanNode::TableScan { .. } = &**scan
+                && let TableObj(table_obj) = execute_dml_plan_node(scan, tnx_id, true, conn_id)? {
+                let rows = limit_rows(table_obj.table_obj.get_all_rows()?, skip, take)?;
+                let exprs = expand_qualified_wildcards(exprs, scan, &table_obj.cols.0, tnx_id)?;
+                return project_rows(&exprs, table_obj.cols, rows);
+            }
+            match execute_dml_plan_node(input, tnx_id, read_only, conn_id)? {
+                Query { cols, rows } => Ok(Query { cols, rows: rows.into_iter().skip(skip).take(take).collect() }),
+                // the target rows of a DELETE or UPDATE with LIMIT
+                TableObj(table_obj) => {
+                    let rows = limit_rows(table_obj.table_obj.get_all_rows()?, skip, take)?;
+                    Ok(TableWithFilter { table_obj, rows })
+                },
+                TableWithFilter { table_obj, rows } => {
+                    Ok(TableWithFilter { table_obj, rows: rows.into_iter().skip(skip).take(take).collect() })
+                },
+                _ => Err(RsqlError::ExecutionError("Limit input must be a Query".to_string())),
+            }
+        },
         PlanNode::Join { left, right, join_type, on } => {
             info!("Implement Join execution");
-            if let (TableObj(left_table_obj), TableObj(right_table_obj)) = (execute_dml_plan_node(left, tnx_id, read_only, conn_id)?, execute_dml_plan_node(right, tnx_id, read_only, conn_id)?) {
-                let (joined_cols, joined_rows) = handle_join(&left_table_obj, &right_table_obj, join_type, on)?;
-                Ok(TempTable { cols: joined_cols, rows: joined_rows, table_name: None })
-            }else {
-                Err(RsqlError::ExecutionError(format!("Join input must be a TableObj")))
-            }
+            // each side is read before the next one is opened, so the left side
+            // can be the result of a previous join (FROM a, b, c)
+            let left_input = join_input(left, execute_dml_plan_node(left, tnx_id, read_only, conn_id)?)?;
+            let right_input = join_input(right, execute_dml_plan_node(right, tnx_id, read_only, conn_id)?)?;
+            let (joined_cols, joined_rows) = handle_join(left_input, right_input, join_type, on, crate::config::get().max_join_rows)?;
+            Ok(TempTable { cols: joined_cols, rows: joined_rows, table_name: None })
         },
         PlanNode::Aggregate { group_by, aggr_exprs, input } => {
             info!("Implement Aggregate execution");
@@ -307,6 +286,50 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
                 Err(RsqlError::ExecutionError(format!("Aggregate input must be a TableObj")))
             }
         },
+        PlanNode::Apply { input, subquery, apply_type } => {
+            info!("Implement Apply execution");
+            // run the subquery first, it may read the table the input is going to write
+            let (sub_cols, sub_rows) = match execute_dml_plan_node(subquery, tnx_id, true, conn_id)? {
+                Query { cols, rows } | TempTable { cols, rows, .. } => (cols.0, rows),
+                _ => return Err(RsqlError::ExecutionError("Subquery must return a Query or TempTable".to_string())),
+            };
+            match execute_dml_plan_node(input, tnx_id, read_only, conn_id)? {
+                TableObj(table_obj) => {
+                    let rows = table_obj.table_obj.get_all_rows()?.collect::<RsqlResult<Vec<_>>>()?;
+                    let rows = handle_subquery_apply(&table_obj.cols.0, rows, apply_type, &sub_cols, &sub_rows)?;
+                    Ok(TableWithFilter { table_obj, rows })
+                },
+                TableWithFilter { table_obj, rows } => {
+                    let rows = handle_subquery_apply(&table_obj.cols.0, rows, apply_type, &sub_cols, &sub_rows)?;
+                    Ok(TableWithFilter { table_obj, rows })
+                },
+                TempTable { cols, rows, table_name } => {
+                    let rows = handle_subquery_apply(&cols.0, rows, apply_type, &sub_cols, &sub_rows)?;
+                    Ok(TempTable { cols, rows, table_name })
+                },
+                _ => Err(RsqlError::ExecutionError("Apply input must be a TableObj, TableWithFilter or TempTable".to_string())),
+            }
+        },
+        PlanNode::With { ctes, input } => {
+            // each query is materialized once, however many times it is read
+            let mut scope = CteScope::default();
+            for (name, plan) in ctes {
+                match execute_dml_plan_node(plan, tnx_id, true, conn_id)? {
+                    Query { cols, rows } | TempTable { cols, rows, .. } => scope.bind(name, (cols, rows)),
+                    _ => return Err(RsqlError::ExecutionError(format!("WITH query {} must return rows", name))),
+                }
+            }
+            execute_dml_plan_node(input, tnx_id, read_only, conn_id)
+        },
+        PlanNode::CteScan { name, alias } => {
+            let (cols, rows) = CTE_ROWS.with(|ctes| ctes.borrow().get(name).cloned())
+                .ok_or(RsqlError::ExecutionError(format!("WITH query {} not found", name)))?;
+            Ok(TempTable { cols, rows, table_name: Some(alias.clone().unwrap_or_else(|| name.clone())) })
+        },
+        PlanNode::Values { columns, rows } => {
+            let (cols, rows) = handle_values(columns, rows)?;
+            Ok(TempTable { cols, rows, table_name: None })
+        },
         PlanNode::Subquery { subquery, alias } => {
             info!("Implement Subquery execution");
             let subquery_result = execute_dml_plan_node(subquery, tnx_id, read_only, conn_id)?;
@@ -320,8 +343,14 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
                 }
             }
         },
-        PlanNode::Insert { table_name, columns, values, input: _ } => {
+        PlanNode::Insert { table_name, columns, values, input, on_conflict, returning } => {
             info!("Implement Insert execution");
+            // run the select of INSERT ... SELECT before opening the target table,
+            // which may be the table being selected from
+            let input_result = match input {
+                Some(input) => Some(execute_dml_plan_node(input, tnx_id, true, conn_id)?),
+                None => None,
+            };
             let mut table_object = get_table_object(table_name, false, tnx_id)?;
             // check if table is system table
             if sys_catalog::is_sys_table(table_object.table_obj.get_table_id()) {
@@ -338,58 +367,60 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
                 table_object.cols.0.clone()
             };
 
-            let mut null_cols = vec![];
-            for col_type in table_object.cols.1.iter() {
-                match col_type {
-                    ColType::Integer => {
-                        null_cols.push(DataItem::NullInt);
-                    },
-                    ColType::Float => {
-                        null_cols.push(DataItem::NullFloat);
-                    },
-                    ColType::Chars(size) => {
-                        null_cols.push(DataItem::NullChars { len: *size as u64 });
-                    },
-                    ColType::Bool => {
-                        null_cols.push(DataItem::NullBool);
-                    },
-                    ColType::VarChar(_) => {
-                        null_cols.push(DataItem::NullVarChar);
-                    },
+            let default_row = default_row(table_object.table_obj.get_schema())?;
+            if let Some(input_result) = input_result {
+                let (src_cols, rows) = match input_result {
+                    Query { cols, rows } | TempTable { cols, rows, .. } => (cols, rows),
+                    _ => return Err(RsqlError::ExecutionError("Insert input must be a Query or TempTable".to_string())),
+                };
+                let data_items = handle_insert_rows(&table_object.cols, &target_cols, &default_row, &src_cols, &rows)?;
+                let mut inserted = vec![];
+                for data_item in data_items {
+                    inserted.extend(upsert_row(&mut table_object, data_item, on_conflict, tnx_id)?);
                 }
+                return mutation_result(returning, &table_object.cols, &inserted, "Insert successful");
+            }
+            // every row is checked before the first one is written, an empty list inserts nothing
+            if let Some(row) = values.iter().find(|row| columns.is_some() && row.len() != target_cols.len()) {
+                return Err(RsqlError::InvalidInput(format!("INSERT has {} values for {} columns", row.len(), target_cols.len())));
+            }
+            let data_items = values.iter()
+                .map(|row| handle_insert_expr(&table_object, &target_cols, &default_row, row))
+                .collect::<RsqlResult<Vec<_>>>()?;
+            let mut inserted = vec![];
+            for data_item in data_items {
+                inserted.extend(upsert_row(&mut table_object, data_item, on_conflict, tnx_id)?);
             }
-            let data_item = handle_insert_expr(&table_object, &target_cols, &null_cols, &values[0])?;
-            table_object.table_obj.insert_row(data_item, tnx_id)?;
-            Ok(Mutation("Insert successful".to_string()))
+            mutation_result(returning, &table_object.cols, &inserted, "Insert successful")
         },
-        PlanNode::Delete { input } => {
+        PlanNode::Delete { input, returning } => {
             info!("Implement Delete execution");
+            // DELETE ... WHERE <indexed col> BETWEEN a AND b deletes the range found in the index
+            if let PlanNode::Filter { predicate, input: scan } = &**input && let PlanNode::TableScan { .. } = &**scan {
+                let TableObj(mut table_obj) = execute_dml_plan_node(scan, tnx_id, false, conn_id)? else {
+                    return Err(RsqlError::ExecutionError("Delete input must be a TableObj".to_string()));
+                };
+                // the range delete does not read the rows, so RETURNING goes through the filter
+                let range = if returning.is_none() { index_range_bounds(&table_obj, predicate)? } else { None };
+                let Some((col, low, high)) = range else {
+                    let rows = handle_table_obj_filter_expr(&table_obj, predicate)?;
+                    return delete_rows(table_obj, &rows, returning, &username, tnx_id);
+                };
+                check_delete_permission(&table_obj, &username, tnx_id)?;
+                table_obj.table_obj.delete_rows_by_range(&col, &Some(low), &Some(high), tnx_id)?;
+                return Ok(Mutation("Delete successful".to_string()));
+            }
             let input_result = execute_dml_plan_node(input, tnx_id, false, conn_id)?;
-            if let TableWithFilter{mut table_obj, rows} = input_result {
-                let table_id = table_obj.table_obj.get_table_id();
-                let table_name = SysCatalog::global().get_table_name(table_id, tnx_id)?.ok_or(RsqlError::ExecutionError("Table name not found".to_string()))?;
-
-                // check if table is system table
-                if sys_catalog::is_sys_table(table_id) {
-                    return Err(RsqlError::ExecutionError(format!("System table {} cannot be deleted.", table_name)));
-                }
-                let has_permission = SysCatalog::global().check_user_privilege(tnx_id, &username, Some(&table_name), "W")?;
-                if !has_permission {
-                    return Err(RsqlError::ExecutionError(format!("User {} has no permission to delete table {}.", username, table_name)));
-                }
-                for row in rows.iter() {
-                    let pk_col_idx = table_obj.map.get(&table_obj.pk_col.0).unwrap();
-                    table_obj.table_obj.delete_row(&row[*pk_col_idx], tnx_id)?;
-                }
-                Ok(Mutation("Delete successful".to_string()))
+            if let Some((table_obj, rows)) = target_rows(input_result)? {
+                delete_rows(table_obj, &rows, returning, &username, tnx_id)
             }else {
                 Err(RsqlError::ExecutionError(format!("Delete input must be a TableWithFilter")))
             }
         },
-        PlanNode::Update { input, assignments } => {
+        PlanNode::Update { input, assignments, returning } => {
             info!("Implement Update execution");
             let input_result = execute_dml_plan_node(input, tnx_id, false, conn_id)?;
-            if let TableWithFilter {mut table_obj, rows} = input_result {
+            if let Some((mut table_obj, rows)) = target_rows(input_result)? {
                 let table_id = table_obj.table_obj.get_table_id();
                 let table_name = SysCatalog::global().get_table_name(table_id, tnx_id)?.ok_or(RsqlError::ExecutionError("Table name not found".to_string()))?;
 
@@ -401,8 +432,8 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
                 if !has_permission {
                     return Err(RsqlError::ExecutionError(format!("User {} has no permission to update table {}.", username, table_name)));
                 }
-                handle_update_expr(&mut table_obj, assignments, &rows, tnx_id)?;
-                Ok(Mutation("Update successful".to_string()))
+                let updated = handle_update_expr(&mut table_obj, assignments, &rows, tnx_id)?;
+                mutation_result(returning, &table_obj.cols, &updated, "Update successful")
             }else {
                 Err(RsqlError::ExecutionError(format!("Update input must be a TableWithFilter")))
             }
@@ -413,63 +444,468 @@ pub fn execute_dml_plan_node(node: &PlanNode, tnx_id: u64, read_only: bool, conn
     }
 }
 
-fn handle_join(left_table_obj: &TableObject, right_table_obj: &TableObject, join_type: &JoinType, on: &Option<Expr>) -> RsqlResult<((Vec<String>, Vec<ColType>), Vec<Vec<DataItem>>)> {
-    let mut extended_cols = left_table_obj.cols.0.clone();
-    let mut extended_cols_type = left_table_obj.cols.1.clone();
-    extended_cols.extend(right_table_obj.cols.0.clone());
-    extended_cols_type.extend(right_table_obj.cols.1.clone());
+/// Evaluate the select list over the input rows, a lone * returns them as they are
+fn project_rows(exprs: &[Expr], cols: (Vec<String>, Vec<ColType>), rows: Vec<Vec<DataItem>>) -> RsqlResult<MiddleResult> {
+    if let [Expr::Identifier(ident)] = exprs && ident.value == "*" {
+        return Ok(Query { cols, rows });
+    }
+    let (cols, rows) = handle_projection_exprs(exprs, &cols, &rows)?;
+    Ok(Query { cols, rows })
+}
+
+/// Name and column count of each table in the rows of a FROM clause, in the order of their columns.
+/// None when a source is only known once it runs, like a derived table
+fn from_tables(node: &PlanNode, tnx_id: u64) -> RsqlResult<Option<Vec<(String, usize)>>> {
+    match node {
+        PlanNode::TableScan { table, alias } => {
+            let Some(table_id) = SysCatalog::global().get_table_id(tnx_id, table)? else {
+                return Err(RsqlError::ExecutionError(format!("Table {} not found", table)));
+            };
+            let schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+            let width = schema.get_columns().iter().filter(|col| !col.is_dropped).count();
+            Ok(Some(vec![(alias.clone().unwrap_or_else(|| table.clone()), width)]))
+        },
+        PlanNode::CteScan { name, alias } => {
+            let width = CTE_ROWS.with(|ctes| ctes.borrow().get(name).map(|(cols, _)| cols.0.len()));
+            Ok(width.map(|width| vec![(alias.clone().unwrap_or_else(|| name.clone()), width)]))
+        },
+        PlanNode::Join { left, right, .. } => {
+            match (from_tables(left, tnx_id)?, from_tables(right, tnx_id)?) {
+                (Some(mut left), Some(right)) => {
+                    left.extend(right);
+                    Ok(Some(left))
+                },
+                _ => Ok(None),
+            }
+        },
+        PlanNode::Filter { input, .. } | PlanNode::Apply { input, .. } => from_tables(input, tnx_id),
+        _ => Ok(None),
+    }
+}
+
+/// Collation of each output column when the rows are read from a single table, BINARY otherwise
+fn output_collations(node: &PlanNode, cols: &[String], tnx_id: u64) -> RsqlResult<Vec<Collation>> {
+    let mut node = node;
+    loop {
+        match node {
+            PlanNode::Projection { input, .. } | PlanNode::Filter { input, .. }
+            | PlanNode::Apply { input, .. } | PlanNode::Limit { input, .. } => node = input,
+            PlanNode::TableScan { table, .. } => {
+                let Some(table_id) = SysCatalog::global().get_table_id(tnx_id, table)? else {
+                    break;
+                };
+                let schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+                return Ok(cols.iter().map(|col| schema.get_collation(col)).collect());
+            },
+            _ => break,
+        }
+    }
+    Ok(vec![Collation::Binary; cols.len()])
+}
+
+/// Replace each `t.*` of a select list by the columns table `t` contributes to `cols`
+fn expand_qualified_wildcards(exprs: &[Expr], input: &PlanNode, cols: &[String], tnx_id: u64) -> RsqlResult<Vec<Expr>> {
+    if !exprs.iter().any(|expr| matches!(expr, Expr::QualifiedWildcard(..))) {
+        return Ok(exprs.to_vec());
+    }
+    let tables = from_tables(input, tnx_id)?
+        .filter(|tables| tables.iter().map(|(_, width)| width).sum::<usize>() == cols.len())
+        .ok_or(RsqlError::ExecutionError("t.* is only supported over tables and WITH queries".to_string()))?;
+    let mut expanded = vec![];
+    for expr in exprs {
+        let Expr::QualifiedWildcard(name, _) = expr else {
+            expanded.push(expr.clone());
+            continue;
+        };
+        let name = name.to_string();
+        let mut start = 0;
+        let mut span = None;
+        for (table, width) in tables.iter() {
+            if *table == name {
+                span = Some(start..start + width);
+                break;
+            }
+            start += width;
+        }
+        let span = span.ok_or(RsqlError::ExecutionError(format!("Table {} is not in FROM", name)))?;
+        expanded.extend(cols[span].iter().map(|col| Expr::Identifier(Ident::new(col))));
+    }
+    Ok(expanded)
+}
+
+/// Rows `skip..skip + take` of a table read, a failed read among the skipped rows is reported too
+fn limit_rows(
+    mut rows: impl Iterator<Item = RsqlResult<Vec<DataItem>>>,
+    skip: usize,
+    take: usize,
+) -> RsqlResult<Vec<Vec<DataItem>>> {
+    rows.by_ref().take(skip).try_for_each(|row| row.map(|_| ()))?;
+    rows.take(take).collect()
+}
+
+/// Table and rows an UPDATE or DELETE applies to, without WHERE every row of the table
+fn target_rows(input_result: MiddleResult) -> RsqlResult<Option<(TableObject, Vec<Vec<DataItem>>)>> {
+    match input_result {
+        TableWithFilter { table_obj, rows } => Ok(Some((table_obj, rows))),
+        TableObj(table_obj) => {
+            let rows = table_obj.table_obj.get_all_rows()?.collect::<RsqlResult<Vec<_>>>()?;
+            Ok(Some((table_obj, rows)))
+        },
+        _ => Ok(None),
+    }
+}
+
+fn check_delete_permission(table_obj: &TableObject, username: &str, tnx_id: u64) -> RsqlResult<()> {
+    let table_id = table_obj.table_obj.get_table_id();
+    let table_name = SysCatalog::global().get_table_name(table_id, tnx_id)?.ok_or(RsqlError::ExecutionError("Table name not found".to_string()))?;
+
+    // check if table is system table
+    if sys_catalog::is_sys_table(table_id) {
+        return Err(RsqlError::ExecutionError(format!("System table {} cannot be deleted.", table_name)));
+    }
+    let has_permission = SysCatalog::global().check_user_privilege(tnx_id, username, Some(&table_name), "W")?;
+    if !has_permission {
+        return Err(RsqlError::ExecutionError(format!("User {} has no permission to delete table {}.", username, table_name)));
+    }
+    Ok(())
+}
+
+fn delete_rows(mut table_obj: TableObject, rows: &[Vec<DataItem>], returning: &Option<Vec<Expr>>, username: &str, tnx_id: u64) -> RsqlResult<MiddleResult> {
+    check_delete_permission(&table_obj, username, tnx_id)?;
+    for row in rows.iter() {
+        let pk = table_obj.table_obj.get_schema().pk_key(row)?;
+        table_obj.table_obj.delete_row(&pk, tnx_id)?;
+    }
+    mutation_result(returning, &table_obj.cols, rows, "Delete successful")
+}
+
+/// The affected rows projected through the RETURNING list, or the mutation message without one
+fn mutation_result(returning: &Option<Vec<Expr>>, cols: &(Vec<String>, Vec<ColType>), rows: &[Vec<DataItem>], message: &str) -> RsqlResult<MiddleResult> {
+    match returning {
+        Some(exprs) => {
+            let (cols, rows) = handle_projection_exprs(exprs, cols, rows)?;
+            Ok(Query { cols, rows })
+        },
+        None => Ok(Mutation(message.to_string())),
+    }
+}
+
+/// Insert a row, or when ON CONFLICT is given and its primary key exists,
+/// skip it (DO NOTHING) or update the existing row (DO UPDATE, EXCLUDED.col is the rejected value).
+/// Returns the row as written, None when it was skipped
+fn upsert_row(table_object: &mut TableObject, row: Vec<DataItem>, on_conflict: &Option<InsertConflict>, tnx_id: u64) -> RsqlResult<Option<Vec<DataItem>>> {
+    check_row(table_object.table_obj.get_schema().get_checks(), &table_object.cols, &row)?;
+    let Some(on_conflict) = on_conflict else {
+        table_object.table_obj.insert_row(row.clone(), tnx_id)?;
+        return Ok(Some(row));
+    };
+    // the target names the whole key in any order, a composite key is then found through its own index
+    let pk_cols = table_object.table_obj.get_schema().get_primary_key();
+    if let Some(target) = &on_conflict.target
+        && (target.len() != pk_cols.len() || !pk_cols.iter().all(|col| target.contains(col))) {
+        return Err(RsqlError::ExecutionError(format!(
+            "ON CONFLICT target ({}) must be the primary key ({})", target.join(", "), pk_cols.join(", ")
+        )));
+    }
+    let pk = table_object.table_obj.get_schema().pk_key(&row)?;
+    let Some(mut existing) = table_object.table_obj.get_row_by_pk(&pk)? else {
+        table_object.table_obj.insert_row(row.clone(), tnx_id)?;
+        return Ok(Some(row));
+    };
+    match &on_conflict.action {
+        ConflictAction::DoNothing => Ok(None),
+        ConflictAction::DoUpdate { assignments } => {
+            let mut remaining = vec![];
+            for (col_name, expr) in assignments {
+                let Some(tar_col_idx) = table_object.map.get(col_name).copied() else {
+                    return Err(RsqlError::ExecutionError(format!("Column {} not found", col_name)));
+                };
+                match expr {
+                    Expr::CompoundIdentifier(idents) if idents.len() == 2 && idents[0].value.eq_ignore_ascii_case("excluded") => {
+                        let Some(src_col_idx) = table_object.map.get(&idents[1].value).copied() else {
+                            return Err(RsqlError::ExecutionError(format!("Column {} not found", idents[1].value)));
+                        };
+                        existing[tar_col_idx] = row[src_col_idx].clone();
+                    },
+                    _ => remaining.push((col_name.clone(), expr.clone())),
+                }
+            }
+            Ok(handle_update_expr(table_object, &remaining, &vec![existing], tnx_id)?.pop())
+        },
+    }
+}
+
+/// Rows of one side of a join, with the table name used to qualify its clashing columns
+struct JoinInput {
+    cols: (Vec<String>, Vec<ColType>),
+    rows: Vec<Vec<DataItem>>,
+    table_name: Option<String>,
+}
+
+/// Columns and rows a join produces
+type JoinedRows = ((Vec<String>, Vec<ColType>), Vec<Vec<DataItem>>);
+
+fn join_input(node: &PlanNode, result: MiddleResult) -> RsqlResult<JoinInput> {
+    match result {
+        TableObj(table_obj) => {
+            let rows = table_obj.table_obj.get_all_rows()?.collect::<RsqlResult<Vec<_>>>()?;
+            let table_name = match node {
+                PlanNode::TableScan { table, alias } => Some(alias.clone().unwrap_or_else(|| table.clone())),
+                _ => None,
+            };
+            Ok(JoinInput { cols: table_obj.cols, rows, table_name })
+        },
+        TempTable { cols, rows, table_name } => Ok(JoinInput { cols, rows, table_name }),
+        _ => Err(RsqlError::ExecutionError("Join input must be a TableObj or TempTable".to_string())),
+    }
+}
+
+/// Name of a right side column in the joined result: its own name when it is free,
+/// otherwise `table.col`, with a numeric suffix if that is taken as well
+fn join_col_name(existing: &[String], col: &str, table_name: &Option<String>) -> String {
+    if !existing.iter().any(|c| c == col) {
+        return col.to_string();
+    }
+    let name = match table_name {
+        Some(table_name) => format!("{}.{}", table_name, col),
+        None => col.to_string(),
+    };
+    let mut candidate = name.clone();
+    let mut suffix = 2;
+    while existing.iter().any(|c| c == &candidate) {
+        candidate = format!("{}_{}", name, suffix);
+        suffix += 1;
+    }
+    candidate
+}
+
+fn null_row(cols_type: &[ColType]) -> Vec<DataItem> {
+    cols_type.iter().map(|col_type| match col_type {
+        ColType::Integer => DataItem::NullInt,
+        ColType::Float => DataItem::NullFloat,
+        ColType::Chars(size) => DataItem::NullChars { len: *size as u64 },
+        ColType::Bool => DataItem::NullBool,
+        ColType::VarChar(_) => DataItem::NullVarChar,
+    }).collect()
+}
+
+/// Join two inputs through their cross product, which is refused up front when it has more than `max_rows` rows
+fn handle_join(left: JoinInput, right: JoinInput, join_type: &JoinType, on: &Option<Expr>, max_rows: u64) -> RsqlResult<JoinedRows> {
+    let product = (left.rows.len() as u64).saturating_mul(right.rows.len() as u64);
+    if product > max_rows {
+        return Err(RsqlError::ExecutionError(format!(
+            "Join of {} x {} rows exceeds the limit of {} rows (max_join_rows)", left.rows.len(), right.rows.len(), max_rows
+        )));
+    }
+    let mut extended_cols = left.cols.0.clone();
+    let mut extended_cols_type = left.cols.1.clone();
+    for col in right.cols.0.iter() {
+        let name = join_col_name(&extended_cols, col, &right.table_name);
+        extended_cols.push(name);
+    }
+    extended_cols_type.extend(right.cols.1.clone());
     let mut extended_rows: Vec<Vec<DataItem>> = vec![];
-    let left_rows_iter = left_table_obj.table_obj.get_all_rows()?;
-    for left_row in left_rows_iter {
-        let left_row = left_row?;
-        let right_rows_iter = right_table_obj.table_obj.get_all_rows()?;
-        for right_row in right_rows_iter {
-            let right_row = right_row?;
+    for left_row in left.rows.iter() {
+        for right_row in right.rows.iter() {
             let mut extended_row = left_row.clone();
             extended_row.extend(right_row.clone());
             extended_rows.push(extended_row);
         }
     } // extend the left table with right table
-    let mut left_null_row = vec![];
-    let mut right_null_row = vec![];
-    for col_type in left_table_obj.cols.1.iter() {
-        match col_type {
-            ColType::Integer => {
-                left_null_row.push(DataItem::NullInt);
-            },
-            ColType::Float => {
-                left_null_row.push(DataItem::NullFloat);
-            },
-            ColType::Chars(size) => {
-                left_null_row.push(DataItem::NullChars { len: *size as u64 });
-            },
-            ColType::Bool => {
-                left_null_row.push(DataItem::NullBool);
-            },
-            ColType::VarChar(_) => {
-                left_null_row.push(DataItem::NullVarChar);
-            }
+    let left_null_row = null_row(&left.cols.1);
+    let right_null_row = null_row(&right.cols.1);
+    let join_result = handle_on_expr(&left_null_row, &right_null_row, &extended_cols, &extended_rows, join_type, on)?;
+    Ok(((extended_cols, extended_cols_type), join_result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::executor::execute;
+    use crate::execution::test_utils::{query_rows, TestConn};
+    use crate::execution::result::ExecutionResult;
+
+    fn id_input(table_name: &str, row_count: i64) -> JoinInput {
+        JoinInput {
+            cols: (vec!["id".to_string()], vec![ColType::Integer]),
+            rows: (0..row_count).map(|id| vec![DataItem::Integer(id)]).collect(),
+            table_name: Some(table_name.to_string()),
         }
     }
-    for col_type in right_table_obj.cols.1.iter() {
-        match col_type {
-            ColType::Integer => {
-                right_null_row.push(DataItem::NullInt);
-            },
-            ColType::Float => {
-                right_null_row.push(DataItem::NullFloat);
-            },
-            ColType::Chars(size) => {
-                right_null_row.push(DataItem::NullChars { len: *size as u64 });
-            },
-            ColType::Bool => {
-                right_null_row.push(DataItem::NullBool);
-            },
-            ColType::VarChar(_) => {
-                right_null_row.push(DataItem::NullVarChar);
+
+    #[test]
+    fn test_join_size_guard() {
+        // 30 x 40 rows is over the limit, nothing is materialized
+        let err = handle_join(id_input("a", 30), id_input("b", 40), &JoinType::Cross, &None, 1000).unwrap_err();
+        assert!(err.to_string().contains("Join of 30 x 40 rows exceeds the limit of 1000 rows"), "{err}");
+
+        let ((cols, _), rows) = handle_join(id_input("a", 20), id_input("b", 50), &JoinType::Cross, &None, 1000).unwrap();
+        assert_eq!(cols, ["id", "b.id"]);
+        assert_eq!(rows.len(), 1000);
+    }
+
+    #[test]
+    fn test_limit_reports_skipped_errors() {
+        let rows = || (0..5).map(|id| match id {
+            1 => Err(RsqlError::StorageError("corrupt page".to_string())),
+            _ => Ok(vec![DataItem::Integer(id)]),
+        });
+        // the failed row is inside the OFFSET window
+        let err = limit_rows(rows(), 2, 2).unwrap_err();
+        assert!(err.to_string().contains("corrupt page"), "{err}");
+        assert_eq!(limit_rows(rows().skip(2), 1, 2).unwrap(), [vec![DataItem::Integer(3)], vec![DataItem::Integer(4)]]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_cross_join_three_tables() {
+        let test_conn = TestConn::setup(&[]);
+        let conn = test_conn.id;
+        for (table, rows) in [("cross_a", 2), ("cross_b", 3), ("cross_c", 4)] {
+            execute(&format!("DROP TABLE IF EXISTS {}", table), conn).unwrap();
+            execute(&format!("CREATE TABLE {} (id INTEGER PRIMARY KEY, v INTEGER)", table), conn).unwrap();
+            for i in 0..rows {
+                execute(&format!("INSERT INTO {} VALUES ({}, {})", table, i, i * 10), conn).unwrap();
             }
         }
+
+        let res = execute("SELECT * FROM cross_a, cross_b, cross_c", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, vec!["id", "v", "cross_b.id", "cross_b.v", "cross_c.id", "cross_c.v"]);
+        assert_eq!(rows.len(), 2 * 3 * 4);
+        assert!(rows.iter().all(|row| row.len() == 6));
+
+        let res = execute("SELECT cross_b.id, cross_c.v FROM cross_a, cross_b, cross_c WHERE id = 1", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, vec!["cross_b.id", "cross_c.v"]);
+        assert_eq!(rows.len(), 3 * 4);
+
+        // a table listed twice keeps every column distinct
+        let res = execute("SELECT * FROM cross_a, cross_a", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, vec!["id", "v", "cross_a.id", "cross_a.v"]);
+        assert_eq!(rows.len(), 4);
     }
-    let join_result = handle_on_expr(&left_null_row, &right_null_row, &extended_cols, &extended_rows, join_type, on)?;
-    Ok(((extended_cols, extended_cols_type), join_result))
-}
\ No newline at end of file
+
+    #[test]
+    #[serial_test::serial]
+    fn test_delete_and_insert_returning() {
+        let test_conn = TestConn::setup(&["returning_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE returning_table (id INTEGER PRIMARY KEY, price INTEGER)", conn).unwrap();
+
+        let res = execute("INSERT INTO returning_table VALUES (1, 10) RETURNING *", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["id", "price"]);
+        assert_eq!(rows, vec![[1, 10].map(DataItem::Integer).to_vec()]);
+        let res = execute("INSERT INTO returning_table VALUES (2, 25) RETURNING price * 2", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["price * 2"]);
+        assert_eq!(rows, vec![vec![DataItem::Integer(50)]]);
+        execute("INSERT INTO returning_table VALUES (3, 40)", conn).unwrap();
+
+        let res = execute("UPDATE returning_table SET price = price + 1 WHERE id = 3 RETURNING id, price", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        assert_eq!(rows, vec![[3, 41].map(DataItem::Integer).to_vec()]);
+
+        let res = execute("DELETE FROM returning_table WHERE price > 20 RETURNING *", conn).unwrap();
+        let (cols, mut rows) = query_rows(&res[0]);
+        rows.sort_by_key(|row| row[0].display());
+        assert_eq!(cols, ["id", "price"]);
+        assert_eq!(rows, vec![
+            [2, 25].map(DataItem::Integer).to_vec(),
+            [3, 41].map(DataItem::Integer).to_vec(),
+        ]);
+        let res = execute("SELECT * FROM returning_table", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        assert_eq!(rows, vec![[1, 10].map(DataItem::Integer).to_vec()]);
+        // without RETURNING the statement still reports a mutation
+        let res = execute("DELETE FROM returning_table WHERE id = 1", conn).unwrap();
+        assert!(matches!(res[0], ExecutionResult::Mutation(_)));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_order_by_pk_skips_sort() {
+        use crate::execution::expr_interpreter::SORTS;
+        let test_conn = TestConn::setup(&["order_pk"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE order_pk (id INTEGER PRIMARY KEY, name CHAR(8))", conn).unwrap();
+        for (id, name) in [(3, "c"), (1, "b"), (2, "a")] {
+            execute(&format!("INSERT INTO order_pk VALUES ({}, '{}')", id, name), conn).unwrap();
+        }
+        let ids = |sql: &str| -> Vec<DataItem> {
+            let res = execute(sql, conn).unwrap();
+            query_rows(&res[0]).1.iter().map(|row| row[0].clone()).collect()
+        };
+        SORTS.with(|sorts| sorts.set(0));
+        assert_eq!(ids("SELECT * FROM order_pk ORDER BY id"), [1, 2, 3].map(DataItem::Integer));
+        assert_eq!(ids("SELECT id, name FROM order_pk ORDER BY id DESC"), [3, 2, 1].map(DataItem::Integer));
+        assert_eq!(SORTS.with(|sorts| sorts.get()), 0);
+
+        // other keys are still sorted
+        assert_eq!(ids("SELECT id FROM order_pk WHERE id > 1 ORDER BY id DESC"), [3, 2].map(DataItem::Integer));
+        assert_eq!(ids("SELECT * FROM order_pk ORDER BY name"), [2, 1, 3].map(DataItem::Integer));
+        assert_eq!(SORTS.with(|sorts| sorts.get()), 2);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_qualified_wildcard() {
+        let test_conn = TestConn::setup(&["qw_orders", "qw_items"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE qw_orders (id INTEGER PRIMARY KEY, item INTEGER, qty INTEGER)", conn).unwrap();
+        execute("CREATE TABLE qw_items (id INTEGER PRIMARY KEY, name CHAR(8))", conn).unwrap();
+        execute("INSERT INTO qw_orders VALUES (1, 10, 2)", conn).unwrap();
+        execute("INSERT INTO qw_items VALUES (10, 'pen')", conn).unwrap();
+
+        let res = execute("SELECT o.*, i.id FROM qw_orders o JOIN qw_items i ON o.item = i.id", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, vec!["id", "item", "qty", "i.id"]);
+        assert_eq!(rows, vec![vec![DataItem::Integer(1), DataItem::Integer(10), DataItem::Integer(2), DataItem::Integer(10)]]);
+        // the right side expands to its own columns, a single table to all of them
+        let res = execute("SELECT qw_items.* FROM qw_orders JOIN qw_items ON qw_orders.item = qw_items.id", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).0, vec!["qw_items.id", "name"]);
+        let res = execute("SELECT qw_orders.* FROM qw_orders", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).0, vec!["id", "item", "qty"]);
+        assert!(execute("SELECT x.* FROM qw_orders", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_empty_in_list_and_insert() {
+        let test_conn = TestConn::setup(&["empty_cases"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE empty_cases (id INTEGER PRIMARY KEY DEFAULT 9, name CHAR(8))", conn).unwrap();
+        let count = || {
+            let res = execute("SELECT * FROM empty_cases", conn).unwrap();
+            query_rows(&res[0]).1.len()
+        };
+        // every row of a multi-row insert is written, a failing row writes none of them
+        execute("INSERT INTO empty_cases VALUES (1, 'a'), (2, 'b'), (3, 'c')", conn).unwrap();
+        assert_eq!(count(), 3);
+        assert!(execute("INSERT INTO empty_cases VALUES (4, 'd'), (4, 'e')", conn).is_err());
+        assert_eq!(count(), 3);
+        execute("INSERT INTO empty_cases DEFAULT VALUES", conn).unwrap();
+        assert_eq!(count(), 4);
+
+        // an insert without rows succeeds and writes nothing
+        for sql in ["INSERT INTO empty_cases VALUES", "INSERT INTO empty_cases (id, name) VALUES;"] {
+            assert!(matches!(execute(sql, conn).unwrap()[0], ExecutionResult::Mutation(_)), "{sql}");
+        }
+        assert_eq!(count(), 4);
+
+        // an empty IN list matches no row, an empty NOT IN list every row
+        let res = execute("SELECT * FROM empty_cases WHERE id IN ()", conn).unwrap();
+        assert!(query_rows(&res[0]).1.is_empty());
+        let res = execute("SELECT id FROM empty_cases WHERE name = 'a' OR id IN ( )", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(1)]]);
+        let res = execute("SELECT * FROM empty_cases WHERE id NOT IN ()", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1.len(), 4);
+        execute("DELETE FROM empty_cases WHERE id IN ()", conn).unwrap();
+        execute("UPDATE empty_cases SET name = 'z' WHERE id IN ()", conn).unwrap();
+        let res = execute("SELECT id FROM empty_cases WHERE name = 'z'", conn).unwrap();
+        assert!(query_rows(&res[0]).1.is_empty());
+        assert_eq!(count(), 4);
+    }
+}
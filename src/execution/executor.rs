@@ -1,23 +1,28 @@
 use std::collections::{HashSet, HashMap};
-use std::sync::{Arc, Mutex, LazyLock};
+use std::sync::{Arc, Mutex, RwLock, LazyLock};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::{thread, vec};
+use std::time::{Duration, Instant};
 
 use crate::catalog::SysCatalog;
 use crate::common::{RsqlResult, RsqlError};
+use crate::common::data_item::DataItem;
+use crate::catalog::table_schema::ColType;
 use crate::execution::result::ExecutionResult;
-use crate::sql::{Plan, plan::{PlanItem}};
+use crate::sql::{Plan, plan::{PlanItem, PlanNode}};
 use crate::storage;
 use crate::storage::WAL;
+use crate::storage::wal::wal::CheckpointStats;
 use crate::storage::storage::{Page, StorageManager};
 use crate::catalog::sys_catalog::is_sys_table;
-use crate::storage::table::get_table_path;
+use crate::storage::table::{Table, get_table_path};
 use super::{dml_interpreter::execute_dml_plan_node, ddl_interpreter::execute_ddl_plan_node, dcl_interpreter::execute_dcl_plan_node};
 use tracing::{info, warn};
-use crate::transaction::TnxManager;
-use crate::config::LOCK_MAX_RETRY;
+use crate::transaction::{TnxManager, CancelScope, check_cancelled};
+use crate::config::{LOCK_MAX_RETRY, TRANSACTION_MAX_RETRY, TRANSACTION_RETRY_BACKOFF_MS, STATEMENT_LOG, SLOW_STATEMENT_MS};
 use crate::common::PrivilegeConn;
+use crate::server::conncetion_user_map::ConnectionUserMap;
 
 static ACTIVE_CONN: LazyLock<Mutex<HashSet<u64>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
 
@@ -52,7 +57,8 @@ fn commit_transaction(connection_id: u64) -> RsqlResult<()> {
     if tnx_id.is_none() {
         return Err(RsqlError::InvalidInput("No active transaction to commit".to_string()));
     }
-    WAL::global().commit_tnx(tnx_id.unwrap())?;
+    Table::commit_row_counts(tnx_id.unwrap())?;
+    WAL::global()?.commit_tnx(tnx_id.unwrap())?;
     TnxManager::global().end_transaction(connection_id);
     Ok(())
 }
@@ -62,9 +68,9 @@ fn rollback_transaction(connection_id: u64) -> RsqlResult<()> {
     if tnx_id.is_none() {
         return Err(RsqlError::InvalidInput("No active transaction to rollback".to_string()));
     }
-    let tmp_storages: Rc<RefCell<HashMap<u64, Arc<Mutex<StorageManager>>>>> = Rc::new(RefCell::new(HashMap::new()));
+    let tmp_storages: Rc<RefCell<HashMap<u64, Arc<RwLock<StorageManager>>>>> = Rc::new(RefCell::new(HashMap::new()));
     // Helper closures
-    let get_sm = |table_id: u64| -> RsqlResult<Arc<Mutex<StorageManager>>> {
+    let get_sm = |table_id: u64| -> RsqlResult<Arc<RwLock<StorageManager>>> {
         let mut tmp_storages = tmp_storages.borrow_mut();
         if let Some(sm) = tmp_storages.get(&table_id) {
             Ok(sm.clone())
@@ -77,7 +83,7 @@ fn rollback_transaction(connection_id: u64) -> RsqlResult<()> {
     };
     let mut write_page = |table_id: u64, page_id: u64, data: &[u8]| -> RsqlResult<()> {
         let sm_rc = get_sm(table_id)?;
-        let mut sm = sm_rc.lock().unwrap();
+        let mut sm = sm_rc.write().unwrap();
         let mut page = sm.read_page(page_id)?;
         assert_eq!(Page::max_size(), data.len());
         page.data[..data.len()].copy_from_slice(data);
@@ -85,7 +91,7 @@ fn rollback_transaction(connection_id: u64) -> RsqlResult<()> {
     };
     let mut update_page = |table_id: u64, page_id: u64, offset: u64, len: u64, data: &[u8]| -> RsqlResult<()> {
         let sm_rc = get_sm(table_id)?;
-        let mut sm = sm_rc.lock().unwrap();
+        let mut sm = sm_rc.write().unwrap();
         let mut page = sm.read_page(page_id)?;
         assert!(offset + len <= Page::max_size() as u64);
         page.data[offset as usize..(offset + len) as usize].copy_from_slice(data);
@@ -93,22 +99,22 @@ fn rollback_transaction(connection_id: u64) -> RsqlResult<()> {
     };
     let mut append_page = |table_id: u64| -> RsqlResult<u64> {
         let sm_rc = get_sm(table_id)?;
-        let mut sm = sm_rc.lock().unwrap();
+        let mut sm = sm_rc.write().unwrap();
         Ok(sm.new_page()?.0)
     };
     let mut trunc_page = |table_id: u64| -> RsqlResult<()> {
         let sm_rc = get_sm(table_id)?;
-        let mut sm = sm_rc.lock().unwrap();
+        let mut sm = sm_rc.write().unwrap();
         sm.free()?;
         Ok(())
     };
     let mut max_page_idx = |table_id: u64| -> RsqlResult<Option<u64>> {
         let sm_rc = get_sm(table_id)?;
-        let sm = sm_rc.lock().unwrap();
+        let sm = sm_rc.read().unwrap();
         Ok(sm.max_page_index())
     };
     // Execute WAL operation
-    WAL::global().rollback_tnx(
+    WAL::global()?.rollback_tnx(
         tnx_id.unwrap(),
         &mut write_page,
         &mut update_page,
@@ -116,10 +122,56 @@ fn rollback_transaction(connection_id: u64) -> RsqlResult<()> {
         &mut trunc_page,
         &mut max_page_idx,
     )?;
+    // closed tables may still hold headers the rollback has just restored,
+    // and the catalog names and schemas the transaction changed
+    Table::clear_cache();
+    Table::discard_row_counts(tnx_id.unwrap());
+    SysCatalog::global().clear_cache();
     TnxManager::global().end_transaction(connection_id);
     Ok(())
 }
 
+/// List active transactions known to the transaction manager or still open in the WAL
+/// Start time is NULL for transactions the manager does not track
+fn show_transactions() -> ExecutionResult {
+    let mut tnxs: Vec<(u64, Option<u64>)> = TnxManager::global()
+        .active_transactions()
+        .into_iter()
+        .map(|(id, start)| (id, Some(start)))
+        .collect();
+    for id in WAL::try_global().map(|wal| wal.active_tnx_ids()).unwrap_or_default() {
+        if !tnxs.iter().any(|(tid, _)| *tid == id) {
+            tnxs.push((id, None));
+        }
+    }
+    tnxs.sort_by_key(|&(id, _)| id);
+    let rows = tnxs.into_iter().map(|(id, start)| vec![
+        DataItem::Integer(id as i64),
+        start.map_or(DataItem::NullInt, |t| DataItem::Integer(t as i64)),
+    ]).collect();
+    ExecutionResult::Query {
+        cols: (
+            vec!["tnx_id".to_string(), "start_time".to_string()],
+            vec![ColType::Integer, ColType::Integer],
+        ),
+        rows,
+    }
+}
+
+/// Transaction a statement runs in, and whether the statement began it and commits it itself.
+/// Without an open transaction one is begun, with autocommit off a statement that writes
+/// leaves it open for a later COMMIT or ROLLBACK
+fn statement_transaction(connection_id: u64, writes: bool) -> (u64, bool) {
+    match TnxManager::global().get_transaction_id(connection_id) {
+        Some(id) => (id, false),
+        None => {
+            // auto begin transaction
+            let auto_commit = !writes || ConnectionUserMap::global().autocommit(connection_id);
+            (TnxManager::global().begin_transaction(connection_id), auto_commit)
+        },
+    }
+}
+
 fn execute_inner(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResult>> {
     let plan = Plan::build_plan(sql)?;
     let mut results = vec![];
@@ -137,17 +189,24 @@ fn execute_inner(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResul
                 rollback_transaction(connection_id)?;
                 results.push(ExecutionResult::RollbackSuccess);
             },
+            PlanItem::ShowTransactions => {
+                results.push(show_transactions());
+            },
+            PlanItem::ResetDatabase => {
+                results.push(reset_database(connection_id)?);
+            },
+            PlanItem::SetAutocommit(autocommit) => {
+                // turning autocommit back on commits the open transaction
+                if *autocommit && TnxManager::global().get_transaction_id(connection_id).is_some() {
+                    commit_transaction(connection_id)?;
+                }
+                if !ConnectionUserMap::global().set_autocommit(connection_id, *autocommit) {
+                    return Err(RsqlError::ExecutionError(format!("Unknown connection {}", connection_id)));
+                }
+                results.push(ExecutionResult::Dcl(format!("Autocommit {}", if *autocommit { "on" } else { "off" })));
+            },
             PlanItem::DCL(plan_node) => {
-                let tnx_id = TnxManager::global().get_transaction_id(connection_id);
-                let mut auto_tnx = false;
-                let tnx_id = match tnx_id {
-                    Some(id) => id,
-                    None => {
-                        // auto begin transaction
-                        auto_tnx = true;
-                        TnxManager::global().begin_transaction(connection_id)
-                    },
-                };
+                let (tnx_id, auto_tnx) = statement_transaction(connection_id, true);
                 let res = execute_dcl_plan_node(plan_node, tnx_id, connection_id)?;
                 if auto_tnx {
                     commit_transaction(connection_id)?;
@@ -155,16 +214,9 @@ fn execute_inner(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResul
                 results.push(res);
             },
             PlanItem::DDL(plan_node) => {
-                let tnx_id = TnxManager::global().get_transaction_id(connection_id);
-                let mut auto_tnx = false;
-                let tnx_id = match tnx_id {
-                    Some(id) => id,
-                    None => {
-                        // auto begin transaction
-                        auto_tnx = true;
-                        TnxManager::global().begin_transaction(connection_id)
-                    },
-                };
+                let (tnx_id, auto_tnx) = statement_transaction(connection_id, true);
+                let _cancel = CancelScope::enter(tnx_id);
+                check_cancelled()?;
                 let res = execute_ddl_plan_node(plan_node, tnx_id, connection_id)?;
                 if auto_tnx {
                     commit_transaction(connection_id)?;
@@ -172,16 +224,10 @@ fn execute_inner(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResul
                 results.push(res);
             },
             PlanItem::DML(plan_node) => {
-                let tnx_id = TnxManager::global().get_transaction_id(connection_id);
-                let mut auto_tnx = false;
-                let tnx_id = match tnx_id {
-                    Some(id) => id,
-                    None => {
-                        // auto begin transaction
-                        auto_tnx = true;
-                        TnxManager::global().begin_transaction(connection_id)
-                    },
-                };
+                let writes = matches!(plan_node, PlanNode::Insert { .. } | PlanNode::Update { .. } | PlanNode::Delete { .. });
+                let (tnx_id, auto_tnx) = statement_transaction(connection_id, writes);
+                let _cancel = CancelScope::enter(tnx_id);
+                check_cancelled()?;
                 let res = execute_dml_plan_node(plan_node, tnx_id, false, connection_id)?;
                 let res = res.to_exec_result()?;
                 if auto_tnx {
@@ -194,10 +240,59 @@ fn execute_inner(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResul
     Ok(results)
 }
 
+/// Log a finished statement with its user, returned rows and duration
+/// Statements slower than SLOW_STATEMENT_MS are logged at warn level
+fn log_statement(sql: &str, connection_id: u64, result: &RsqlResult<Vec<ExecutionResult>>, elapsed: Duration) {
+    if !STATEMENT_LOG {
+        return;
+    }
+    let user = ConnectionUserMap::global().get_username(connection_id).unwrap_or_default();
+    let elapsed_ms = elapsed.as_millis() as u64;
+    let (status, rows) = match result {
+        Ok(results) => ("ok", results.iter().map(|res| match res {
+            ExecutionResult::Query { rows, .. } => rows.len(),
+            _ => 0,
+        }).sum::<usize>()),
+        Err(_) => ("error", 0),
+    };
+    if elapsed_ms >= SLOW_STATEMENT_MS {
+        warn!(connection_id, user = %user, rows, elapsed_ms, status, "Slow statement: {}", sql);
+    } else {
+        info!(connection_id, user = %user, rows, elapsed_ms, status, "Statement: {}", sql);
+    }
+}
+
 /// Execute a SQL statement
 pub fn execute(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResult>> {
-    let _guard = ExecGuard::new(connection_id);
+    let guard = ExecGuard::new(connection_id);
+    let start = Instant::now();
+    let result = execute_with_retry(sql, connection_id);
+    log_statement(sql, connection_id, &result, start.elapsed());
+    drop(guard);
+    disconnect_terminated();
+    result
+}
+
+/// Disconnect the connections marked for termination that run no statement,
+/// one running a statement is disconnected after it by its own `execute`
+fn disconnect_terminated() {
+    for connection_id in ConnectionUserMap::global().terminated_connections() {
+        // holding the lock keeps the connection from starting a statement meanwhile
+        let active = ACTIVE_CONN.lock().unwrap();
+        if active.contains(&connection_id) {
+            continue;
+        }
+        if let Err(e) = disconnect_callback(connection_id) {
+            warn!("Failed to disconnect terminated connection {}: {}", connection_id, e);
+        }
+    }
+}
+
+/// Execute a SQL statement, retrying it when it fails on a lock
+/// A statement of an explicit transaction is not retried, the conflict aborts the whole transaction
+fn execute_with_retry(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResult>> {
     info!("Executing SQL: {}, in thread {:?}", sql, thread::current().id());
+    let in_tnx = TnxManager::global().get_transaction_id(connection_id).is_some();
     let mut retry_count = 0;
     while retry_count < LOCK_MAX_RETRY {
         let exec_res = execute_inner(sql, connection_id);
@@ -212,6 +307,9 @@ pub fn execute(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResult>
                 if TnxManager::global().get_transaction_id(connection_id).is_some() {
                     rollback_transaction(connection_id)?;
                 }
+                if in_tnx {
+                    return Err(RsqlError::LockError(e));
+                }
                 // continue to retry
             }
             Err(e) => {
@@ -227,20 +325,73 @@ pub fn execute(sql: &str, connection_id: u64) -> RsqlResult<Vec<ExecutionResult>
     Err(RsqlError::LockError("Maximum retry limit reached".to_string()))
 }
 
+/// Run `f` in a new transaction of the connection, committed when `f` returns Ok and rolled back when it fails.
+/// A lock conflict or deadlock aborts the transaction, `f` is then run again in a new one, at most TRANSACTION_MAX_RETRY times
+pub fn run_transaction<T>(connection_id: u64, mut f: impl FnMut() -> RsqlResult<T>) -> RsqlResult<T> {
+    if TnxManager::global().get_transaction_id(connection_id).is_some() {
+        return Err(RsqlError::ExecutionError("A transaction is already active on this connection".to_string()));
+    }
+    let mut attempts = 0;
+    loop {
+        execute("BEGIN", connection_id)?;
+        let res = f().and_then(|value| {
+            execute("COMMIT", connection_id)?;
+            Ok(value)
+        });
+        let err = match res {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        if TnxManager::global().get_transaction_id(connection_id).is_some() {
+            rollback_transaction(connection_id)?;
+        }
+        attempts += 1;
+        if !matches!(err, RsqlError::LockError(_) | RsqlError::Deadlock(_)) || attempts >= TRANSACTION_MAX_RETRY {
+            return Err(err);
+        }
+        warn!("Transaction of connection {} aborted by a conflict, retrying it: {}", connection_id, err);
+        // a random backoff, so transactions aborted together do not conflict again
+        let max_backoff = TRANSACTION_RETRY_BACKOFF_MS << attempts;
+        thread::sleep(Duration::from_millis(rand::random::<u64>() % max_backoff));
+    }
+}
+
 /// Check if checkpoint is needed
-/// If needed, perform a checkpoint operation
-pub fn checkpoint() -> RsqlResult<()> {
-    let need_checkpoint = WAL::global().need_checkpoint();
+/// If needed, perform a checkpoint operation and return its stats
+pub fn checkpoint() -> RsqlResult<Option<CheckpointStats>> {
+    let need_checkpoint = WAL::global()?.need_checkpoint();
     if !need_checkpoint {
-        info!("No checkpoint needed at this time.");
-        return Ok(());
+        info!("No checkpoint needed at this time, last checkpoint: {:?}", WAL::global()?.last_checkpoint_stats());
+        return Ok(None);
     }
     // Perform checkpoint operation
     info!("Performing checkpoint operation.");
     // Execute WAL operation
-    WAL::global().checkpoint(&|| {
+    let stats = WAL::global()?.checkpoint(&|| {
         StorageManager::flush_all()
     })?;
+    Ok(Some(stats))
+}
+
+/// Force the log and every open table file to disk
+/// The log is synced first, so the pages written afterwards are always covered by it
+pub fn sync() -> RsqlResult<()> {
+    WAL::global()?.flush()?;
+    StorageManager::flush_all()?;
+    info!("Synced the log and all open tables to disk.");
+    Ok(())
+}
+
+/// Close every table for a shutdown that archives the table files:
+/// waits until no thread holds a table, checkpoints the log so the files hold all committed data,
+/// then closes the cached tables so no file handle stays open
+pub fn close_all_tables() -> RsqlResult<()> {
+    Table::wait_all_released()?;
+    let stats = WAL::global()?.checkpoint(&|| {
+        StorageManager::flush_all()
+    })?;
+    Table::clear_cache();
+    info!("Closed all tables, checkpoint kept {} log entries.", stats.entries_after);
     Ok(())
 }
 
@@ -252,9 +403,19 @@ pub fn validate_user(username: &str, password: &str) -> RsqlResult<bool> {
     Ok(is_valid)
 }
 
+/// Whether `username` is a user of the database
+pub fn user_exists(username: &str) -> RsqlResult<bool> {
+    let tnx_id = TnxManager::global().begin_transaction(PrivilegeConn::USER_VALIDATE);
+    let users = SysCatalog::global().get_all_users(tnx_id);
+    TnxManager::global().end_transaction(PrivilegeConn::USER_VALIDATE);
+    Ok(users?.iter().any(|user| user == username))
+}
+
 /// Callback function when a connection is disconnected
-/// Will automatically rollback any active transaction for the connection
+/// Will automatically rollback any active transaction for the connection,
+/// and release its slot in the connection map
 pub fn disconnect_callback(connection_id: u64) -> RsqlResult<()> {
+    ConnectionUserMap::global().remove_connection(connection_id);
     let tnx_id_opt = TnxManager::global().get_transaction_id(connection_id);
     if tnx_id_opt.is_some() {
         warn!("Connection {} disconnected with active transaction, rolling back...", connection_id);
@@ -263,9 +424,453 @@ pub fn disconnect_callback(connection_id: u64) -> RsqlResult<()> {
     Ok(())
 }
 
+/// Drop every user table and remove its file, then checkpoint so the log no longer refers to them.
+/// System tables, users and sequences are kept. Needs write permission and no open transaction
+pub fn reset_database(connection_id: u64) -> RsqlResult<ExecutionResult> {
+    if TnxManager::global().get_transaction_id(connection_id).is_some() {
+        return Err(RsqlError::ExecutionError("RESET DATABASE cannot run inside a transaction".to_string()));
+    }
+    let username = ConnectionUserMap::global()
+        .get_username(connection_id)
+        .ok_or(RsqlError::ExecutionError("Failed to get username from connection ID".to_string()))?;
+    let tnx_id = TnxManager::global().begin_transaction(connection_id);
+    let table_ids = match drop_all_tables(tnx_id, &username) {
+        Ok(table_ids) => {
+            commit_transaction(connection_id)?;
+            table_ids
+        },
+        Err(e) => {
+            rollback_transaction(connection_id)?;
+            return Err(e);
+        },
+    };
+    // the files can only go once no log entry would recreate them on recovery
+    WAL::global()?.checkpoint(&|| StorageManager::flush_all())?;
+    Table::clear_cache();
+    for table_id in table_ids.iter() {
+        let path = get_table_path(*table_id, false);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    info!("Database reset by {}, {} table(s) dropped.", username, table_ids.len());
+    Ok(ExecutionResult::Dcl(format!("Database reset, {} table(s) dropped.", table_ids.len())))
+}
+
+fn drop_all_tables(tnx_id: u64, username: &str) -> RsqlResult<Vec<u64>> {
+    let has_permission = SysCatalog::global().check_user_write_permission(tnx_id, username)?;
+    if !has_permission {
+        return Err(RsqlError::ExecutionError(format!("User {} does not have permission to reset the database.", username)));
+    }
+    let table_ids: Vec<u64> = SysCatalog::global().get_all_table_ids(tnx_id)?
+        .into_iter()
+        .filter(|table_id| !is_sys_table(*table_id))
+        .collect();
+    for table_id in table_ids.iter() {
+        let table_schema = SysCatalog::global().get_table_schema(tnx_id, *table_id)?;
+        let table = Table::from(*table_id, table_schema, false)?;
+        if crate::config::get().secure_drop {
+            table.drop_zeroed(tnx_id)?;
+        } else {
+            table.drop(tnx_id)?;
+        }
+        SysCatalog::global().unregister_table(tnx_id, *table_id)?;
+    }
+    Ok(table_ids)
+}
+
+/// Pass every row of a table to `f` in primary key order, without planning SQL.
+/// Runs in the transaction of the connection, or in a new one when it has none
+pub fn scan_table(table_name: &str, connection_id: u64, mut f: impl FnMut(Vec<DataItem>) -> RsqlResult<()>) -> RsqlResult<()> {
+    match TnxManager::global().get_transaction_id(connection_id) {
+        Some(tnx_id) => scan_table_in(table_name, connection_id, tnx_id, &mut f),
+        None => run_transaction(connection_id, || {
+            let tnx_id = TnxManager::global().get_transaction_id(connection_id)
+                .ok_or(RsqlError::ExecutionError("Failed to begin a transaction for the scan".to_string()))?;
+            scan_table_in(table_name, connection_id, tnx_id, &mut f)
+        }),
+    }
+}
+
+fn scan_table_in(table_name: &str, connection_id: u64, tnx_id: u64, f: &mut dyn FnMut(Vec<DataItem>) -> RsqlResult<()>) -> RsqlResult<()> {
+    let username = ConnectionUserMap::global()
+        .get_username(connection_id)
+        .ok_or(RsqlError::ExecutionError("Failed to get username from connection ID".to_string()))?;
+    if !SysCatalog::global().check_user_privilege(tnx_id, &username, Some(table_name), "R")? {
+        return Err(RsqlError::ExecutionError(format!("User {} has no permission to read table {}.", username, table_name)));
+    }
+    let Some(table_id) = SysCatalog::global().get_table_id(tnx_id, table_name)? else {
+        return Err(RsqlError::ExecutionError(format!("Table {} not found", table_name)));
+    };
+    TnxManager::global().acquire_read_locks(tnx_id, &[table_id])?;
+    let schema = SysCatalog::global().get_table_schema(tnx_id, table_id)?;
+    let table = Table::from_shared(table_id, schema, is_sys_table(table_id))?;
+    for row in table.scan() {
+        f(row?)?;
+    }
+    Ok(())
+}
+
 pub fn backup_database() -> RsqlResult<()> {
     info!("Backing up database...");
     storage::archiver::backup()?;
     info!("Database backup completed.");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::test_utils::{query_rows, TestConn};
+    use std::sync::Arc;
+    use tracing::Level;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    /// Collects the levels of all events it sees
+    struct CaptureLayer {
+        levels: Arc<Mutex<Vec<Level>>>,
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for CaptureLayer {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            self.levels.lock().unwrap().push(*event.metadata().level());
+        }
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_readers_never_see_partial_inserts() {
+        let test_conns = [TestConn::setup(&["snapshot_table"]), TestConn::new(), TestConn::new()];
+        let writer = test_conns[0].id;
+        let readers = [test_conns[1].id, test_conns[2].id];
+        execute("CREATE TABLE snapshot_table (id INTEGER PRIMARY KEY, a INTEGER, b INTEGER)", writer).unwrap();
+        execute("CREATE INDEX snapshot_table_a ON snapshot_table (a)", writer).unwrap();
+
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let reader_handles: Vec<_> = readers.iter().map(|&conn| {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut snapshots = 0;
+                while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                    execute("BEGIN", conn).unwrap();
+                    let res = execute("SELECT id, a, b FROM snapshot_table", conn).unwrap();
+                    let (_, rows) = query_rows(&res[0]);
+                    let res = execute("SELECT id FROM snapshot_table WHERE a >= 0", conn).unwrap();
+                    let (_, indexed) = query_rows(&res[0]);
+                    execute("COMMIT", conn).unwrap();
+                    // every row is complete, the index agrees with the data pages,
+                    // and the rows of a writer transaction show up together
+                    assert!(rows.iter().all(|row| row[1] == row[2]), "{:?}", rows);
+                    assert_eq!(indexed.len(), rows.len());
+                    assert_eq!(rows.len() % 5, 0, "{:?}", rows);
+                    snapshots += 1;
+                }
+                snapshots
+            })
+        }).collect();
+
+        for batch in 0..10 {
+            execute("BEGIN", writer).unwrap();
+            for i in 0..5 {
+                let id = batch * 5 + i;
+                execute(&format!("INSERT INTO snapshot_table VALUES ({}, {}, {})", id, id, id), writer).unwrap();
+            }
+            execute("COMMIT", writer).unwrap();
+        }
+        done.store(true, std::sync::atomic::Ordering::SeqCst);
+        for handle in reader_handles {
+            assert!(handle.join().unwrap() > 0);
+        }
+        let res = execute("SELECT id FROM snapshot_table", writer).unwrap();
+        assert_eq!(query_rows(&res[0]).1.len(), 50);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_run_transaction_retries_conflicts() {
+        let test_conns = [TestConn::setup(&["retry_table"]), TestConn::new()];
+        let conns = [test_conns[0].id, test_conns[1].id];
+        execute("CREATE TABLE retry_table (id INTEGER PRIMARY KEY, v INTEGER)", conns[0]).unwrap();
+        execute("INSERT INTO retry_table VALUES (1, 0)", conns[0]).unwrap();
+
+        // both read the counter before either writes it, so their lock upgrades deadlock
+        // until one times out, is rolled back and runs again after the other committed
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let attempts = Arc::new(Mutex::new(0));
+        let handles: Vec<_> = conns.into_iter().map(|conn| {
+            let (barrier, attempts) = (barrier.clone(), attempts.clone());
+            // rollbacks reopen the tables, named like the test so they resolve to its table files
+            let name = thread::current().name().unwrap().to_string();
+            thread::Builder::new().name(name).spawn(move || {
+                let mut first = true;
+                run_transaction(conn, || {
+                    *attempts.lock().unwrap() += 1;
+                    let res = execute("SELECT v FROM retry_table WHERE id = 1", conn)?;
+                    let DataItem::Integer(v) = query_rows(&res[0]).1[0][0] else { unreachable!() };
+                    if std::mem::take(&mut first) {
+                        barrier.wait();
+                    }
+                    execute(&format!("UPDATE retry_table SET v = {} WHERE id = 1", v + 1), conn)?;
+                    Ok(v)
+                })
+            }).unwrap()
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        assert!(*attempts.lock().unwrap() > 2);
+        // no increment was lost
+        let res = execute("SELECT v FROM retry_table WHERE id = 1", conns[0]).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(2)]]);
+        // a failure other than a conflict is not retried
+        let mut calls = 0;
+        assert!(run_transaction(conns[0], || {
+            calls += 1;
+            execute("INSERT INTO retry_table VALUES (1, 0)", conns[0])
+        }).is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_reset_database() {
+        let test_conn = TestConn::setup(&[]);
+        let conn = test_conn.id;
+        let names = ["reset_a", "reset_b", "reset_c"];
+        for name in names {
+            execute(&format!("DROP TABLE IF EXISTS {}", name), conn).unwrap();
+            execute(&format!("CREATE TABLE {} (id INTEGER PRIMARY KEY, v INTEGER)", name), conn).unwrap();
+            execute(&format!("INSERT INTO {} VALUES (1, 2)", name), conn).unwrap();
+        }
+        let table_ids = run_transaction(conn, || {
+            let tnx_id = TnxManager::global().get_transaction_id(conn).unwrap();
+            names.iter().map(|name| Ok(SysCatalog::global().get_table_id(tnx_id, name)?.unwrap())).collect::<RsqlResult<Vec<u64>>>()
+        }).unwrap();
+
+        // only users with write permission may reset
+        execute("DROP USER IF EXISTS reset_reader", conn).unwrap();
+        execute("CREATE USER reset_reader PASSWORD 'pw'", conn).unwrap();
+        let reader = TestConn::as_user("reset_reader");
+        assert!(execute("RESET DATABASE", reader.id).is_err());
+        drop(reader);
+        execute("DROP USER reset_reader", conn).unwrap();
+        // nor inside a transaction
+        execute("BEGIN", conn).unwrap();
+        assert!(execute("RESET DATABASE", conn).is_err());
+        let _ = execute("ROLLBACK", conn);
+
+        execute("RESET DATABASE", conn).unwrap();
+        let remaining = run_transaction(conn, || {
+            let tnx_id = TnxManager::global().get_transaction_id(conn).unwrap();
+            SysCatalog::global().get_all_table_ids(tnx_id)
+        }).unwrap();
+        assert!(remaining.iter().all(|table_id| is_sys_table(*table_id)));
+        for table_id in table_ids {
+            assert!(!get_table_path(table_id, false).exists());
+        }
+        // the catalog is usable afterwards
+        execute("CREATE TABLE reset_a (id INTEGER PRIMARY KEY)", conn).unwrap();
+        execute("DROP TABLE reset_a", conn).unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_scan_table() {
+        let test_conn = TestConn::setup(&["scan_api"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE scan_api (id INTEGER PRIMARY KEY, qty INTEGER)", conn).unwrap();
+        for (id, qty) in [(3, 30), (1, 10), (2, 5)] {
+            execute(&format!("INSERT INTO scan_api VALUES ({}, {})", id, qty), conn).unwrap();
+        }
+        let mut ids = vec![];
+        let mut total = 0;
+        scan_table("scan_api", conn, |row| {
+            if let (DataItem::Integer(id), DataItem::Integer(qty)) = (&row[0], &row[1]) {
+                ids.push(*id);
+                total += qty;
+            }
+            Ok(())
+        }).unwrap();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(total, 45);
+        // the scan ran in its own transaction, which is over
+        assert!(TnxManager::global().get_transaction_id(conn).is_none());
+
+        // an error of the callback ends the scan
+        let mut seen = 0;
+        let err = scan_table("scan_api", conn, |_| {
+            seen += 1;
+            Err(RsqlError::ExecutionError("stop".to_string()))
+        });
+        assert!(err.is_err());
+        assert_eq!(seen, 1);
+        assert!(scan_table("scan_api_missing", conn, |_| Ok(())).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_autocommit_off() {
+        let test_conn = TestConn::setup(&["manual_commit"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE manual_commit (id INTEGER PRIMARY KEY)", conn).unwrap();
+        let ids = |conn: u64| {
+            let res = execute("SELECT id FROM manual_commit", conn).unwrap();
+            query_rows(&res[0]).1.into_iter().map(|row| row[0].clone()).collect::<Vec<_>>()
+        };
+
+        execute("SET autocommit = off", conn).unwrap();
+        // reads alone do not open a transaction
+        assert!(ids(conn).is_empty());
+        assert!(TnxManager::global().get_transaction_id(conn).is_none());
+        execute("INSERT INTO manual_commit VALUES (1)", conn).unwrap();
+        assert!(TnxManager::global().get_transaction_id(conn).is_some());
+        execute("ROLLBACK", conn).unwrap();
+        assert!(ids(conn).is_empty());
+
+        execute("INSERT INTO manual_commit VALUES (2)", conn).unwrap();
+        execute("INSERT INTO manual_commit VALUES (3)", conn).unwrap();
+        execute("COMMIT", conn).unwrap();
+        assert!(TnxManager::global().get_transaction_id(conn).is_none());
+        assert_eq!(ids(conn), vec![DataItem::Integer(2), DataItem::Integer(3)]);
+
+        // switching back on commits the pending work
+        execute("INSERT INTO manual_commit VALUES (4)", conn).unwrap();
+        execute("SET autocommit TO 1", conn).unwrap();
+        assert!(TnxManager::global().get_transaction_id(conn).is_none());
+        execute("INSERT INTO manual_commit VALUES (5)", conn).unwrap();
+        assert!(TnxManager::global().get_transaction_id(conn).is_none());
+        assert_eq!(ids(conn).len(), 4);
+        assert!(execute("SET autocommit = maybe", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_close_all_tables() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let test_conn = TestConn::setup(&["closing_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE closing_table (id INTEGER PRIMARY KEY, v INTEGER)", conn).unwrap();
+        execute("INSERT INTO closing_table VALUES (1, 10)", conn).unwrap();
+        let tnx_id = TnxManager::global().begin_transaction(conn);
+        let table_id = SysCatalog::global().get_table_id(tnx_id, "closing_table").unwrap().unwrap();
+        let schema = SysCatalog::global().get_table_schema(tnx_id, table_id).unwrap();
+        commit_transaction(conn).unwrap();
+
+        // a worker still has the table open when the shutdown starts
+        let released = Arc::new(AtomicBool::new(false));
+        let (opened_tx, opened_rx) = std::sync::mpsc::channel();
+        let worker = {
+            let (schema, released) = (schema.clone(), released.clone());
+            thread::spawn(move || {
+                let table = Table::from(table_id, schema, false).unwrap();
+                opened_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(200));
+                released.store(true, Ordering::SeqCst);
+                drop(table);
+            })
+        };
+        opened_rx.recv().unwrap();
+        close_all_tables().unwrap();
+        assert!(released.load(Ordering::SeqCst));
+        worker.join().unwrap();
+
+        // the table is read from its file again and has the committed row
+        let parses = Table::header_parse_count(table_id);
+        let table = Table::from(table_id, schema, false).unwrap();
+        assert_eq!(Table::header_parse_count(table_id), parses + 1);
+        let row = table.get_row_by_pk(&DataItem::Integer(1)).unwrap().unwrap();
+        assert_eq!(row[1], DataItem::Integer(10));
+        drop(table);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_sync_writes_pages_to_disk() {
+        TnxManager::init(1);
+        SysCatalog::init().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sync_target.db");
+        let path_str = path.to_str().unwrap();
+        // keep the manager open, dropping it would flush on its own
+        let sm = StorageManager::new(path_str).unwrap();
+        let (page_idx, mut page) = sm.write().unwrap().new_page().unwrap();
+        page.data[..18].copy_from_slice(b"durable-after-sync");
+        sm.write().unwrap().write_page(&page, page_idx).unwrap();
+        sync().unwrap();
+
+        // read the file as a restarted process would, bypassing the page cache
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.windows(18).any(|w| w == b"durable-after-sync"));
+        drop(sm);
+    }
+
+    #[test]
+    fn test_slow_statement_logs_warn() {
+        let levels = Arc::new(Mutex::new(vec![]));
+        let subscriber = tracing_subscriber::registry().with(CaptureLayer { levels: levels.clone() });
+        tracing::subscriber::with_default(subscriber, || {
+            let result = Ok(vec![ExecutionResult::Mutation("Insert successful".to_string())]);
+            log_statement("SELECT 1", 1, &result, Duration::from_millis(1));
+            log_statement("SELECT 1", 1, &result, Duration::from_millis(SLOW_STATEMENT_MS * 2));
+        });
+        assert_eq!(*levels.lock().unwrap(), vec![Level::INFO, Level::WARN]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_show_transactions() {
+        let test_conns = [TestConn::setup(&["shown_open", "shown_done"]), TestConn::new(), TestConn::new()];
+        let (conn, open_conn, done_conn) = (test_conns[0].id, test_conns[1].id, test_conns[2].id);
+        for table in ["shown_open", "shown_done"] {
+            execute(&format!("CREATE TABLE {} (id INTEGER PRIMARY KEY)", table), conn).unwrap();
+        }
+        execute("BEGIN", open_conn).unwrap();
+        execute("INSERT INTO shown_open VALUES (1)", open_conn).unwrap();
+        let open_tnx = TnxManager::global().get_transaction_id(open_conn).unwrap();
+        execute("BEGIN", done_conn).unwrap();
+        execute("INSERT INTO shown_done VALUES (1)", done_conn).unwrap();
+        let done_tnx = TnxManager::global().get_transaction_id(done_conn).unwrap();
+        execute("COMMIT", done_conn).unwrap();
+        // a transaction left open in the log only, e.g. by a crash, has no start time
+        let log_tnx = TnxManager::global().new_tnx_id();
+        WAL::global().unwrap().open_tnx(log_tnx).unwrap();
+
+        let res = execute("SHOW TRANSACTIONS;", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["tnx_id", "start_time"]);
+        let row_of = |tnx_id: u64| rows.iter().find(|row| row[0] == DataItem::Integer(tnx_id as i64));
+        assert!(matches!(row_of(open_tnx), Some(row) if matches!(row[1], DataItem::Integer(start) if start > 0)));
+        assert_eq!(row_of(log_tnx), Some(&vec![DataItem::Integer(log_tnx as i64), DataItem::NullInt]));
+        assert!(row_of(done_tnx).is_none());
+        let ids: Vec<&DataItem> = rows.iter().map(|row| &row[0]).collect();
+        assert!(ids.windows(2).all(|pair| matches!((pair[0], pair[1]), (DataItem::Integer(a), DataItem::Integer(b)) if a < b)));
+        assert!(matches!(execute("SHOW TRANSACTIONS now", conn), Err(RsqlError::ParserError(_))));
+
+        WAL::global().unwrap().commit_tnx(log_tnx).unwrap();
+        execute("ROLLBACK", open_conn).unwrap();
+        execute("DROP TABLE shown_open", conn).unwrap();
+        execute("DROP TABLE shown_done", conn).unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_terminated_connection_finishes_its_statement() {
+        let test_conns = [TestConn::setup(&[]), TestConn::new()];
+        let (conn, marked_conn) = (test_conns[0].id, test_conns[1].id);
+        execute("BEGIN", marked_conn).unwrap();
+        ConnectionUserMap::global().mark_terminated(marked_conn);
+
+        // while a statement of the connection runs it keeps its transaction
+        let running = ExecGuard::new(marked_conn);
+        execute("SHOW TRANSACTIONS", conn).unwrap();
+        assert!(ConnectionUserMap::global().get_username(marked_conn).is_some());
+        assert!(TnxManager::global().get_transaction_id(marked_conn).is_some());
+
+        // once it finished, the next statement of any connection disconnects it
+        drop(running);
+        execute("SHOW TRANSACTIONS", conn).unwrap();
+        assert!(ConnectionUserMap::global().get_username(marked_conn).is_none());
+        assert!(TnxManager::global().get_transaction_id(marked_conn).is_none());
+    }
+}
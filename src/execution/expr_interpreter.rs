@@ -1,19 +1,22 @@
 use crate::common::{RsqlResult, RsqlError};
-use crate::sql::plan::{JoinType};
+use crate::sql::plan::{JoinType, ApplyType, Plan, data_type_to_col_type};
 use crate::common::data_item::{DataItem, VarCharHead};
-use crate::catalog::table_schema::{ColType};
+use crate::catalog::table_schema::{ColType, CheckConstraint, Collation, TableSchema};
+use crate::config::{INDEX_LOOKUP_COST, TRUNCATE_LONG_STRINGS};
 use super::result::{TableObject};
 use sqlparser::ast::{Expr, 
     BinaryOperator, 
-    Value::{Number, SingleQuotedString, Boolean}, 
+    UnaryOperator, 
+    Value::{Number, SingleQuotedString, EscapedStringLiteral, Boolean, Null}, 
     FunctionArguments,
     FunctionArg,
     FunctionArgExpr,
     ObjectName,
     ObjectNamePart,
+    Ident,
 };
 use regex::RegexBuilder;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 // use tracing::info;
 
 fn parse_number(s: &str) -> RsqlResult<DataItem> {
@@ -21,15 +24,47 @@ fn parse_number(s: &str) -> RsqlResult<DataItem> {
     if let Ok(int) = s.parse::<i64>() {
         return Ok(DataItem::Integer(int));
     }
+    // integer literals out of i64 range would lose precision as a float
+    if is_integer_literal(s) {
+        return Err(RsqlError::InvalidInput(format!("Integer literal {} is out of range", s)));
+    }
     
     // 2. try to parse float
     if let Ok(float) = s.parse::<f64>() {
-        return Ok(DataItem::Float(float));
+        return finite_float(s, float);
     }
 
     Err(RsqlError::InvalidInput(format!("Failed to parse number from string: {}", s)))
 }
 
+/// Parse a number literal as the type of the column it is stored into
+fn parse_number_as(s: &str, col_type: &ColType) -> RsqlResult<DataItem> {
+    match col_type {
+        ColType::Integer => match parse_number(s)? {
+            DataItem::Integer(int) => Ok(DataItem::Integer(int)),
+            _ => Err(RsqlError::InvalidInput(format!("Number {} is not an integer", s))),
+        },
+        ColType::Float => match s.parse::<f64>() {
+            Ok(float) => finite_float(s, float),
+            Err(_) => Err(RsqlError::InvalidInput(format!("Failed to parse number from string: {}", s))),
+        },
+        _ => parse_number(s),
+    }
+}
+
+fn is_integer_literal(s: &str) -> bool {
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// NaN and infinities cannot be stored in numeric columns
+fn finite_float(s: &str, float: f64) -> RsqlResult<DataItem> {
+    if !float.is_finite() {
+        return Err(RsqlError::InvalidInput(format!("Number {} is not finite", s)));
+    }
+    Ok(DataItem::Float(float))
+}
+
 fn get_func_arg(args: &FunctionArguments) -> RsqlResult<String> {
     let arg = match args {
         FunctionArguments::List(arg_list) => {
@@ -59,8 +94,187 @@ fn get_func_name(func_obj_name: &ObjectName) -> RsqlResult<String> {
     Ok(name)
 }
 
+/// Whether collecting `col <> literal` through the index of `col` is estimated cheaper than a full scan.
+/// The index reads every row of the other keys at INDEX_LOOKUP_COST each, so it only pays off
+/// when one key holds most rows, or when the table has no primary key to scan
+fn not_eq_index_pays_off(table_obj: &TableObject, col: &str) -> RsqlResult<bool> {
+    if table_obj.pk_col.0.is_empty() {
+        return Ok(true);
+    }
+    // a full scan walks the primary key index already, two ranges of it read more pages
+    if table_obj.pk_col.0 == col {
+        return Ok(false);
+    }
+    let stats = table_obj.table_obj.column_stats(col)?;
+    let row_count = table_obj.table_obj.row_count() as f64;
+    let others = row_count - row_count / stats.distinct.max(1) as f64;
+    Ok(others * INDEX_LOOKUP_COST < row_count)
+}
+
+/// Rows whose indexed column differs from `value`,
+/// collected by scanning the index on both sides of the value
+fn not_eq_by_index(table_obj: &TableObject, col: &str, col_idx: usize, value: &DataItem) -> RsqlResult<Vec<Vec<DataItem>>> {
+    let some_value = Some(value.clone());
+    let mut rows = vec![];
+    let value = collated(table_obj, col, value);
+    for row in table_obj.table_obj.get_rows_by_range_indexed_col(col, &None, &some_value)? {
+        let row = row?;
+        if collated(table_obj, col, &row[col_idx]) != value {
+            rows.push(row);
+        }
+    }
+    for row in table_obj.table_obj.get_rows_by_range_indexed_col(col, &some_value, &None)? {
+        let row = row?;
+        if collated(table_obj, col, &row[col_idx]) != value {
+            rows.push(row);
+        }
+    }
+    Ok(rows)
+}
+
+/// A value of column `col` as it compares under the column collation
+fn collated(table_obj: &TableObject, col: &str, item: &DataItem) -> DataItem {
+    table_obj.table_obj.get_schema().collation_key(col, item)
+}
+
+/// Build the item a string literal is compared with, typed like the column
+fn string_literal_item(col_type: &ColType, s: &str) -> Option<DataItem> {
+    match col_type {
+        ColType::Chars(size) => Some(DataItem::Chars { len: *size as u64, value: s.to_string() }),
+        ColType::VarChar(_) => Some(DataItem::VarChar {
+            head: VarCharHead { max_len: s.len() as u64, len: s.len() as u64, page_ptr: None },
+            value: s.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Rewrite `literal op column` as `column op' literal` so the filter arms only see the column on the left
+fn flip_literal_comparison(predicate: &Expr) -> Option<Expr> {
+    let Expr::BinaryOp { left, op, right } = predicate else {
+        return None;
+    };
+    if !matches!((&**left, &**right), (Expr::Value(_), Expr::Identifier(_))) {
+        return None;
+    }
+    let flipped_op = match op {
+        BinaryOperator::Eq => BinaryOperator::Eq,
+        BinaryOperator::NotEq => BinaryOperator::NotEq,
+        BinaryOperator::Lt => BinaryOperator::Gt,
+        BinaryOperator::LtEq => BinaryOperator::GtEq,
+        BinaryOperator::Gt => BinaryOperator::Lt,
+        BinaryOperator::GtEq => BinaryOperator::LtEq,
+        _ => return None,
+    };
+    Some(Expr::BinaryOp { left: right.clone(), op: flipped_op, right: left.clone() })
+}
+
+/// Make a comparison of a Float column with a number literal match the stored values:
+/// an integer literal is compared as a float, and when `epsilon` is above 0, `=` and `<>`
+/// become BETWEEN and NOT BETWEEN the literal plus or minus `epsilon`.
+/// Strict equality is kept with an `epsilon` of 0, the default of the `float_epsilon` setting
+fn float_literal_comparison(predicate: &Expr, col_type: impl Fn(&str) -> Option<ColType>, epsilon: f64) -> RsqlResult<Option<Expr>> {
+    let Expr::BinaryOp { left, op, right } = predicate else {
+        return Ok(None);
+    };
+    let (Expr::Identifier(ident), Expr::Value(value)) = (&**left, &**right) else {
+        return Ok(None);
+    };
+    let Number(n, _) = &value.value else {
+        return Ok(None);
+    };
+    if !matches!(col_type(&ident.value), Some(ColType::Float)) {
+        return Ok(None);
+    }
+    let DataItem::Float(float) = parse_number_as(n, &ColType::Float)? else {
+        return Ok(None);
+    };
+    // Debug formatting keeps the decimal point and round-trips exactly
+    let literal = |f: f64| Box::new(Expr::Value(Number(format!("{:?}", f), false).with_empty_span()));
+    match op {
+        BinaryOperator::Eq | BinaryOperator::NotEq if epsilon > 0.0 => Ok(Some(Expr::Between {
+            expr: left.clone(),
+            negated: *op == BinaryOperator::NotEq,
+            low: literal(float - epsilon),
+            high: literal(float + epsilon),
+        })),
+        _ if is_integer_literal(n) => Ok(Some(Expr::BinaryOp { left: left.clone(), op: op.clone(), right: literal(float) })),
+        _ => Ok(None),
+    }
+}
+
+/// Whether looking up `col = literal` through the index of a non primary key column is estimated
+/// cheaper than a full scan: the lookup reads row count / distinct keys rows at INDEX_LOOKUP_COST each
+fn index_lookup_pays_off(table_obj: &TableObject, col: &str) -> RsqlResult<bool> {
+    // a full scan walks the primary key index, without one the rows are only reachable through this index
+    if table_obj.pk_col.0.is_empty() {
+        return Ok(true);
+    }
+    let stats = table_obj.table_obj.column_stats(col)?;
+    let row_count = table_obj.table_obj.row_count() as f64;
+    let matched = row_count / stats.distinct.max(1) as f64;
+    Ok(matched * INDEX_LOOKUP_COST < row_count)
+}
+
+/// Value of a BETWEEN bound literal compared with column `col`
+fn between_bound(e: &Expr, col: &str, col_type: &ColType) -> RsqlResult<DataItem> {
+    match e {
+        Expr::Value(v) => match &v.value {
+            Number(n, _) => parse_number(n),
+            Boolean(b) => Ok(DataItem::Bool(*b)),
+            SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                match col_type {
+                    ColType::Chars(size) => Ok(DataItem::Chars{len: *size as u64, value: s.clone()}),
+                    ColType::VarChar(_) => Ok(DataItem::VarChar {
+                        head: VarCharHead {max_len: s.len() as u64, len: s.len() as u64, page_ptr: None},
+                        value: s.clone(),
+                    }),
+                    _ => Err(RsqlError::ExecutionError(format!("Type mismatch for column {} and string literal", col)))
+                }
+            }
+            _ => Err(RsqlError::ExecutionError(format!("Unsupported BETWEEN value: {:?}", e))),
+        },
+        _ => Err(RsqlError::ExecutionError(format!("Unsupported BETWEEN expression: {:?}", e))),
+    }
+}
+
+/// The (column, low, high) of a `col BETWEEN low AND high` predicate on an indexed column,
+/// which can be answered by a range of the column index
+pub fn index_range_bounds(table_obj: &TableObject, predicate: &Expr) -> RsqlResult<Option<(String, DataItem, DataItem)>> {
+    let Expr::Between { expr, negated: false, low, high } = predicate else {
+        return Ok(None);
+    };
+    let Expr::Identifier(ident) = &**expr else {
+        return Ok(None);
+    };
+    let col = ident.value.clone();
+    if !table_obj.indexed_cols.contains(&col) {
+        return Ok(None);
+    }
+    let col_type = &table_obj.cols.1[table_obj.map[&col]];
+    Ok(Some((col.clone(), between_bound(low, &col, col_type)?, between_bound(high, &col, col_type)?)))
+}
+
 pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -> RsqlResult<Vec<Vec<DataItem>>> {
+    if let Some(flipped) = flip_literal_comparison(predicate) {
+        return handle_table_obj_filter_expr(table_obj, &flipped);
+    }
+    let col_type = |col: &str| table_obj.map.get(col).map(|idx| table_obj.cols.1[*idx].clone());
+    if let Some(rewritten) = float_literal_comparison(predicate, col_type, crate::config::get().float_epsilon)? {
+        return handle_table_obj_filter_expr(table_obj, &rewritten);
+    }
+    if let Some(null_safe) = spaceship_as_not_distinct(predicate) {
+        return handle_table_obj_filter_expr(table_obj, &null_safe);
+    }
     match predicate {
+        // a constant condition, `x IN ()` is planned as FALSE and `x NOT IN ()` as TRUE
+        Expr::Value(value) if let Boolean(b) = value.value => {
+            if b {
+                table_obj.table_obj.get_all_rows()?.collect()
+            } else {
+                Ok(vec![])
+            }
+        },
         Expr::BinaryOp { left, op, right } => {
             match op {
                 BinaryOperator::And => {
@@ -99,6 +313,15 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         }else {
                                             Ok(vec![])
                                         }
+                                    }else if table_obj.indexed_cols.contains(&col) && index_lookup_pays_off(table_obj, &col)? {
+                                        let some_bool_value = Some(DataItem::Bool(*b));
+                                        let rows_iter = table_obj.table_obj.get_rows_by_range_indexed_col(&col, &some_bool_value, &some_bool_value)?;
+                                        let mut rows = vec![];
+                                        for row in rows_iter {
+                                            let row = row?;
+                                            rows.push(row);
+                                        }
+                                        Ok(rows)
                                     }else {
                                         let col_idx = table_obj.map.get(&col).unwrap();
                                         let bool_value = DataItem::Bool(*b);
@@ -123,7 +346,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         }else {
                                             Ok(vec![])
                                         }
-                                    }else if table_obj.indexed_cols.contains(&col) {
+                                    }else if table_obj.indexed_cols.contains(&col) && index_lookup_pays_off(table_obj, &col)? {
                                         let number_value = parse_number(n)?;
                                         let some_number_value = Some(number_value.clone());
                                         let rows_iter = table_obj.table_obj.get_rows_by_range_indexed_col(&col, &some_number_value, &some_number_value)?;
@@ -147,7 +370,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         Ok(rows)
                                     }
                                 },
-                                SingleQuotedString(s) => {
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
                                     let col = ident.value.clone();
                                     let col_idx = table_obj.map.get(&col).unwrap();
                                     let col_type = table_obj.cols.1[*col_idx].clone();
@@ -167,7 +390,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         }else {
                                             Ok(vec![])
                                         }
-                                    }else if table_obj.indexed_cols.contains(&col) {
+                                    }else if table_obj.indexed_cols.contains(&col) && index_lookup_pays_off(table_obj, &col)? {
                                         let rows_iter = table_obj.table_obj.get_rows_by_range_indexed_col(&col, &some_string_value, &some_string_value)?;
                                         let mut rows = vec![];
                                         for row in rows_iter {
@@ -180,7 +403,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         let mut rows = vec![];
                                         for row in rows_iter {
                                             let row = row?;
-                                            if row[*col_idx] == string_value {
+                                            if collated(table_obj, &ident.value, &row[*col_idx]) == collated(table_obj, &ident.value, &string_value) {
                                                 rows.push(row);
                                             }
                                         }
@@ -201,7 +424,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                             let mut rows = vec![];
                             for row in rows_iter {
                                 let row = row?;
-                                if row[*left_col_idx] == row[*right_col_idx] {
+                                if !is_null_item(&row[*left_col_idx]) && !is_null_item(&row[*right_col_idx]) && row[*left_col_idx] == row[*right_col_idx] {
                                     rows.push(row);
                                 }
                             }
@@ -234,17 +457,21 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                     let col = ident.value.clone();
                                     let col_idx = table_obj.map.get(&col).unwrap();
                                     let number_value = parse_number(n)?;
-                                    let rows_iter = table_obj.table_obj.get_all_rows()?;
-                                    let mut rows = vec![];
-                                    for row in rows_iter {
-                                        let row = row?;
-                                        if row[*col_idx] != number_value {
-                                            rows.push(row);
+                                    if table_obj.indexed_cols.contains(&col) && not_eq_index_pays_off(table_obj, &col)? {
+                                        not_eq_by_index(table_obj, &col, *col_idx, &number_value)
+                                    }else {
+                                        let rows_iter = table_obj.table_obj.get_all_rows()?;
+                                        let mut rows = vec![];
+                                        for row in rows_iter {
+                                            let row = row?;
+                                            if row[*col_idx] != number_value {
+                                                rows.push(row);
+                                            }
                                         }
+                                        Ok(rows)
                                     }
-                                    Ok(rows)
                                 },
-                                SingleQuotedString(s) => {
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
                                     let col = ident.value.clone();
                                     let col_idx = table_obj.map.get(&col).unwrap();
                                     let col_type = table_obj.cols.1[*col_idx].clone();
@@ -254,17 +481,21 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                             head: VarCharHead {max_len: s.len() as u64, len: s.len() as u64, page_ptr: None},
                                             value: s.clone(),
                                         },
-                                        _ => return Err(RsqlError::ExecutionError(format!("Unsupported char type on table {col}")))
+                                        _ => return Err(RsqlError::ExecutionError(format!("Unsupported char type on column {col}")))
                                     };
-                                    let rows_iter = table_obj.table_obj.get_all_rows()?;
-                                    let mut rows = vec![];
-                                    for row in rows_iter {
-                                        let row = row?;
-                                        if row[*col_idx] != string_value {
-                                            rows.push(row);
+                                    if table_obj.indexed_cols.contains(&col) && not_eq_index_pays_off(table_obj, &col)? {
+                                        not_eq_by_index(table_obj, &col, *col_idx, &string_value)
+                                    }else {
+                                        let rows_iter = table_obj.table_obj.get_all_rows()?;
+                                        let mut rows = vec![];
+                                        for row in rows_iter {
+                                            let row = row?;
+                                            if collated(table_obj, &ident.value, &row[*col_idx]) != collated(table_obj, &ident.value, &string_value) {
+                                                rows.push(row);
+                                            }
                                         }
+                                        Ok(rows)
                                     }
-                                    Ok(rows)
                                 },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
@@ -280,7 +511,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                             let mut rows = vec![];
                             for row in rows_iter {
                                 let row = row?;
-                                if row[*left_col_idx] != row[*right_col_idx] {
+                                if !is_null_item(&row[*left_col_idx]) && !is_null_item(&row[*right_col_idx]) && row[*left_col_idx] != row[*right_col_idx] {
                                     rows.push(row);
                                 }
                             }
@@ -321,6 +552,20 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         Ok(rows)
                                     }
                                 },
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                                    let col_idx = *table_obj.map.get(&ident.value).unwrap();
+                                    let string_value = string_literal_item(&table_obj.cols.1[col_idx], s).ok_or(
+                                        RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate))
+                                    )?;
+                                    let mut rows = vec![];
+                                    for row in table_obj.table_obj.get_all_rows()? {
+                                        let row = row?;
+                                        if collated(table_obj, &ident.value, &row[col_idx]) <= collated(table_obj, &ident.value, &string_value) {
+                                            rows.push(row);
+                                        }
+                                    }
+                                    Ok(rows)
+                                },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
                                 }
@@ -335,7 +580,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                             let mut rows = vec![];
                             for row in rows_iter {
                                 let row = row?;
-                                if row[*left_col_idx] <= row[*right_col_idx] {
+                                if !is_null_item(&row[*left_col_idx]) && !is_null_item(&row[*right_col_idx]) && row[*left_col_idx] <= row[*right_col_idx] {
                                     rows.push(row);
                                 }
                             }
@@ -376,6 +621,20 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         Ok(rows)
                                     }
                                 },
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                                    let col_idx = *table_obj.map.get(&ident.value).unwrap();
+                                    let string_value = string_literal_item(&table_obj.cols.1[col_idx], s).ok_or(
+                                        RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate))
+                                    )?;
+                                    let mut rows = vec![];
+                                    for row in table_obj.table_obj.get_all_rows()? {
+                                        let row = row?;
+                                        if collated(table_obj, &ident.value, &row[col_idx]) >= collated(table_obj, &ident.value, &string_value) {
+                                            rows.push(row);
+                                        }
+                                    }
+                                    Ok(rows)
+                                },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
                                 }
@@ -390,7 +649,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                             let mut rows = vec![];
                             for row in rows_iter {
                                 let row = row?;
-                                if row[*left_col_idx] >= row[*right_col_idx] {
+                                if !is_null_item(&row[*left_col_idx]) && !is_null_item(&row[*right_col_idx]) && row[*left_col_idx] >= row[*right_col_idx] {
                                     rows.push(row);
                                 }
                             }
@@ -433,6 +692,20 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         Ok(rows)
                                     }
                                 },
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                                    let col_idx = *table_obj.map.get(&ident.value).unwrap();
+                                    let string_value = string_literal_item(&table_obj.cols.1[col_idx], s).ok_or(
+                                        RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate))
+                                    )?;
+                                    let mut rows = vec![];
+                                    for row in table_obj.table_obj.get_all_rows()? {
+                                        let row = row?;
+                                        if collated(table_obj, &ident.value, &row[col_idx]) < collated(table_obj, &ident.value, &string_value) {
+                                            rows.push(row);
+                                        }
+                                    }
+                                    Ok(rows)
+                                },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
                                 }
@@ -447,7 +720,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                             let mut rows = vec![];
                             for row in rows_iter {
                                 let row = row?;
-                                if row[*left_col_idx] < row[*right_col_idx] {
+                                if !is_null_item(&row[*left_col_idx]) && !is_null_item(&row[*right_col_idx]) && row[*left_col_idx] < row[*right_col_idx] {
                                     rows.push(row);
                                 }
                             }
@@ -490,6 +763,20 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                                         Ok(rows)
                                     }
                                 },
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                                    let col_idx = *table_obj.map.get(&ident.value).unwrap();
+                                    let string_value = string_literal_item(&table_obj.cols.1[col_idx], s).ok_or(
+                                        RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate))
+                                    )?;
+                                    let mut rows = vec![];
+                                    for row in table_obj.table_obj.get_all_rows()? {
+                                        let row = row?;
+                                        if collated(table_obj, &ident.value, &row[col_idx]) > collated(table_obj, &ident.value, &string_value) {
+                                            rows.push(row);
+                                        }
+                                    }
+                                    Ok(rows)
+                                },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
                                 }
@@ -504,7 +791,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                             let mut rows = vec![];
                             for row in rows_iter {
                                 let row = row?;
-                                if row[*left_col_idx] > row[*right_col_idx] {
+                                if !is_null_item(&row[*left_col_idx]) && !is_null_item(&row[*right_col_idx]) && row[*left_col_idx] > row[*right_col_idx] {
                                     rows.push(row);
                                 }
                             }
@@ -523,7 +810,7 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
         Expr::Like { negated, expr, pattern, .. } | Expr::ILike { negated, expr, pattern, .. } => {
             let case_insensitive = matches!(predicate, Expr::ILike { .. });
             if let (Expr::Identifier(ident), Expr::Value(value)) = (&**expr, &**pattern) {
-                if let SingleQuotedString(s_pattern) = &value.value {
+                if let SingleQuotedString(s_pattern) | EscapedStringLiteral(s_pattern) = &value.value {
                     let col = ident.value.clone();
                     let col_idx = table_obj.map.get(&col).ok_or_else(|| RsqlError::ExecutionError(format!("Column {} not found", col)))?;
                     
@@ -557,34 +844,26 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
                 Err(RsqlError::ExecutionError(format!("Unsupported LIKE expression format. Expected: column LIKE 'pattern'")))
             }
         },
+        Expr::Nested(inner) => handle_table_obj_filter_expr(table_obj, inner),
+        Expr::IsDistinctFrom(left, right) | Expr::IsNotDistinctFrom(left, right) => {
+            let distinct = matches!(predicate, Expr::IsDistinctFrom(..));
+            let mut rows = vec![];
+            for row in table_obj.table_obj.get_all_rows()? {
+                let row = row?;
+                if eval_null_safe_eq(left, right, &table_obj.cols, &row)? != distinct {
+                    rows.push(row);
+                }
+            }
+            Ok(rows)
+        },
         Expr::Between { expr, negated, low, high } => {
             if let Expr::Identifier(ident) = &**expr {
                 let col = ident.value.clone();
                 let col_idx = table_obj.map.get(&col).ok_or_else(|| RsqlError::ExecutionError(format!("Column {} not found", col)))?;
                 let col_type = table_obj.cols.1[*col_idx].clone();
 
-                let get_value = |e: &Expr| -> RsqlResult<DataItem> {
-                    match e {
-                        Expr::Value(v) => match &v.value {
-                            Number(n, _) => parse_number(n),
-                            SingleQuotedString(s) => {
-                                match col_type {
-                                    ColType::Chars(size) => Ok(DataItem::Chars{len: size as u64, value: s.clone()}),
-                                    ColType::VarChar(_) => Ok(DataItem::VarChar {
-                                        head: VarCharHead {max_len: s.len() as u64, len: s.len() as u64, page_ptr: None},
-                                        value: s.clone(),
-                                    }),
-                                    _ => Err(RsqlError::ExecutionError(format!("Type mismatch for column {} and string literal", col)))
-                                }
-                            }
-                            _ => Err(RsqlError::ExecutionError(format!("Unsupported BETWEEN value: {:?}", e))),
-                        },
-                        _ => Err(RsqlError::ExecutionError(format!("Unsupported BETWEEN expression: {:?}", e))),
-                    }
-                };
-
-                let low_val = get_value(low)?;
-                let high_val = get_value(high)?;
+                let low_val = between_bound(low, &col, &col_type)?;
+                let high_val = between_bound(high, &col, &col_type)?;
 
                 if table_obj.indexed_cols.contains(&col) && !*negated {
                     let some_low_val = Some(low_val);
@@ -619,7 +898,18 @@ pub fn handle_table_obj_filter_expr(table_obj: &TableObject, predicate: &Expr) -
 }
 
 pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType>, rows: &Vec<Vec<DataItem>>, predicate: &Expr) -> RsqlResult<Vec<Vec<DataItem>>> {
+    if let Some(flipped) = flip_literal_comparison(predicate) {
+        return handle_temp_table_filter_expr(cols, cols_type, rows, &flipped);
+    }
+    let col_type = |col: &str| cols.iter().position(|c| c == col).map(|idx| cols_type[idx].clone());
+    if let Some(rewritten) = float_literal_comparison(predicate, col_type, crate::config::get().float_epsilon)? {
+        return handle_temp_table_filter_expr(cols, cols_type, rows, &rewritten);
+    }
+    if let Some(null_safe) = spaceship_as_not_distinct(predicate) {
+        return handle_temp_table_filter_expr(cols, cols_type, rows, &null_safe);
+    }
     match predicate {
+        Expr::Value(value) if let Boolean(b) = value.value => Ok(if b { rows.clone() } else { vec![] }),
         Expr::BinaryOp { left, op, right } => {
             match op {
                 BinaryOperator::And => {
@@ -670,7 +960,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                                     }
                                     Ok(filtered_rows)
                                 },
-                                SingleQuotedString(s) => {
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
                                     let col_type = cols_type[col_idx].clone();
                                     let string_value = match col_type {
                                         ColType::Chars(size) => DataItem::Chars{len: size as u64, value: s.clone()},
@@ -702,7 +992,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                             let right_col_idx = cols.iter().position(|col| col == &right_col).unwrap();
                             let mut filtered_rows = vec![];
                             for row in rows.iter() {
-                                if row[left_col_idx] == row[right_col_idx] {
+                                if !is_null_item(&row[left_col_idx]) && !is_null_item(&row[right_col_idx]) && row[left_col_idx] == row[right_col_idx] {
                                     filtered_rows.push(row.clone());
                                 }
                             }
@@ -739,7 +1029,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                                     }
                                     Ok(filtered_rows)
                                 },
-                                SingleQuotedString(s) => {
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
                                     let col_type = cols_type[col_idx].clone();
                                     let string_value = match col_type {
                                         ColType::Chars(size) => DataItem::Chars{len: size as u64, value: s.clone()},
@@ -771,7 +1061,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                             let right_col_idx = cols.iter().position(|col| col == &right_col).unwrap();
                             let mut filtered_rows = vec![];
                             for row in rows.iter() {
-                                if row[left_col_idx] != row[right_col_idx] {
+                                if !is_null_item(&row[left_col_idx]) && !is_null_item(&row[right_col_idx]) && row[left_col_idx] != row[right_col_idx] {
                                     filtered_rows.push(row.clone());
                                 }
                             }
@@ -798,6 +1088,19 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                                     }
                                     Ok(filtered_rows)
                                 },
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                                    let col_idx = cols.iter().position(|c| c == &ident.value).unwrap();
+                                    let string_value = string_literal_item(&cols_type[col_idx], s).ok_or(
+                                        RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate))
+                                    )?;
+                                    let mut filtered_rows = vec![];
+                                    for row in rows.iter() {
+                                        if row[col_idx] <= string_value {
+                                            filtered_rows.push(row.clone());
+                                        }
+                                    }
+                                    Ok(filtered_rows)
+                                },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
                                 }
@@ -810,7 +1113,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                             let right_col_idx = cols.iter().position(|col| col == &right_col).unwrap();
                             let mut filtered_rows = vec![];
                             for row in rows.iter() {
-                                if row[left_col_idx] <= row[right_col_idx] {
+                                if !is_null_item(&row[left_col_idx]) && !is_null_item(&row[right_col_idx]) && row[left_col_idx] <= row[right_col_idx] {
                                     filtered_rows.push(row.clone());
                                 }
                             }
@@ -837,6 +1140,19 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                                     }
                                     Ok(filtered_rows)
                                 },
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                                    let col_idx = cols.iter().position(|c| c == &ident.value).unwrap();
+                                    let string_value = string_literal_item(&cols_type[col_idx], s).ok_or(
+                                        RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate))
+                                    )?;
+                                    let mut filtered_rows = vec![];
+                                    for row in rows.iter() {
+                                        if row[col_idx] >= string_value {
+                                            filtered_rows.push(row.clone());
+                                        }
+                                    }
+                                    Ok(filtered_rows)
+                                },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
                                 }
@@ -849,7 +1165,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                             let right_col_idx = cols.iter().position(|col| col == &right_col).unwrap();
                             let mut filtered_rows = vec![];
                             for row in rows.iter() {
-                                if row[left_col_idx] >= row[right_col_idx] {
+                                if !is_null_item(&row[left_col_idx]) && !is_null_item(&row[right_col_idx]) && row[left_col_idx] >= row[right_col_idx] {
                                     filtered_rows.push(row.clone());
                                 }
                             }
@@ -876,6 +1192,19 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                                     }
                                     Ok(filtered_rows)
                                 },
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                                    let col_idx = cols.iter().position(|c| c == &ident.value).unwrap();
+                                    let string_value = string_literal_item(&cols_type[col_idx], s).ok_or(
+                                        RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate))
+                                    )?;
+                                    let mut filtered_rows = vec![];
+                                    for row in rows.iter() {
+                                        if row[col_idx] < string_value {
+                                            filtered_rows.push(row.clone());
+                                        }
+                                    }
+                                    Ok(filtered_rows)
+                                },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
                                 }
@@ -888,7 +1217,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                             let right_col_idx = cols.iter().position(|col| col == &right_col).unwrap();
                             let mut filtered_rows = vec![];
                             for row in rows.iter() {
-                                if row[left_col_idx] < row[right_col_idx] {
+                                if !is_null_item(&row[left_col_idx]) && !is_null_item(&row[right_col_idx]) && row[left_col_idx] < row[right_col_idx] {
                                     filtered_rows.push(row.clone());
                                 }
                             }
@@ -915,6 +1244,19 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                                     }
                                     Ok(filtered_rows)
                                 },
+                                SingleQuotedString(s) | EscapedStringLiteral(s) => {
+                                    let col_idx = cols.iter().position(|c| c == &ident.value).unwrap();
+                                    let string_value = string_literal_item(&cols_type[col_idx], s).ok_or(
+                                        RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate))
+                                    )?;
+                                    let mut filtered_rows = vec![];
+                                    for row in rows.iter() {
+                                        if row[col_idx] > string_value {
+                                            filtered_rows.push(row.clone());
+                                        }
+                                    }
+                                    Ok(filtered_rows)
+                                },
                                 _ => {
                                     Err(RsqlError::ExecutionError(format!("Unsupported filter expression: {:?}", predicate)))
                                 }
@@ -927,7 +1269,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                             let right_col_idx = cols.iter().position(|col| col == &right_col).unwrap();
                             let mut filtered_rows = vec![];
                             for row in rows.iter() {
-                                if row[left_col_idx] > row[right_col_idx] {
+                                if !is_null_item(&row[left_col_idx]) && !is_null_item(&row[right_col_idx]) && row[left_col_idx] > row[right_col_idx] {
                                     filtered_rows.push(row.clone());
                                 }
                             }
@@ -946,7 +1288,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
         Expr::Like { negated, expr, pattern, .. } | Expr::ILike { negated, expr, pattern, .. } => {
             let case_insensitive = matches!(predicate, Expr::ILike { .. });
             if let (Expr::Identifier(ident), Expr::Value(value)) = (&**expr, &**pattern) {
-                if let SingleQuotedString(s_pattern) = &value.value {
+                if let SingleQuotedString(s_pattern) | EscapedStringLiteral(s_pattern) = &value.value {
                     let col = ident.value.clone();
                     let col_idx = cols.iter().position(|c| c == &col).ok_or_else(|| RsqlError::ExecutionError(format!("Column {} not found", col)))?;
                     
@@ -978,6 +1320,18 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                 Err(RsqlError::ExecutionError(format!("Unsupported LIKE expression format. Expected: column LIKE 'pattern'")))
             }
         },
+        Expr::Nested(inner) => handle_temp_table_filter_expr(cols, cols_type, rows, inner),
+        Expr::IsDistinctFrom(left, right) | Expr::IsNotDistinctFrom(left, right) => {
+            let distinct = matches!(predicate, Expr::IsDistinctFrom(..));
+            let typed_cols = (cols.clone(), cols_type.clone());
+            let mut filtered_rows = vec![];
+            for row in rows.iter() {
+                if eval_null_safe_eq(left, right, &typed_cols, row)? != distinct {
+                    filtered_rows.push(row.clone());
+                }
+            }
+            Ok(filtered_rows)
+        },
         Expr::Between { expr, negated, low, high } => {
             if let Expr::Identifier(ident) = &**expr {
                 let col = ident.value.clone();
@@ -988,7 +1342,7 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
                     match e {
                         Expr::Value(v) => match &v.value {
                             Number(n, _) => parse_number(n),
-                            SingleQuotedString(s) => {
+                            SingleQuotedString(s) | EscapedStringLiteral(s) => {
                                 match col_type {
                                     ColType::Chars(size) => Ok(DataItem::Chars{len: size as u64, value: s.clone()}),
                                     ColType::VarChar(_) => Ok(DataItem::VarChar {
@@ -1026,6 +1380,19 @@ pub fn handle_temp_table_filter_expr(cols: &Vec<String>, cols_type: &Vec<ColType
     }
 }
 
+/// Index of a `table.col` reference in joined columns: the qualified name when the column
+/// was renamed to avoid a clash, otherwise the first (or, for the right side of a join, last) `col`
+fn qualified_col_idx(extended_cols: &[String], idents: &[Ident], right_side: bool) -> RsqlResult<usize> {
+    let qualified = idents.iter().map(|i| i.value.clone()).collect::<Vec<_>>().join(".");
+    let col = &idents[idents.len() - 1].value;
+    let col_idx = match extended_cols.iter().position(|c| c == &qualified) {
+        Some(col_idx) => Some(col_idx),
+        None if right_side => extended_cols.iter().rposition(|c| c == col),
+        None => extended_cols.iter().position(|c| c == col),
+    };
+    col_idx.ok_or(RsqlError::ExecutionError(format!("column {} is not found", qualified)))
+}
+
 pub fn handle_on_expr(left_null_row: &Vec<DataItem>, right_null_row: &Vec<DataItem>, extended_cols: &Vec<String>, extended_rows: &Vec<Vec<DataItem>>, join_type: &JoinType, on: &Option<Expr>) -> RsqlResult<Vec<Vec<DataItem>>> {
     match join_type {
         JoinType::Inner => {
@@ -1036,10 +1403,8 @@ pub fn handle_on_expr(left_null_row: &Vec<DataItem>, right_null_row: &Vec<DataIt
                             BinaryOperator::Eq => {
                                 match (&**left, &**right) {
                                     (Expr::CompoundIdentifier(left_ident), Expr::CompoundIdentifier(right_ident)) => {
-                                        let left_col = left_ident[1].value.clone();
-                                        let left_col_idx = extended_cols.iter().position(|col| col == &left_col).unwrap();
-                                        let right_col = right_ident[1].value.clone();
-                                        let right_col_idx = extended_cols.iter().rposition(|col| col == &right_col).unwrap();
+                                        let left_col_idx = qualified_col_idx(extended_cols, left_ident, false)?;
+                                        let right_col_idx = qualified_col_idx(extended_cols, right_ident, true)?;
                                         let mut filtered_rows = vec![];
                                         for row in extended_rows.iter() {
                                             if row[left_col_idx] == row[right_col_idx] {
@@ -1074,10 +1439,8 @@ pub fn handle_on_expr(left_null_row: &Vec<DataItem>, right_null_row: &Vec<DataIt
                             BinaryOperator::Eq => {
                                 match (&**left, &**right) {
                                     (Expr::CompoundIdentifier(left_ident), Expr::CompoundIdentifier(right_ident)) => {
-                                        let left_col = left_ident[1].value.clone();
-                                        let left_col_idx = extended_cols.iter().position(|col| col == &left_col).unwrap();
-                                        let right_col = right_ident[1].value.clone();
-                                        let right_col_idx = extended_cols.iter().rposition(|col| col == &right_col).unwrap();
+                                        let left_col_idx = qualified_col_idx(extended_cols, left_ident, false)?;
+                                        let right_col_idx = qualified_col_idx(extended_cols, right_ident, true)?;
                                         let mut filtered_rows = vec![];
                                         let mut is_left_matched = HashSet::new();
                                         for row in extended_rows.iter() {
@@ -1124,10 +1487,8 @@ pub fn handle_on_expr(left_null_row: &Vec<DataItem>, right_null_row: &Vec<DataIt
                             BinaryOperator::Eq => {
                                 match (&**left, &**right) {
                                     (Expr::CompoundIdentifier(left_ident), Expr::CompoundIdentifier(right_ident)) => {
-                                        let left_col = left_ident[1].value.clone();
-                                        let left_col_idx = extended_cols.iter().position(|col| col == &left_col).unwrap();
-                                        let right_col = right_ident[1].value.clone();
-                                        let right_col_idx = extended_cols.iter().rposition(|col| col == &right_col).unwrap();
+                                        let left_col_idx = qualified_col_idx(extended_cols, left_ident, false)?;
+                                        let right_col_idx = qualified_col_idx(extended_cols, right_ident, true)?;
                                         let mut filtered_rows = vec![];
                                         let mut is_right_matched = HashSet::new();
                                         for row in extended_rows.iter() {
@@ -1175,10 +1536,8 @@ pub fn handle_on_expr(left_null_row: &Vec<DataItem>, right_null_row: &Vec<DataIt
                             BinaryOperator::Eq => {
                                 match (&**left, &**right) {
                                     (Expr::CompoundIdentifier(left_ident), Expr::CompoundIdentifier(right_ident)) => {
-                                        let left_col = left_ident[1].value.clone();
-                                        let left_col_idx = extended_cols.iter().position(|col| col == &left_col).unwrap();
-                                        let right_col = right_ident[1].value.clone();
-                                        let right_col_idx = extended_cols.iter().rposition(|col| col == &right_col).unwrap();
+                                        let left_col_idx = qualified_col_idx(extended_cols, left_ident, false)?;
+                                        let right_col_idx = qualified_col_idx(extended_cols, right_ident, true)?;
                                         let mut filtered_rows = vec![];
                                         let mut is_left_matched = HashSet::new();
                                         let mut is_right_matched = HashSet::new();
@@ -1236,87 +1595,327 @@ pub fn handle_on_expr(left_null_row: &Vec<DataItem>, right_null_row: &Vec<DataIt
     }
 }
 
-pub fn handle_insert_expr(table_object: &TableObject, cols: &Vec<String>, null_cols: &Vec<DataItem>, values: &Vec<Expr>) -> RsqlResult<Vec<DataItem>> {
-    let mut data_item = null_cols.clone();
-    for (idx, expr) in values.iter().enumerate() {
+pub fn handle_insert_expr(table_object: &TableObject, cols: &[String], default_row: &[DataItem], values: &[Expr]) -> RsqlResult<Vec<DataItem>> {
+    if values.len() > cols.len() {
+        return Err(RsqlError::InvalidInput(format!("INSERT has {} values but only {} columns", values.len(), cols.len())));
+    }
+    let mut data_item = default_row.to_vec();
+    for (col, expr) in cols.iter().zip(values) {
+        let col_idx = *table_object.map.get(col)
+            .ok_or(RsqlError::InvalidInput(format!("Column {} not found", col)))?;
+        let (Some(col_type), Some(item)) = (table_object.cols.1.get(col_idx), data_item.get_mut(col_idx)) else {
+            return Err(RsqlError::InvalidInput(format!("Column {} is not in the row being inserted", col)));
+        };
         match expr {
-            Expr::Value(value) => {
-                match &value.value {
-                    Boolean(b) => {
-                        let col_idx = table_object.map.get(&cols[idx]).unwrap();
-                        data_item[*col_idx] = DataItem::Bool(*b);
-                    },
-                    Number(n, _) => {
-                        let number_value = parse_number(n)?;
-                        let col_idx = table_object.map.get(&cols[idx]).unwrap();
-                        data_item[*col_idx] = number_value;
-                    },
-                    SingleQuotedString(s) => {
-                        let col_idx = table_object.map.get(&cols[idx]).unwrap();
-                        let col_type = table_object.cols.1[*col_idx].clone();
-                        match col_type {
-                            ColType::Chars(size) => {
-                                data_item[*col_idx] = DataItem::Chars{len: size as u64, value: s.clone()};
-                            },
-                            ColType::VarChar(_) => {
-                                data_item[*col_idx] = DataItem::VarChar {
-                                    head: VarCharHead {max_len: s.len() as u64, len: s.len() as u64, page_ptr: None},
-                                    value: s.clone(),
-                                }
-                            },
-                            _ => {
-                                return Err(RsqlError::ExecutionError(format!("Unsupported insert value type: {:?}", value.value)))
-                            },
-                        }
-                    },
-                    _ => {
-                        return Err(RsqlError::ExecutionError(format!("Unsupported insert value type: {:?}", value.value)))
+            // DEFAULT keeps the value the row starts with
+            Expr::Identifier(ident) if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("default") => {},
+            _ => *item = literal_item(expr, col_type, col)?,
+        }
+    }
+    Ok(data_item)
+}
+
+/// The value a constant of INSERT ... VALUES or a column DEFAULT gives a column
+fn literal_item(expr: &Expr, col_type: &ColType, col_name: &str) -> RsqlResult<DataItem> {
+    if is_current_timestamp(expr) {
+        return match col_type {
+            ColType::Integer => Ok(DataItem::Integer(current_timestamp())),
+            _ => Err(RsqlError::ExecutionError(format!("CURRENT_TIMESTAMP can only fill a TIMESTAMP or INTEGER column, not {}", col_name))),
+        };
+    }
+    // a stored DEFAULT of -2 reads back as a sign applied to 2
+    let folded = Plan::fold_signed_number(expr);
+    let Expr::Value(value) = folded.as_ref().unwrap_or(expr) else {
+        return Err(RsqlError::ExecutionError("Insert value must be a constant expression".to_string()));
+    };
+    match &value.value {
+        Boolean(b) => Ok(DataItem::Bool(*b)),
+        Number(n, _) => parse_number_as(n, col_type),
+        SingleQuotedString(s) | EscapedStringLiteral(s) => match col_type {
+            ColType::Chars(_) | ColType::VarChar(_) => column_string_item(s, col_type, col_name),
+            _ => Err(RsqlError::ExecutionError(format!("Unsupported insert value type: {:?}", value.value))),
+        },
+        Null => Ok(null_of_type(col_type)),
+        _ => Err(RsqlError::ExecutionError(format!("Unsupported insert value type: {:?}", value.value))),
+    }
+}
+
+/// CURRENT_TIMESTAMP or NOW(), evaluated by literal_item at insert time
+fn is_current_timestamp(expr: &Expr) -> bool {
+    let Expr::Function(func) = expr else {
+        return false;
+    };
+    matches!(get_func_name(&func.name).as_deref(), Ok("CURRENT_TIMESTAMP") | Ok("NOW"))
+}
+
+/// Seconds since the Unix epoch, the value a TIMESTAMP column stores
+fn current_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// The row an insert starts from, each column holds its DEFAULT or null
+pub fn default_row(schema: &TableSchema) -> RsqlResult<Vec<DataItem>> {
+    schema.get_columns().iter()
+        .filter(|col| !col.is_dropped)
+        .map(|col| match schema.get_default(&col.name) {
+            Some(expr) => literal_item(expr, &col.data_type, &col.name),
+            None => Ok(null_of_type(&col.data_type)),
+        })
+        .collect()
+}
+
+/// The rows of a VALUES list with the types inferred from its constants: numbers are INTEGER
+/// unless one has a fraction, strings are VARCHAR as long as the longest one, a column of NULL is INTEGER
+pub fn handle_values(columns: &[String], rows: &[Vec<Expr>]) -> RsqlResult<ProjectionResult> {
+    let mut col_types: Vec<Option<ColType>> = vec![None; columns.len()];
+    for row in rows {
+        for ((expr, col_type), col) in row.iter().zip(col_types.iter_mut()).zip(columns) {
+            let folded = Plan::fold_signed_number(expr);
+            let value_type = match folded.as_ref().unwrap_or(expr) {
+                Expr::Value(value) => match &value.value {
+                    Number(n, _) => match parse_number(n)? {
+                        DataItem::Integer(_) => ColType::Integer,
+                        _ => ColType::Float,
                     },
+                    SingleQuotedString(s) | EscapedStringLiteral(s) => ColType::VarChar(s.len().max(1)),
+                    Boolean(_) => ColType::Bool,
+                    Null => continue,
+                    other => return Err(RsqlError::ExecutionError(format!("Unsupported VALUES item: {}", other))),
+                },
+                other => return Err(RsqlError::ExecutionError(format!("VALUES items must be constants, got {}", other))),
+            };
+            *col_type = Some(match (col_type.take(), value_type) {
+                (None, t) => t,
+                (Some(ColType::VarChar(a)), ColType::VarChar(b)) => ColType::VarChar(a.max(b)),
+                (Some(ColType::Integer), ColType::Integer) => ColType::Integer,
+                (Some(ColType::Integer | ColType::Float), ColType::Integer | ColType::Float) => ColType::Float,
+                (Some(ColType::Bool), ColType::Bool) => ColType::Bool,
+                (Some(a), b) => return Err(RsqlError::ExecutionError(format!(
+                    "VALUES column {} mixes {:?} and {:?} values", col, a, b
+                ))),
+            });
+        }
+    }
+    let col_types: Vec<ColType> = col_types.into_iter().map(|t| t.unwrap_or(ColType::Integer)).collect();
+    let rows = rows.iter()
+        .map(|row| row.iter().zip(col_types.iter().zip(columns))
+            .map(|(expr, (col_type, col))| literal_item(expr, col_type, col))
+            .collect::<RsqlResult<Vec<_>>>())
+        .collect::<RsqlResult<Vec<_>>>()?;
+    Ok(((columns.to_vec(), col_types), rows))
+}
+
+/// Hash key of a value matched against IN subquery results, None for NULL
+/// Strings are keyed by their value only, so chars and varchar values match
+fn subquery_key(item: &DataItem) -> Option<DataItem> {
+    match item {
+        DataItem::NullInt | DataItem::NullFloat | DataItem::NullChars { .. } | DataItem::NullVarChar | DataItem::NullBool => None,
+        DataItem::Chars { value, .. } | DataItem::VarChar { value, .. } => Some(DataItem::Chars { len: value.len() as u64, value: value.clone() }),
+        other => Some(other.clone()),
+    }
+}
+
+/// Filter rows by an [NOT] IN / [NOT] EXISTS subquery apply
+/// The subquery result is hashed once instead of being checked again for every row
+pub fn handle_subquery_apply(
+    cols: &[String],
+    rows: Vec<Vec<DataItem>>,
+    apply_type: &ApplyType,
+    sub_cols: &[String],
+    sub_rows: &[Vec<DataItem>],
+) -> RsqlResult<Vec<Vec<DataItem>>> {
+    match apply_type {
+        ApplyType::Exists { negated } => {
+            if sub_rows.is_empty() == *negated {
+                Ok(rows)
+            } else {
+                Ok(vec![])
+            }
+        },
+        ApplyType::In { expr, negated } => {
+            // (a, b) IN (SELECT x, y ...) would need row keys, comparing only the first column is wrong
+            if matches!(&**expr, Expr::Tuple(_)) {
+                return Err(RsqlError::ExecutionError("row-valued IN not supported".to_string()));
+            }
+            if sub_cols.len() != 1 {
+                return Err(RsqlError::ExecutionError(format!("IN subquery must return exactly one column, got {}", sub_cols.len())));
+            }
+            let col = match &**expr {
+                Expr::Identifier(ident) => ident.value.clone(),
+                Expr::CompoundIdentifier(idents) => idents.last().map(|i| i.value.clone()).unwrap_or_default(),
+                _ => return Err(RsqlError::ExecutionError(format!("IN subquery left side must be a column, got {}", expr))),
+            };
+            let col_idx = cols.iter().position(|c| c == &col)
+                .ok_or_else(|| RsqlError::ExecutionError(format!("Column {} not found", col)))?;
+            let mut keys = HashSet::new();
+            let mut has_null = false;
+            for sub_row in sub_rows {
+                match subquery_key(&sub_row[0]) {
+                    Some(key) => { keys.insert(key); },
+                    None => has_null = true,
                 }
-            },
-            _ => {
-                return Err(RsqlError::ExecutionError(format!("Insert value must be a constant expression")))
             }
+            if *negated && sub_rows.is_empty() {
+                // NOT IN an empty set holds for every row, even NULL ones
+                return Ok(rows);
+            }
+            if *negated && has_null {
+                // x NOT IN (..., NULL) is never true
+                return Ok(vec![]);
+            }
+            Ok(rows.into_iter().filter(|row| {
+                match subquery_key(&row[col_idx]) {
+                    Some(key) => keys.contains(&key) != *negated,
+                    None => false,
+                }
+            }).collect())
+        },
+        ApplyType::Scalar => Err(RsqlError::ExecutionError("Scalar subqueries are not supported".to_string())),
+    }
+}
+
+/// Build the item a string is stored as in a CHAR(n)/VARCHAR(n) column.
+/// A string longer than n bytes is rejected, or truncated when TRUNCATE_LONG_STRINGS is set;
+/// CHAR values are padded to exactly n bytes when written
+fn column_string_item(s: &str, col_type: &ColType, col_name: &str) -> RsqlResult<DataItem> {
+    let size = match col_type {
+        ColType::Chars(size) | ColType::VarChar(size) => *size,
+        _ => return Err(RsqlError::ExecutionError(format!("Column {} of type {:?} does not hold strings", col_name, col_type))),
+    };
+    let mut value = s.to_string();
+    if value.len() > size {
+        if !TRUNCATE_LONG_STRINGS {
+            return Err(RsqlError::ExecutionError(format!(
+                "Value length {} exceeds size {} of column {}", value.len(), size, col_name
+            )));
         }
+        let mut end = size;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+    }
+    match col_type {
+        ColType::Chars(_) => Ok(DataItem::Chars { len: size as u64, value }),
+        _ => Ok(DataItem::VarChar {
+            head: VarCharHead { max_len: size as u64, len: value.len() as u64, page_ptr: None },
+            value,
+        }),
     }
-    Ok(data_item)
 }
 
-pub fn handle_update_expr(table_object: &mut TableObject, assignments: &Vec<(String, Expr)>, rows: &Vec<Vec<DataItem>>, tnx_id: u64) -> RsqlResult<()> {
-    let mut updated_rows = rows.clone(); // clone the rows to update
-    for (col_name, expr) in assignments.iter() {
-        let tar_col_idx = table_object.map.get(col_name).unwrap();
-        match expr {
-            Expr::Identifier(ident) => {
-                let src_col_name = ident.value.clone();
-                let src_col_idx = table_object.map.get(&src_col_name).unwrap(); // get the source column index to assign its value to the target column
-                for row in updated_rows.iter_mut() {
-                    let src_col_value = row[*src_col_idx].clone();
-                    row[*tar_col_idx] = src_col_value;
-                }
-            },
-            Expr::Value(value) => {
-                match &value.value {
-                    Boolean(b) => {
+/// Check if values of a column type can be inserted into a column of another type
+fn is_insert_compatible(src_type: &ColType, tar_type: &ColType) -> bool {
+    matches!(
+        (src_type, tar_type),
+        (ColType::Integer, ColType::Integer | ColType::Float)
+            | (ColType::Float, ColType::Float)
+            | (ColType::Bool, ColType::Bool)
+            | (ColType::Chars(_) | ColType::VarChar(_), ColType::Chars(_) | ColType::VarChar(_))
+    )
+}
+
+/// Convert a selected value to the representation of the target column type
+fn coerce_insert_item(item: &DataItem, tar_type: &ColType, tar_name: &str) -> RsqlResult<DataItem> {
+    let coerced = match (item, tar_type) {
+        (DataItem::Integer(i), ColType::Integer) => DataItem::Integer(*i),
+        (DataItem::Integer(i), ColType::Float) => DataItem::Float(*i as f64),
+        (DataItem::Float(f), ColType::Float) => DataItem::Float(*f),
+        (DataItem::Bool(b), ColType::Bool) => DataItem::Bool(*b),
+        (DataItem::Chars { value, .. } | DataItem::VarChar { value, .. }, ColType::Chars(_) | ColType::VarChar(_)) => {
+            column_string_item(value, tar_type, tar_name)?
+        },
+        (DataItem::NullInt, ColType::Integer) => DataItem::NullInt,
+        (DataItem::NullInt | DataItem::NullFloat, ColType::Float) => DataItem::NullFloat,
+        (DataItem::NullBool, ColType::Bool) => DataItem::NullBool,
+        (DataItem::NullChars { .. } | DataItem::NullVarChar, ColType::Chars(size)) => DataItem::NullChars { len: *size as u64 },
+        (DataItem::NullChars { .. } | DataItem::NullVarChar, ColType::VarChar(_)) => DataItem::NullVarChar,
+        _ => {
+            return Err(RsqlError::ExecutionError(format!("Cannot insert value {:?} into column of type {:?}", item, tar_type)))
+        },
+    };
+    Ok(coerced)
+}
+
+/// Build the rows of an INSERT ... SELECT
+/// The selected columns are checked against the target columns before any row is built,
+/// so a mismatch fails the statement before anything is inserted
+pub fn handle_insert_rows(
+    tar_cols: &(Vec<String>, Vec<ColType>),
+    cols: &[String],
+    null_cols: &[DataItem],
+    src_cols: &(Vec<String>, Vec<ColType>),
+    rows: &[Vec<DataItem>],
+) -> RsqlResult<Vec<Vec<DataItem>>> {
+    // 1. check column count
+    if cols.len() != src_cols.1.len() {
+        return Err(RsqlError::ExecutionError(format!(
+            "INSERT has {} target columns but SELECT returns {} columns", cols.len(), src_cols.1.len()
+        )));
+    }
+    // 2. check column types
+    let mut col_idxs = vec![];
+    for (idx, col) in cols.iter().enumerate() {
+        let Some(col_idx) = tar_cols.0.iter().position(|x| x == col) else {
+            return Err(RsqlError::ExecutionError(format!("column {} is not found", col)));
+        };
+        let tar_type = &tar_cols.1[col_idx];
+        let src_type = &src_cols.1[idx];
+        if !is_insert_compatible(src_type, tar_type) {
+            return Err(RsqlError::ExecutionError(format!(
+                "Cannot insert column {} of type {:?} into column {} of type {:?}", src_cols.0[idx], src_type, col, tar_type
+            )));
+        }
+        col_idxs.push(col_idx);
+    }
+    // 3. build rows
+    let mut data_items = vec![];
+    for row in rows.iter() {
+        let mut data_item = null_cols.to_vec();
+        for (idx, col_idx) in col_idxs.iter().enumerate() {
+            data_item[*col_idx] = coerce_insert_item(&row[idx], &tar_cols.1[*col_idx], &tar_cols.0[*col_idx])?;
+        }
+        data_items.push(data_item);
+    }
+    Ok(data_items)
+}
+
+pub fn handle_update_expr(table_object: &mut TableObject, assignments: &[(String, Expr)], rows: &Vec<Vec<DataItem>>, tnx_id: u64) -> RsqlResult<Vec<Vec<DataItem>>> {
+    let mut updated_rows = rows.clone(); // clone the rows to update
+    // every assignment reads the row as it was before the update, so SET a = b, b = a swaps
+    for (col_name, expr) in assignments.iter() {
+        let tar_col_idx = table_object.map.get(col_name).unwrap();
+        match expr {
+            Expr::Identifier(ident) => {
+                let src_col_name = ident.value.clone();
+                let src_col_idx = table_object.map.get(&src_col_name).ok_or(
+                    RsqlError::ExecutionError(format!("column {} is not found", src_col_name))
+                )?; // get the source column index to assign its value to the target column
+                for (row, old_row) in updated_rows.iter_mut().zip(rows) {
+                    row[*tar_col_idx] = old_row[*src_col_idx].clone();
+                }
+            },
+            Expr::Value(value) => {
+                match &value.value {
+                    Boolean(b) => {
                         let bool_value = DataItem::Bool(*b);
                         for row in updated_rows.iter_mut() {
                             row[*tar_col_idx] = bool_value.clone();
                         }
                     },
                     Number(n, _) => {
-                        let number_value = parse_number(n)?;
+                        let number_value = parse_number_as(n, &table_object.cols.1[*tar_col_idx])?;
                         for row in updated_rows.iter_mut() {
                             row[*tar_col_idx] = number_value.clone();
                         }
                     },
-                    SingleQuotedString(s) => {
+                    SingleQuotedString(s) | EscapedStringLiteral(s) => {
                         let col_type = table_object.cols.1[*tar_col_idx].clone();
                         let string_value = match col_type {
-                            ColType::Chars(size) => DataItem::Chars{len: size as u64, value: s.clone()},
-                            ColType::VarChar(_) => DataItem::VarChar {
-                                head: VarCharHead {max_len: s.len() as u64, len: s.len() as u64, page_ptr: None},
-                                value: s.clone(),
-                            },
+                            ColType::Chars(_) | ColType::VarChar(_) => column_string_item(s, &col_type, col_name)?,
                             _ => {
                                 return Err(RsqlError::ExecutionError(format!("Unsupported update value type: {:?}", value.value)))
                             },
@@ -1330,210 +1929,734 @@ pub fn handle_update_expr(table_object: &mut TableObject, assignments: &Vec<(Str
                     }
                 }
             },
-            Expr::BinaryOp { left, op, right } => {
-                match op {
-                    BinaryOperator::Plus => {
-                        match (&**left, &**right) {
-                            (Expr::Identifier(ident), Expr::Value(value)) => {
-                                match &value.value {
-                                    Number(n, _) => {
-                                        let src_col_name = ident.value.clone();
-                                        let src_col_idx = table_object.map.get(&src_col_name).unwrap();
-                                        let number_value = parse_number(n)?;
-                                        match number_value {
-                                            DataItem::Integer(n_right) => {
-                                                for row in updated_rows.iter_mut() {
-                                                    let src_col_value = row[*src_col_idx].clone();
-                                                    if let DataItem::Integer(n_left) = src_col_value {
-                                                        row[*tar_col_idx] = DataItem::Integer(n_left + n_right);
-                                                    }else {
-                                                        return Err(RsqlError::ExecutionError(format!("dismatched operation number type")))
-                                                    }
-                                                }
-                                            },
-                                            DataItem::Float(f_right) => {
-                                                for row in updated_rows.iter_mut() {
-                                                    let src_col_value = row[*src_col_idx].clone();
-                                                    if let DataItem::Float(f_left) = src_col_value {
-                                                        row[*tar_col_idx] = DataItem::Float(f_left + f_right);
-                                                    }else {
-                                                        return Err(RsqlError::ExecutionError(format!("dismatched operation number type")))
-                                                    }
-                                                }
-                                            },
-                                            _ => {
-                                                return Err(RsqlError::ExecutionError(format!("unsupported operation number type {number_value:?}")))
-                                            },
-                                        }
-                                    },
-                                    _ => {
-                                        return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
-                                    },
-                                }
-                            },
-                            _ => {
-                                return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
-                            },
-                        }
-                    },
-                    BinaryOperator::Minus => {
-                        match (&**left, &**right) {
-                            (Expr::Identifier(ident), Expr::Value(value)) => {
-                                match &value.value {
-                                     Number(n, _) => {
-                                        let src_col_name = ident.value.clone();
-                                        let src_col_idx = table_object.map.get(&src_col_name).unwrap();
-                                        let number_value = parse_number(n)?;
-                                        match number_value {
-                                             DataItem::Integer(n_right) => {
-                                                for row in updated_rows.iter_mut() {
-                                                    let src_col_value = row[*src_col_idx].clone();
-                                                    if let DataItem::Integer(n_left) = src_col_value {
-                                                        row[*tar_col_idx] = DataItem::Integer(n_left - n_right);
-                                                    }else {
-                                                        return Err(RsqlError::ExecutionError(format!("dismatched operation number type")))
-                                                    }
-                                                }
-                                            },
-                                            DataItem::Float(f_right) => {
-                                                for row in updated_rows.iter_mut() {
-                                                    let src_col_value = row[*src_col_idx].clone();
-                                                    if let DataItem::Float(f_left) = src_col_value {
-                                                        row[*tar_col_idx] = DataItem::Float(f_left - f_right);
-                                                    }else {
-                                                        return Err(RsqlError::ExecutionError(format!("dismatched operation number type")))
-                                                    }
-                                                }
-                                            },
-                                             _ => {
-                                                 return Err(RsqlError::ExecutionError(format!("unsupported operation number type {number_value:?}")))
-                                             },
-                                        }
-                                     },
-                                     _ => {
-                                         return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
-                                     },
-                                }
-                            },
-                            _ => {
-                                return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
-                            },
-                        }
-                    },
-                    BinaryOperator::Multiply => {
-                        match (&**left, &**right) {
-                            (Expr::Identifier(ident), Expr::Value(value)) => {
-                                match &value.value {
-                                    Number(n, _) => {
-                                        let src_col_name = ident.value.clone();
-                                        let src_col_idx = table_object.map.get(&src_col_name).unwrap();
-                                        let number_value = parse_number(n)?;
-                                        match number_value {
-                                            DataItem::Integer(n_right) => {
-                                                for row in updated_rows.iter_mut() {
-                                                    let src_col_value = row[*src_col_idx].clone();
-                                                    if let DataItem::Integer(n_left) = src_col_value {
-                                                        row[*tar_col_idx] = DataItem::Integer(n_left * n_right);
-                                                    }else {
-                                                        return Err(RsqlError::ExecutionError(format!("dismatched operation number type")))
-                                                    }
-                                                }
-                                            },
-                                            DataItem::Float(f_right) => {
-                                                for row in updated_rows.iter_mut() {
-                                                    let src_col_value = row[*src_col_idx].clone();
-                                                    if let DataItem::Float(f_left) = src_col_value {
-                                                        row[*tar_col_idx] = DataItem::Float(f_left * f_right);
-                                                    }else {
-                                                        return Err(RsqlError::ExecutionError(format!("dismatched operation number type")))
-                                                    }
-                                                }
-                                            },
-                                            _ => {
-                                                return Err(RsqlError::ExecutionError(format!("unsupported operation number type {number_value:?}")))
-                                            },
-                                        }
-                                    },
-                                    _ => {
-                                        return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
-                                    },
-                                }
-                            },
-                            _ => {
-                                return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
-                            },
-                        }
-                    },
-                    BinaryOperator::Divide => {
-                        match (&**left, &**right) {
-                            (Expr::Identifier(ident), Expr::Value(value)) => {
-                                match &value.value {
-                                    Number(n, _) => {
-                                        let src_col_name = ident.value.clone();
-                                        let src_col_idx = table_object.map.get(&src_col_name).unwrap();
-                                        let number_value = parse_number(n)?;
-                                        match number_value {
-                                            DataItem::Integer(n_right) => {
-                                                for row in updated_rows.iter_mut() {
-                                                    let src_col_value = row[*src_col_idx].clone();
-                                                    if let DataItem::Integer(n_left) = src_col_value {
-                                                        row[*tar_col_idx] = DataItem::Integer(n_left / n_right);
-                                                    }else {
-                                                        return Err(RsqlError::ExecutionError(format!("dismatched operation number type")))
-                                                    }
-                                                }
-                                            },
-                                            DataItem::Float(f_right) => {
-                                                for row in updated_rows.iter_mut() {
-                                                    let src_col_value = row[*src_col_idx].clone();
-                                                    if let DataItem::Float(f_left) = src_col_value {
-                                                        row[*tar_col_idx] = DataItem::Float(f_left / f_right);
-                                                    }else {
-                                                        return Err(RsqlError::ExecutionError(format!("dismatched operation number type")))
-                                                    }
-                                                }
-                                            },
-                                            _ => {
-                                                return Err(RsqlError::ExecutionError(format!("unsupported operation number type {number_value:?}")))
-                                            },
-                                        }
-                                    },
-                                    _ => {
-                                        return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
-                                    },
-                                }
-                            },
-                            _ => {
-                                return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
-                            },
-                        }
-                    },
-                    _ => {
-                        return Err(RsqlError::ExecutionError(format!("Unsupported binary operator: {:?}", op)))
-                    }
-                }
-            },
             _ => {
-                return Err(RsqlError::ExecutionError(format!("Unsupported update expression: {:?}", expr)))
+                // any other scalar expression over the row's columns, e.g. SET total = price * qty
+                // mixed integer and float operands are computed as float, integer by integer truncates
+                let col_type = table_object.cols.1[*tar_col_idx].clone();
+                let numeric = matches!(scalar_expr_type(expr, &table_object.cols)?, ColType::Integer | ColType::Float);
+                for (row, old_row) in updated_rows.iter_mut().zip(rows) {
+                    let value = eval_scalar_expr(expr, &table_object.cols, old_row)?;
+                    row[*tar_col_idx] = if numeric {
+                        assign_number(value, &col_type, col_name)?
+                    } else {
+                        coerce_insert_item(&value, &col_type, col_name)?
+                    };
+                }
             },
         }
     }
-    // uniformly update the rows at the end
-    let pk_col_idx = table_object.map.get(&table_object.pk_col.0).unwrap();
     for row in updated_rows.iter() {
-        let pk_col_value = &row[*pk_col_idx].clone();
-        table_object.table_obj.update_row(pk_col_value, row.clone(), tnx_id)?;
+        check_row(table_object.table_obj.get_schema().get_checks(), &table_object.cols, row)?;
+    }
+    // uniformly update the rows at the end, each found by the key it had before the update
+    for (row, old_row) in updated_rows.iter().zip(rows) {
+        let pk = table_object.table_obj.get_schema().pk_key(old_row)?;
+        table_object.table_obj.update_row(&pk, row.clone(), tnx_id)?;
+    }
+    Ok(updated_rows)
+}
+
+/// Fit a computed number to the target column, an integer widens to a float column
+/// but a float is not silently truncated into an integer column
+fn assign_number(value: DataItem, col_type: &ColType, col_name: &str) -> RsqlResult<DataItem> {
+    match (value, col_type) {
+        (DataItem::Integer(i), ColType::Integer) => Ok(DataItem::Integer(i)),
+        (DataItem::Integer(i), ColType::Float) => Ok(DataItem::Float(i as f64)),
+        (DataItem::Float(f), ColType::Float) => finite_float(&f.to_string(), f),
+        (DataItem::Float(f), ColType::Integer) => Err(RsqlError::ExecutionError(format!(
+            "Cannot assign float value {} to integer column {}", f, col_name
+        ))),
+        (DataItem::NullInt | DataItem::NullFloat, ColType::Integer | ColType::Float) => Ok(null_of_type(col_type)),
+        (value, _) => Err(RsqlError::ExecutionError(format!(
+            "Cannot assign {:?} to column {} of type {:?}", value, col_name, col_type
+        ))),
+    }
+}
+
+/// Argument expressions of a function call
+fn get_func_arg_exprs(args: &FunctionArguments) -> RsqlResult<Vec<&Expr>> {
+    match args {
+        FunctionArguments::List(arg_list) => arg_list.args.iter().map(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Ok(expr),
+            _ => Err(RsqlError::ExecutionError(format!("Failed to parse function argument: {:?}", arg))),
+        }).collect(),
+        _ => Err(RsqlError::ExecutionError(format!("Failed to parse function argument: {:?}", args))),
+    }
+}
+
+pub(crate) fn null_of_type(col_type: &ColType) -> DataItem {
+    match col_type {
+        ColType::Integer => DataItem::NullInt,
+        ColType::Float => DataItem::NullFloat,
+        ColType::Chars(len) => DataItem::NullChars { len: *len as u64 },
+        ColType::VarChar(_) => DataItem::NullVarChar,
+        ColType::Bool => DataItem::NullBool,
+    }
+}
+
+/// Result type of a select list expression, checks the argument types of scalar functions
+fn scalar_expr_type(expr: &Expr, cols: &(Vec<String>, Vec<ColType>)) -> RsqlResult<ColType> {
+    match expr {
+        Expr::Identifier(ident) => {
+            let col_idx = cols.0.iter().position(|c| c == &ident.value)
+                .ok_or(RsqlError::ExecutionError(format!("column {} is not found", &ident.value)))?;
+            Ok(cols.1[col_idx].clone())
+        },
+        Expr::CompoundIdentifier(idents) => Ok(cols.1[qualified_col_idx(&cols.0, idents, false)?].clone()),
+        Expr::Value(value) => match &value.value {
+            Number(n, _) => match parse_number(n)? {
+                DataItem::Integer(_) => Ok(ColType::Integer),
+                _ => Ok(ColType::Float),
+            },
+            _ => Err(RsqlError::ExecutionError(format!("Projection expr {:?} is not supported", expr))),
+        },
+        Expr::UnaryOp { op: UnaryOperator::Minus | UnaryOperator::Plus, expr: inner } => {
+            match scalar_expr_type(inner, cols)? {
+                col_type @ (ColType::Integer | ColType::Float) => Ok(col_type),
+                _ => Err(RsqlError::ExecutionError(format!("Projection expr {:?} is not supported", expr))),
+            }
+        },
+        Expr::Nested(inner) => scalar_expr_type(inner, cols),
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::Plus | BinaryOperator::Minus | BinaryOperator::Multiply | BinaryOperator::Divide,
+            right,
+        } => {
+            match (scalar_expr_type(left, cols)?, scalar_expr_type(right, cols)?) {
+                (ColType::Integer, ColType::Integer) => Ok(ColType::Integer),
+                (ColType::Integer | ColType::Float, ColType::Integer | ColType::Float) => Ok(ColType::Float),
+                _ => Err(RsqlError::ExecutionError(format!("Arithmetic on non-numeric values: {}", expr))),
+            }
+        },
+        Expr::Cast { expr: inner, data_type, .. } => {
+            let target = data_type_to_col_type(data_type, &expr.to_string())?;
+            match (scalar_expr_type(inner, cols)?, &target) {
+                (_, ColType::Chars(_) | ColType::VarChar(_))
+                | (ColType::Integer | ColType::Float | ColType::Chars(_) | ColType::VarChar(_), ColType::Integer | ColType::Float)
+                | (ColType::Bool, ColType::Bool) => Ok(target),
+                (src, _) => Err(RsqlError::ExecutionError(format!("Cannot cast {:?} to {:?}", src, target))),
+            }
+        },
+        Expr::Function(func) => {
+            let func_name = get_func_name(&func.name)?;
+            let args = get_func_arg_exprs(&func.args)?;
+            let arg_types = args.iter().map(|arg| scalar_expr_type(arg, cols)).collect::<RsqlResult<Vec<_>>>()?;
+            match (func_name.as_str(), arg_types.as_slice()) {
+                ("ABS", [ColType::Integer | ColType::Float]) => Ok(arg_types[0].clone()),
+                ("ROUND", [ColType::Integer | ColType::Float]) | ("ROUND", [ColType::Integer | ColType::Float, ColType::Integer]) => {
+                    Ok(arg_types[0].clone())
+                },
+                ("LENGTH", [ColType::Chars(_) | ColType::VarChar(_)]) => Ok(ColType::Integer),
+                ("UPPER" | "LOWER", [ColType::Chars(_) | ColType::VarChar(_)]) => Ok(arg_types[0].clone()),
+                ("ABS" | "ROUND" | "LENGTH" | "UPPER" | "LOWER", _) => Err(RsqlError::ExecutionError(format!(
+                    "Invalid arguments for function {}: {:?}", func_name, arg_types
+                ))),
+                _ => Err(RsqlError::ExecutionError(format!("Unsupported function {}", func_name))),
+            }
+        },
+        _ => Err(RsqlError::ExecutionError(format!("Projection expr {:?} is not supported", expr))),
+    }
+}
+
+/// Evaluate a select list expression on one row, the expression must have passed `scalar_expr_type`
+fn eval_scalar_expr(expr: &Expr, cols: &(Vec<String>, Vec<ColType>), row: &[DataItem]) -> RsqlResult<DataItem> {
+    match expr {
+        Expr::Identifier(ident) => {
+            let col_idx = cols.0.iter().position(|c| c == &ident.value).unwrap();
+            Ok(row[col_idx].clone())
+        },
+        Expr::CompoundIdentifier(idents) => Ok(row[qualified_col_idx(&cols.0, idents, false)?].clone()),
+        Expr::Value(value) => match &value.value {
+            Number(n, _) => parse_number(n),
+            _ => Err(RsqlError::ExecutionError(format!("Projection expr {:?} is not supported", expr))),
+        },
+        Expr::UnaryOp { op, expr: inner } => match (op, eval_scalar_expr(inner, cols, row)?) {
+            (UnaryOperator::Minus, DataItem::Integer(i)) => i.checked_neg().map(DataItem::Integer).ok_or(
+                RsqlError::ExecutionError(format!("-({}) is out of range", i))
+            ),
+            (UnaryOperator::Minus, DataItem::Float(f)) => Ok(DataItem::Float(-f)),
+            (_, item) => Ok(item),
+        },
+        Expr::Nested(inner) => eval_scalar_expr(inner, cols, row),
+        Expr::BinaryOp { left, op, right } => {
+            let left = eval_scalar_expr(left, cols, row)?;
+            let right = eval_scalar_expr(right, cols, row)?;
+            match (left, right) {
+                (DataItem::Integer(l), DataItem::Integer(r)) => {
+                    let result = match op {
+                        BinaryOperator::Plus => l.checked_add(r),
+                        BinaryOperator::Minus => l.checked_sub(r),
+                        BinaryOperator::Multiply => l.checked_mul(r),
+                        _ => {
+                            if r == 0 {
+                                return Err(RsqlError::ExecutionError("Division by zero".to_string()));
+                            }
+                            l.checked_div(r)
+                        },
+                    };
+                    result.map(DataItem::Integer).ok_or(RsqlError::ExecutionError(format!("{} is out of range", expr)))
+                },
+                (l @ (DataItem::Integer(_) | DataItem::Float(_)), r @ (DataItem::Integer(_) | DataItem::Float(_))) => {
+                    let as_float = |item: DataItem| match item {
+                        DataItem::Integer(i) => i as f64,
+                        DataItem::Float(f) => f,
+                        _ => unreachable!(),
+                    };
+                    let (l, r) = (as_float(l), as_float(r));
+                    if *op == BinaryOperator::Divide && r == 0.0 {
+                        return Err(RsqlError::ExecutionError("Division by zero".to_string()));
+                    }
+                    Ok(DataItem::Float(match op {
+                        BinaryOperator::Plus => l + r,
+                        BinaryOperator::Minus => l - r,
+                        BinaryOperator::Multiply => l * r,
+                        _ => l / r,
+                    }))
+                },
+                // a null operand gives a null of the result type
+                _ => Ok(null_of_type(&scalar_expr_type(expr, cols)?)),
+            }
+        },
+        Expr::Cast { expr: inner, .. } => {
+            let item = eval_scalar_expr(inner, cols, row)?;
+            cast_item(&item, &scalar_expr_type(expr, cols)?, expr)
+        },
+        Expr::Function(func) => {
+            let func_name = get_func_name(&func.name)?;
+            let args = get_func_arg_exprs(&func.args)?
+                .into_iter()
+                .map(|arg| eval_scalar_expr(arg, cols, row))
+                .collect::<RsqlResult<Vec<_>>>()?;
+            let result = match (func_name.as_str(), &args[0]) {
+                ("ABS", DataItem::Integer(i)) => DataItem::Integer(i.checked_abs().ok_or(
+                    RsqlError::ExecutionError(format!("ABS({}) is out of range", i))
+                )?),
+                ("ABS", DataItem::Float(f)) => DataItem::Float(f.abs()),
+                ("ROUND", DataItem::Integer(i)) => {
+                    let digits = match args.get(1) {
+                        Some(DataItem::Integer(d)) => *d,
+                        _ => 0,
+                    };
+                    if digits >= 0 {
+                        DataItem::Integer(*i)
+                    } else {
+                        let factor = 10f64.powi((-digits).min(18) as i32);
+                        DataItem::Integer(((*i as f64 / factor).round() * factor) as i64)
+                    }
+                },
+                ("ROUND", DataItem::Float(f)) => {
+                    let digits = match args.get(1) {
+                        Some(DataItem::Integer(d)) => (*d).clamp(-308, 308) as i32,
+                        _ => 0,
+                    };
+                    let factor = 10f64.powi(digits);
+                    DataItem::Float((f * factor).round() / factor)
+                },
+                ("LENGTH", DataItem::Chars { value, .. } | DataItem::VarChar { value, .. }) => {
+                    DataItem::Integer(value.chars().count() as i64)
+                },
+                ("UPPER" | "LOWER", DataItem::Chars { len, value }) => DataItem::Chars {
+                    len: *len,
+                    value: if func_name == "UPPER" { value.to_uppercase() } else { value.to_lowercase() },
+                },
+                ("UPPER" | "LOWER", DataItem::VarChar { head, value }) => {
+                    let value = if func_name == "UPPER" { value.to_uppercase() } else { value.to_lowercase() };
+                    DataItem::VarChar {
+                        head: VarCharHead { max_len: head.max_len, len: value.len() as u64, page_ptr: None },
+                        value,
+                    }
+                },
+                // a null argument gives a null of the result type
+                _ => null_of_type(&scalar_expr_type(expr, cols)?),
+            };
+            Ok(result)
+        },
+        _ => Err(RsqlError::ExecutionError(format!("Projection expr {:?} is not supported", expr))),
+    }
+}
+
+/// Convert a value to the target type of a CAST, CHAR results are padded to their length
+/// and strings longer than the target length are rejected like on insert
+fn cast_item(item: &DataItem, target: &ColType, expr: &Expr) -> RsqlResult<DataItem> {
+    if is_null_item(item) {
+        return Ok(null_of_type(target));
+    }
+    match (item, target) {
+        (_, ColType::Chars(size)) => match column_string_item(&item.display(), target, &expr.to_string())? {
+            DataItem::Chars { len, mut value } => {
+                value.push_str(&" ".repeat(size.saturating_sub(value.len())));
+                Ok(DataItem::Chars { len, value })
+            },
+            other => Ok(other),
+        },
+        (_, ColType::VarChar(_)) => column_string_item(&item.display(), target, &expr.to_string()),
+        (DataItem::Integer(i), ColType::Integer) => Ok(DataItem::Integer(*i)),
+        (DataItem::Integer(i), ColType::Float) => Ok(DataItem::Float(*i as f64)),
+        (DataItem::Float(f), ColType::Float) => Ok(DataItem::Float(*f)),
+        (DataItem::Float(f), ColType::Integer) => {
+            let rounded = f.round();
+            if rounded < i64::MIN as f64 || rounded >= i64::MAX as f64 {
+                return Err(RsqlError::ExecutionError(format!("{} is out of range", expr)));
+            }
+            Ok(DataItem::Integer(rounded as i64))
+        },
+        (DataItem::Chars { .. } | DataItem::VarChar { .. }, ColType::Integer | ColType::Float) => {
+            parse_number_as(item.display().trim(), target)
+        },
+        (DataItem::Bool(b), ColType::Bool) => Ok(DataItem::Bool(*b)),
+        _ => Err(RsqlError::ExecutionError(format!("Cannot cast {:?} to {:?}", item, target))),
+    }
+}
+
+/// Reject a row for which a CHECK constraint is false, an unknown (null) result passes
+pub fn check_row(checks: &[CheckConstraint], cols: &(Vec<String>, Vec<ColType>), row: &[DataItem]) -> RsqlResult<()> {
+    for check in checks {
+        if eval_check_expr(&check.expr, cols, row)? == Some(false) {
+            return Err(RsqlError::ConstraintViolation(format!("Row violates CHECK ({})", check.sql)));
+        }
     }
     Ok(())
 }
 
+/// Three-valued evaluation of a CHECK expression, None is unknown
+fn eval_check_expr(expr: &Expr, cols: &(Vec<String>, Vec<ColType>), row: &[DataItem]) -> RsqlResult<Option<bool>> {
+    match expr {
+        Expr::Nested(inner) => eval_check_expr(inner, cols, row),
+        Expr::UnaryOp { op: UnaryOperator::Not, expr: inner } => Ok(eval_check_expr(inner, cols, row)?.map(|b| !b)),
+        Expr::IsNull(inner) => Ok(Some(is_null_item(&eval_check_operand(inner, cols, row)?))),
+        Expr::IsNotNull(inner) => Ok(Some(!is_null_item(&eval_check_operand(inner, cols, row)?))),
+        Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+            match (eval_check_expr(left, cols, row)?, eval_check_expr(right, cols, row)?) {
+                (Some(false), _) | (_, Some(false)) => Ok(Some(false)),
+                (Some(true), Some(true)) => Ok(Some(true)),
+                _ => Ok(None),
+            }
+        },
+        Expr::BinaryOp { left, op: BinaryOperator::Or, right } => {
+            match (eval_check_expr(left, cols, row)?, eval_check_expr(right, cols, row)?) {
+                (Some(true), _) | (_, Some(true)) => Ok(Some(true)),
+                (Some(false), Some(false)) => Ok(Some(false)),
+                _ => Ok(None),
+            }
+        },
+        Expr::BinaryOp { left, op, right } => {
+            let left = eval_check_operand(left, cols, row)?;
+            let right = eval_check_operand(right, cols, row)?;
+            let Some(ordering) = compare_check_items(&left, &right, expr)? else {
+                return Ok(None);
+            };
+            let result = match op {
+                BinaryOperator::Eq => ordering.is_eq(),
+                BinaryOperator::NotEq => ordering.is_ne(),
+                BinaryOperator::Lt => ordering.is_lt(),
+                BinaryOperator::LtEq => ordering.is_le(),
+                BinaryOperator::Gt => ordering.is_gt(),
+                BinaryOperator::GtEq => ordering.is_ge(),
+                _ => return Err(RsqlError::ExecutionError(format!("Unsupported CHECK expression: {}", expr))),
+            };
+            Ok(Some(result))
+        },
+        _ => Err(RsqlError::ExecutionError(format!("Unsupported CHECK expression: {}", expr))),
+    }
+}
+
+/// A compared value of a CHECK expression, string and boolean literals besides the scalar expressions
+fn eval_check_operand(expr: &Expr, cols: &(Vec<String>, Vec<ColType>), row: &[DataItem]) -> RsqlResult<DataItem> {
+    if let Expr::Value(value) = expr {
+        match &value.value {
+            SingleQuotedString(s) | EscapedStringLiteral(s) => return Ok(DataItem::VarChar {
+                head: VarCharHead { max_len: s.len() as u64, len: s.len() as u64, page_ptr: None },
+                value: s.clone(),
+            }),
+            Boolean(b) => return Ok(DataItem::Bool(*b)),
+            _ => {},
+        }
+    }
+    scalar_expr_type(expr, cols)?;
+    eval_scalar_expr(expr, cols, row)
+}
+
+/// Order two values of a CHECK comparison, integers compare with floats, None when one is null
+fn compare_check_items(left: &DataItem, right: &DataItem, expr: &Expr) -> RsqlResult<Option<std::cmp::Ordering>> {
+    let as_float = |item: &DataItem| match item {
+        DataItem::Integer(i) => Some(*i as f64),
+        DataItem::Float(f) => Some(*f),
+        _ => None,
+    };
+    let comparable = matches!(
+        (left, right),
+        (DataItem::Integer(_) | DataItem::NullInt | DataItem::Float(_) | DataItem::NullFloat,
+            DataItem::Integer(_) | DataItem::NullInt | DataItem::Float(_) | DataItem::NullFloat)
+        | (DataItem::Chars { .. } | DataItem::NullChars { .. } | DataItem::VarChar { .. } | DataItem::NullVarChar,
+            DataItem::Chars { .. } | DataItem::NullChars { .. } | DataItem::VarChar { .. } | DataItem::NullVarChar)
+        | (DataItem::Bool(_) | DataItem::NullBool, DataItem::Bool(_) | DataItem::NullBool)
+    );
+    if !comparable {
+        return Err(RsqlError::ExecutionError(format!("Cannot compare values of different types in CHECK ({})", expr)));
+    }
+    if is_null_item(left) || is_null_item(right) {
+        return Ok(None);
+    }
+    match (left, right) {
+        (DataItem::Integer(l), DataItem::Integer(r)) => Ok(Some(l.cmp(r))),
+        (DataItem::Integer(_) | DataItem::Float(_), _) => Ok(as_float(left).partial_cmp(&as_float(right))),
+        _ => Ok(left.partial_cmp(right)),
+    }
+}
+
+/// `a <=> b` is the MySQL spelling of `a IS NOT DISTINCT FROM b`
+fn spaceship_as_not_distinct(predicate: &Expr) -> Option<Expr> {
+    match predicate {
+        Expr::BinaryOp { left, op: BinaryOperator::Spaceship, right } => Some(Expr::IsNotDistinctFrom(left.clone(), right.clone())),
+        _ => None,
+    }
+}
+
+/// The predicate with the columns qualified by `table_name`, `e.id` of `FROM emp e WHERE e.id = 1`,
+/// named by the column alone, as the filters look columns up by their own name
+pub fn unqualify_columns(predicate: &Expr, table_name: &str) -> Expr {
+    let unqualify = |expr: &Expr| Box::new(unqualify_columns(expr, table_name));
+    match predicate {
+        Expr::CompoundIdentifier(idents) if idents.len() == 2 && idents[0].value == table_name => Expr::Identifier(idents[1].clone()),
+        Expr::BinaryOp { left, op, right } => Expr::BinaryOp { left: unqualify(left), op: op.clone(), right: unqualify(right) },
+        Expr::UnaryOp { op, expr } => Expr::UnaryOp { op: *op, expr: unqualify(expr) },
+        Expr::Nested(expr) => Expr::Nested(unqualify(expr)),
+        Expr::IsNull(expr) => Expr::IsNull(unqualify(expr)),
+        Expr::IsNotNull(expr) => Expr::IsNotNull(unqualify(expr)),
+        Expr::IsDistinctFrom(left, right) => Expr::IsDistinctFrom(unqualify(left), unqualify(right)),
+        Expr::IsNotDistinctFrom(left, right) => Expr::IsNotDistinctFrom(unqualify(left), unqualify(right)),
+        Expr::Between { expr, negated, low, high } => Expr::Between { expr: unqualify(expr), negated: *negated, low: unqualify(low), high: unqualify(high) },
+        Expr::InList { expr, list, negated } => Expr::InList {
+            expr: unqualify(expr),
+            list: list.iter().map(|item| unqualify_columns(item, table_name)).collect(),
+            negated: *negated,
+        },
+        _ => predicate.clone(),
+    }
+}
+
+/// NULL-safe equality of two operands on one row: two nulls are equal, a null never equals a value
+fn eval_null_safe_eq(left: &Expr, right: &Expr, cols: &(Vec<String>, Vec<ColType>), row: &[DataItem]) -> RsqlResult<bool> {
+    let operand = |expr: &Expr| match expr {
+        Expr::Value(value) if value.value == Null => Ok(None),
+        _ => eval_check_operand(expr, cols, row).map(|item| Some(item).filter(|item| !is_null_item(item))),
+    };
+    match (operand(left)?, operand(right)?) {
+        (None, None) => Ok(true),
+        (Some(l), Some(r)) => {
+            let expr = Expr::IsNotDistinctFrom(Box::new(left.clone()), Box::new(right.clone()));
+            Ok(compare_check_items(&l, &r, &expr)?.is_some_and(|ordering| ordering.is_eq()))
+        },
+        _ => Ok(false),
+    }
+}
+
+pub(crate) fn is_null_item(item: &DataItem) -> bool {
+    matches!(item, DataItem::NullInt | DataItem::NullFloat | DataItem::NullChars { .. } | DataItem::NullVarChar | DataItem::NullBool)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Calls of sort_rows on this thread, for tests checking a sort was skipped
+    pub static SORTS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Sort the output rows of a query by ORDER BY keys.
+/// A key is an output column, a 1-based position in the select list, or a scalar expression
+/// over the output columns; nulls sort first like in `DataItem` ordering.
+/// Output columns compare under `collations`, one for each column
+pub fn sort_rows(
+    exprs: &[Expr],
+    asc: &[bool],
+    cols: &(Vec<String>, Vec<ColType>),
+    collations: &[Collation],
+    rows: Vec<Vec<DataItem>>,
+) -> RsqlResult<Vec<Vec<DataItem>>> {
+    #[cfg(test)]
+    SORTS.with(|sorts| sorts.set(sorts.get() + 1));
+    enum SortKey<'a> {
+        Column(usize),
+        Expr(&'a Expr),
+    }
+    // 1. resolve the keys before any row is evaluated
+    let mut keys = vec![];
+    for expr in exprs {
+        let key = match expr {
+            Expr::Value(value) => match &value.value {
+                Number(n, _) => match n.parse::<usize>() {
+                    Ok(pos) if pos >= 1 && pos <= cols.0.len() => SortKey::Column(pos - 1),
+                    _ => return Err(RsqlError::ExecutionError(format!("ORDER BY position {} is not in select list", n))),
+                },
+                _ => return Err(RsqlError::ExecutionError(format!("ORDER BY {} is not supported", expr))),
+            },
+            Expr::Identifier(ident) => {
+                let col_idx = cols.0.iter().position(|c| c == &ident.value)
+                    .ok_or(RsqlError::ExecutionError(format!("ORDER BY column {} is not in select list", ident.value)))?;
+                SortKey::Column(col_idx)
+            },
+            Expr::CompoundIdentifier(idents) => {
+                let name = idents.iter().map(|i| i.value.clone()).collect::<Vec<_>>().join(".");
+                let col_idx = cols.0.iter().position(|c| c == &name)
+                    .ok_or(RsqlError::ExecutionError(format!("ORDER BY column {} is not in select list", name)))?;
+                SortKey::Column(col_idx)
+            },
+            _ => {
+                scalar_expr_type(expr, cols)?;
+                SortKey::Expr(expr)
+            },
+        };
+        keys.push(key);
+    }
+    // 2. compute the key values of each row, then sort by them
+    let mut keyed_rows = vec![];
+    for row in rows {
+        let mut key_values = vec![];
+        for key in keys.iter() {
+            key_values.push(match key {
+                SortKey::Column(col_idx) => collations[*col_idx].key(&row[*col_idx]),
+                SortKey::Expr(expr) => eval_scalar_expr(expr, cols, &row)?,
+            });
+        }
+        keyed_rows.push((key_values, row));
+    }
+    keyed_rows.sort_by(|(a, _), (b, _)| {
+        for (i, is_asc) in asc.iter().enumerate() {
+            let cmp = a[i].partial_cmp(&b[i]).unwrap_or(std::cmp::Ordering::Equal);
+            if cmp != std::cmp::Ordering::Equal {
+                return if *is_asc { cmp } else { cmp.reverse() };
+            }
+        }
+        std::cmp::Ordering::Equal
+    });
+    Ok(keyed_rows.into_iter().map(|(_, row)| row).collect())
+}
+
+/// Output columns with their types, and the projected rows
+type ProjectionResult = ((Vec<String>, Vec<ColType>), Vec<Vec<DataItem>>);
+
+fn is_wildcard(expr: &Expr) -> bool {
+    matches!(expr, Expr::Identifier(ident) if ident.value == "*")
+}
+
+/// A select list expression and the name it is given with AS, if any
+pub fn unalias(expr: &Expr) -> (&Expr, Option<&Ident>) {
+    match expr {
+        Expr::Named { expr, name } => (expr, Some(name)),
+        _ => (expr, None),
+    }
+}
+
+/// Evaluate the select list over the input rows
+/// Supports plain columns, CAST and the scalar functions ABS, ROUND, LENGTH, UPPER and LOWER,
+/// a `*` among them expands to all input columns in place, an aliased expression is named by its alias
+pub fn handle_projection_exprs(
+    exprs: &[Expr],
+    cols: &(Vec<String>, Vec<ColType>),
+    rows: &[Vec<DataItem>],
+) -> RsqlResult<ProjectionResult> {
+    // 1. get projection columns, argument types are checked before any row is evaluated
+    let mut cols_name = vec![];
+    let mut cols_type = vec![];
+    for expr in exprs {
+        if is_wildcard(expr) {
+            cols_name.extend(cols.0.iter().cloned());
+            cols_type.extend(cols.1.iter().cloned());
+            continue;
+        }
+        let (expr, name) = unalias(expr);
+        cols_type.push(scalar_expr_type(expr, cols)?);
+        cols_name.push(match (name, expr) {
+            (Some(name), _) => name.value.clone(),
+            (None, Expr::Identifier(ident)) => ident.value.clone(),
+            (None, _) => expr.to_string(),
+        });
+    }
+    // 2. get projection rows
+    let mut projected_rows = vec![];
+    for row in rows.iter() {
+        let mut r = vec![];
+        for expr in exprs {
+            if is_wildcard(expr) {
+                r.extend(row.iter().cloned());
+            } else {
+                r.push(eval_scalar_expr(unalias(expr).0, cols, row)?);
+            }
+        }
+        projected_rows.push(r);
+    }
+    Ok(((cols_name, cols_type), projected_rows))
+}
+
+fn is_count_star(expr: &Expr) -> bool {
+    let Expr::Function(func) = expr else {
+        return false;
+    };
+    matches!(get_func_name(&func.name).as_deref(), Ok("COUNT"))
+        && matches!(&func.args, FunctionArguments::List(list)
+            if matches!(list.args.as_slice(), [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)]))
+}
+
+/// Running value of one aggregate function over the rows of a group
+#[derive(Clone)]
+enum Accumulator {
+    Count(i64),
+    Avg { sum: f64, count: i64 },
+    SumInt(i64),
+    SumFloat(f64),
+    MinInt(Option<i64>),
+    MinFloat(Option<f64>),
+    MaxInt(Option<i64>),
+    MaxFloat(Option<f64>),
+}
+
+impl Accumulator {
+    /// Empty accumulator of an aggregate function over a column of `col_type`
+    fn new(func_name: &str, col_type: Option<&ColType>) -> RsqlResult<Self> {
+        match (func_name, col_type) {
+            ("COUNT", _) => Ok(Accumulator::Count(0)),
+            ("AVG", _) => Ok(Accumulator::Avg { sum: 0.0, count: 0 }),
+            ("SUM", Some(ColType::Integer)) => Ok(Accumulator::SumInt(0)),
+            ("SUM", Some(ColType::Float)) => Ok(Accumulator::SumFloat(0.0)),
+            ("MIN", Some(ColType::Integer)) => Ok(Accumulator::MinInt(None)),
+            ("MIN", Some(ColType::Float)) => Ok(Accumulator::MinFloat(None)),
+            ("MAX", Some(ColType::Integer)) => Ok(Accumulator::MaxInt(None)),
+            ("MAX", Some(ColType::Float)) => Ok(Accumulator::MaxFloat(None)),
+            ("SUM" | "MIN" | "MAX", _) => Err(RsqlError::ExecutionError(format!("unsupported type for {}", func_name))),
+            _ => Err(RsqlError::ExecutionError(format!("Unsupported aggregate function: {:?}", func_name))),
+        }
+    }
+    /// Fold the argument value of one row in, COUNT takes no value
+    fn update(&mut self, value: Option<&DataItem>) -> RsqlResult<()> {
+        match (self, value) {
+            (Accumulator::Count(count), _) => *count += 1,
+            (Accumulator::Avg { sum, count }, Some(DataItem::Integer(i))) => {
+                *sum += *i as f64;
+                *count += 1;
+            },
+            (Accumulator::Avg { sum, count }, Some(DataItem::Float(f))) => {
+                *sum += f;
+                *count += 1;
+            },
+            (Accumulator::SumInt(sum), Some(DataItem::Integer(i))) => *sum += i,
+            (Accumulator::SumFloat(sum), Some(DataItem::Float(f))) => *sum += f,
+            (Accumulator::MinInt(min), Some(DataItem::Integer(i))) => {
+                if min.is_none_or(|m| *i < m) {
+                    *min = Some(*i);
+                }
+            },
+            (Accumulator::MinFloat(min), Some(DataItem::Float(f))) => {
+                if min.is_none_or(|m| *f < m) {
+                    *min = Some(*f);
+                }
+            },
+            (Accumulator::MaxInt(max), Some(DataItem::Integer(i))) => {
+                if max.is_none_or(|m| *i > m) {
+                    *max = Some(*i);
+                }
+            },
+            (Accumulator::MaxFloat(max), Some(DataItem::Float(f))) => {
+                if max.is_none_or(|m| *f > m) {
+                    *max = Some(*f);
+                }
+            },
+            (_, None) => return Err(RsqlError::ExecutionError("aggregate function has no argument column".to_string())),
+            (acc, Some(value)) => {
+                let (func_name, col_type) = match acc {
+                    Accumulator::Avg { .. } => return Err(RsqlError::ExecutionError(format!("unsupported type for AVG: {:?}", value))),
+                    Accumulator::SumInt(_) => ("sum", "integer"),
+                    Accumulator::SumFloat(_) => ("sum", "float"),
+                    Accumulator::MinInt(_) => ("min", "integer"),
+                    Accumulator::MinFloat(_) => ("min", "float"),
+                    Accumulator::MaxInt(_) => ("max", "integer"),
+                    _ => ("max", "float"),
+                };
+                return Err(RsqlError::ExecutionError(format!("cannot {} other type with {}: {:?}", func_name, col_type, value)));
+            },
+        }
+        Ok(())
+    }
+    fn finish(self) -> DataItem {
+        match self {
+            Accumulator::Count(count) => DataItem::Integer(count),
+            Accumulator::Avg { sum, count } => DataItem::Float(sum / count as f64),
+            Accumulator::SumInt(sum) => DataItem::Integer(sum),
+            Accumulator::SumFloat(sum) => DataItem::Float(sum),
+            Accumulator::MinInt(value) | Accumulator::MaxInt(value) => value.map_or(DataItem::NullInt, DataItem::Integer),
+            Accumulator::MinFloat(value) | Accumulator::MaxFloat(value) => value.map_or(DataItem::NullFloat, DataItem::Float),
+        }
+    }
+}
+
+/// Group the rows by the values at `group_by_cols_idx` and fold every aggregate of a group
+/// in one pass over the rows, each aggregate given as its empty accumulator and argument column.
+/// Rows are consumed as they are produced, only the groups are kept in memory.
+/// Groups are returned in the order they first appear, as their values followed by the aggregates
+/// Whether a GROUP BY expression is a condition, grouped by its truth value
+fn is_group_condition(expr: &Expr) -> bool {
+    match expr {
+        Expr::Nested(inner) => is_group_condition(inner),
+        Expr::BinaryOp { op, .. } => matches!(op,
+            BinaryOperator::Eq | BinaryOperator::NotEq | BinaryOperator::Lt | BinaryOperator::LtEq
+            | BinaryOperator::Gt | BinaryOperator::GtEq | BinaryOperator::And | BinaryOperator::Or
+        ),
+        Expr::UnaryOp { op: UnaryOperator::Not, .. } | Expr::IsNull(_) | Expr::IsNotNull(_) => true,
+        _ => false,
+    }
+}
+
+/// The type of a computed GROUP BY key, BOOL for a condition
+fn group_key_type(expr: &Expr, cols: &(Vec<String>, Vec<ColType>)) -> RsqlResult<ColType> {
+    if is_group_condition(expr) {
+        return Ok(ColType::Bool);
+    }
+    scalar_expr_type(expr, cols)
+}
+
+/// The value of a computed GROUP BY key on one row, an unknown condition groups as null
+fn eval_group_key(expr: &Expr, cols: &(Vec<String>, Vec<ColType>), row: &[DataItem]) -> RsqlResult<DataItem> {
+    if is_group_condition(expr) {
+        return Ok(eval_check_expr(expr, cols, row)?.map_or(DataItem::NullBool, DataItem::Bool));
+    }
+    eval_scalar_expr(expr, cols, row)
+}
+
+fn aggregate_rows(
+    rows: impl IntoIterator<Item = RsqlResult<Vec<DataItem>>>,
+    group_by_cols_idx: &[usize],
+    aggrs: &[(Accumulator, Option<usize>)],
+) -> RsqlResult<Vec<Vec<DataItem>>> {
+    let mut group_idx: HashMap<Vec<DataItem>, usize> = HashMap::new();
+    let mut groups: Vec<(Vec<DataItem>, Vec<Accumulator>)> = vec![];
+    for row in rows {
+        let row = row?;
+        let group_by_values: Vec<DataItem> = group_by_cols_idx.iter().map(|i| row[*i].clone()).collect();
+        let idx = match group_idx.get(&group_by_values) {
+            Some(idx) => *idx,
+            None => {
+                groups.push((group_by_values.clone(), aggrs.iter().map(|(acc, _)| acc.clone()).collect()));
+                group_idx.insert(group_by_values, groups.len() - 1);
+                groups.len() - 1
+            },
+        };
+        for (acc, (_, col_idx)) in groups[idx].1.iter_mut().zip(aggrs) {
+            acc.update(col_idx.map(|i| &row[i]))?;
+        }
+    }
+    Ok(groups.into_iter().map(|(mut aggr_row, accs)| {
+        aggr_row.extend(accs.into_iter().map(Accumulator::finish));
+        aggr_row
+    }).collect())
+}
+
 pub fn handle_aggr_expr (table_obj: TableObject, group_by: &Vec<Expr>, aggr_exprs: &Vec<Expr>) -> RsqlResult<((Vec<String>, Vec<ColType>), Vec<Vec<DataItem>>, Vec<String>)> {
     let mut cols_name = vec![];
     let mut cols_type = vec![];
     let mut aggr_cols = vec![];
-    let mut aggr_rows = vec![];
+    let mut aggrs = vec![];
     let mut group_by_cols_idx = vec![];
-    // 1. construct aggr_cols, (cols_name, cols_type), group_by_cols_idx
+    // group keys computed from an expression, appended to each row before grouping
+    let mut computed_keys = vec![];
+    // 1. construct aggr_cols, (cols_name, cols_type), group_by_cols_idx and the accumulators
     for group_by_col_expr in group_by.iter() {
         if let Expr::Identifier(ident) = group_by_col_expr {
             let col_idx = table_obj.map.get(&ident.value).unwrap();
@@ -1541,6 +2664,11 @@ pub fn handle_aggr_expr (table_obj: TableObject, group_by: &Vec<Expr>, aggr_expr
             cols_type.push(col_type);
             cols_name.push(ident.value.clone());
             group_by_cols_idx.push(*col_idx);
+        } else {
+            cols_type.push(group_key_type(group_by_col_expr, &table_obj.cols)?);
+            cols_name.push(group_by_col_expr.to_string());
+            group_by_cols_idx.push(table_obj.cols.0.len() + computed_keys.len());
+            computed_keys.push(group_by_col_expr);
         }
     }
     for aggr_expr in aggr_exprs.iter() {
@@ -1553,13 +2681,16 @@ pub fn handle_aggr_expr (table_obj: TableObject, group_by: &Vec<Expr>, aggr_expr
                     aggr_cols.push(aggr_col_name.clone());
                     cols_name.push(aggr_col_name.clone());
                     cols_type.push(col_type);
+                    aggrs.push((Accumulator::new(&func_name, None)?, None));
                 }else if func_name == "AVG" {
                     let col_type = ColType::Float;
                     let func_arg = get_func_arg(&func.args)?;
+                    let col_idx = table_obj.map.get(&func_arg).unwrap();
                     let aggr_col_name = format!("AVG_{}", &func_arg);
                     aggr_cols.push(aggr_col_name.clone());
                     cols_name.push(aggr_col_name.clone());
                     cols_type.push(col_type);
+                    aggrs.push((Accumulator::new(&func_name, None)?, Some(*col_idx)));
                 }else {
                     let func_arg = get_func_arg(&func.args)?;
                     let col_idx = table_obj.map.get(&func_arg).unwrap();
@@ -1567,6 +2698,7 @@ pub fn handle_aggr_expr (table_obj: TableObject, group_by: &Vec<Expr>, aggr_expr
                     let aggr_col_name = format!("{}_{}", &func_name, &func_arg);
                     aggr_cols.push(aggr_col_name.clone());
                     cols_name.push(aggr_col_name.clone());
+                    aggrs.push((Accumulator::new(&func_name, Some(&col_type))?, Some(*col_idx)));
                     cols_type.push(col_type);
                 }
             },
@@ -1575,225 +2707,838 @@ pub fn handle_aggr_expr (table_obj: TableObject, group_by: &Vec<Expr>, aggr_expr
             },
         }
     }
-    // 2. get all rows from the table
-    let mut rows = vec![];
-    let rows_iter = table_obj.table_obj.get_all_rows()?;
-    for row in rows_iter {
-        let row = row?;
-        rows.push(row);
-    }
-    // 3. get all distinct group_by values
-    let mut distinct_group_by_values = vec![];
-    for row in rows.iter() {
-        let mut group_by_values = vec![];
-        for group_by_col_expr in group_by.iter() {
-            if let Expr::Identifier(ident) = group_by_col_expr {
-                let col_idx = table_obj.map.get(&ident.value).unwrap();
-                group_by_values.push(row[*col_idx].clone());
-            }
-        }
-        if !distinct_group_by_values.contains(&group_by_values) {
-            distinct_group_by_values.push(group_by_values);
-        }
+    // COUNT(*) over the whole table is read from the row count of the table header
+    if group_by.is_empty() && aggr_exprs.iter().all(is_count_star) {
+        let count = table_obj.table_obj.row_count();
+        let rows = vec![vec![DataItem::Integer(count as i64); aggr_exprs.len()]];
+        return Ok(((cols_name, cols_type), rows, aggr_cols));
     }
-    // 4. construct aggr_rows
-    for row in distinct_group_by_values.iter() {
-        let mut aggr_row = row.clone();
-        for aggr_expr in aggr_exprs.iter() {
-            match aggr_expr {
-                Expr::Function(func) => {
-                    let func_name = get_func_name(&func.name)?;
-                    match func_name.as_str() {
-                        "COUNT" => {
-                            let mut count: i64 = 0;
-                            for r in rows.iter() {
-                                let group_by_row: Vec<DataItem> = group_by_cols_idx.iter().map(|i| r[*i].clone()).collect();
-                                if group_by_row == *row {
-                                    count += 1;
-                                }
-                            }
-                            aggr_row.push(DataItem::Integer(count));
-                        },
-                        "AVG" => {
-                            let mut sum: f64 = 0.0;
-                            let mut count: i64 = 0;
-                            let func_arg = get_func_arg(&func.args)?;
-                            let col_idx = table_obj.map.get(&func_arg).unwrap(); // get col_idx from func_arg
-                            for r in rows.iter() {
-                                let group_by_row: Vec<DataItem> = group_by_cols_idx.iter().map(|i| r[*i].clone()).collect();
-                                if group_by_row == *row {
-                                    match r[*col_idx] {
-                                        DataItem::Integer(i) => {
-                                            sum += i as f64;
-                                            count += 1;
-                                        },
-                                        DataItem::Float(f) => {
-                                            sum += f;
-                                            count += 1;
-                                        },
-                                        _ => {
-                                            return Err(RsqlError::ExecutionError(format!("unsupported type for AVG: {:?}", r[*col_idx])));
-                                        },
-                                    }
-                                }
-                            }
-                            aggr_row.push(DataItem::Float(sum / count as f64));
-                        },
-                        "SUM" => {
-                            let func_arg = get_func_arg(&func.args)?;
-                            let col_idx = table_obj.map.get(&func_arg).unwrap(); // get col_idx from func_arg
-                            let col_type = table_obj.cols.1[*col_idx].clone();
-                            match col_type {
-                                ColType::Integer => {
-                                    let mut sum = 0 as i64;
-                                    for r in rows.iter() {
-                                        let group_by_row: Vec<DataItem> = group_by_cols_idx.iter().map(|i| r[*i].clone()).collect();
-                                        if group_by_row == *row {
-                                            match r[*col_idx] {
-                                                DataItem::Integer(i) => {
-                                                    sum += i;
-                                                },
-                                                _ => {
-                                                    return Err(RsqlError::ExecutionError(format!("cannot sum other type with integer: {:?}", r[*col_idx])));
-                                                },
-                                            }
-                                        }
-                                    }
-                                    aggr_row.push(DataItem::Integer(sum));
-                                },
-                                ColType::Float => {
-                                    let mut sum = 0.0 as f64;
-                                    for r in rows.iter() {
-                                        let group_by_row: Vec<DataItem> = group_by_cols_idx.iter().map(|i| r[*i].clone()).collect();
-                                        if group_by_row == *row {
-                                            match r[*col_idx] {
-                                                DataItem::Float(f) => {
-                                                    sum += f;
-                                                },
-                                                _ => {
-                                                    return Err(RsqlError::ExecutionError(format!("cannot sum other type with float: {:?}", r[*col_idx])));
-                                                },
-                                            }
-                                        }
-                                    }
-                                    aggr_row.push(DataItem::Float(sum));
-                                },
-                                _ => {
-                                    return Err(RsqlError::ExecutionError(format!("unsupported type for SUM")));
-                                }
-                            }
-                        },
-                        "MIN" => {
-                            let func_arg = get_func_arg(&func.args)?;
-                            let col_idx = table_obj.map.get(&func_arg).unwrap(); // get col_idx from func_arg
-                            let col_type = table_obj.cols.1[*col_idx].clone();
-                            match col_type {
-                                ColType::Integer => {
-                                    let mut min = None;
-                                    for r in rows.iter() {
-                                        let group_by_row: Vec<DataItem> = group_by_cols_idx.iter().map(|i| r[*i].clone()).collect();
-                                        if group_by_row == *row {
-                                            match r[*col_idx] {
-                                                DataItem::Integer(i) => {
-                                                    if min.is_none() || i < min.unwrap() {
-                                                        min = Some(i);
-                                                    }
-                                                },
-                                                _ => {
-                                                    return Err(RsqlError::ExecutionError(format!("cannot min other type with integer: {:?}", r[*col_idx])));
-                                                },
-                                            }
-                                        }
-                                    }
-                                    aggr_row.push(DataItem::Integer(min.unwrap()));
-                                },
-                                ColType::Float => {
-                                    let mut min = None;
-                                    for r in rows.iter() {
-                                        let group_by_row: Vec<DataItem> = group_by_cols_idx.iter().map(|i| r[*i].clone()).collect();
-                                        if group_by_row == *row {
-                                            match r[*col_idx] {
-                                                DataItem::Float(f) => {
-                                                    if min.is_none() || f < min.unwrap() {
-                                                        min = Some(f);
-                                                    }
-                                                },
-                                                _ => {
-                                                    return Err(RsqlError::ExecutionError(format!("cannot min other type with float: {:?}", r[*col_idx])));
-                                                },
-                                            }
-                                        }
-                                    }
-                                    aggr_row.push(DataItem::Float(min.unwrap()));
-                                },
-                                _ => {
-                                    return Err(RsqlError::ExecutionError(format!("unsupported type for MIN")));
-                                },
-                            }
-                        },
-                        "MAX" => {
-                            let func_arg = get_func_arg(&func.args)?;
-                            let col_idx = table_obj.map.get(&func_arg).unwrap(); // get col_idx from func_arg
-                            let col_type = table_obj.cols.1[*col_idx].clone();
-                            match col_type {
-                                ColType::Integer => {
-                                    let mut max = None;
-                                    for r in rows.iter() {
-                                        let group_by_row: Vec<DataItem> = group_by_cols_idx.iter().map(|i| r[*i].clone()).collect();
-                                        if group_by_row == *row {
-                                            match r[*col_idx] {
-                                                DataItem::Integer(i) => {
-                                                    if max.is_none() || i > max.unwrap() {
-                                                        max = Some(i);
-                                                    }
-                                                },
-                                                _ => {
-                                                    return Err(RsqlError::ExecutionError(format!("cannot max other type with integer: {:?}", r[*col_idx])));
-                                                },
-                                            }
-                                        }
-                                    }
-                                    aggr_row.push(DataItem::Integer(max.unwrap()));
-                                },
-                                ColType::Float => {
-                                    let mut max = None;
-                                    for r in rows.iter() {
-                                        let group_by_row: Vec<DataItem> = group_by_cols_idx.iter().map(|i| r[*i].clone()).collect();
-                                        if group_by_row == *row {
-                                            match r[*col_idx] {
-                                                DataItem::Float(f) => {
-                                                    if max.is_none() || f > max.unwrap() {
-                                                        max = Some(f);
-                                                    }
-                                                },
-                                                _ => {
-                                                    return Err(RsqlError::ExecutionError(format!("cannot max other type with float: {:?}", r[*col_idx])));
-                                                },
-                                            }
-                                        }
-                                    }
-                                    aggr_row.push(DataItem::Float(max.unwrap()));
-                                },
-                                _ => {
-                                    return Err(RsqlError::ExecutionError(format!("unsupported type for MAX")));
-                                },
-                            }
-                        },
-                        _ => {
-                            return Err(RsqlError::ExecutionError(format!("Unsupported aggregate function: {:?}", func_name)))
-                        }
-                    }
-                },
-                _ => {
-                    return Err(RsqlError::ExecutionError(format!("Unsupported aggregate expression: {:?}", aggr_expr)))
-                },
-            }
+    // 2. stream the rows of the table through the accumulators
+    let rows = table_obj.table_obj.get_all_rows()?.map(|row| {
+        let mut row = row?;
+        for expr in computed_keys.iter() {
+            let key = eval_group_key(expr, &table_obj.cols, &row)?;
+            row.push(key);
         }
-        aggr_rows.push(aggr_row);
-    }
+        Ok(row)
+    });
+    let aggr_rows = aggregate_rows(rows, &group_by_cols_idx, &aggrs)?;
     Ok((
         (cols_name, cols_type),
         aggr_rows,
         aggr_cols,
     ))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::executor::execute;
+    use crate::execution::test_utils::{query_rows, TestConn};
+    use crate::catalog::SysCatalog;
+    use crate::storage::Table;
+    use crate::transaction::TnxManager;
+    use sqlparser::dialect::GenericDialect;
+    use sqlparser::parser::Parser;
+
+    fn parse_predicate(sql: &str) -> Expr {
+        let stmt = Parser::parse_sql(&GenericDialect {}, sql).unwrap().remove(0);
+        let sqlparser::ast::Statement::Query(query) = stmt else {
+            panic!("not a query");
+        };
+        let sqlparser::ast::SetExpr::Select(select) = *query.body else {
+            panic!("not a select");
+        };
+        select.selection.unwrap()
+    }
+
+    fn varchar(s: &str) -> DataItem {
+        DataItem::VarChar {
+            head: VarCharHead { max_len: 32, len: s.len() as u64, page_ptr: None },
+            value: s.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_float_equality_tolerance() {
+        let cols = vec!["id".to_string(), "price".to_string()];
+        let cols_type = vec![ColType::Integer, ColType::Float];
+        let rows = vec![
+            vec![DataItem::Integer(1), DataItem::Float(0.1 + 0.2)],
+            vec![DataItem::Integer(2), DataItem::Float(0.3)],
+            vec![DataItem::Integer(3), DataItem::Float(3.0)],
+        ];
+        let ids = |filtered: Vec<Vec<DataItem>>| filtered.into_iter().map(|row| row[0].clone()).collect::<Vec<_>>();
+        let col_type = |col: &str| cols.iter().position(|c| c == col).map(|idx| cols_type[idx].clone());
+
+        // equality is strict by default
+        let predicate = parse_predicate("SELECT * FROM t WHERE price = 0.3");
+        assert_eq!(ids(handle_temp_table_filter_expr(&cols, &cols_type, &rows, &predicate).unwrap()), vec![DataItem::Integer(2)]);
+        // an integer literal matches the stored float
+        let predicate = parse_predicate("SELECT * FROM t WHERE price = 3");
+        assert_eq!(ids(handle_temp_table_filter_expr(&cols, &cols_type, &rows, &predicate).unwrap()), vec![DataItem::Integer(3)]);
+
+        // with a tolerance both representations of 0.3 match
+        for (sql, expected) in [("SELECT * FROM t WHERE price = 0.3", vec![1, 2]), ("SELECT * FROM t WHERE 0.3 <> price", vec![3])] {
+            let predicate = flip_literal_comparison(&parse_predicate(sql)).unwrap_or_else(|| parse_predicate(sql));
+            let widened = float_literal_comparison(&predicate, col_type, 1e-9).unwrap().unwrap();
+            assert_eq!(ids(handle_temp_table_filter_expr(&cols, &cols_type, &rows, &widened).unwrap()),
+                expected.into_iter().map(DataItem::Integer).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_not_eq_on_varchar() {
+        let cols = vec!["id".to_string(), "name".to_string()];
+        let cols_type = vec![ColType::Integer, ColType::VarChar(32)];
+        let rows = vec![
+            vec![DataItem::Integer(1), varchar("alice")],
+            vec![DataItem::Integer(2), varchar("bob")],
+            vec![DataItem::Integer(3), varchar("alice")],
+        ];
+        for sql in [
+            "SELECT * FROM t WHERE name <> 'alice'",
+            "SELECT * FROM t WHERE name != 'alice'",
+        ] {
+            let predicate = parse_predicate(sql);
+            assert!(matches!(predicate, Expr::BinaryOp { op: BinaryOperator::NotEq, .. }));
+            let filtered = handle_temp_table_filter_expr(&cols, &cols_type, &rows, &predicate).unwrap();
+            assert_eq!(filtered, vec![vec![DataItem::Integer(2), varchar("bob")]]);
+        }
+    }
+
+    #[test]
+    fn test_insert_select_rows() {
+        let tar_cols = (
+            vec!["id".to_string(), "score".to_string(), "name".to_string()],
+            vec![ColType::Integer, ColType::Float, ColType::VarChar(32)],
+        );
+        let null_cols = vec![DataItem::NullInt, DataItem::NullFloat, DataItem::NullVarChar];
+        let cols = vec!["id".to_string(), "score".to_string()];
+        let src_cols = (
+            vec!["x".to_string(), "y".to_string()],
+            vec![ColType::Integer, ColType::Integer],
+        );
+        let rows = vec![
+            vec![DataItem::Integer(1), DataItem::Integer(90)],
+            vec![DataItem::Integer(2), DataItem::NullInt],
+        ];
+        let data_items = handle_insert_rows(&tar_cols, &cols, &null_cols, &src_cols, &rows).unwrap();
+        assert_eq!(data_items, vec![
+            vec![DataItem::Integer(1), DataItem::Float(90.0), DataItem::NullVarChar],
+            vec![DataItem::Integer(2), DataItem::NullFloat, DataItem::NullVarChar],
+        ]);
+
+        // chars are accepted by varchar columns
+        let cols = vec!["name".to_string()];
+        let src_cols = (vec!["s".to_string()], vec![ColType::Chars(8)]);
+        let rows = vec![vec![DataItem::Chars { len: 8, value: "bob".to_string() }]];
+        let data_items = handle_insert_rows(&tar_cols, &cols, &null_cols, &src_cols, &rows).unwrap();
+        assert_eq!(data_items[0][2], varchar("bob"));
+    }
+
+    #[test]
+    fn test_insert_select_mismatch() {
+        let tar_cols = (
+            vec!["id".to_string(), "name".to_string()],
+            vec![ColType::Integer, ColType::VarChar(32)],
+        );
+        let null_cols = vec![DataItem::NullInt, DataItem::NullVarChar];
+        let cols = tar_cols.0.clone();
+        let rows = vec![vec![DataItem::Integer(1)]];
+
+        // column count mismatch
+        let src_cols = (vec!["x".to_string()], vec![ColType::Integer]);
+        assert!(handle_insert_rows(&tar_cols, &cols, &null_cols, &src_cols, &rows).is_err());
+
+        // column type mismatch
+        let src_cols = (
+            vec!["x".to_string(), "y".to_string()],
+            vec![ColType::Integer, ColType::Float],
+        );
+        let rows = vec![vec![DataItem::Integer(1), DataItem::Float(1.5)]];
+        assert!(handle_insert_rows(&tar_cols, &cols, &null_cols, &src_cols, &rows).is_err());
+
+        // unknown target column
+        let cols = vec!["id".to_string(), "age".to_string()];
+        let src_cols = (
+            vec!["x".to_string(), "y".to_string()],
+            vec![ColType::Integer, ColType::Integer],
+        );
+        let rows = vec![vec![DataItem::Integer(1), DataItem::Integer(2)]];
+        assert!(handle_insert_rows(&tar_cols, &cols, &null_cols, &src_cols, &rows).is_err());
+    }
+
+    #[test]
+    fn test_parse_number_edge_cases() {
+        assert_eq!(parse_number("42").unwrap(), DataItem::Integer(42));
+        assert_eq!(parse_number("1e10").unwrap(), DataItem::Float(1e10));
+        // overflows i64, must not be rounded to a float
+        assert!(parse_number("9999999999999999999").is_err());
+        assert!(parse_number("-9999999999999999999").is_err());
+        // non-finite floats
+        assert!(parse_number("inf").is_err());
+        assert!(parse_number("NaN").is_err());
+        assert!(parse_number("1e400").is_err());
+
+        // the target column type decides the representation
+        assert_eq!(parse_number_as("3", &ColType::Float).unwrap(), DataItem::Float(3.0));
+        assert_eq!(parse_number_as("9999999999999999999", &ColType::Float).unwrap(), DataItem::Float(9999999999999999999.0));
+        assert!(parse_number_as("inf", &ColType::Float).is_err());
+        assert!(parse_number_as("1.5", &ColType::Integer).is_err());
+        assert!(parse_number_as("9999999999999999999", &ColType::Integer).is_err());
+    }
+
+    fn parse_projection(sql: &str) -> Vec<Expr> {
+        let stmt = Parser::parse_sql(&GenericDialect {}, sql).unwrap().remove(0);
+        let sqlparser::ast::Statement::Query(query) = stmt else {
+            panic!("not a query");
+        };
+        let sqlparser::ast::SetExpr::Select(select) = *query.body else {
+            panic!("not a select");
+        };
+        select.projection.into_iter().map(|item| match item {
+            sqlparser::ast::SelectItem::UnnamedExpr(expr) => expr,
+            _ => panic!("not an unnamed expr"),
+        }).collect()
+    }
+
+    #[test]
+    fn test_scalar_functions() {
+        let cols = (
+            vec!["i".to_string(), "f".to_string(), "c".to_string(), "v".to_string()],
+            vec![ColType::Integer, ColType::Float, ColType::Chars(8), ColType::VarChar(32)],
+        );
+        let rows = vec![
+            vec![DataItem::Integer(-1250), DataItem::Float(-2.345), DataItem::Chars { len: 8, value: "MiXed".to_string() }, varchar("Hello, 世界")],
+            vec![DataItem::NullInt, DataItem::Float(7.5), DataItem::NullChars { len: 8 }, varchar("")],
+        ];
+        let exprs = parse_projection(
+            "SELECT ABS(i), ABS(f), ROUND(f), ROUND(f, 2), ROUND(i, -2), LENGTH(c), LENGTH(v), UPPER(c), LOWER(v) FROM t"
+        );
+        let ((names, types), result) = handle_projection_exprs(&exprs, &cols, &rows).unwrap();
+        assert_eq!(names[0], "ABS(i)");
+        assert!(matches!(
+            types.as_slice(),
+            [ColType::Integer, ColType::Float, ColType::Float, ColType::Float, ColType::Integer,
+             ColType::Integer, ColType::Integer, ColType::Chars(8), ColType::VarChar(32)]
+        ));
+        assert_eq!(result[0], vec![
+            DataItem::Integer(1250),
+            DataItem::Float(2.345),
+            DataItem::Float(-2.0),
+            DataItem::Float(-2.35),
+            DataItem::Integer(-1300),
+            DataItem::Integer(5),
+            DataItem::Integer(9),
+            DataItem::Chars { len: 8, value: "MIXED".to_string() },
+            varchar("hello, 世界"),
+        ]);
+        // nulls stay null, typed like the result
+        assert_eq!(result[1][0], DataItem::NullInt);
+        assert_eq!(result[1][3], DataItem::Float(7.5));
+        assert_eq!(result[1][5], DataItem::NullInt);
+        assert_eq!(result[1][6], DataItem::Integer(0));
+        assert_eq!(result[1][7], DataItem::NullChars { len: 8 });
+
+        // wrong argument types fail before any row is evaluated
+        for sql in ["SELECT ABS(c) FROM t", "SELECT LENGTH(i) FROM t", "SELECT UPPER(f) FROM t", "SELECT ROUND(f, 1.5) FROM t", "SELECT SQRT(f) FROM t"] {
+            assert!(handle_projection_exprs(&parse_projection(sql), &cols, &rows).is_err(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_cast_to_character_types() {
+        let cols = (
+            vec!["i".to_string(), "f".to_string(), "v".to_string()],
+            vec![ColType::Integer, ColType::Float, ColType::VarChar(32)],
+        );
+        let rows = vec![
+            vec![DataItem::Integer(42), DataItem::Float(2.5), varchar(" 17 ")],
+            vec![DataItem::NullInt, DataItem::Float(-0.4), varchar("x")],
+        ];
+        let exprs = parse_projection("SELECT CAST(i AS CHAR(5)), CAST(i AS VARCHAR(5)), CAST(f AS INTEGER) FROM t");
+        let ((_, types), result) = handle_projection_exprs(&exprs, &cols, &rows).unwrap();
+        assert!(matches!(types.as_slice(), [ColType::Chars(5), ColType::VarChar(5), ColType::Integer]));
+        // CHAR is padded to its length, VARCHAR is not
+        assert_eq!(result[0], vec![
+            DataItem::Chars { len: 5, value: "42   ".to_string() },
+            varchar("42"),
+            DataItem::Integer(3),
+        ]);
+        assert_eq!(result[1], vec![DataItem::NullChars { len: 5 }, DataItem::NullVarChar, DataItem::Integer(0)]);
+
+        let exprs = parse_projection("SELECT CAST(v AS INTEGER) FROM t");
+        assert_eq!(handle_projection_exprs(&exprs, &cols, &rows[..1]).unwrap().1[0][0], DataItem::Integer(17));
+        // longer than the target length, or not a number
+        for sql in ["SELECT CAST(f AS VARCHAR(2)) FROM t", "SELECT CAST(v AS INTEGER) FROM t"] {
+            assert!(handle_projection_exprs(&parse_projection(sql), &cols, &rows).is_err(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_literal_on_left_comparison() {
+        let cols = vec!["name".to_string(), "age".to_string()];
+        let cols_type = vec![ColType::VarChar(32), ColType::Integer];
+        let rows = vec![
+            vec![varchar("Alice"), DataItem::Integer(25)],
+            vec![varchar("Mia"), DataItem::Integer(30)],
+            vec![varchar("Zoe"), DataItem::Integer(41)],
+        ];
+        for (sql, expected) in [
+            ("SELECT * FROM t WHERE 30 > age", vec!["Alice"]),
+            ("SELECT * FROM t WHERE 30 <= age", vec!["Mia", "Zoe"]),
+            ("SELECT * FROM t WHERE 30 = age", vec!["Mia"]),
+            ("SELECT * FROM t WHERE 'M' <= name", vec!["Mia", "Zoe"]),
+            ("SELECT * FROM t WHERE 'Zoe' > name AND 20 < age", vec!["Alice", "Mia"]),
+        ] {
+            let predicate = parse_predicate(sql);
+            let filtered = handle_temp_table_filter_expr(&cols, &cols_type, &rows, &predicate).unwrap();
+            let names: Vec<DataItem> = filtered.into_iter().map(|row| row[0].clone()).collect();
+            assert_eq!(names, expected.into_iter().map(varchar).collect::<Vec<_>>(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_escaped_string_literals() {
+        let cols = vec!["id".to_string(), "name".to_string()];
+        let cols_type = vec![ColType::Integer, ColType::VarChar(32)];
+        let rows = vec![
+            vec![DataItem::Integer(1), varchar("O'Brien")],
+            vec![DataItem::Integer(2), varchar("line1\nline2")],
+            vec![DataItem::Integer(3), varchar("日本語 🎉")],
+        ];
+        for (sql, id) in [
+            ("SELECT * FROM t WHERE name = 'O''Brien'", 1),
+            ("SELECT * FROM t WHERE name = 'line1\nline2'", 2),
+            ("SELECT * FROM t WHERE name = E'line1\\nline2'", 2),
+            ("SELECT * FROM t WHERE name = '日本語 🎉'", 3),
+        ] {
+            let predicate = parse_predicate(sql);
+            let filtered = handle_temp_table_filter_expr(&cols, &cols_type, &rows, &predicate).unwrap();
+            assert_eq!(filtered.len(), 1, "{sql}");
+            assert_eq!(filtered[0][0], DataItem::Integer(id), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_not_in_subquery() {
+        let cols = vec!["id".to_string(), "name".to_string()];
+        let rows = vec![
+            vec![DataItem::Integer(1), varchar("alice")],
+            vec![DataItem::Integer(2), varchar("bob")],
+            vec![DataItem::NullInt, varchar("carol")],
+        ];
+        let sub_cols = vec!["uid".to_string()];
+        let not_in = ApplyType::In { expr: Box::new(Expr::Identifier("id".into())), negated: true };
+        let is_in = ApplyType::In { expr: Box::new(Expr::Identifier("id".into())), negated: false };
+
+        // inner set without NULL
+        let sub_rows = vec![vec![DataItem::Integer(1)], vec![DataItem::Integer(3)]];
+        let kept = handle_subquery_apply(&cols, rows.clone(), &not_in, &sub_cols, &sub_rows).unwrap();
+        assert_eq!(kept, vec![rows[1].clone()]);
+        let kept = handle_subquery_apply(&cols, rows.clone(), &is_in, &sub_cols, &sub_rows).unwrap();
+        assert_eq!(kept, vec![rows[0].clone()]);
+
+        // a NULL in the inner set makes NOT IN reject every row
+        let sub_rows = vec![vec![DataItem::Integer(1)], vec![DataItem::NullInt]];
+        assert!(handle_subquery_apply(&cols, rows.clone(), &not_in, &sub_cols, &sub_rows).unwrap().is_empty());
+        let kept = handle_subquery_apply(&cols, rows.clone(), &is_in, &sub_cols, &sub_rows).unwrap();
+        assert_eq!(kept, vec![rows[0].clone()]);
+
+        // NOT IN an empty set keeps everything
+        let kept = handle_subquery_apply(&cols, rows.clone(), &not_in, &sub_cols, &[]).unwrap();
+        assert_eq!(kept, rows);
+
+        // strings match across chars and varchar
+        let by_name = ApplyType::In { expr: Box::new(Expr::Identifier("name".into())), negated: true };
+        let sub_rows = vec![vec![DataItem::Chars { len: 8, value: "bob".to_string() }]];
+        let kept = handle_subquery_apply(&cols, rows.clone(), &by_name, &sub_cols, &sub_rows).unwrap();
+        assert_eq!(kept, vec![rows[0].clone(), rows[2].clone()]);
+
+        // EXISTS / NOT EXISTS
+        let exists = ApplyType::Exists { negated: false };
+        let not_exists = ApplyType::Exists { negated: true };
+        assert_eq!(handle_subquery_apply(&cols, rows.clone(), &exists, &sub_cols, &sub_rows).unwrap(), rows);
+        assert!(handle_subquery_apply(&cols, rows.clone(), &not_exists, &sub_cols, &sub_rows).unwrap().is_empty());
+        assert_eq!(handle_subquery_apply(&cols, rows.clone(), &not_exists, &sub_cols, &[]).unwrap(), rows);
+
+        // IN subquery must return one column
+        let two_cols = vec!["a".to_string(), "b".to_string()];
+        assert!(handle_subquery_apply(&cols, rows.clone(), &is_in, &two_cols, &[]).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_rows_single_pass() {
+        // (dept, salary) rows over many groups, every aggregate is folded in the same pass
+        let rows: Vec<Vec<DataItem>> = (0..20000)
+            .map(|i| vec![DataItem::Integer(i % 2000), DataItem::Integer(i)])
+            .collect();
+        let aggrs = [
+            (Accumulator::new("SUM", Some(&ColType::Integer)).unwrap(), Some(1)),
+            (Accumulator::new("AVG", None).unwrap(), Some(1)),
+            (Accumulator::new("MAX", Some(&ColType::Integer)).unwrap(), Some(1)),
+            (Accumulator::new("COUNT", None).unwrap(), None),
+        ];
+        let start = std::time::Instant::now();
+        let aggr_rows = aggregate_rows(rows.iter().cloned().map(Ok), &[0], &aggrs).unwrap();
+        // rescanning all rows for each group and aggregate takes seconds here
+        assert!(start.elapsed() < std::time::Duration::from_secs(1), "took {:?}", start.elapsed());
+        assert_eq!(aggr_rows.len(), 2000);
+        // dept 7 holds 7, 2007, ..., 18007
+        assert_eq!(aggr_rows[7], vec![
+            DataItem::Integer(7),
+            DataItem::Integer(10 * 7 + 2000 * 45),
+            DataItem::Float(9007.0),
+            DataItem::Integer(18007),
+            DataItem::Integer(10),
+        ]);
+        assert!(Accumulator::new("SUM", Some(&ColType::Bool)).is_err());
+    }
+
+    #[test]
+    fn test_aggregate_rows_streams_input() {
+        // the rows are generated one at a time and never collected
+        let rows = (0..300_000i64).map(|i| Ok(vec![DataItem::Integer(i % 3), DataItem::Integer(i)]));
+        let aggrs = [
+            (Accumulator::new("COUNT", None).unwrap(), None),
+            (Accumulator::new("MIN", Some(&ColType::Integer)).unwrap(), Some(1)),
+        ];
+        let aggr_rows = aggregate_rows(rows, &[0], &aggrs).unwrap();
+        assert_eq!(aggr_rows, (0..3).map(|g| vec![DataItem::Integer(g), DataItem::Integer(100_000), DataItem::Integer(g)]).collect::<Vec<_>>());
+
+        // a failing row stops the aggregation
+        let rows = (0..10i64).map(|i| if i == 5 {
+            Err(RsqlError::StorageError("bad row".to_string()))
+        } else {
+            Ok(vec![DataItem::Integer(i % 2), DataItem::Integer(i)])
+        });
+        assert!(aggregate_rows(rows, &[0], &aggrs).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_string_column_sizes() {
+        let test_conn = TestConn::setup(&["sized_strings"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE sized_strings (id INTEGER PRIMARY KEY, code CHAR(4), note VARCHAR(5))", conn).unwrap();
+
+        // over-length values are rejected for both string types
+        assert!(execute("INSERT INTO sized_strings VALUES (1, 'ab', 'too long')", conn).is_err());
+        assert!(execute("INSERT INTO sized_strings VALUES (1, 'abcde', 'hi')", conn).is_err());
+
+        // a short CHAR is padded to its declared size
+        execute("INSERT INTO sized_strings VALUES (1, 'ab', 'hello')", conn).unwrap();
+        let res = execute("SELECT code, note FROM sized_strings WHERE code = 'ab'", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], DataItem::Chars { len: 4, value: "ab".to_string() });
+        assert_eq!(rows[0][0].to_bytes().unwrap().0.len(), 1 + 8 + 4);
+        assert!(execute("UPDATE sized_strings SET note = 'longer' WHERE id = 1", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_order_by_positions_and_exprs() {
+        let test_conn = TestConn::setup(&["sort_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE sort_table (id INTEGER PRIMARY KEY, a INTEGER, b INTEGER)", conn).unwrap();
+        for (id, a, b) in [(1, 5, 1), (2, 1, 2), (3, 3, 9)] {
+            execute(&format!("INSERT INTO sort_table VALUES ({}, {}, {})", id, a, b), conn).unwrap();
+        }
+        let ids = |sql: &str| -> Vec<DataItem> {
+            let res = execute(sql, conn).unwrap();
+            query_rows(&res[0]).1.into_iter().map(|row| row[0].clone()).collect()
+        };
+
+        // position 2 is column a
+        assert_eq!(ids("SELECT id, a FROM sort_table ORDER BY 2 DESC"), vec![DataItem::Integer(1), DataItem::Integer(3), DataItem::Integer(2)]);
+        // a + b is 6, 3 and 12
+        assert_eq!(ids("SELECT id, a, b FROM sort_table ORDER BY a + b"), vec![DataItem::Integer(2), DataItem::Integer(1), DataItem::Integer(3)]);
+        assert_eq!(ids("SELECT * FROM sort_table ORDER BY a * -1, id"), vec![DataItem::Integer(1), DataItem::Integer(3), DataItem::Integer(2)]);
+        assert!(execute("SELECT id FROM sort_table ORDER BY 3", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_bool_index_lookup() {
+        let test_conn = TestConn::setup(&["bool_index_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE bool_index_table (id INTEGER PRIMARY KEY, flag BOOL)", conn).unwrap();
+        execute("CREATE INDEX bool_index_table_flag ON bool_index_table (flag)", conn).unwrap();
+        for i in 0..10 {
+            execute(&format!("INSERT INTO bool_index_table VALUES ({}, {})", i, i % 3 == 0), conn).unwrap();
+        }
+
+        let res = execute("SELECT id FROM bool_index_table WHERE flag = true", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        let ids: Vec<_> = rows.iter().map(|row| row[0].clone()).collect();
+        assert_eq!(ids, [0, 3, 6, 9].map(DataItem::Integer));
+        let res = execute("SELECT id FROM bool_index_table WHERE flag BETWEEN false AND false", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1.len(), 6);
+
+        // false keys sort before true keys in the index
+        let res = execute("DEBUG INDEX bool_index_table flag", conn).unwrap();
+        let (_, lines) = query_rows(&res[0]);
+        let keys: Vec<String> = lines.iter().filter_map(|line| match &line[0] {
+            DataItem::VarChar { value, .. } if value.trim_start().starts_with("Bool(") => Some(value.trim_start()[..10].to_string()),
+            _ => None,
+        }).collect();
+        assert_eq!(keys.len(), 10);
+        assert!(keys[..6].iter().all(|key| key.starts_with("Bool(false")));
+        assert!(keys[6..].iter().all(|key| key.starts_with("Bool(true)")));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_select_wildcard_with_extra_columns() {
+        let test_conn = TestConn::setup(&["wildcard_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE wildcard_table (id INTEGER PRIMARY KEY, price INTEGER)", conn).unwrap();
+        execute("INSERT INTO wildcard_table VALUES (1, 10)", conn).unwrap();
+        execute("INSERT INTO wildcard_table VALUES (2, 25)", conn).unwrap();
+
+        let res = execute("SELECT *, price * 2 FROM wildcard_table", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["id", "price", "price * 2"]);
+        assert_eq!(rows, vec![
+            [1, 10, 20].map(DataItem::Integer).to_vec(),
+            [2, 25, 50].map(DataItem::Integer).to_vec(),
+        ]);
+        let res = execute("SELECT *, price * 2 AS double FROM wildcard_table ORDER BY double DESC", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["id", "price", "double"]);
+        assert_eq!(rows, vec![
+            [2, 25, 50].map(DataItem::Integer).to_vec(),
+            [1, 10, 20].map(DataItem::Integer).to_vec(),
+        ]);
+        let res = execute("SELECT id, * FROM wildcard_table WHERE price > 20", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["id", "id", "price"]);
+        assert_eq!(rows, vec![[2, 2, 25].map(DataItem::Integer).to_vec()]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_group_by_several_aggregates_of_one_column() {
+        let test_conn = TestConn::setup(&["aggr_emp"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE aggr_emp (id INTEGER PRIMARY KEY, dept INTEGER, salary INTEGER)", conn).unwrap();
+        for (id, dept, salary) in [(1, 10, 100), (2, 20, 300), (3, 10, 200), (4, 20, 500), (5, 10, 600)] {
+            execute(&format!("INSERT INTO aggr_emp VALUES ({}, {}, {})", id, dept, salary), conn).unwrap();
+        }
+
+        let res = execute("SELECT dept, SUM(salary), AVG(salary), MAX(salary) FROM aggr_emp GROUP BY dept", conn).unwrap();
+        let (cols, mut rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["dept", "SUM_salary", "AVG_salary", "MAX_salary"]);
+        rows.sort_by_key(|row| row[0].display());
+        assert_eq!(rows, vec![
+            vec![DataItem::Integer(10), DataItem::Integer(900), DataItem::Float(300.0), DataItem::Integer(600)],
+            vec![DataItem::Integer(20), DataItem::Integer(800), DataItem::Float(400.0), DataItem::Integer(500)],
+        ]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_update_division() {
+        let test_conn = TestConn::setup(&["upd_div"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE upd_div (id INTEGER PRIMARY KEY, y INTEGER, half INTEGER, ratio FLOAT)", conn).unwrap();
+        execute("INSERT INTO upd_div VALUES (1, 7, 0, 0.0)", conn).unwrap();
+        let row = || {
+            let res = execute("SELECT * FROM upd_div WHERE id = 1", conn).unwrap();
+            query_rows(&res[0]).1.remove(0)
+        };
+        // integer by integer truncates
+        execute("UPDATE upd_div SET half = y / 2 WHERE id = 1", conn).unwrap();
+        assert_eq!(row()[2], DataItem::Integer(3));
+        // a float operand promotes the division to float
+        execute("UPDATE upd_div SET ratio = y / 2.0 WHERE id = 1", conn).unwrap();
+        assert_eq!(row()[3], DataItem::Float(3.5));
+        execute("UPDATE upd_div SET ratio = y / 2 WHERE id = 1", conn).unwrap();
+        assert_eq!(row()[3], DataItem::Float(3.0));
+        assert!(execute("UPDATE upd_div SET half = y / 2.0 WHERE id = 1", conn).is_err());
+
+        for sql in ["UPDATE upd_div SET half = y / 0 WHERE id = 1", "UPDATE upd_div SET ratio = y / 0.0 WHERE id = 1"] {
+            let err = execute(sql, conn).unwrap_err();
+            assert!(err.to_string().contains("Division by zero"), "{}", err);
+        }
+        assert_eq!(row(), vec![DataItem::Integer(1), DataItem::Integer(7), DataItem::Integer(3), DataItem::Float(3.0)]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_check_constraints() {
+        let test_conn = TestConn::setup(&["checked"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE checked (id INTEGER PRIMARY KEY, age INTEGER CHECK (age >= 0), lo FLOAT, hi FLOAT, CHECK (lo < hi))", conn).unwrap();
+        execute("INSERT INTO checked VALUES (1, 30, 1.5, 2)", conn).unwrap();
+        // a null makes the check unknown, which passes
+        execute("INSERT INTO checked (id, lo, hi) VALUES (2, 0.5, 1)", conn).unwrap();
+
+        let violation = |sql: &str| match execute(sql, conn) {
+            Err(RsqlError::ConstraintViolation(msg)) => msg,
+            other => panic!("expected a constraint violation for {sql}, got {:?}", other.map(|_| ())),
+        };
+        assert!(violation("INSERT INTO checked VALUES (3, -1, 1.5, 2)").contains("age >= 0"));
+        assert!(violation("INSERT INTO checked VALUES (3, 20, 2, 2)").contains("lo < hi"));
+        assert!(violation("UPDATE checked SET age = age - 31 WHERE id = 1").contains("age >= 0"));
+        let res = execute("SELECT age FROM checked WHERE id = 1", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(30)]]);
+
+        // the check names a column of the table
+        execute("DROP TABLE IF EXISTS bad_check", conn).unwrap();
+        assert!(execute("CREATE TABLE bad_check (id INTEGER PRIMARY KEY, CHECK (missing > 0))", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_insert_default_values() {
+        let test_conn = TestConn::setup(&["defaulted"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE defaulted (id INTEGER PRIMARY KEY, score INTEGER DEFAULT 7, delta INTEGER DEFAULT -2, note CHAR(8))", conn).unwrap();
+        execute("INSERT INTO defaulted (id, score, note) VALUES (1, DEFAULT, DEFAULT)", conn).unwrap();
+        // an omitted column takes its default as well
+        execute("INSERT INTO defaulted (id, note) VALUES (2, 'x')", conn).unwrap();
+        execute("INSERT INTO defaulted VALUES (3, 1, default, NULL)", conn).unwrap();
+
+        let res = execute("SELECT id, score, delta, note FROM defaulted", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![
+            vec![DataItem::Integer(1), DataItem::Integer(7), DataItem::Integer(-2), DataItem::NullChars { len: 8 }],
+            vec![DataItem::Integer(2), DataItem::Integer(7), DataItem::Integer(-2), DataItem::Chars { len: 8, value: "x".to_string() }],
+            vec![DataItem::Integer(3), DataItem::Integer(1), DataItem::Integer(-2), DataItem::NullChars { len: 8 }],
+        ]);
+
+        // the default must fit its column
+        execute("DROP TABLE IF EXISTS bad_default", conn).unwrap();
+        assert!(execute("CREATE TABLE bad_default (id INTEGER PRIMARY KEY, score INTEGER DEFAULT 'seven')", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_index_or_scan_by_selectivity() {
+        let test_conn = TestConn::setup(&["selectivity"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE selectivity (id INTEGER PRIMARY KEY, flag INTEGER, code INTEGER)", conn).unwrap();
+        for id in 0..40 {
+            execute(&format!("INSERT INTO selectivity VALUES ({}, {}, {})", id, id % 2, id), conn).unwrap();
+        }
+        execute("CREATE INDEX selectivity_flag ON selectivity (flag)", conn).unwrap();
+        execute("CREATE INDEX selectivity_code ON selectivity (code)", conn).unwrap();
+        let tnx_id = TnxManager::global().begin_transaction(conn);
+        let table_id = SysCatalog::global().get_table_id(tnx_id, "selectivity").unwrap().unwrap();
+        TnxManager::global().end_transaction(conn);
+
+        // half of the rows match, the scan is cheaper than the index
+        let (lookups, scans) = Table::read_counts(table_id);
+        let res = execute("SELECT id FROM selectivity WHERE flag = 1", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1.len(), 20);
+        assert_eq!(Table::read_counts(table_id), (lookups, scans + 1));
+
+        // a single row matches, the index is used
+        let res = execute("SELECT id FROM selectivity WHERE code = 7", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(7)]]);
+        assert_eq!(Table::read_counts(table_id), (lookups + 1, scans + 1));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_not_eq_through_index() {
+        let test_conn = TestConn::setup(&[]);
+        let conn = test_conn.id;
+        let table_id = |name: &str| {
+            let tnx_id = TnxManager::global().begin_transaction(conn);
+            let table_id = SysCatalog::global().get_table_id(tnx_id, name).unwrap().unwrap();
+            TnxManager::global().end_transaction(conn);
+            table_id
+        };
+        let ids = |sql: &str| {
+            let (_, rows) = query_rows(&execute(sql, conn).unwrap()[0]);
+            rows.into_iter().map(|row| row[0].clone()).collect::<Vec<_>>()
+        };
+
+        // without a primary key the rows are only reachable through the index
+        execute("DROP TABLE IF EXISTS ne_keyless", conn).unwrap();
+        execute("CREATE TABLE ne_keyless (code INTEGER UNIQUE, name CHAR(8) UNIQUE)", conn).unwrap();
+        execute("INSERT INTO ne_keyless VALUES (1, 'a'), (2, 'b'), (3, 'c')", conn).unwrap();
+        let keyless = table_id("ne_keyless");
+        let (lookups, scans) = Table::read_counts(keyless);
+        assert_eq!(ids("SELECT code FROM ne_keyless WHERE code <> 2"), [1, 3].map(DataItem::Integer));
+        assert_eq!(ids("SELECT code FROM ne_keyless WHERE name != 'a'"), [2, 3].map(DataItem::Integer));
+        assert_eq!(Table::read_counts(keyless), (lookups + 4, scans));
+
+        // most rows differ from the value, a full scan reads less than two index ranges
+        execute("DROP TABLE IF EXISTS ne_spread", conn).unwrap();
+        execute("CREATE TABLE ne_spread (id INTEGER PRIMARY KEY, code INTEGER)", conn).unwrap();
+        for id in 0..10 {
+            execute(&format!("INSERT INTO ne_spread VALUES ({}, {})", id, id * 10), conn).unwrap();
+        }
+        execute("CREATE INDEX ne_spread_code ON ne_spread (code)", conn).unwrap();
+        let spread = table_id("ne_spread");
+        let (lookups, scans) = Table::read_counts(spread);
+        assert_eq!(ids("SELECT id FROM ne_spread WHERE code <> 30").len(), 9);
+        assert_eq!(ids("SELECT id FROM ne_spread WHERE id <> 3").len(), 9);
+        assert_eq!(Table::read_counts(spread), (lookups, scans + 2));
+
+        execute("DROP TABLE ne_keyless", conn).unwrap();
+        execute("DROP TABLE ne_spread", conn).unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_row_valued_in_subquery_rejected() {
+        let test_conn = TestConn::setup(&["in_outer", "in_inner"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE in_outer (id INTEGER PRIMARY KEY, grp INTEGER)", conn).unwrap();
+        execute("CREATE TABLE in_inner (id INTEGER PRIMARY KEY, grp INTEGER)", conn).unwrap();
+        for (id, grp) in [(1, 10), (2, 20), (3, 30)] {
+            execute(&format!("INSERT INTO in_outer VALUES ({}, {})", id, grp), conn).unwrap();
+        }
+        for (id, grp) in [(1, 10), (2, 99)] {
+            execute(&format!("INSERT INTO in_inner VALUES ({}, {})", id, grp), conn).unwrap();
+        }
+
+        let err = execute("SELECT id FROM in_outer WHERE (id, grp) IN (SELECT id, grp FROM in_inner)", conn).unwrap_err();
+        assert!(matches!(&err, RsqlError::ExecutionError(msg) if msg == "row-valued IN not supported"), "{:?}", err);
+        let res = execute("SELECT id FROM in_outer WHERE id IN (SELECT id FROM in_inner)", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, [1, 2].map(|id| vec![DataItem::Integer(id)]));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_group_by_key_only() {
+        let test_conn = TestConn::setup(&["group_key_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE group_key_table (id INTEGER PRIMARY KEY, dept CHAR(8))", conn).unwrap();
+        for (id, dept) in [(1, "eng"), (2, "ops"), (3, "eng"), (4, "hr"), (5, "ops")] {
+            execute(&format!("INSERT INTO group_key_table VALUES ({}, '{}')", id, dept), conn).unwrap();
+        }
+        // no aggregate in the select list, each group gives one row like DISTINCT
+        let res = execute("SELECT dept FROM group_key_table GROUP BY dept", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, vec!["dept".to_string()]);
+        let mut depts: Vec<String> = rows.iter().map(|row| row[0].display()).collect();
+        depts.sort();
+        assert_eq!(depts, vec!["eng", "hr", "ops"]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_timestamp_default_current_timestamp() {
+        let test_conn = TestConn::setup(&["ts_default"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE ts_default (id INTEGER PRIMARY KEY, created TIMESTAMP DEFAULT CURRENT_TIMESTAMP)", conn).unwrap();
+        let now = || std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+        let before = now();
+        execute("INSERT INTO ts_default (id) VALUES (1)", conn).unwrap();
+        execute("INSERT INTO ts_default (id) VALUES (2)", conn).unwrap();
+        let after = now();
+        // an explicit value still wins over the default
+        execute("INSERT INTO ts_default VALUES (3, 42)", conn).unwrap();
+
+        let res = execute("SELECT id, created FROM ts_default", conn).unwrap();
+        let (_, mut rows) = query_rows(&res[0]);
+        rows.sort_by_key(|row| row[0].display());
+        for row in &rows[..2] {
+            let DataItem::Integer(created) = row[1] else {
+                panic!("expected a timestamp, got {:?}", row[1]);
+            };
+            assert!((before..=after).contains(&created), "{created} not in {before}..={after}");
+        }
+        assert_eq!(rows[2][1], DataItem::Integer(42));
+
+        // the default cannot fill a column of another type
+        execute("DROP TABLE IF EXISTS ts_default", conn).unwrap();
+        assert!(execute("CREATE TABLE ts_default (id INTEGER PRIMARY KEY, created CHAR(8) DEFAULT CURRENT_TIMESTAMP)", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_insert_column_list_checks() {
+        let test_conn = TestConn::setup(&["insert_checks"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE insert_checks (id INTEGER PRIMARY KEY, qty INTEGER)", conn).unwrap();
+        let invalid = |sql: &str| match execute(sql, conn) {
+            Err(RsqlError::InvalidInput(msg)) => msg,
+            other => panic!("expected invalid input for {sql}, got {:?}", other.map(|_| ())),
+        };
+        assert!(invalid("INSERT INTO insert_checks (id, missing) VALUES (1, 2)").contains("Column missing not found"));
+        assert!(invalid("INSERT INTO insert_checks VALUES (1, 2, 3)").contains("3 values but only 2 columns"));
+        assert!(invalid("INSERT INTO insert_checks (id, qty) VALUES (1)").contains("1 values for 2 columns"));
+        assert!(invalid("INSERT INTO insert_checks (id) VALUES (1, 2)").contains("2 values for 1 columns"));
+
+        // nothing was inserted, and a short list without column names still fills the rest with defaults
+        execute("INSERT INTO insert_checks VALUES (1)", conn).unwrap();
+        let res = execute("SELECT * FROM insert_checks", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(1), DataItem::NullInt]]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_update_multi_column_expr() {
+        let test_conn = TestConn::setup(&["upd_expr"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE upd_expr (id INTEGER PRIMARY KEY, price INTEGER, qty INTEGER, total INTEGER, tag CHAR(8))", conn).unwrap();
+        execute("INSERT INTO upd_expr VALUES (1, 3, 4, 0, 'ab'), (2, 5, 6, 0, 'cd')", conn).unwrap();
+        let rows = || {
+            let res = execute("SELECT * FROM upd_expr", conn).unwrap();
+            query_rows(&res[0]).1
+        };
+        execute("UPDATE upd_expr SET total = price * qty", conn).unwrap();
+        assert_eq!(rows().iter().map(|r| r[3].clone()).collect::<Vec<_>>(), vec![DataItem::Integer(12), DataItem::Integer(30)]);
+        // the old forms keep working and all assignments read the row before the update
+        execute("UPDATE upd_expr SET price = qty, qty = price, total = total + 1, tag = UPPER(tag) WHERE id = 1", conn).unwrap();
+        let row = rows().remove(0);
+        assert_eq!(row[1..4], [DataItem::Integer(4), DataItem::Integer(3), DataItem::Integer(13)]);
+        assert!(matches!(&row[4], DataItem::Chars { value, .. } if value.trim_end() == "AB"), "{:?}", row[4]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_null_safe_equality() {
+        let test_conn = TestConn::setup(&["pairs"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE pairs (id INTEGER PRIMARY KEY, a INTEGER, b INTEGER)", conn).unwrap();
+        execute("INSERT INTO pairs VALUES (1, NULL, NULL), (2, 1, NULL), (3, 1, 1), (4, 1, 2)", conn).unwrap();
+        let ids = |sql: &str| {
+            let (_, rows) = query_rows(&execute(sql, conn).unwrap()[0]);
+            rows.iter().map(|row| row[0].clone()).collect::<Vec<_>>()
+        };
+        let int = DataItem::Integer;
+        assert_eq!(ids("SELECT id FROM pairs WHERE a = b"), [int(3)]);
+        assert_eq!(ids("SELECT id FROM pairs WHERE a IS NOT DISTINCT FROM b"), [int(1), int(3)]);
+        assert_eq!(ids("SELECT id FROM pairs WHERE a <=> b"), [int(1), int(3)]);
+        assert_eq!(ids("SELECT id FROM pairs WHERE a IS DISTINCT FROM b"), [int(2), int(4)]);
+        assert_eq!(ids("SELECT id FROM pairs WHERE b IS NOT DISTINCT FROM NULL"), [int(1), int(2)]);
+        assert_eq!(ids("SELECT id FROM pairs WHERE (b IS DISTINCT FROM 1) AND id > 1"), [int(2), int(4)]);
+        execute("DROP TABLE pairs", conn).unwrap();
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_group_by_expression() {
+        let test_conn = TestConn::setup(&["goods"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE goods (id INTEGER PRIMARY KEY, price INTEGER)", conn).unwrap();
+        execute("INSERT INTO goods VALUES (1, 50), (2, 150), (3, 80), (4, 300), (5, 120), (6, NULL)", conn).unwrap();
+        let res = execute("SELECT price > 100, COUNT(*) FROM goods GROUP BY price > 100", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["price > 100".to_string(), "COUNT".to_string()]);
+        // groups come in the order of their first row, a null price gets a group of its own
+        assert_eq!(rows, [
+            vec![DataItem::Bool(false), DataItem::Integer(2)],
+            vec![DataItem::Bool(true), DataItem::Integer(3)],
+            vec![DataItem::NullBool, DataItem::Integer(1)],
+        ]);
+
+        execute("DELETE FROM goods WHERE id = 6", conn).unwrap();
+        let res = execute("SELECT price / 100, COUNT(*) FROM goods GROUP BY price / 100", conn).unwrap();
+        let (_, rows) = query_rows(&res[0]);
+        assert_eq!(rows, [
+            vec![DataItem::Integer(0), DataItem::Integer(2)],
+            vec![DataItem::Integer(1), DataItem::Integer(2)],
+            vec![DataItem::Integer(3), DataItem::Integer(1)],
+        ]);
+        execute("DROP TABLE goods", conn).unwrap();
+    }
+}
@@ -4,9 +4,19 @@ mod dml_interpreter;
 mod ddl_interpreter;
 mod dcl_interpreter;
 mod expr_interpreter;
+mod analyzer;
+#[cfg(test)]
+pub(crate) mod test_utils;
 
 pub use executor::execute;
+pub use executor::run_transaction;
 pub use executor::checkpoint;
+pub use executor::sync;
+pub use executor::close_all_tables;
 pub use executor::validate_user;
+pub use executor::user_exists;
 pub use executor::disconnect_callback;
-pub use executor::backup_database;
\ No newline at end of file
+pub use executor::backup_database;
+pub use executor::reset_database;
+pub use executor::scan_table;
+pub use analyzer::analyze;
\ No newline at end of file
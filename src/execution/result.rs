@@ -58,4 +58,40 @@ pub enum ExecutionResult {
         rows: Vec<Vec<DataItem>>, // query result
     },
     Mutation(String), // update, delete, insert
+    /// A statement that succeeded but warrants a warning, e.g. an IF EXISTS that matched nothing
+    Warned {
+        result: Box<ExecutionResult>,
+        warnings: Vec<String>,
+    },
+}
+
+impl ExecutionResult {
+    /// The result with the warnings attached, unchanged without warnings
+    pub fn with_warnings(self, mut warnings: Vec<String>) -> Self {
+        if warnings.is_empty() {
+            return self;
+        }
+        match self {
+            ExecutionResult::Warned { result, warnings: mut existing } => {
+                existing.append(&mut warnings);
+                ExecutionResult::Warned { result, warnings: existing }
+            },
+            result => ExecutionResult::Warned { result: Box::new(result), warnings },
+        }
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        match self {
+            ExecutionResult::Warned { warnings, .. } => warnings,
+            _ => &[],
+        }
+    }
+
+    /// The result without its warnings
+    pub fn result(&self) -> &ExecutionResult {
+        match self {
+            ExecutionResult::Warned { result, .. } => result,
+            result => result,
+        }
+    }
 }
\ No newline at end of file
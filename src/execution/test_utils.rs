@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::catalog::SysCatalog;
+use crate::common::data_item::DataItem;
+use crate::execution::executor::{disconnect_callback, execute};
+use crate::execution::result::ExecutionResult;
+use crate::server::conncetion_user_map::ConnectionUserMap;
+use crate::transaction::TnxManager;
+
+static NEXT_TEST_CONN: AtomicU64 = AtomicU64::new(1 << 32);
+
+/// A connection registered for a test, disconnected when dropped
+/// so a failing test does not leave it or its transaction behind
+pub(crate) struct TestConn {
+    pub(crate) id: u64,
+}
+
+impl TestConn {
+    /// Open the catalog, connect as the default user and drop `tables` left over by an earlier run
+    pub(crate) fn setup(tables: &[&str]) -> Self {
+        TnxManager::init(1);
+        SysCatalog::init().unwrap();
+        let conn = Self::new();
+        for table in tables {
+            execute(&format!("DROP TABLE IF EXISTS {}", table), conn.id).unwrap();
+        }
+        conn
+    }
+
+    pub(crate) fn new() -> Self {
+        Self::as_user(crate::config::DEFAULT_USERNAME)
+    }
+
+    pub(crate) fn as_user(username: &str) -> Self {
+        let id = NEXT_TEST_CONN.fetch_add(1, Ordering::Relaxed);
+        ConnectionUserMap::global().try_insert_connection(id, username.to_string(), usize::MAX);
+        Self { id }
+    }
+}
+
+impl Drop for TestConn {
+    fn drop(&mut self) {
+        let _ = disconnect_callback(self.id);
+    }
+}
+
+pub(crate) fn query_rows(res: &ExecutionResult) -> (Vec<String>, Vec<Vec<DataItem>>) {
+    match res {
+        ExecutionResult::Query { cols, rows } => (cols.0.clone(), rows.clone()),
+        other => panic!("Expected a query result, got {:?}", other),
+    }
+}
@@ -10,15 +10,16 @@ mod utils;
 
 use tracing_subscriber::prelude::*;
 use std::fs;
-use std::path;
 use std::collections::HashMap;
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::common::RsqlResult;
+use crate::common::{RsqlResult, RsqlError};
 use crate::server::conncetion_user_map::ConnectionUserMap;
 use crate::storage::WAL;
 use crate::storage::storage::{Page, StorageManager};
@@ -26,9 +27,15 @@ use crate::storage::table::get_table_path;
 use crate::catalog::sys_catalog::is_sys_table;
 use crate::transaction::TnxManager;
 
+pub use crate::config::Config;
+pub use crate::common::DataItem;
+pub use crate::execution::result::ExecutionResult;
+pub use crate::sql::Plan;
+
 pub fn init_log() {
-    let log_dir = path::Path::new(config::LOG_PATH).parent().unwrap();
-    let log_filename = path::Path::new(config::LOG_PATH).file_name().unwrap().to_str().unwrap();
+    let log_path = &config::get().log_path;
+    let log_dir = log_path.parent().unwrap();
+    let log_filename = log_path.file_name().unwrap().to_str().unwrap();
     fs::create_dir_all(log_dir).unwrap();
 
     let stdout_log = tracing_subscriber::fmt::layer()
@@ -49,16 +56,16 @@ pub fn init_log() {
         .with_level(true);
 
     tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(config::LOG_LEVEL))
+        .with(tracing_subscriber::EnvFilter::new(&config::get().log_level))
         .with(stdout_log)
         .with(file_log)
         .init();
 }
 
 fn recovery_wal() -> RsqlResult<u64> {
-    let tmp_storages: Rc<RefCell<HashMap<u64, Arc<Mutex<StorageManager>>>>> = Rc::new(RefCell::new(HashMap::new()));
+    let tmp_storages: Rc<RefCell<HashMap<u64, Arc<RwLock<StorageManager>>>>> = Rc::new(RefCell::new(HashMap::new()));
     // Helper closures
-    let get_sm = |table_id: u64| -> RsqlResult<Arc<Mutex<StorageManager>>> {
+    let get_sm = |table_id: u64| -> RsqlResult<Arc<RwLock<StorageManager>>> {
         let mut tmp_storages = tmp_storages.borrow_mut();
         if let Some(sm) = tmp_storages.get(&table_id) {
             Ok(sm.clone())
@@ -71,7 +78,7 @@ fn recovery_wal() -> RsqlResult<u64> {
     };
     let mut write_page = |table_id: u64, page_id: u64, data: &[u8]| -> RsqlResult<()> {
         let sm_rc = get_sm(table_id)?;
-        let mut sm = sm_rc.lock().unwrap();
+        let mut sm = sm_rc.write().unwrap();
         let mut page = sm.read_page(page_id)?;
         assert_eq!(Page::max_size(), data.len());
         page.data[..data.len()].copy_from_slice(data);
@@ -79,7 +86,7 @@ fn recovery_wal() -> RsqlResult<u64> {
     };
     let mut update_page = |table_id: u64, page_id: u64, offset: u64, len: u64, data: &[u8]| -> RsqlResult<()> {
         let sm_rc = get_sm(table_id)?;
-        let mut sm = sm_rc.lock().unwrap();
+        let mut sm = sm_rc.write().unwrap();
         let mut page = sm.read_page(page_id)?;
         assert!(offset + len <= Page::max_size() as u64);
         page.data[offset as usize..(offset + len) as usize].copy_from_slice(data);
@@ -87,18 +94,18 @@ fn recovery_wal() -> RsqlResult<u64> {
     };
     let mut append_page = |table_id: u64| -> RsqlResult<u64> {
         let sm_rc = get_sm(table_id)?;
-        let mut sm = sm_rc.lock().unwrap();
+        let mut sm = sm_rc.write().unwrap();
         Ok(sm.new_page()?.0)
     };
     let mut trunc_page = |table_id: u64| -> RsqlResult<()> {
         let sm_rc = get_sm(table_id)?;
-        let mut sm = sm_rc.lock().unwrap();
+        let mut sm = sm_rc.write().unwrap();
         sm.free()?;
         Ok(())
     };
     let mut max_page_idx = |table_id: u64| -> RsqlResult<Option<u64>> {
         let sm_rc = get_sm(table_id)?;
-        let sm = sm_rc.lock().unwrap();
+        let sm = sm_rc.read().unwrap();
         Ok(sm.max_page_index())
     };
     // Execute WAL operation
@@ -113,9 +120,8 @@ fn recovery_wal() -> RsqlResult<u64> {
 
 pub fn init_database() -> RsqlResult<()> {
     info!("Initializing database...");
-    let db_path = path::Path::new(config::DB_DIR);
-    let sys_path = db_path.join("sys");
-    let tables_path = db_path.join("tables");
+    let sys_path = config::get().sys_dir();
+    let tables_path = config::get().tables_dir();
 
     // If data/sys and data/tables do not exist, try to restore from backup
     if !sys_path.exists() || !tables_path.exists() {
@@ -126,7 +132,7 @@ pub fn init_database() -> RsqlResult<()> {
     }
 
     // If single file mode is enabled, unpack the archive first
-    if config::SINGLE_FILE_MODE {
+    if config::get().single_file_mode {
         storage::archiver::init_single_file()?;
     }
     let max_tnx_id = recovery_wal()?;
@@ -136,19 +142,147 @@ pub fn init_database() -> RsqlResult<()> {
     Ok(())
 }
 
+/// The database of this process, opened with its configuration
+pub struct Database {
+    config: &'static Config,
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(config::EMBEDDED_CONNECTION_BASE);
+
+impl Database {
+    /// Use `config` for this process, then recover the log and load the catalog.
+    /// Fails if a configuration was already in use
+    pub fn open(config: Config) -> RsqlResult<Self> {
+        config::install(config)?;
+        init_database()?;
+        Ok(Database { config: config::get() })
+    }
+    pub fn config(&self) -> &Config {
+        self.config
+    }
+    /// Open a connection running statements as `username`, which must be a user of the database.
+    /// It counts toward `max_connections` like the connections of the server
+    pub fn connect(&self, username: &str) -> RsqlResult<Connection<'_>> {
+        if !execution::user_exists(username)? {
+            return Err(RsqlError::ExecutionError(format!("User {} does not exist.", username)));
+        }
+        let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        if !ConnectionUserMap::global().try_insert_connection(id, username.to_string(), self.config.max_connections) {
+            return Err(RsqlError::ExecutionError(format!("Too many connections: maximum is {}", self.config.max_connections)));
+        }
+        Ok(Connection { id, _marker: PhantomData })
+    }
+}
+
+/// A connection of the database, closed when dropped: its open transaction is rolled back
+pub struct Connection<'a> {
+    id: u64,
+    // borrows the database, and is not shared between threads: a connection runs one statement at a time
+    _marker: PhantomData<(&'a Database, std::cell::Cell<()>)>,
+}
+
+impl Connection<'_> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    /// Execute SQL statements
+    pub fn execute(&self, sql: &str) -> RsqlResult<Vec<ExecutionResult>> {
+        execution::execute(sql, self.id)
+    }
+    /// Run `f` in a transaction, committed when it returns Ok and rolled back when it fails.
+    /// Transactions aborted by a lock conflict are retried, up to `config::TRANSACTION_MAX_RETRY` times
+    pub fn transaction<T>(&self, mut f: impl FnMut(&Self) -> RsqlResult<T>) -> RsqlResult<T> {
+        execution::run_transaction(self.id, || f(self))
+    }
+    /// Drop every user table and its file, like RESET DATABASE
+    pub fn reset(&self) -> RsqlResult<ExecutionResult> {
+        execution::reset_database(self.id)
+    }
+    /// Pass every row of a table to `f` in primary key order, without going through SQL.
+    /// Runs in the open transaction, or in a new one when there is none
+    pub fn scan_table(&self, name: &str, f: impl FnMut(Vec<DataItem>) -> RsqlResult<()>) -> RsqlResult<()> {
+        execution::scan_table(name, self.id, f)
+    }
+}
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = execution::disconnect_callback(self.id) {
+            warn!("Failed to close connection {}: {}", self.id, e);
+        }
+    }
+}
+
+/// Flush the log and all open tables, for callers that want durability at a chosen point
+pub fn sync() -> RsqlResult<()> {
+    execution::sync()
+}
+
+/// Bring the database up at the state of a backup: the data files and the log are replaced
+/// with the backup content and recovered. Refused once the database is open in this process
+/// or while a server is listening
+pub fn restore_from(backup_path: &str) -> RsqlResult<()> {
+    if WAL::has_recovered() {
+        return Err(RsqlError::StorageError("Cannot restore a backup while the database is open".to_string()));
+    }
+    if !server::daemon::check_port_available(config::PORT) {
+        return Err(RsqlError::StorageError(format!("Cannot restore a backup while a server is running on port {}", config::PORT)));
+    }
+    info!("Restoring database from backup {}", backup_path);
+    storage::archiver::replace_with_backup(backup_path)?;
+    init_database()?;
+    info!("Database restored from backup {}", backup_path);
+    Ok(())
+}
+
+/// Parse SQL into statements, without planning or checking it against the catalog
+pub fn parse_sql(sql: &str) -> RsqlResult<Vec<sqlparser::ast::Statement>> {
+    sql::Plan::parse_sql(sql)
+}
+
+/// Plan SQL and check the tables and columns it names against the catalog, without executing it.
+/// The database must be initialized
+pub fn analyze(sql: &str) -> RsqlResult<Plan> {
+    execution::analyze(sql)
+}
+
 pub fn init_connection_user_map() {
     ConnectionUserMap::init();
 }
 
 pub fn run() {
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    });
+    config::install(config).expect("Configuration installed twice");
     init_log();
     init_connection_user_map();
-    init_database().expect("Failed to initialize database");
+    if std::env::args().nth(1).as_deref() == Some("restore") {
+        let Some(backup_path) = std::env::args().nth(2) else {
+            eprintln!("Usage: restore <backup file>");
+            std::process::exit(1);
+        };
+        if let Err(e) = restore_from(&backup_path) {
+            eprintln!("Restore failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("sql") {
+        // the sql server answers clients while recovering, connections are refused until it is ready
+        server::readiness::init_in_background(init_database);
+    } else {
+        init_database().expect("Failed to initialize database");
+        server::readiness::set_state(server::readiness::ServerState::Ready);
+    }
     server::daemon::daemon();
 
     // After daemon returns (server shut down)
-    if config::SINGLE_FILE_MODE {
+    if config::get().single_file_mode {
         info!("Single file mode enabled, archiving database...");
+        // the archive must not capture a table still being written or pages only in the log
+        execution::close_all_tables().expect("Failed to close tables before archiving");
         storage::archiver::archive_single_file().expect("Failed to archive single file on shutdown");
     }
 }
@@ -1,10 +1,22 @@
 use std::sync::{Mutex, OnceLock};
 use std::collections::HashMap;
 
+use actix::Recipient;
+
+use super::sqlserver_actor::CloseConnection;
+
 static INSTANCE: OnceLock<ConnectionUserMap> = OnceLock::new();
 
+/// State of one connection
+struct Session {
+    username: String,
+    autocommit: bool, // false: statements stay in a transaction until COMMIT or ROLLBACK
+    closer: Option<Recipient<CloseConnection>>, // the websocket serving the connection, if any
+    terminated: bool, // to be disconnected once it runs no statement
+}
+
 pub struct ConnectionUserMap {
-    map: Mutex<HashMap<u64, String>>,
+    map: Mutex<HashMap<u64, Session>>,
 }
 
 impl ConnectionUserMap {
@@ -24,7 +36,25 @@ impl ConnectionUserMap {
 
     pub fn get_username(&self, connection_id: u64) -> Option<String> {
         let map = self.map.lock().unwrap();
-        map.get(&connection_id).cloned()
+        map.get(&connection_id).map(|session| session.username.clone())
+    }
+
+    /// Whether each statement of the connection commits on its own, true unless turned off
+    pub fn autocommit(&self, connection_id: u64) -> bool {
+        let map = self.map.lock().unwrap();
+        map.get(&connection_id).is_none_or(|session| session.autocommit)
+    }
+
+    /// Set the autocommit mode of a connection, returns false if the connection is unknown
+    pub fn set_autocommit(&self, connection_id: u64, autocommit: bool) -> bool {
+        let mut map = self.map.lock().unwrap();
+        match map.get_mut(&connection_id) {
+            Some(session) => {
+                session.autocommit = autocommit;
+                true
+            },
+            None => false,
+        }
     }
 
     // pub fn get_all_connections(&self) -> Vec<u64> {
@@ -32,9 +62,79 @@ impl ConnectionUserMap {
     //     map.keys().cloned().collect()
     // }
 
-    pub fn insert_connection(&self, connection_id: u64, username: String) {
+    /// Register a connection only if fewer than `max_connections` are active.
+    /// Returns false if the limit has been reached.
+    pub fn try_insert_connection(&self, connection_id: u64, username: String, max_connections: usize) -> bool {
+        let mut map = self.map.lock().unwrap();
+        if !map.contains_key(&connection_id) && map.len() >= max_connections {
+            return false;
+        }
+        map.insert(connection_id, Session { username, autocommit: true, closer: None, terminated: false });
+        true
+    }
+
+    /// Register the websocket serving a connection, so it can be asked to close.
+    /// Returns false if the connection is unknown
+    pub fn set_closer(&self, connection_id: u64, closer: Recipient<CloseConnection>) -> bool {
+        let mut map = self.map.lock().unwrap();
+        match map.get_mut(&connection_id) {
+            Some(session) => {
+                session.closer = Some(closer);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Ids of every connection opened by `username`
+    pub fn user_connections(&self, username: &str) -> Vec<u64> {
+        let map = self.map.lock().unwrap();
+        map.iter()
+            .filter(|(_, session)| session.username == username)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Ask the websocket serving a connection to close. It rolls back the open transaction
+    /// and leaves the map once it stopped, so its slot counts until then.
+    /// Returns false if no websocket serves the connection
+    pub fn close_connection(&self, connection_id: u64, reason: &str) -> bool {
+        let map = self.map.lock().unwrap();
+        match map.get(&connection_id).and_then(|session| session.closer.as_ref()) {
+            Some(closer) => {
+                closer.do_send(CloseConnection { reason: reason.to_string() });
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Mark a connection without a websocket to be disconnected, the executor rolls back its
+    /// transaction and removes it once no statement of it runs.
+    /// Returns false if the connection is unknown
+    pub fn mark_terminated(&self, connection_id: u64) -> bool {
         let mut map = self.map.lock().unwrap();
-        map.insert(connection_id, username);
+        match map.get_mut(&connection_id) {
+            Some(session) => {
+                session.terminated = true;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Ids of the connections marked to be disconnected
+    pub fn terminated_connections(&self) -> Vec<u64> {
+        let map = self.map.lock().unwrap();
+        map.iter()
+            .filter(|(_, session)| session.terminated)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    pub fn connection_count(&self) -> usize {
+        let map = self.map.lock().unwrap();
+        map.len()
     }
 
     pub fn remove_connection(&self, connection_id: u64) {
@@ -42,3 +142,24 @@ impl ConnectionUserMap {
         map.remove(&connection_id);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_limit() {
+        let map = ConnectionUserMap::new();
+        assert!(map.try_insert_connection(10, "a".to_string(), 2));
+        assert!(map.try_insert_connection(11, "b".to_string(), 2));
+        // the 3rd connection is refused
+        assert!(!map.try_insert_connection(12, "c".to_string(), 2));
+        assert_eq!(map.connection_count(), 2);
+        assert_eq!(map.get_username(12), None);
+        // a slot is freed after disconnect
+        map.remove_connection(10);
+        assert!(map.try_insert_connection(12, "c".to_string(), 2));
+        assert_eq!(map.get_username(12), Some("c".to_string()));
+    }
+}
@@ -46,7 +46,7 @@ fn init_tracing() {
 }
 
 /// Check if port is available
-fn check_port_available(port: u16) -> bool {
+pub fn check_port_available(port: u16) -> bool {
     match std::net::TcpListener::bind(("127.0.0.1", port)) {
         Ok(_) => true,
         Err(e) => {
@@ -222,6 +222,7 @@ pub fn daemon() {
             println!("Usage:");
             println!("  {} [daemon] - Start daemon process", args[0]);
             println!("  {} sql     - Start SQL server directly", args[0]);
+            println!("  {} restore <backup> - Restore the database from a backup file", args[0]);
             std::process::exit(1);
         }
     }
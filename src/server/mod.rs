@@ -3,4 +3,5 @@ mod types;
 mod thread_pool;
 mod sqlserver_actor;
 pub mod daemon;
-pub mod conncetion_user_map;
\ No newline at end of file
+pub mod conncetion_user_map;
+pub mod readiness;
\ No newline at end of file
@@ -0,0 +1,97 @@
+use std::sync::RwLock;
+use std::thread;
+use tracing::{error, info};
+
+use crate::common::RsqlResult;
+
+/// Startup state of the SQL server process
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerState {
+    /// WAL recovery and catalog initialization are still running
+    Starting,
+    /// The database is initialized and connections are served
+    Ready,
+    /// Initialization failed with the given message
+    Failed(String),
+}
+
+static STATE: RwLock<ServerState> = RwLock::new(ServerState::Starting);
+
+pub fn state() -> ServerState {
+    STATE.read().unwrap().clone()
+}
+
+pub fn set_state(state: ServerState) {
+    *STATE.write().unwrap() = state;
+}
+
+/// Ok when connections can be served, otherwise the message sent back to the client
+pub fn check_ready() -> Result<(), String> {
+    match state() {
+        ServerState::Ready => Ok(()),
+        ServerState::Starting => Err("Server starting up: recovery in progress, try again later".to_string()),
+        ServerState::Failed(msg) => Err(format!("Server failed to start: {}", msg)),
+    }
+}
+
+/// Run the database initialization on its own thread and flip the state when it finishes,
+/// so the server can already answer clients while recovery is running
+pub fn init_in_background<F>(init: F) -> thread::JoinHandle<RsqlResult<()>>
+where
+    F: FnOnce() -> RsqlResult<()> + Send + 'static,
+{
+    set_state(ServerState::Starting);
+    thread::Builder::new()
+        .name("db-init".to_string())
+        .spawn(move || {
+            let result = init();
+            match &result {
+                Ok(_) => {
+                    set_state(ServerState::Ready);
+                    info!("Server is ready to accept connections");
+                }
+                Err(e) => {
+                    error!("Database initialization failed: {}", e);
+                    set_state(ServerState::Failed(e.to_string()));
+                }
+            }
+            result
+        })
+        .expect("Failed to spawn database initialization thread")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::RsqlError;
+    use serial_test::serial;
+    use std::time::Duration;
+
+    #[test]
+    #[serial]
+    fn test_connections_rejected_during_recovery() {
+        let (release, wait) = std::sync::mpsc::channel::<()>();
+        let handle = init_in_background(move || {
+            // a long recovery, it only finishes once the test releases it
+            wait.recv().unwrap();
+            Ok(())
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(state(), ServerState::Starting);
+        assert!(check_ready().unwrap_err().contains("starting up"));
+
+        release.send(()).unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(check_ready(), Ok(()));
+    }
+
+    #[test]
+    #[serial]
+    fn test_failed_recovery_is_reported() {
+        let handle = init_in_background(|| Err(RsqlError::WalError("corrupted log".to_string())));
+        assert!(handle.join().unwrap().is_err());
+        let msg = check_ready().unwrap_err();
+        assert!(msg.contains("failed to start") && msg.contains("corrupted log"));
+        set_state(ServerState::Ready);
+    }
+}
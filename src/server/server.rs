@@ -5,10 +5,12 @@ use tokio::time::interval;
 use tracing::{info, error};
 use rust_embed::RustEmbed;
 
-use crate::config::{PORT, BACKUP_INTERVAL_SECS, CHECKPOINT_INTERVAL_SECS};
+use crate::config::{self, PORT, BACKUP_INTERVAL_SECS, CHECKPOINT_INTERVAL_SECS};
 use crate::server::conncetion_user_map::ConnectionUserMap;
+use super::readiness;
 use super::sqlserver_actor::SQLWebsocketActor;
 use super::thread_pool::WorkingThreadPool;
+use super::types::ResponseFormat;
 
 use std::sync::atomic::{AtomicU64};
 use std::sync::Arc;
@@ -63,6 +65,7 @@ async fn index() -> HttpResponse {
 struct AppState{
     working_thread_pool: Arc<WorkingThreadPool>,
     working_query: Arc<AtomicU64>,
+    max_connections: usize, // connections beyond this are refused
 }
 
 fn url_decode(encoded: &str) -> String {
@@ -109,16 +112,23 @@ async fn handle_ws_query(
     state: web::Data<AppState>
 )-> Result<HttpResponse, actix_web::Error> {
     info!("WebSocket connection requested from: {:?}", request.peer_addr());
+    // reject connections until recovery has finished
+    if let Err(msg) = readiness::check_ready() {
+        info!("Connection refused: {}", msg);
+        return Err(actix_web::error::ErrorServiceUnavailable(msg));
+    }
 
     let query_params = request.query_string();
     let mut username = String::new();
     let mut password = String::new();
+    let mut format = ResponseFormat::default();
 
     for param in query_params.split('&'){
         if let Some((key,value)) = param.split_once('='){
             match key{
                 "username"=> username = url_decode(value),
                 "password"=> password = url_decode(value),
+                "format"=> format = url_decode(value).parse().map_err(actix_web::error::ErrorBadRequest)?,
                 _=>{}
             }
         }
@@ -133,7 +143,13 @@ async fn handle_ws_query(
                         .unwrap_or_default()
                         .as_millis() as u64;
             let username_tmp = username.clone();
-            ConnectionUserMap::global().insert_connection(connection_id, username_tmp);
+            if !ConnectionUserMap::global().try_insert_connection(connection_id, username_tmp, state.max_connections) {
+                info!("Connection refused: {} active connections, maximum is {}",
+                      ConnectionUserMap::global().connection_count(), state.max_connections);
+                return Err(actix_web::error::ErrorServiceUnavailable(
+                    format!("Too many connections: maximum is {}", state.max_connections)
+                ));
+            }
             ws::start(
                 SQLWebsocketActor::new(
                     state.working_thread_pool.clone(),
@@ -141,6 +157,7 @@ async fn handle_ws_query(
                     connection_id,
                     true,
                     username,
+                    format,
                 ),
                 &request,
                 stream,
@@ -157,6 +174,19 @@ async fn handle_ws_query(
     }
 }
 
+/// Liveness, answers as soon as the process serves HTTP
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().body(format!("{:?}", readiness::state()))
+}
+
+/// Readiness, only succeeds once the database is initialized
+async fn ready() -> HttpResponse {
+    match readiness::check_ready() {
+        Ok(_) => HttpResponse::Ok().body("Ready"),
+        Err(msg) => HttpResponse::ServiceUnavailable().body(msg),
+    }
+}
+
 fn start_scheduled_tasks(thread_pool: Arc<WorkingThreadPool>) {
     let backup_pool = thread_pool.clone();
     actix::spawn(async move {
@@ -168,6 +198,9 @@ fn start_scheduled_tasks(thread_pool: Arc<WorkingThreadPool>) {
         
         loop {
             interval.tick().await;
+            if readiness::check_ready().is_err() {
+                continue;
+            }
             
             match backup_pool.make_backup().await {
                 Ok(msg) => info!("Scheduled backup successful: {}", msg),
@@ -184,6 +217,9 @@ fn start_scheduled_tasks(thread_pool: Arc<WorkingThreadPool>) {
         
         loop {
             interval.tick().await;
+            if readiness::check_ready().is_err() {
+                continue;
+            }
             
             match checkpoint_pool.make_checkpoint().await {
                 Ok(msg) => info!("Scheduled checkpoint successful: {}", msg),
@@ -199,6 +235,7 @@ pub async fn start_server() -> std::io::Result<()> {
     let state = web::Data::new(AppState{
         working_thread_pool,
         working_query: Arc::new(AtomicU64::new(0)),
+        max_connections: config::get().max_connections,
     });
     let working_thread_pool_schedule = state.working_thread_pool.clone();
     start_scheduled_tasks(working_thread_pool_schedule);
@@ -207,6 +244,8 @@ pub async fn start_server() -> std::io::Result<()> {
         App::new()
             .app_data(state.clone())
             .route("/ws",web::get().to(handle_ws_query))
+            .route("/health", web::get().to(health))
+            .route("/ready", web::get().to(ready))
             .route("/", web::get().to(index))
             .route("/{path:.*}", web::get().to(handle_static_files))
             .wrap(NormalizePath::trim())
@@ -214,4 +253,65 @@ pub async fn start_server() -> std::io::Result<()> {
     .bind(("127.0.0.1",PORT))?
     .run()
     .await
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::SysCatalog;
+    use crate::config::{DEFAULT_USERNAME, DEFAULT_PASSWORD};
+    use crate::transaction::TnxManager;
+    use futures_util::SinkExt;
+    use tokio_tungstenite::{connect_async, tungstenite};
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn test_connections_over_the_limit_are_refused() {
+        TnxManager::init(1);
+        // users are validated on the unnamed threads of the pool, which share one test directory
+        std::thread::spawn(SysCatalog::init).join().unwrap().unwrap();
+        let old_state = readiness::state();
+        readiness::set_state(readiness::ServerState::Ready);
+        let limit = ConnectionUserMap::global().connection_count() + 2;
+        let state = web::Data::new(AppState {
+            working_thread_pool: Arc::new(WorkingThreadPool::with_threads(2)),
+            working_query: Arc::new(AtomicU64::new(0)),
+            max_connections: limit,
+        });
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .route("/ws", web::get().to(handle_ws_query))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let url = format!("ws://127.0.0.1:{}/ws?username={}&password={}", port, DEFAULT_USERNAME, DEFAULT_PASSWORD);
+        let mut sockets = vec![];
+        for _ in 0..2 {
+            sockets.push(connect_async(&url).await.expect("Connection under the limit failed").0);
+            // connection ids are taken from the clock
+            actix_web::rt::time::sleep(Duration::from_millis(5)).await;
+        }
+        match connect_async(&url).await {
+            Err(tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), 503);
+                let body = String::from_utf8(response.body().clone().unwrap_or_default()).unwrap();
+                assert!(body.contains("Too many connections"), "{}", body);
+            },
+            other => panic!("Connection over the limit was not refused: {:?}", other.map(|_| ())),
+        }
+
+        for mut socket in sockets {
+            socket.close(None).await.unwrap();
+            socket.flush().await.ok();
+        }
+        handle.stop(true).await;
+        readiness::set_state(old_state);
+    }
+}
@@ -1,17 +1,19 @@
 use super::thread_pool::WorkingThreadPool;
-use super::types::{RayonQueryRequest, WebsocketResponse, RayonQueryResponse, UniformedResult};
+use super::types::{RayonQueryRequest, WebsocketResponse, RayonQueryResponse, UniformedResult, ResponseFormat};
+use crate::common::RsqlResult;
 use crate::common::data_item::DataItem;
 use crate::execution::result::ExecutionResult;
 use crate::catalog::table_schema::ColType;
-use crate::server::conncetion_user_map::ConnectionUserMap;
+use crate::config::{self, IDLE_CHECK_INTERVAL_SECS};
+use super::conncetion_user_map::ConnectionUserMap;
 
 use actix_web_actors::ws;
-use actix::{Actor, StreamHandler, AsyncContext};
+use actix::{Actor, ActorContext, StreamHandler, AsyncContext, Handler, Message};
 use serde_json::{self, Value};
-use tracing::{info, error};
+use tracing::{info, warn, error};
 use futures::executor;
 
-use std::time::{SystemTime, UNIX_EPOCH, Instant};
+use std::time::{SystemTime, UNIX_EPOCH, Instant, Duration};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
@@ -22,11 +24,11 @@ fn data_item_to_value(item: &DataItem) -> Value {
             if let Some(num) = serde_json::Number::from_f64(*f) {
                 Value::Number(num)
             } else {
-                Value::String(f.to_string())
+                Value::String(item.display())
             }
         }
-        DataItem::Chars { value, .. } => Value::String(value.clone()),
-        DataItem::VarChar { value, .. } => Value::String(value.clone()),
+        // CHAR values are sent without their padding
+        DataItem::Chars { .. } | DataItem::VarChar { .. } => Value::String(item.display()),
         DataItem::Bool(b) => Value::Bool(*b),
         DataItem::NullInt
         | DataItem::NullFloat
@@ -140,6 +142,15 @@ fn convert_execution_result(result: &ExecutionResult) -> UniformedResult {
                 data,
             }
         }
+
+        ExecutionResult::Warned { result, warnings } => {
+            // the result as without warnings, with the warnings next to its other fields
+            let mut uniformed = convert_execution_result(result);
+            if let Value::Object(data) = &mut uniformed.data {
+                data.insert("warnings".to_string(), serde_json::json!(warnings));
+            }
+            uniformed
+        }
     }
 }
 
@@ -150,6 +161,56 @@ fn convert_execution_results(exec_results: &[ExecutionResult]) -> Vec<UniformedR
         .collect()
 }
 
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Response to a query in the format of the connection
+fn query_response(result: RsqlResult<Vec<ExecutionResult>>, format: ResponseFormat, exec_ms: u64, connection_id: u64) -> WebsocketResponse {
+    match result {
+        Ok(content) => {
+            let uniform_results = convert_execution_results(&content);
+            let response_content = match format {
+                ResponseFormat::Full => content,
+                ResponseFormat::Json => Vec::new(),
+            };
+            WebsocketResponse {
+                rayon_response: RayonQueryResponse {
+                    response_content,
+                    uniform_result: uniform_results,
+                    error: String::from("Query Success"),
+                    execution_time: exec_ms,
+                },
+                timestamp: now_secs(),
+                success: true,
+                connection_id,
+            }
+        },
+        Err(e) => WebsocketResponse {
+            rayon_response: RayonQueryResponse {
+                response_content: Vec::new(),
+                uniform_result: Vec::new(),
+                error: e.to_string(),
+                execution_time: exec_ms,
+            },
+            timestamp: now_secs(),
+            success: false,
+            connection_id,
+        },
+    }
+}
+
+/// The format of a `SET format = json` (or `TO`) request, None for any other request
+fn parse_set_format(sql: &str) -> Option<Result<ResponseFormat, String>> {
+    let sql = sql.trim().trim_end_matches(';').trim().to_lowercase();
+    let rest = sql.strip_prefix("set")?.trim_start().strip_prefix("format")?.trim_start();
+    let value = rest.strip_prefix('=').or_else(|| rest.strip_prefix("to "))?;
+    Some(value.trim().trim_matches('\'').parse())
+}
+
 // one SQLWebsocketActor corresponds to one websocket connection and multiple transactions
 pub struct SQLWebsocketActor {
     working_thread_pool: Arc<WorkingThreadPool>,
@@ -157,6 +218,10 @@ pub struct SQLWebsocketActor {
     current_connection_id: u64,
     authenticated: bool,
     username: String,
+    format: ResponseFormat,
+    last_activity: Instant, // last request or response on the connection
+    pending_queries: usize, // requests not answered yet, the connection is not idle while they run
+    idle_timeout: Duration, // zero keeps an idle connection open
 }
 
 impl Actor for SQLWebsocketActor {
@@ -186,8 +251,22 @@ impl Actor for SQLWebsocketActor {
         if let Ok(json_msg) = serde_json::to_string(&welcome_msg) {
             ctx.text(json_msg);
         }
+        ConnectionUserMap::global().set_closer(self.current_connection_id, ctx.address().recipient());
 
-        // let addr = ctx.address().clone();
+        // close the connection once idle, stopped() rolls back its transaction
+        if !self.idle_timeout.is_zero() {
+            let interval = self.idle_timeout.min(Duration::from_secs(IDLE_CHECK_INTERVAL_SECS));
+            ctx.run_interval(interval, |act, ctx| {
+                if act.is_idle(Instant::now()) {
+                    warn!("Connection {} idle for {:?}, closing", act.current_connection_id, act.idle_timeout);
+                    ctx.close(Some(ws::CloseReason {
+                        code: ws::CloseCode::Normal,
+                        description: Some("Idle timeout".to_string()),
+                    }));
+                    ctx.stop();
+                }
+            });
+        }
     }
 
     //stop the websocket connection
@@ -197,8 +276,6 @@ impl Actor for SQLWebsocketActor {
         
         let thread_pool = self.working_thread_pool.clone();
         let connection_id = self.current_connection_id;
-        let connection_id_temp = self.current_connection_id.clone();
-        ConnectionUserMap::global().remove_connection(connection_id_temp);
         let result = executor::block_on(async {
             thread_pool.rollback(connection_id).await
         });
@@ -210,20 +287,52 @@ impl Actor for SQLWebsocketActor {
     }
 }
 
+/// Ask a connection to close, e.g. when its user is dropped
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CloseConnection {
+    pub reason: String,
+}
+
+impl Handler<CloseConnection> for SQLWebsocketActor {
+    type Result = ();
+
+    /// stopped() then rolls back the transaction of the connection
+    fn handle(&mut self, msg: CloseConnection, ctx: &mut Self::Context) {
+        warn!("Closing connection {}: {}", self.current_connection_id, msg.reason);
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(msg.reason),
+        }));
+        ctx.stop();
+    }
+}
+
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SQLWebsocketActor {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
             Ok(ws::Message::Text(text)) => {
+                self.last_activity = Instant::now();
                 match serde_json::from_str::<RayonQueryRequest>(&text) {
                     Ok(query_request) => {
                         info!("Received query request on connection {} from user: {}", 
                               self.current_connection_id, query_request.username);
+                        // the response format is a setting of the connection, answered here
+                        if let Some(format) = parse_set_format(&query_request.request_content) {
+                            let response = self.set_format(format);
+                            if let Ok(json_msg) = serde_json::to_string(&response) {
+                                ctx.text(json_msg);
+                            }
+                            return;
+                        }
                         
                         let pool = self.working_thread_pool.clone();
                         let connection_id = self.current_connection_id;
                         let query_counter = self.working_query.clone();
+                        let format = self.format;
                         
                         let addr = ctx.address();
+                        self.pending_queries += 1;
                         
                         actix::spawn(async move {
                             let start = Instant::now();
@@ -239,40 +348,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SQLWebsocketActor
                             
                             let exec_ms = start.elapsed().as_millis() as u64;
                             
-                            let response = match result {
-                                Ok(content) => {
-                                    let uniform_results = convert_execution_results(&content);
-                                    
-                                    WebsocketResponse {
-                                        rayon_response: RayonQueryResponse {
-                                            response_content: content,
-                                            uniform_result: uniform_results,
-                                            error: String::from("Query Success"),
-                                            execution_time: exec_ms,
-                                        },
-                                        timestamp: SystemTime::now()
-                                            .duration_since(UNIX_EPOCH)
-                                            .unwrap_or_default()
-                                            .as_secs(),
-                                        success: true,
-                                        connection_id,
-                                    }
-                                },
-                                Err(e) => WebsocketResponse {
-                                    rayon_response: RayonQueryResponse {
-                                        response_content: Vec::new(),
-                                        uniform_result: Vec::new(),
-                                        error: e.to_string(),
-                                        execution_time: exec_ms,
-                                    },
-                                    timestamp: SystemTime::now()
-                                        .duration_since(UNIX_EPOCH)
-                                        .unwrap_or_default()
-                                        .as_secs(),
-                                    success: false,
-                                    connection_id,
-                                },
-                            };
+                            let response = query_response(result, format, exec_ms, connection_id);
 
                             //send back
                             if let Ok(json_response) = serde_json::to_string(&response) {
@@ -349,6 +425,7 @@ impl SQLWebsocketActor {
         current_connection_id: u64,
         authenticated: bool,
         username: String,
+        format: ResponseFormat,
     ) -> Self {
         Self {
             working_thread_pool,
@@ -356,6 +433,49 @@ impl SQLWebsocketActor {
             current_connection_id,
             authenticated,
             username,
+            format,
+            last_activity: Instant::now(),
+            pending_queries: 0,
+            idle_timeout: Duration::from_secs(config::get().idle_timeout_secs),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// No request is running and none came in for the idle timeout
+    fn is_idle(&self, now: Instant) -> bool {
+        !self.idle_timeout.is_zero()
+            && self.pending_queries == 0
+            && now.duration_since(self.last_activity) >= self.idle_timeout
+    }
+
+    fn set_format(&mut self, format: Result<ResponseFormat, String>) -> WebsocketResponse {
+        let (uniform_result, error, success) = match format {
+            Ok(format) => {
+                self.format = format;
+                info!("Connection {} responds in {:?} format", self.current_connection_id, format);
+                let result = UniformedResult {
+                    result_type: "set".to_string(),
+                    data: serde_json::json!({ "message": format!("Response format set to {:?}", format) }),
+                };
+                (vec![result], String::from("Query Success"), true)
+            },
+            Err(e) => (Vec::new(), e, false),
+        };
+        WebsocketResponse {
+            rayon_response: RayonQueryResponse {
+                response_content: Vec::new(),
+                uniform_result,
+                error,
+                execution_time: 0,
+            },
+            timestamp: now_secs(),
+            success,
+            connection_id: self.current_connection_id,
         }
     }
 }
@@ -371,6 +491,117 @@ impl actix::Handler<SendTextMessage> for SQLWebsocketActor {
     type Result = ();
     
     fn handle(&mut self, msg: SendTextMessage, ctx: &mut Self::Context) -> Self::Result {
+        self.pending_queries = self.pending_queries.saturating_sub(1);
+        self.last_activity = Instant::now();
         ctx.text(msg.json);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::SysCatalog;
+    use crate::execution::execute;
+    use crate::server::conncetion_user_map::ConnectionUserMap;
+    use crate::transaction::TnxManager;
+
+    #[test]
+    #[serial_test::serial]
+    fn test_json_response_format() {
+        TnxManager::init(1);
+        SysCatalog::init().unwrap();
+        let conn = 9024;
+        ConnectionUserMap::global().try_insert_connection(conn, crate::config::DEFAULT_USERNAME.to_string(), usize::MAX);
+        execute("DROP TABLE IF EXISTS json_table", conn).unwrap();
+        execute("CREATE TABLE json_table (id INTEGER PRIMARY KEY, name VARCHAR(16), score FLOAT)", conn).unwrap();
+        execute("INSERT INTO json_table VALUES (1, 'ann', 1.5)", conn).unwrap();
+
+        assert_eq!(parse_set_format("SET format = json;"), Some(Ok(ResponseFormat::Json)));
+        assert_eq!(parse_set_format("set FORMAT to 'full'"), Some(Ok(ResponseFormat::Full)));
+        assert!(matches!(parse_set_format("SET format = xml"), Some(Err(_))));
+        assert_eq!(parse_set_format("SELECT * FROM json_table"), None);
+
+        let result = execute("SELECT * FROM json_table", conn);
+        let text = serde_json::to_string(&query_response(result, ResponseFormat::Json, 0, conn)).unwrap();
+        let response: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(response["success"], true);
+        assert_eq!(response["rayon_response"]["response_content"], serde_json::json!([]));
+        let query = &response["rayon_response"]["uniform_result"][0];
+        assert_eq!(query["result_type"], "query");
+        assert_eq!(query["data"]["columns"], serde_json::json!(["id", "name", "score"]));
+        assert_eq!(query["data"]["column_types"], serde_json::json!(["INTEGER", "VARCHAR(16)", "FLOAT"]));
+        assert_eq!(query["data"]["rows"], serde_json::json!([[1, "ann", 1.5]]));
+        ConnectionUserMap::global().remove_connection(conn);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_idle_connection_is_closed() {
+        use actix_web::{error::PayloadError, web::Bytes};
+        use futures::StreamExt;
+        TnxManager::init(1);
+        SysCatalog::init().unwrap();
+        let conn = 9048;
+        ConnectionUserMap::global().try_insert_connection(conn, crate::config::DEFAULT_USERNAME.to_string(), usize::MAX);
+        // the rollback runs on a pool thread, whose test directory differs, so the transaction writes nothing
+        execute("BEGIN", conn).unwrap();
+
+        // the client sends nothing, the output stream ends once the actor stops
+        let actor = SQLWebsocketActor::new(
+            Arc::new(WorkingThreadPool::with_threads(2)),
+            Arc::new(AtomicU64::new(0)),
+            conn,
+            true,
+            crate::config::DEFAULT_USERNAME.to_string(),
+            ResponseFormat::default(),
+        ).with_idle_timeout(Duration::from_millis(50));
+        let start = Instant::now();
+        actix::System::new().block_on(async move {
+            let input = futures::stream::pending::<Result<Bytes, PayloadError>>();
+            ws::WebsocketContext::create(actor, input).for_each(|_| async {}).await;
+        });
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        assert!(ConnectionUserMap::global().get_username(conn).is_none());
+        assert!(TnxManager::global().get_transaction_id(conn).is_none());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_dropped_user_connection_is_closed() {
+        use actix_web::{error::PayloadError, web::Bytes};
+        use futures::StreamExt;
+        TnxManager::init(1);
+        SysCatalog::init().unwrap();
+        let (root_conn, conn) = (9075, 9076);
+        ConnectionUserMap::global().try_insert_connection(root_conn, crate::config::DEFAULT_USERNAME.to_string(), usize::MAX);
+        execute("DROP USER IF EXISTS dropped_ws", root_conn).unwrap();
+        execute("CREATE USER dropped_ws PASSWORD 'pw'", root_conn).unwrap();
+        ConnectionUserMap::global().try_insert_connection(conn, "dropped_ws".to_string(), usize::MAX);
+        execute("BEGIN", conn).unwrap();
+
+        let actor = SQLWebsocketActor::new(
+            Arc::new(WorkingThreadPool::with_threads(2)),
+            Arc::new(AtomicU64::new(0)),
+            conn,
+            true,
+            "dropped_ws".to_string(),
+            ResponseFormat::default(),
+        ).with_idle_timeout(Duration::ZERO);
+        actix::System::new().block_on(async move {
+            let input = futures::stream::pending::<Result<Bytes, PayloadError>>();
+            let output = actix::spawn(ws::WebsocketContext::create(actor, input).for_each(|_| async {}));
+            // let the actor start and register itself
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+            execute("DROP USER dropped_ws", root_conn).unwrap();
+            // the live socket keeps its slot until it has stopped
+            assert!(ConnectionUserMap::global().get_username(conn).is_some());
+            actix_web::rt::time::timeout(Duration::from_secs(5), output).await
+                .expect("the connection of the dropped user was not closed")
+                .unwrap();
+        });
+
+        assert!(ConnectionUserMap::global().get_username(conn).is_none());
+        assert!(TnxManager::global().get_transaction_id(conn).is_none());
+        ConnectionUserMap::global().remove_connection(root_conn);
+    }
+}
@@ -11,6 +11,9 @@ use crate::common::{ RsqlResult};
 use super::types::{ RayonQueryRequest };
 use crate::execution::{execute, checkpoint, validate_user, disconnect_callback, backup_database};
 
+/// Runs the work of every connection on a fixed number of worker threads,
+/// a connection owns no thread and its requests queue up until a worker is free.
+/// The requests of one connection run one at a time, in order
 pub struct WorkingThreadPool{
     thread_pool: rayon::ThreadPool,
     max_thread_num: usize,
@@ -20,22 +23,18 @@ pub struct WorkingThreadPool{
 impl WorkingThreadPool{
     pub fn new() -> Self{
         if THREAD_MAXNUM == 0 {
-            let detected = num_cpus::get();
-            return Self{
-                thread_pool: ThreadPoolBuilder::new()
-                   .num_threads(detected)
-                   .build()
-                   .unwrap(),
-                max_thread_num: detected,
-                serialize_lock: Arc::new(Mutex::new(HashMap::new()))
-            }
+            return Self::with_threads(num_cpus::get());
         }
+        Self::with_threads(THREAD_MAXNUM)
+    }
+
+    pub fn with_threads(max_thread_num: usize) -> Self{
         Self{
             thread_pool: ThreadPoolBuilder::new()
-                .num_threads(THREAD_MAXNUM)
+                .num_threads(max_thread_num)
                 .build()
                 .unwrap(),
-            max_thread_num: THREAD_MAXNUM,
+            max_thread_num,
             serialize_lock: Arc::new(Mutex::new(HashMap::new()))
         }
     }
@@ -123,6 +122,8 @@ impl WorkingThreadPool{
             };
 
             let _conn_guard = conn_mutex.lock().unwrap();
+            // the connection is gone, its slot would otherwise stay in the map forever
+            serialize_lock.lock().unwrap().remove(&connection_id);
 
             match disconnect_callback(connection_id){
                 Ok(_) => {
@@ -147,8 +148,14 @@ impl WorkingThreadPool{
         self.thread_pool.spawn(move ||{
 
             match checkpoint(){
-                Ok(_) => {
-                    sender.send(Ok(format!("making checkpoint"))).unwrap();
+                Ok(Some(stats)) => {
+                    sender.send(Ok(format!(
+                        "making checkpoint, reclaimed {} of {} bytes",
+                        stats.bytes_reclaimed(), stats.bytes_before
+                    ))).unwrap();
+                }
+                Ok(None) => {
+                    sender.send(Ok("no checkpoint needed".to_string())).unwrap();
                 }
                 Err(e) => {
                     sender.send(Err(e)).unwrap();
@@ -189,4 +196,54 @@ impl WorkingThreadPool{
         info!("max thread num: {}", self.max_thread_num);
         info!("thread pool:{:?}",self.thread_pool)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::SysCatalog;
+    use crate::common::DataItem;
+    use crate::server::conncetion_user_map::ConnectionUserMap;
+    use crate::transaction::TnxManager;
+    use futures::executor::block_on;
+    use futures::future::join_all;
+
+    #[test]
+    #[serial_test::serial]
+    fn test_more_connections_than_workers() {
+        TnxManager::init(1);
+        SysCatalog::init().unwrap();
+        let conns: Vec<u64> = (9030..9038).collect();
+        for conn in &conns {
+            ConnectionUserMap::global().try_insert_connection(*conn, crate::config::DEFAULT_USERNAME.to_string(), usize::MAX);
+        }
+        execute("DROP TABLE IF EXISTS pooled", conns[0]).unwrap();
+        execute("CREATE TABLE pooled (id INTEGER PRIMARY KEY, conn INTEGER)", conns[0]).unwrap();
+
+        let pool = WorkingThreadPool::with_threads(2);
+        let request = |sql: String| RayonQueryRequest { username: crate::config::DEFAULT_USERNAME.to_string(), userid: 0, request_content: sql };
+        // every connection queues its statements at once, two workers run them all
+        let mut statements = vec![];
+        for &conn in &conns {
+            for i in 0..3 {
+                let sql = format!("INSERT INTO pooled VALUES ({}, {})", conn * 10 + i, conn);
+                statements.push(pool.parse_and_execute_query(request(sql), conn));
+            }
+        }
+        let results = block_on(join_all(statements));
+        assert!(results.iter().all(|res| res.is_ok()), "{:?}", results.iter().find(|res| res.is_err()));
+        assert_eq!(pool.thread_pool.current_num_threads(), 2);
+        let res = execute("SELECT COUNT(*) FROM pooled", conns[0]).unwrap();
+        let ExecutionResult::Query { rows, .. } = &res[0] else {
+            panic!("Expected a query result, got {:?}", res[0]);
+        };
+        assert_eq!(rows[0][0], DataItem::Integer(conns.len() as i64 * 3));
+
+        // disconnecting releases the connection everywhere
+        for conn in &conns {
+            block_on(pool.rollback(*conn)).unwrap();
+            assert!(ConnectionUserMap::global().get_username(*conn).is_none());
+        }
+        assert!(pool.serialize_lock.lock().unwrap().is_empty());
+    }
+}
@@ -40,6 +40,27 @@ pub struct RayonQueryResponse {
     pub execution_time: u64,
 }
 
+/// Shape of the responses sent on a connection, chosen with `format=` when connecting or `SET format = ...`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ResponseFormat {
+    /// the execution results as serialized by the server together with their uniformed form
+    #[default]
+    Full,
+    /// only the uniformed results, plain JSON columns, types and rows for clients in any language
+    Json,
+}
+
+impl std::str::FromStr for ResponseFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(ResponseFormat::Full),
+            "json" => Ok(ResponseFormat::Json),
+            _ => Err(format!("Unknown response format {}, expected full or json", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebsocketResponse {
     pub rayon_response: RayonQueryResponse,
@@ -4,29 +4,39 @@
 // sqlparser crate
 use sqlparser::ast::{
     Expr,
+    BinaryOperator,
     Statement,
     Query,
     SetExpr,
     Select,
     GroupByExpr,
     SelectItem,
+    SelectItemQualifiedWildcardKind,
     TableWithJoins,
     TableFactor,
     ObjectType,
-    AlterTableOperation,
     AlterTableOperation as AstAlterTableOperation,
     ColumnDef,
     RenameTableNameKind,
     Ident,
+    UnaryOperator,
+    Value,
+    ValueWithSpan,
+    LimitClause,
+    TableConstraint,
 };
 use sqlparser::dialect::GenericDialect;
-use sqlparser::parser::Parser;
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::{Token, TokenWithSpan, Tokenizer};
+use sqlparser::ast::helpers::attached_token::AttachedToken;
 use sqlparser::tokenizer::{Span, Location};
 
 // Internal modules
 use crate::sql::utils::is_aggregate_expr;
 use crate::common::{RsqlResult, RsqlError};
-use crate::catalog::table_schema::{TableSchema, TableColumn, ColType};
+use crate::catalog::table_schema::{TableSchema, TableColumn, ColType, CheckConstraint, Collation};
+use crate::config::{MAX_COL_NAME_SIZE, MAX_TABLE_NAME_SIZE};
 
 /// Represents the type of join operation.
 #[derive(Debug, Clone, Copy)]
@@ -38,12 +48,31 @@ pub enum JoinType {
     Cross, // CROSS JOIN
 }
 
+/// A subquery pulled out of an expression, with how to apply it.
+type SubqueryApply = Option<(PlanNode, ApplyType)>;
+
 /// Represents the type of apply operation for subqueries.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum ApplyType {
     Scalar,  // Scalar subquery
-    // In,      // IN subquery
-    // NotIn,   // NOT IN subquery
+    In { expr: Box<Expr>, negated: bool },  // [NOT] IN subquery, semi/anti join on expr
+    Exists { negated: bool },  // [NOT] EXISTS subquery
+}
+
+/// What an INSERT does with a row whose primary key already exists.
+#[derive(Debug, Clone)]
+pub enum ConflictAction {
+    /// ON CONFLICT DO NOTHING: skip the row
+    DoNothing,
+    /// ON CONFLICT DO UPDATE SET ...: update the existing row instead
+    DoUpdate { assignments: Vec<(String, Expr)> },
+}
+
+/// ON CONFLICT clause of an INSERT.
+#[derive(Debug, Clone)]
+pub struct InsertConflict {
+    pub target: Option<Vec<String>>, // conflict columns, must be the primary key columns
+    pub action: ConflictAction,
 }
 
 // Removed unused: pub type AlterTableOperation = AstAlterTableOperation;
@@ -83,21 +112,83 @@ pub enum DdlOperation {
         column_name: String,
         if_exists: bool,
     },
+    /// ALTER COLUMN ... TYPE, the stored values are converted to `data_type`
+    AlterColumnType {
+        table_name: String,
+        column_name: String,
+        data_type: ColType,
+    },
+    /// ADD PRIMARY KEY (col) or ADD UNIQUE (col) on an existing column, the table is rewritten
+    AddKey {
+        table_name: String,
+        column_name: String,
+        primary: bool,
+    },
+    /// Rebuild the index on `column`, or every index of the table when `column` is None
+    Reindex {
+        table_name: String,
+        column: Option<String>,
+    },
+    /// DEBUG INDEX, dump the B-Tree of the index on `column` as text
+    DebugIndex {
+        table_name: String,
+        column: String,
+    },
+    /// COMMENT ON COLUMN, a None comment removes it
+    CommentOnColumn {
+        table_name: String,
+        column_name: String,
+        comment: Option<String>,
+    },
+    /// DESCRIBE, list the columns of a table with their comments
+    Describe {
+        table_name: String,
+    },
+    /// SHOW INDEX STATS FOR, how often each index of a table was read since start
+    IndexStats {
+        table_name: String,
+    },
+    /// CREATE TABLE ... AS SELECT, the schema is inferred from the output of `input`
+    CreateTableAs {
+        table_name: String,
+        input: Box<PlanNode>,
+        if_not_exists: bool,
+    },
+    /// EXPLAIN of a schema change, the checks of `op` run and report what it would do without applying it
+    DryRun {
+        op: Box<DdlOperation>,
+    },
 }
 
 /// Represents a logical query plan.
 /// Each variant corresponds to a relational algebra operation or DDL/DML/DCL operation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PlanNode {
     /// Scans a table for all rows.
     TableScan {
         table: String,
+        alias: Option<String>, // names the columns of the table in a join, `FROM emp e2`
     },
     /// Represents a subquery.
     Subquery {
         subquery: Box<PlanNode>,
         alias: Option<String>,
     },
+    /// A literal table, `VALUES (1, 'a'), (2, 'b')`, the column types are inferred from the values
+    Values {
+        columns: Vec<String>,
+        rows: Vec<Vec<Expr>>,
+    },
+    /// WITH, every named query is run once before `input`, which reads its rows through `CteScan`.
+    With {
+        ctes: Vec<(String, PlanNode)>,
+        input: Box<PlanNode>,
+    },
+    /// Reads the rows of a query named in an enclosing WITH.
+    CteScan {
+        name: String,
+        alias: Option<String>, // names its columns like the alias of a table, `FROM x a JOIN x b`
+    },
     /// Applies a subquery to each row from the input.
     Apply {
         input: Box<PlanNode>,
@@ -120,13 +211,19 @@ pub enum PlanNode {
         exprs: Vec<Expr>,
         input: Box<PlanNode>,
     },
-    /// Sorts rows based on ORDER BY columns.
-    /// Store flattened column-level information instead of Expr
+    /// Sorts rows based on ORDER BY keys.
+    /// A key is an output column name, a 1-based select list position or a scalar expression
     Sort {
-        columns: Vec<String>, // column name or simple identifier
+        exprs: Vec<Expr>,
         asc: Vec<bool>,       // true = ASC, false = DESC
         input: Box<PlanNode>,
     },
+    /// Skips `offset` rows of the input and returns at most `limit` of the rest.
+    Limit {
+        limit: u64,
+        offset: u64,
+        input: Box<PlanNode>,
+    },
     /// Joins two plans based on a condition.
     Join {
         left: Box<PlanNode>,
@@ -145,15 +242,19 @@ pub enum PlanNode {
         columns: Option<Vec<String>>,
         values: Vec<Vec<Expr>>,
         input: Option<Box<PlanNode>>, // for INSERT ... SELECT subquery
+        on_conflict: Option<InsertConflict>,
+        returning: Option<Vec<Expr>>, // RETURNING select list over the inserted rows
     },
     /// Deletes rows produced by the input plan.
     Delete {
         input: Box<PlanNode>,
+        returning: Option<Vec<Expr>>, // RETURNING select list over the deleted rows
     },
     /// Updates rows produced by the input plan.
     Update {
         input: Box<PlanNode>,
         assignments: Vec<(String, Expr)>,
+        returning: Option<Vec<Expr>>, // RETURNING select list over the updated rows
     },
     /// Creates a new user.
     CreateUser {
@@ -178,6 +279,12 @@ pub enum PlanNode {
         table_name: Option<String>,
         user_name: String,
     },
+    /// Cancels the running statement of a transaction.
+    Kill {
+        tnx_id: u64,
+    },
+    /// DEBUG WAL, lists the entries of the write-ahead log.
+    DebugWal,
 }
 
 #[derive(Debug)]
@@ -188,6 +295,11 @@ pub enum PlanItem {
     Begin,
     Commit,
     Rollback,
+    ShowTransactions,
+    /// RESET DATABASE, drop every user table
+    ResetDatabase,
+    /// SET autocommit = on | off, for the statements of the connection
+    SetAutocommit(bool),
 }
 
 #[derive(Debug)]
@@ -196,9 +308,28 @@ pub struct Plan {
 }
 
 impl Plan {
+    /// Parses SQL into statements without planning them.
+    pub fn parse_sql(sql: &str) -> RsqlResult<Vec<Statement>> {
+        parse_statements(sql).map_err(|e| RsqlError::ParserError(e.to_string()))
+    }
+
     /// Builds a logical plan from a SQL string.
     /// Flattens all statements into Plan.items, including transaction boundaries.
     pub fn build_plan(sql: &str) -> RsqlResult<Plan> {
+        let statements = split_statements(sql, crate::config::STATEMENT_TERMINATOR);
+        if statements.len() <= 1 {
+            return Self::build_statement_plan(sql);
+        }
+        let mut items = Vec::new();
+        for statement in statements {
+            items.extend(Self::build_statement_plan(statement)?.items);
+        }
+        Ok(Plan { items })
+    }
+
+    /// Builds the plan of a request holding at most one statement, the manual
+    /// DCL/SHOW parsers below read the whole string as a single statement.
+    fn build_statement_plan(sql: &str) -> RsqlResult<Plan> {
         let mut items = Vec::new();
 
         // Check for DCL CREATE USER or DROP USER before parsing
@@ -325,6 +456,9 @@ impl Plan {
             if user_name.is_empty() {
                 return Err(RsqlError::ParserError("DROP USER missing user name".to_string()));
             }
+            if let Some(extra) = tokens.get(i + 1).map(|t| t.trim_matches(';')).filter(|t| !t.is_empty()) {
+                return Err(RsqlError::ParserError(format!("Unexpected token after DROP USER: {}", extra)));
+            }
             items.push(PlanItem::DCL(PlanNode::DropUser { user_name, if_exists }));
             return Ok(Plan { items });
         } else if lower.starts_with("grant") {
@@ -356,6 +490,8 @@ impl Plan {
 
                 if tokens.len() > i && tokens[i].eq_ignore_ascii_case("to") {
                     i += 1;
+                } else if tokens.len() > i {
+                    return Err(RsqlError::ParserError(format!("Expected TO in GRANT ON, found unexpected token: {}", tokens[i])));
                 } else {
                     return Err(RsqlError::ParserError("Missing TO keyword in GRANT ON".to_string()));
                 }
@@ -399,7 +535,10 @@ impl Plan {
                 items.push(PlanItem::DCL(PlanNode::Grant { privilege, table_name, user_name }));
                 return Ok(Plan { items });
             } else {
-                return Err(RsqlError::ParserError("Invalid GRANT syntax. Expected GRANT <priv> [ON <table>] TO <user>".to_string()));
+                return Err(RsqlError::ParserError(format!(
+                    "Invalid GRANT syntax, unexpected token: {}. Expected GRANT <priv> [ON <table>] TO <user>",
+                    tokens.get(1).unwrap_or(&"end of statement")
+                )));
             }
         } else if lower.starts_with("revoke") {
             // Parse: 
@@ -430,6 +569,8 @@ impl Plan {
 
                 if tokens.len() > i && tokens[i].eq_ignore_ascii_case("from") {
                     i += 1;
+                } else if tokens.len() > i {
+                    return Err(RsqlError::ParserError(format!("Expected FROM in REVOKE ON, found unexpected token: {}", tokens[i])));
                 } else {
                     return Err(RsqlError::ParserError("Missing FROM keyword in REVOKE ON".to_string()));
                 }
@@ -473,14 +614,145 @@ impl Plan {
                 items.push(PlanItem::DCL(PlanNode::Revoke { privilege, table_name, user_name }));
                 return Ok(Plan { items });
             } else {
-                return Err(RsqlError::ParserError("Invalid REVOKE syntax. Expected REVOKE <priv> [ON <table>] FROM <user>".to_string()));
+                return Err(RsqlError::ParserError(format!(
+                    "Invalid REVOKE syntax, unexpected token: {}. Expected REVOKE <priv> [ON <table>] FROM <user>",
+                    tokens.get(1).unwrap_or(&"end of statement")
+                )));
+            }
+        } else if lower.starts_with("show index stats") {
+            // Parse: SHOW INDEX STATS FOR <table_name>[;]
+            let tokens: Vec<&str> = sql_trimmed[("show index stats".len())..]
+                .split_whitespace()
+                .map(|t| t.trim_matches(|c: char| c == ';'))
+                .filter(|t| !t.is_empty())
+                .collect();
+            let [keyword, table_name] = tokens.as_slice() else {
+                return Err(RsqlError::ParserError("SHOW INDEX STATS expects FOR <table>".to_string()));
+            };
+            if !keyword.eq_ignore_ascii_case("for") {
+                return Err(RsqlError::ParserError(format!("Expected FOR after SHOW INDEX STATS, got: {}", keyword)));
             }
+            items.push(PlanItem::DDL(PlanNode::DDL { op: DdlOperation::IndexStats { table_name: table_name.to_string() } }));
+            return Ok(Plan { items });
+        } else if let Some(rest) = lower.strip_prefix("show transactions") {
+            let rest = rest.trim().trim_end_matches(';').trim();
+            if !rest.is_empty() {
+                return Err(RsqlError::ParserError(format!("Unexpected token after SHOW TRANSACTIONS: {}", rest)));
+            }
+            items.push(PlanItem::ShowTransactions);
+            return Ok(Plan { items });
+        } else if let Some(rest) = lower.strip_prefix("reset database") {
+            let rest = rest.trim().trim_end_matches(';').trim();
+            if !rest.is_empty() {
+                return Err(RsqlError::ParserError(format!("Unexpected token after RESET DATABASE: {}", rest)));
+            }
+            items.push(PlanItem::ResetDatabase);
+            return Ok(Plan { items });
+        } else if let Some(rest) = lower.strip_prefix("set autocommit") {
+            // Parse: SET autocommit {= | TO} {on | off | 1 | 0 | true | false}[;]
+            let rest = rest.trim().trim_end_matches(';').trim();
+            let value = rest.strip_prefix('=')
+                .or_else(|| rest.strip_prefix("to "))
+                .unwrap_or(rest)
+                .trim();
+            let autocommit = match value {
+                "on" | "1" | "true" => true,
+                "off" | "0" | "false" => false,
+                _ => return Err(RsqlError::ParserError(format!("SET autocommit expects ON or OFF, got: {}", value))),
+            };
+            items.push(PlanItem::SetAutocommit(autocommit));
+            return Ok(Plan { items });
+        } else if let Some(rest) = lower.strip_prefix("kill ") {
+            // Parse: KILL <tnx_id>[;]
+            let rest = rest.trim().trim_end_matches(';').trim();
+            let tnx_id = rest.parse::<u64>()
+                .map_err(|_| RsqlError::ParserError(format!("KILL expects a transaction id, got: {}", rest)))?;
+            items.push(PlanItem::DCL(PlanNode::Kill { tnx_id }));
+            return Ok(Plan { items });
+        } else if lower.starts_with("reindex") {
+            // Parse: REINDEX <table_name> [<column_name>][;]
+            let rest: &str = sql_trimmed[("reindex".len())..].trim_start();
+            let tokens: Vec<&str> = rest
+                .split_whitespace()
+                .map(|t| t.trim_matches(|c: char| c == ';'))
+                .filter(|t| !t.is_empty())
+                .collect();
+            if tokens.is_empty() {
+                return Err(RsqlError::ParserError("REINDEX missing table name".to_string()));
+            }
+            if tokens.len() > 2 {
+                return Err(RsqlError::ParserError(format!("Unexpected token after REINDEX: {}", tokens[2])));
+            }
+            let table_name = tokens[0].to_string();
+            let column = tokens.get(1).map(|c| c.to_string());
+            items.push(PlanItem::DDL(PlanNode::DDL { op: DdlOperation::Reindex { table_name, column } }));
+            return Ok(Plan { items });
+        } else if lower.starts_with("debug index") {
+            // Parse: DEBUG INDEX <table_name> <column_name>[;]
+            let tokens: Vec<&str> = sql_trimmed[("debug index".len())..]
+                .split_whitespace()
+                .map(|t| t.trim_matches(|c: char| c == ';'))
+                .filter(|t| !t.is_empty())
+                .collect();
+            if tokens.len() < 2 {
+                return Err(RsqlError::ParserError("DEBUG INDEX expects a table name and a column name".to_string()));
+            }
+            if tokens.len() > 2 {
+                return Err(RsqlError::ParserError(format!("Unexpected token after DEBUG INDEX: {}", tokens[2])));
+            }
+            let (table_name, column) = (tokens[0].to_string(), tokens[1].to_string());
+            items.push(PlanItem::DDL(PlanNode::DDL { op: DdlOperation::DebugIndex { table_name, column } }));
+            return Ok(Plan { items });
+        } else if lower.starts_with("comment on column") {
+            // Parse: COMMENT ON COLUMN <table_name>.<column_name> IS '<comment>' | NULL[;]
+            let rest = sql_trimmed[("comment on column".len())..].trim().trim_end_matches(';').trim_end();
+            let (target, rest) = rest.split_once(char::is_whitespace)
+                .ok_or(RsqlError::ParserError("COMMENT ON COLUMN expects <table>.<column> IS '<comment>'".to_string()))?;
+            let (table_name, column_name) = target.split_once('.')
+                .ok_or(RsqlError::ParserError(format!("COMMENT ON COLUMN expects <table>.<column>, got: {}", target)))?;
+            let (keyword, value) = rest.trim_start().split_once(char::is_whitespace).unwrap_or((rest.trim_start(), ""));
+            if !keyword.eq_ignore_ascii_case("is") {
+                return Err(RsqlError::ParserError(format!("Expected IS after the column of COMMENT ON COLUMN, got: {}", keyword)));
+            }
+            let value = value.trim();
+            let comment = if value.eq_ignore_ascii_case("null") {
+                None
+            } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+                Some(value[1..value.len() - 1].replace("''", "'"))
+            } else {
+                return Err(RsqlError::ParserError(format!("COMMENT ON COLUMN expects a quoted string or NULL, got: {}", value)));
+            };
+            let op = DdlOperation::CommentOnColumn {
+                table_name: table_name.to_string(),
+                column_name: column_name.to_string(),
+                comment,
+            };
+            items.push(PlanItem::DDL(PlanNode::DDL { op }));
+            return Ok(Plan { items });
+        } else if lower.starts_with("describe ") {
+            // Parse: DESCRIBE <table_name>[;]
+            let tokens: Vec<&str> = sql_trimmed[("describe".len())..]
+                .split_whitespace()
+                .map(|t| t.trim_matches(|c: char| c == ';'))
+                .filter(|t| !t.is_empty())
+                .collect();
+            let [table_name] = tokens.as_slice() else {
+                return Err(RsqlError::ParserError("DESCRIBE expects a table name".to_string()));
+            };
+            items.push(PlanItem::DDL(PlanNode::DDL { op: DdlOperation::Describe { table_name: table_name.to_string() } }));
+            return Ok(Plan { items });
+        } else if let Some(rest) = lower.strip_prefix("debug wal") {
+            let rest = rest.trim().trim_end_matches(';').trim();
+            if !rest.is_empty() {
+                return Err(RsqlError::ParserError(format!("Unexpected token after DEBUG WAL: {}", rest)));
+            }
+            items.push(PlanItem::DCL(PlanNode::DebugWal));
+            return Ok(Plan { items });
         }
 
         // Otherwise use sqlparser as normal
-        let dialect = GenericDialect {};
-        let ast = Parser::parse_sql(&dialect, sql)
-            .map_err(|e| RsqlError::ParserError(format!("{e}")))?;
+        let ast = parse_statements(sql)
+            .map_err(|e| parser_error_with_context(sql, &e.to_string()))?;
 
         if ast.is_empty() {
             return Err(RsqlError::ParserError("Empty SQL".to_string()));
@@ -500,6 +772,14 @@ impl Plan {
                     let node = Self::from_ast(&stmt)?;
                     items.push(PlanItem::DDL(node));
                 }
+                // EXPLAIN of a schema change is a dry run of it
+                Explain { statement, analyze: false, .. }
+                    if matches!(**statement, CreateTable { .. } | Drop { object_type: ObjectType::Table, .. } | AlterTable { .. } | CreateIndex { .. }) => {
+                    let PlanNode::DDL { op } = Self::from_ast(statement)? else {
+                        return Err(RsqlError::ParserError(format!("EXPLAIN is not supported for: {}", statement)));
+                    };
+                    items.push(PlanItem::DDL(PlanNode::DDL { op: DdlOperation::DryRun { op: Box::new(op) } }));
+                }
                 // DML
                 Insert { .. }
                 | Update { .. }
@@ -557,56 +837,224 @@ impl Plan {
     }
 
     fn build_query(query: &Query) -> RsqlResult<PlanNode> {
-        match &*query.body {
-            SetExpr::Select(select) => {
-                let mut plan = Self::build_select_plan(select)?;
+        let Some(with) = &query.with else {
+            return Self::build_query_body(query);
+        };
+        if with.recursive {
+            return Err(RsqlError::ParserError("WITH RECURSIVE is not supported".to_string()));
+        }
+        let mut ctes: Vec<(String, PlanNode)> = vec![];
+        for cte in with.cte_tables.iter() {
+            if !cte.alias.columns.is_empty() {
+                return Err(RsqlError::ParserError("Column names of a WITH query are not supported".to_string()));
+            }
+            let name = cte.alias.name.value.clone();
+            if ctes.iter().any(|(n, _)| *n == name) {
+                return Err(RsqlError::ParserError(format!("WITH query name {} specified more than once", name)));
+            }
+            // a WITH query can read the ones defined before it
+            let mut plan = Self::build_query(&cte.query)?;
+            Self::scan_ctes(&mut plan, &ctes);
+            ctes.push((name, plan));
+        }
+        let mut input = Self::build_query_body(query)?;
+        Self::scan_ctes(&mut input, &ctes);
+        Ok(PlanNode::With { ctes, input: Box::new(input) })
+    }
+
+    /// Read the named queries of a WITH through `CteScan` instead of scanning a table of that name
+    fn scan_ctes(plan: &mut PlanNode, ctes: &[(String, PlanNode)]) {
+        match plan {
+            PlanNode::TableScan { table, alias } if ctes.iter().any(|(name, _)| name == table) => {
+                *plan = PlanNode::CteScan { name: table.clone(), alias: alias.clone() };
+            },
+            PlanNode::Apply { input, subquery, .. } => {
+                Self::scan_ctes(input, ctes);
+                Self::scan_ctes(subquery, ctes);
+            },
+            PlanNode::Join { left, right, .. } => {
+                Self::scan_ctes(left, ctes);
+                Self::scan_ctes(right, ctes);
+            },
+            // an inner WITH shadows the outer queries of the same name
+            PlanNode::With { ctes: inner, input } => {
+                let outer: Vec<(String, PlanNode)> = ctes.iter()
+                    .filter(|(name, _)| !inner.iter().any(|(n, _)| n == name))
+                    .cloned()
+                    .collect();
+                for (_, cte) in inner.iter_mut() {
+                    Self::scan_ctes(cte, &outer);
+                }
+                Self::scan_ctes(input, &outer);
+            },
+            PlanNode::Subquery { subquery: input, .. }
+            | PlanNode::Filter { input, .. }
+            | PlanNode::Aggregate { input, .. }
+            | PlanNode::Projection { input, .. }
+            | PlanNode::Sort { input, .. }
+            | PlanNode::Limit { input, .. } => Self::scan_ctes(input, ctes),
+            _ => {},
+        }
+    }
+
+    fn build_query_body(query: &Query) -> RsqlResult<PlanNode> {
+        let select_plan = match &*query.body {
+            SetExpr::Select(select) => Some(Self::build_select_plan(select)?),
+            // a bare VALUES list is selected as a whole
+            SetExpr::Values(values) => Some(PlanNode::Projection {
+                exprs: vec![Expr::Identifier(Ident::new("*"))],
+                input: Box::new(Self::build_values(values, &[])?),
+            }),
+            _ => None,
+        };
+        match select_plan {
+            Some(mut plan) => {
 
                 // === ORDER BY handling ===
                 if let Some(order_by) = &query.order_by {
                     if let sqlparser::ast::OrderByKind::Expressions(items) = &order_by.kind {
-                        let mut columns = Vec::new();
+                        let mut exprs = Vec::new();
                         let mut asc = Vec::new();
 
                         for ob in items {
-                            let col = match &ob.expr {
-                                Expr::Identifier(ident) => ident.value.clone(),
-                                Expr::CompoundIdentifier(idents) => {
-                                    idents.iter().map(|i| i.value.clone()).collect::<Vec<_>>().join(".")
-                                }
-                                _ => {
-                                    return Err(RsqlError::ParserError(
-                                        "ORDER BY only supports column identifiers".to_string(),
-                                    ));
-                                }
-                            };
-
-                            columns.push(col);
+                            if let Expr::Subquery(_) = &ob.expr {
+                                return Err(RsqlError::ParserError(
+                                    "ORDER BY does not support subqueries".to_string(),
+                                ));
+                            }
+                            exprs.push(ob.expr.clone());
                             asc.push(ob.options.asc.unwrap_or(true));
                         }
 
                         plan = PlanNode::Sort {
-                            columns,
+                            exprs,
                             asc,
                             input: Box::new(plan),
                         };
                     }
                 }
 
+                // === LIMIT / OFFSET / FETCH handling ===
+                let (mut limit, offset) = match &query.limit_clause {
+                    Some(LimitClause::LimitOffset { limit, offset, limit_by }) => {
+                        if !limit_by.is_empty() {
+                            return Err(RsqlError::ParserError("LIMIT BY is not supported".to_string()));
+                        }
+                        (
+                            limit.as_ref().map(|e| Self::row_count_literal(e, "LIMIT")).transpose()?,
+                            offset.as_ref().map(|o| Self::row_count_literal(&o.value, "OFFSET")).transpose()?,
+                        )
+                    },
+                    Some(LimitClause::OffsetCommaLimit { offset, limit }) => (
+                        Some(Self::row_count_literal(limit, "LIMIT")?),
+                        Some(Self::row_count_literal(offset, "OFFSET")?),
+                    ),
+                    None => (None, None),
+                };
+                // ANSI `FETCH FIRST|NEXT n ROWS ONLY` is a LIMIT, without a count it fetches one row
+                if let Some(fetch) = &query.fetch {
+                    if limit.is_some() {
+                        return Err(RsqlError::ParserError("LIMIT and FETCH cannot be used together".to_string()));
+                    }
+                    if fetch.percent || fetch.with_ties {
+                        return Err(RsqlError::ParserError("FETCH ... PERCENT and WITH TIES are not supported".to_string()));
+                    }
+                    limit = Some(fetch.quantity.as_ref().map_or(Ok(1), |e| Self::row_count_literal(e, "FETCH"))?);
+                }
+                if limit.is_some() || offset.is_some() {
+                    plan = PlanNode::Limit {
+                        limit: limit.unwrap_or(u64::MAX),
+                        offset: offset.unwrap_or(0),
+                        input: Box::new(plan),
+                    };
+                }
+
                 Ok(plan)
             }
-            _ => Err(RsqlError::ParserError("Only simple SELECT is supported".to_string())),
+            None => Err(RsqlError::ParserError("Only simple SELECT is supported".to_string())),
         }
     }
 
+    /// `DELETE ... LIMIT n` and `UPDATE ... LIMIT n` change at most n of the matching rows
+    fn mutation_limit(plan: PlanNode, limit: &Expr) -> RsqlResult<PlanNode> {
+        Ok(PlanNode::Limit {
+            limit: Self::row_count_literal(limit, "LIMIT")?,
+            offset: 0,
+            input: Box::new(plan),
+        })
+    }
+
+    /// A VALUES list as a table, its columns are named by `names` or else column1, column2, ...
+    fn build_values(values: &sqlparser::ast::Values, names: &[String]) -> RsqlResult<PlanNode> {
+        let rows = values.rows.clone();
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 {
+            return Err(RsqlError::ParserError("VALUES needs at least one column".to_string()));
+        }
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(RsqlError::ParserError("VALUES lists must all have the same length".to_string()));
+        }
+        let columns = match names {
+            [] => (1..=width).map(|i| format!("column{}", i)).collect(),
+            names if names.len() == width => names.to_vec(),
+            names => return Err(RsqlError::ParserError(format!("VALUES has {} columns but {} names", width, names.len()))),
+        };
+        Ok(PlanNode::Values { columns, rows })
+    }
+
+    /// A non-negative integer literal of LIMIT, OFFSET or FETCH
+    fn row_count_literal(expr: &Expr, clause: &str) -> RsqlResult<u64> {
+        if let Expr::Value(ValueWithSpan { value: Value::Number(n, _), .. }) = expr
+            && let Ok(count) = n.parse::<u64>() {
+            return Ok(count);
+        }
+        Err(RsqlError::ParserError(format!("{} expects a non-negative integer, got: {}", clause, expr)))
+    }
+
+    /// Extract the ON CONFLICT clause of an INSERT, only the primary key columns are a meaningful target
+    fn insert_conflict(on: &Option<sqlparser::ast::OnInsert>) -> RsqlResult<Option<InsertConflict>> {
+        use sqlparser::ast::{OnInsert, ConflictTarget, OnConflictAction};
+        let Some(on) = on else {
+            return Ok(None);
+        };
+        let OnInsert::OnConflict(on_conflict) = on else {
+            return Err(RsqlError::ParserError("Only ON CONFLICT is supported for INSERT".to_string()));
+        };
+        let target = match &on_conflict.conflict_target {
+            None => None,
+            Some(ConflictTarget::Columns(cols)) if !cols.is_empty() => Some(cols.iter().map(|col| col.value.clone()).collect()),
+            Some(_) => return Err(RsqlError::ParserError(
+                "ON CONFLICT only supports the primary key columns as target".to_string(),
+            )),
+        };
+        let action = match &on_conflict.action {
+            OnConflictAction::DoNothing => ConflictAction::DoNothing,
+            OnConflictAction::DoUpdate(do_update) => {
+                if do_update.selection.is_some() {
+                    return Err(RsqlError::ParserError("ON CONFLICT DO UPDATE ... WHERE is not supported".to_string()));
+                }
+                let assignments = do_update
+                    .assignments
+                    .iter()
+                    .map(|a| (format!("{}", a.target), Self::fold_signed_number(&a.value).unwrap_or_else(|| a.value.clone())))
+                    .collect();
+                ConflictAction::DoUpdate { assignments }
+            }
+        };
+        Ok(Some(InsertConflict { target, action }))
+    }
+
     fn build_select_plan(select: &Select) -> RsqlResult<PlanNode> {
         let mut plan = Self::build_from(&select.from)?;
 
         if let Some(selection) = &select.selection {
-            let (clean_predicate, sub_info) = Self::extract_subqueries_from_expr(selection)?;
-            plan = PlanNode::Filter {
-                predicate: clean_predicate,
-                input: Box::new(plan),
-            };
+            let (clean_predicate, sub_info) = Self::split_subquery_predicate(selection)?;
+            if let Some(predicate) = clean_predicate {
+                plan = PlanNode::Filter {
+                    predicate,
+                    input: Box::new(plan),
+                };
+            }
             if let Some((sub_plan, apply_type)) = sub_info {
                 plan = PlanNode::Apply {
                     input: Box::new(plan),
@@ -729,10 +1177,20 @@ impl Plan {
 
     fn build_table_factor(table_factor: &TableFactor) -> RsqlResult<PlanNode> {
         match table_factor {
-            TableFactor::Table { name, .. } => Ok(PlanNode::TableScan { table: name.to_string() }),
+            TableFactor::Table { name, alias, .. } => Ok(PlanNode::TableScan {
+                table: name.to_string(),
+                alias: alias.as_ref().map(|a| a.name.value.clone()),
+            }),
             TableFactor::Derived { subquery, alias, .. } => {
-                let sub_plan = Self::build_query(subquery)?;
                 let alias_name = alias.as_ref().map(|a| a.name.to_string());
+                // `(VALUES ...) AS t(a, b)` names the columns of the list
+                if let SetExpr::Values(values) = &*subquery.body
+                    && subquery.with.is_none() && subquery.order_by.is_none() && subquery.limit_clause.is_none() {
+                    let names: Vec<String> = alias.iter().flat_map(|a| a.columns.iter().map(|c| c.name.value.clone())).collect();
+                    let values_plan = Self::build_values(values, &names)?;
+                    return Ok(PlanNode::Subquery { subquery: Box::new(values_plan), alias: alias_name });
+                }
+                let sub_plan = Self::build_query(subquery)?;
                 Ok(PlanNode::Subquery { subquery: Box::new(sub_plan), alias: alias_name })
             }
             _ => Err(RsqlError::ParserError("Unsupported table factor".to_string())),
@@ -742,7 +1200,7 @@ impl Plan {
     fn extract_projection(items: &[SelectItem]) -> Vec<Expr> {
         items.iter().filter_map(|item| match item {
             SelectItem::UnnamedExpr(expr) => Some(expr.clone()),
-            SelectItem::ExprWithAlias { expr, .. } => Some(expr.clone()),
+            SelectItem::ExprWithAlias { expr, alias } => Some(Expr::Named { expr: Box::new(expr.clone()), name: alias.clone() }),
             SelectItem::Wildcard(_) => Some(Expr::Identifier(Ident {
                 value: "*".to_string(),
                 quote_style: None,
@@ -751,6 +1209,9 @@ impl Plan {
                     end: Location { line: 0, column: 0 },
                 },
             })),
+            SelectItem::QualifiedWildcard(SelectItemQualifiedWildcardKind::ObjectName(name), _) => {
+                Some(Expr::QualifiedWildcard(name.clone(), AttachedToken::empty()))
+            },
             _ => None,
         }).collect()
     }
@@ -768,6 +1229,19 @@ impl Plan {
             || Self::extract_aggr_exprs(&select.projection).len() > 0
     }
 
+    /// A sign in front of a number literal folded into it, `-5` becomes the literal `-5`,
+    /// so negative values are accepted wherever number literals are
+    pub(crate) fn fold_signed_number(expr: &Expr) -> Option<Expr> {
+        let Expr::UnaryOp { op: op @ (UnaryOperator::Minus | UnaryOperator::Plus), expr: inner } = expr else {
+            return None;
+        };
+        let Expr::Value(ValueWithSpan { value: Value::Number(n, long), span }) = &**inner else {
+            return None;
+        };
+        let n = if *op == UnaryOperator::Minus { format!("-{}", n) } else { n.clone() };
+        Some(Expr::Value(Value::Number(n, *long).with_span(*span)))
+    }
+
     fn extract_subqueries_from_expr(expr: &Expr) -> RsqlResult<(Expr, Option<(PlanNode, ApplyType)>)> {
         match expr {
             Expr::Subquery(query) => {
@@ -776,12 +1250,12 @@ impl Plan {
             }
             Expr::InSubquery { .. } => {
                 Err(RsqlError::ParserError(
-                    "IN / NOT IN subqueries are not supported".to_string(),
+                    "IN / NOT IN subqueries are only supported as WHERE conditions joined by AND".to_string(),
                 ))
             }
             Expr::Exists { .. } => {
                 Err(RsqlError::ParserError(
-                    "EXISTS subquery is not supported".to_string(),
+                    "EXISTS subqueries are only supported as WHERE conditions joined by AND".to_string(),
                 ))
             }
             Expr::Like { negated, expr, pattern, escape_char, any } => {
@@ -838,17 +1312,86 @@ impl Plan {
                     left_sub.or(right_sub),
                 ))
             }
-            Expr::Function(func) if is_aggregate_expr(expr) => {
+            Expr::IsDistinctFrom(left, right) | Expr::IsNotDistinctFrom(left, right) => {
+                let (left_clean, left_sub) = Self::extract_subqueries_from_expr(left)?;
+                let (right_clean, right_sub) = Self::extract_subqueries_from_expr(right)?;
+                let (left_clean, right_clean) = (Box::new(left_clean), Box::new(right_clean));
+                let clean = match expr {
+                    Expr::IsDistinctFrom(..) => Expr::IsDistinctFrom(left_clean, right_clean),
+                    _ => Expr::IsNotDistinctFrom(left_clean, right_clean),
+                };
+                Ok((clean, left_sub.or(right_sub)))
+            }
+            // `x IN ()` holds for no row and `x NOT IN ()` for every row
+            Expr::InList { list, negated, .. } if is_empty_list(list) => Ok((Expr::Value(Value::Boolean(*negated).into()), None)),
+            Expr::UnaryOp { .. } if let Some(folded) = Self::fold_signed_number(expr) => Ok((folded, None)),
+            Expr::UnaryOp { op, expr: inner } => {
+                let (inner_clean, inner_sub) = Self::extract_subqueries_from_expr(inner)?;
+                Ok((
+                    Expr::UnaryOp {
+                        op: *op,
+                        expr: Box::new(inner_clean),
+                    },
+                    inner_sub,
+                ))
+            }
+            Expr::Named { expr: inner, name } => {
+                let (inner_clean, inner_sub) = Self::extract_subqueries_from_expr(inner)?;
+                Ok((Expr::Named { expr: Box::new(inner_clean), name: name.clone() }, inner_sub))
+            }
+            // aggregates, scalar functions and casts, their arguments are checked at execution
+            Expr::Function(_) | Expr::Cast { .. } => {
                 Ok((expr.clone(), None))
             }
             Expr::Identifier(_)
             | Expr::CompoundIdentifier(_)
             | Expr::Value(_)
-            | Expr::Nested(_) => Ok((expr.clone(), None)),
+            | Expr::Nested(_)
+            | Expr::QualifiedWildcard(..) => Ok((expr.clone(), None)),
             _ => Err(RsqlError::ParserError(format!("Unsupported expression: {}", expr))),
         }
     }
 
+    /// Split a WHERE predicate into the conditions left for Filter and an IN / EXISTS subquery
+    /// The subquery may be the whole predicate or one of its AND conditions
+    fn split_subquery_predicate(expr: &Expr) -> RsqlResult<(Option<Expr>, SubqueryApply)> {
+        match expr {
+            Expr::InSubquery { expr: in_expr, subquery, negated } => {
+                let plan = Self::build_query(subquery)?;
+                Ok((None, Some((plan, ApplyType::In { expr: in_expr.clone(), negated: *negated }))))
+            }
+            Expr::Exists { subquery, negated } => {
+                let plan = Self::build_query(subquery)?;
+                Ok((None, Some((plan, ApplyType::Exists { negated: *negated }))))
+            }
+            Expr::Nested(inner) if matches!(**inner, Expr::InSubquery { .. } | Expr::Exists { .. }) => {
+                Self::split_subquery_predicate(inner)
+            }
+            Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+                let (left_rest, left_sub) = Self::split_subquery_predicate(left)?;
+                let (right_rest, right_sub) = Self::split_subquery_predicate(right)?;
+                if left_sub.is_some() && right_sub.is_some() {
+                    return Err(RsqlError::ParserError(
+                        "Only one subquery is supported in a WHERE clause".to_string(),
+                    ));
+                }
+                let rest = match (left_rest, right_rest) {
+                    (Some(l), Some(r)) => Some(Expr::BinaryOp {
+                        left: Box::new(l),
+                        op: BinaryOperator::And,
+                        right: Box::new(r),
+                    }),
+                    (l, r) => l.or(r),
+                };
+                Ok((rest, left_sub.or(right_sub)))
+            }
+            _ => {
+                let (clean, sub) = Self::extract_subqueries_from_expr(expr)?;
+                Ok((Some(clean), sub))
+            }
+        }
+    }
+
     fn extract_subqueries_from_exprs(exprs: &[Expr]) -> RsqlResult<(Vec<Expr>, Option<(PlanNode, ApplyType)>)> {
         let mut clean_exprs = Vec::new();
         let mut sub = None;
@@ -867,10 +1410,27 @@ impl Plan {
     // ==================== DDL / INSERT / UPDATE / DELETE / DCL ====================
     fn from_ddl_ast(stmt: &Statement) -> RsqlResult<PlanNode> {
         match stmt {
+            Statement::CreateTable(create) if create.query.is_some() => {
+                if !create.columns.is_empty() {
+                    return Err(RsqlError::ParserError(
+                        "CREATE TABLE ... AS SELECT does not take a column list".to_string(),
+                    ));
+                }
+                validate_table_name(&create.name.to_string())?;
+                let input = Self::build_query(create.query.as_ref().unwrap())?;
+                Ok(PlanNode::DDL {
+                    op: DdlOperation::CreateTableAs {
+                        table_name: create.name.to_string(),
+                        input: Box::new(input),
+                        if_not_exists: create.if_not_exists,
+                    },
+                })
+            }
             Statement::CreateTable(create) => {
                 // Convert Vec<ColumnDef> (AST) to TableSchema.
                 // This will validate and extract all necessary column information.
-                let schema = columns_ast_to_schema(&create.columns)?;
+                validate_table_name(&create.name.to_string())?;
+                let schema = columns_ast_to_schema(&create.columns, &create.constraints)?;
                 Ok(PlanNode::DDL {
                     op: DdlOperation::CreateTable {
                         table_name: create.name.to_string(),
@@ -933,6 +1493,7 @@ impl Plan {
                                 "Unsupported RENAME TABLE target".to_string(),
                             )),
                         };
+                        validate_table_name(&new_name)?;
                         Ok(PlanNode::DDL {
                             op: DdlOperation::RenameTable {
                                 old_name: alter.name.to_string(),
@@ -942,6 +1503,7 @@ impl Plan {
                         })
                     }
                     AstAlterTableOperation::RenameColumn { old_column_name, new_column_name } => {
+                        validate_column_name(&new_column_name.to_string())?;
                         Ok(PlanNode::DDL {
                             op: DdlOperation::RenameColumn {
                                 table_name: alter.name.to_string(),
@@ -964,23 +1526,67 @@ impl Plan {
                             },
                         })
                     }
+                    AstAlterTableOperation::AlterColumn {
+                        column_name,
+                        op: sqlparser::ast::AlterColumnOperation::SetDataType { data_type, .. },
+                    } => {
+                        Ok(PlanNode::DDL {
+                            op: DdlOperation::AlterColumnType {
+                                table_name: alter.name.to_string(),
+                                column_name: column_name.value.clone(),
+                                data_type: data_type_to_col_type(data_type, &column_name.value)?,
+                            },
+                        })
+                    }
+                    AstAlterTableOperation::AddConstraint { constraint, .. } => {
+                        let (columns, primary) = match constraint {
+                            TableConstraint::PrimaryKey(pk) => (&pk.columns, true),
+                            TableConstraint::Unique(unique) => (&unique.columns, false),
+                            _ => return Err(RsqlError::ParserError(
+                                "Only PRIMARY KEY and UNIQUE constraints can be added".to_string(),
+                            )),
+                        };
+                        let [column] = columns.as_slice() else {
+                            return Err(RsqlError::ParserError(
+                                "A key constraint must name exactly one column".to_string(),
+                            ));
+                        };
+                        let Expr::Identifier(column_name) = &column.column.expr else {
+                            return Err(RsqlError::ParserError(format!(
+                                "Unsupported key column {}", column.column.expr
+                            )));
+                        };
+                        Ok(PlanNode::DDL {
+                            op: DdlOperation::AddKey {
+                                table_name: alter.name.to_string(),
+                                column_name: column_name.value.clone(),
+                                primary,
+                            },
+                        })
+                    }
                     _ => Err(RsqlError::ParserError(
                         "Only ALTER TABLE RENAME TABLE is supported".to_string(),
                     )),
                 }
             }
             Statement::Insert(insert) => {
+                let on_conflict = Self::insert_conflict(&insert.on)?;
+                let returning = insert.returning.as_deref().map(Self::extract_projection);
                 if let Some(source) = &insert.source {
                     match &*source.body {
                         SetExpr::Values(values) => {
+                            // `INSERT INTO t VALUES` without rows inserts nothing
                             let rows: Vec<Vec<Expr>> = values.rows.iter()
-                                .map(|row: &Vec<Expr>| row.iter().map(|expr: &Expr| expr.clone()).collect::<Vec<Expr>>())
+                                .filter(|row| !is_empty_list(row))
+                                .map(|row: &Vec<Expr>| row.iter().map(|expr: &Expr| Self::fold_signed_number(expr).unwrap_or_else(|| expr.clone())).collect::<Vec<Expr>>())
                                 .collect::<Vec<Vec<Expr>>>();
                             Ok(PlanNode::Insert {
                                 table_name: insert.table.to_string(),
                                 columns: if insert.columns.is_empty() { None } else { Some(insert.columns.iter().map(|c| c.to_string()).collect()) },
                                 values: rows,
                                 input: None,
+                                on_conflict,
+                                returning,
                             })
                         },
                         SetExpr::Select(select) => {
@@ -990,16 +1596,24 @@ impl Plan {
                                 columns: if insert.columns.is_empty() { None } else { Some(insert.columns.iter().map(|c| c.to_string()).collect()) },
                                 values: vec![],
                                 input: Some(Box::new(sub_plan)),
+                                on_conflict,
+                                returning,
                             })
                         }
                         _ => return Err(RsqlError::ParserError("Unsupported INSERT source".to_string())),
                     }
                 } else {
+                    if !insert.assignments.is_empty() {
+                        return Err(RsqlError::ParserError("INSERT ... SET is not supported".to_string()));
+                    }
+                    // DEFAULT VALUES, a single row of column defaults
                     Ok(PlanNode::Insert {
                         table_name: insert.table.to_string(),
                         columns: if insert.columns.is_empty() { None } else { Some(insert.columns.iter().map(|c| c.to_string()).collect()) },
-                        values: vec![],
+                        values: vec![vec![]],
                         input: None,
+                        on_conflict,
+                        returning,
                     })
                 }
             }
@@ -1022,16 +1636,18 @@ impl Plan {
                 };
 
                 // Base scan
-                let mut plan = PlanNode::TableScan { table: table_name };
+                let mut plan = PlanNode::TableScan { table: table_name, alias: None };
 
                 // WHERE clause → Filter (+ Apply if needed)
                 if let Some(selection) = &delete.selection {
                     let (clean_pred, sub_info) =
-                        Self::extract_subqueries_from_expr(selection)?;
-                    plan = PlanNode::Filter {
-                        predicate: clean_pred,
-                        input: Box::new(plan),
-                    };
+                        Self::split_subquery_predicate(selection)?;
+                    if let Some(predicate) = clean_pred {
+                        plan = PlanNode::Filter {
+                            predicate,
+                            input: Box::new(plan),
+                        };
+                    }
                     if let Some((sub_plan, apply_type)) = sub_info {
                         plan = PlanNode::Apply {
                             input: Box::new(plan),
@@ -1041,24 +1657,34 @@ impl Plan {
                     }
                 }
 
+                if !delete.order_by.is_empty() {
+                    return Err(RsqlError::ParserError("ORDER BY in DELETE is not supported".to_string()));
+                }
+                if let Some(limit) = &delete.limit {
+                    plan = Self::mutation_limit(plan, limit)?;
+                }
+
                 Ok(PlanNode::Delete {
                     input: Box::new(plan),
+                    returning: delete.returning.as_deref().map(Self::extract_projection),
                 })
             }
             Statement::Update(update) => {
                 let table_name = update.table.to_string();
 
                 // Base scan
-                let mut plan = PlanNode::TableScan { table: table_name };
+                let mut plan = PlanNode::TableScan { table: table_name, alias: None };
 
                 // WHERE clause → Filter (+ Apply if needed)
                 if let Some(selection) = &update.selection {
                     let (clean_pred, sub_info) =
-                        Self::extract_subqueries_from_expr(selection)?;
-                    plan = PlanNode::Filter {
-                        predicate: clean_pred,
-                        input: Box::new(plan),
-                    };
+                        Self::split_subquery_predicate(selection)?;
+                    if let Some(predicate) = clean_pred {
+                        plan = PlanNode::Filter {
+                            predicate,
+                            input: Box::new(plan),
+                        };
+                    }
                     if let Some((sub_plan, apply_type)) = sub_info {
                         plan = PlanNode::Apply {
                             input: Box::new(plan),
@@ -1071,12 +1697,17 @@ impl Plan {
                 let assignments = update
                     .assignments
                     .iter()
-                    .map(|a| (format!("{}", a.target), a.value.clone()))
+                    .map(|a| (format!("{}", a.target), Self::fold_signed_number(&a.value).unwrap_or_else(|| a.value.clone())))
                     .collect();
 
+                if let Some(limit) = &update.limit {
+                    plan = Self::mutation_limit(plan, limit)?;
+                }
+
                 Ok(PlanNode::Update {
                     input: Box::new(plan),
                     assignments,
+                    returning: update.returning.as_deref().map(Self::extract_projection),
                 })
             }
             // --- DCL: CREATE USER, DROP USER ---
@@ -1110,67 +1741,6 @@ impl Plan {
             exprs.iter().map(|e| format!("{}", e)).collect::<Vec<_>>().join(", ")
         }
 
-        fn fmt_alter_op(op: &AlterTableOperation, _table_name: &str) -> String {
-            match op {
-                AlterTableOperation::AddColumn { column_def, .. } => {
-                    format!(
-                        "ADD COLUMN {} {}",
-                        column_def.name,
-                        column_def.data_type
-                    )
-                }
-                AlterTableOperation::DropColumn { column_names, if_exists, .. } => {
-                    let cols = column_names
-                        .iter()
-                        .map(|c| c.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    if *if_exists {
-                        format!("DROP COLUMN IF EXISTS {}", cols)
-                    } else {
-                        format!("DROP COLUMN {}", cols)
-                    }
-                }
-                AlterTableOperation::RenameColumn {
-                    old_column_name,
-                    new_column_name,
-                } => {
-                    format!(
-                        "RENAME COLUMN {} TO {}",
-                        old_column_name,
-                        new_column_name
-                    )
-                }
-                AlterTableOperation::RenameTable { table_name: new_name } => {
-                    // Only print the new table name (do not include "TO" or old name here)
-                    format!("{}", new_name)
-                }
-                AlterTableOperation::AlterColumn { column_name, op } => {
-                    match op {
-                        sqlparser::ast::AlterColumnOperation::SetDataType { data_type, .. } => {
-                            format!(
-                                "ALTER COLUMN {} TYPE {}",
-                                column_name,
-                                data_type
-                            )
-                        }
-                        sqlparser::ast::AlterColumnOperation::SetNotNull => {
-                            format!("ALTER COLUMN {} SET NOT NULL", column_name)
-                        }
-                        sqlparser::ast::AlterColumnOperation::DropNotNull => {
-                            format!("ALTER COLUMN {} DROP NOT NULL", column_name)
-                        }
-                        _ => format!(
-                            "ALTER COLUMN {} {:?}",
-                            column_name,
-                            op
-                        ),
-                    }
-                }
-                _ => format!("{:?}", op),
-            }
-        }
-
         fn inner(plan: &PlanNode, prefix: &str, is_last: bool) {
             let branch = if is_last { "└── " } else { "├── " };
             println!("{}{}{}", prefix, branch, label(plan));
@@ -1183,7 +1753,12 @@ impl Plan {
 
         fn label(plan: &PlanNode) -> String {
             match plan {
-                PlanNode::TableScan { table } => format!("TableScan [{}]", table),
+                PlanNode::TableScan { table, alias: None } => format!("TableScan [{}]", table),
+                PlanNode::TableScan { table, alias: Some(alias) } => format!("TableScan [{} AS {}]", table, alias),
+                PlanNode::With { ctes, .. } => format!("With [{}]", ctes.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ")),
+                PlanNode::CteScan { name, alias: None } => format!("CteScan [{}]", name),
+                PlanNode::CteScan { name, alias: Some(alias) } => format!("CteScan [{} AS {}]", name, alias),
+                PlanNode::Values { columns, rows } => format!("Values [{}] ({} rows)", columns.join(", "), rows.len()),
                 PlanNode::Subquery { alias, .. } => format!("Subquery{}", alias.as_ref().map(|a| format!(" AS {}", a)).unwrap_or_default()),
                 PlanNode::Apply { apply_type, .. } => format!("Apply [{:?}]", apply_type),
                 PlanNode::Filter { predicate, .. } => format!("Filter [{}]", predicate),
@@ -1191,8 +1766,8 @@ impl Plan {
                     format!("Aggregate [group_by: {}, aggr: {}]", fmt_exprs(group_by), fmt_exprs(aggr_exprs))
                 }
                 PlanNode::Projection { exprs, .. } => format!("Projection [{}]", fmt_exprs(exprs)),
-                PlanNode::Sort { columns, asc, .. } => {
-                    let items = columns
+                PlanNode::Sort { exprs, asc, .. } => {
+                    let items = exprs
                         .iter()
                         .zip(asc.iter())
                         .map(|(c, a)| {
@@ -1206,6 +1781,7 @@ impl Plan {
                         .join(", ");
                     format!("Sort [{}]", items)
                 }
+                PlanNode::Limit { limit, offset, .. } => format!("Limit [{} offset {}]", limit, offset),
                 PlanNode::Join { join_type, on, .. } => format!("Join [{:?}, on: {}]", join_type, on.as_ref().map_or("None".to_string(), |e| format!("{}", e))),
                 PlanNode::DDL { op } => match op {
                     DdlOperation::CreateTable { table_name, .. } => {
@@ -1232,8 +1808,33 @@ impl Plan {
                     DdlOperation::DropColumn { table_name, column_name, if_exists } => {
                          format!("AlterTable [{}] DROP COLUMN {} (if_exists={})", table_name, column_name, if_exists)
                     }
+                    DdlOperation::AlterColumnType { table_name, column_name, data_type } => {
+                        format!("AlterTable [{}] ALTER COLUMN {} TYPE {:?}", table_name, column_name, data_type)
+                    }
+                    DdlOperation::AddKey { table_name, column_name, primary } => {
+                        let key = if *primary { "PRIMARY KEY" } else { "UNIQUE" };
+                        format!("AlterTable [{}] ADD {} ({})", table_name, key, column_name)
+                    }
+                    DdlOperation::Reindex { table_name, column } => match column {
+                        Some(column) => format!("Reindex [{}] column {}", table_name, column),
+                        None => format!("Reindex [{}]", table_name),
+                    }
+                    DdlOperation::DebugIndex { table_name, column } => {
+                        format!("DebugIndex [{}] column {}", table_name, column)
+                    }
+                    DdlOperation::CommentOnColumn { table_name, column_name, .. } => {
+                        format!("CommentOnColumn [{}.{}]", table_name, column_name)
+                    }
+                    DdlOperation::Describe { table_name } => format!("Describe [{}]", table_name),
+                    DdlOperation::IndexStats { table_name } => format!("IndexStats [{}]", table_name),
+                    DdlOperation::CreateTableAs { table_name, .. } => {
+                        format!("CreateTableAs [{}]", table_name)
+                    }
+                    DdlOperation::DryRun { op } => {
+                        format!("DryRun {}", label(&PlanNode::DDL { op: (**op).clone() }))
+                    }
                 },
-                PlanNode::Insert { table_name, columns, values, input } => {
+                PlanNode::Insert { table_name, columns, values, input, .. } => {
                     if let Some(_) = input {
                         format!("Insert [{}] cols={:?} [Subquery]", table_name, columns)
                     } else {
@@ -1262,6 +1863,10 @@ impl Plan {
                 PlanNode::Revoke { privilege, user_name, table_name: _ } => {
                     format!("Revoke [{}] FROM {}", privilege, user_name)
                 }
+                PlanNode::Kill { tnx_id } => {
+                    format!("Kill [{}]", tnx_id)
+                }
+                PlanNode::DebugWal => "DebugWal".to_string(),
             }
         }
 
@@ -1269,15 +1874,18 @@ impl Plan {
             match plan {
                 PlanNode::TableScan { .. } => vec![],
                 PlanNode::Subquery { subquery, .. } => vec![subquery],
+                PlanNode::With { ctes, input } => ctes.iter().map(|(_, cte)| cte).chain([&**input]).collect(),
                 PlanNode::Apply { input, subquery, .. } => vec![input, subquery],
                 PlanNode::Filter { input, .. } => vec![input],
                 PlanNode::Aggregate { input, .. } => vec![input],
                 PlanNode::Projection { input, .. } => vec![input],
                 PlanNode::Sort { input, .. } => vec![input],
+                PlanNode::Limit { input, .. } => vec![input],
                 PlanNode::Join { left, right, .. } => vec![left, right],
-                PlanNode::Delete { input } => vec![input],
+                PlanNode::Delete { input, .. } => vec![input],
                 PlanNode::Update { input, .. } => vec![input],
                 PlanNode::Insert { input: Some(sub_plan), .. } => vec![sub_plan],
+                PlanNode::DDL { op: DdlOperation::CreateTableAs { input, .. } } => vec![input],
                 _ => vec![],
             }
         }
@@ -1290,75 +1898,6 @@ impl Plan {
             exprs.iter().map(|e| format!("{}", e)).collect::<Vec<_>>().join(", ")
         }
 
-        fn fmt_alter_op(op: &AlterTableOperation, old_table_name: &str) -> String {
-            match op {
-                AlterTableOperation::AddColumn { column_def, .. } => {
-                    format!(
-                        "ADD COLUMN {} {}",
-                        column_def.name,
-                        column_def.data_type
-                    )
-                }
-                AlterTableOperation::DropColumn { column_names, if_exists, .. } => {
-                    let cols = column_names
-                        .iter()
-                        .map(|c| c.to_string())
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    if *if_exists {
-                        format!("DROP COLUMN IF EXISTS {}", cols)
-                    } else {
-                        format!("DROP COLUMN {}", cols)
-                    }
-                }
-                AlterTableOperation::RenameColumn {
-                    old_column_name,
-                    new_column_name,
-                } => {
-                    format!(
-                        "RENAME COLUMN {} TO {}",
-                        old_column_name,
-                        new_column_name
-                    )
-                }
-                AlterTableOperation::RenameTable { table_name: new_name_kind } => {
-                    // Print as: RENAME TABLE <old> TO <new>
-                    match new_name_kind {
-                        RenameTableNameKind::To(obj_name) => {
-                            format!("RENAME TABLE {} TO {}", old_table_name, obj_name)
-                        }
-                        _ => {
-                            // fallback
-                            format!("RENAME TABLE {} TO {:?}", old_table_name, new_name_kind)
-                        }
-                    }
-                }
-                AlterTableOperation::AlterColumn { column_name, op } => {
-                    match op {
-                        sqlparser::ast::AlterColumnOperation::SetDataType { data_type, .. } => {
-                            format!(
-                                "ALTER COLUMN {} TYPE {}",
-                                column_name,
-                                data_type
-                            )
-                        }
-                        sqlparser::ast::AlterColumnOperation::SetNotNull => {
-                            format!("ALTER COLUMN {} SET NOT NULL", column_name)
-                        }
-                        sqlparser::ast::AlterColumnOperation::DropNotNull => {
-                            format!("ALTER COLUMN {} DROP NOT NULL", column_name)
-                        }
-                        _ => format!(
-                            "ALTER COLUMN {} {:?}",
-                            column_name,
-                            op
-                        ),
-                    }
-                }
-                _ => format!("{:?}", op),
-            }
-        }
-
         // Print the PlanNode recursively, and for each PlanNode, print its expressions with paths.
         fn inner(plan: &PlanNode, prefix: &str, is_last: bool) {
             let branch = if is_last { "└── " } else { "├── " };
@@ -1395,10 +1934,10 @@ impl Plan {
                         print_expr_with_path(expr, prefix, &path);
                     }
                 }
-                PlanNode::Sort { columns, asc, .. } => {
-                    for i in 0..columns.len() {
-                        let col_path = format!("(PlanNode::Sort.columns[{}])", i);
-                        println!("{}{} -> {}", prefix, col_path, columns[i]);
+                PlanNode::Sort { exprs, asc, .. } => {
+                    for i in 0..exprs.len() {
+                        let expr_path = format!("(PlanNode::Sort.exprs[{}])", i);
+                        print_expr_with_path(&exprs[i], prefix, &expr_path);
                         let dir_path = format!("(PlanNode::Sort.asc[{}])", i);
                         println!("{}{} -> {}", prefix, dir_path, asc[i]);
                     }
@@ -1487,6 +2026,59 @@ impl Plan {
                             let path_exists = "(PlanNode::DDL.op[DropColumn].if_exists)";
                             println!("{}{} -> {}", prefix, path_exists, if_exists);
                         }
+                        DdlOperation::AlterColumnType { table_name, column_name, data_type } => {
+                            let path_table = "(PlanNode::DDL.op[AlterColumnType].table_name)";
+                            println!("{}{} -> {}", prefix, path_table, table_name);
+                            let path_col = "(PlanNode::DDL.op[AlterColumnType].column_name)";
+                            println!("{}{} -> {}", prefix, path_col, column_name);
+                            let path_type = "(PlanNode::DDL.op[AlterColumnType].data_type)";
+                            println!("{}{} -> {:?}", prefix, path_type, data_type);
+                        }
+                        DdlOperation::AddKey { table_name, column_name, primary } => {
+                            let path_table = "(PlanNode::DDL.op[AddKey].table_name)";
+                            println!("{}{} -> {}", prefix, path_table, table_name);
+                            let path_col = "(PlanNode::DDL.op[AddKey].column_name)";
+                            println!("{}{} -> {}", prefix, path_col, column_name);
+                            let path_primary = "(PlanNode::DDL.op[AddKey].primary)";
+                            println!("{}{} -> {}", prefix, path_primary, primary);
+                        }
+                        DdlOperation::Reindex { table_name, column } => {
+                            let path_table = "(PlanNode::DDL.op[Reindex].table_name)";
+                            println!("{}{} -> {}", prefix, path_table, table_name);
+                            let path_col = "(PlanNode::DDL.op[Reindex].column)";
+                            println!("{}{} -> {:?}", prefix, path_col, column);
+                        }
+                        DdlOperation::DebugIndex { table_name, column } => {
+                            let path_table = "(PlanNode::DDL.op[DebugIndex].table_name)";
+                            println!("{}{} -> {}", prefix, path_table, table_name);
+                            let path_col = "(PlanNode::DDL.op[DebugIndex].column)";
+                            println!("{}{} -> {}", prefix, path_col, column);
+                        }
+                        DdlOperation::CommentOnColumn { table_name, column_name, comment } => {
+                            let path_table = "(PlanNode::DDL.op[CommentOnColumn].table_name)";
+                            println!("{}{} -> {}", prefix, path_table, table_name);
+                            let path_col = "(PlanNode::DDL.op[CommentOnColumn].column_name)";
+                            println!("{}{} -> {}", prefix, path_col, column_name);
+                            let path_comment = "(PlanNode::DDL.op[CommentOnColumn].comment)";
+                            println!("{}{} -> {:?}", prefix, path_comment, comment);
+                        }
+                        DdlOperation::Describe { table_name } => {
+                            let path_table = "(PlanNode::DDL.op[Describe].table_name)";
+                            println!("{}{} -> {}", prefix, path_table, table_name);
+                        }
+                        DdlOperation::IndexStats { table_name } => {
+                            let path_table = "(PlanNode::DDL.op[IndexStats].table_name)";
+                            println!("{}{} -> {}", prefix, path_table, table_name);
+                        }
+                        DdlOperation::CreateTableAs { table_name, if_not_exists, .. } => {
+                            let path_table = "(PlanNode::DDL.op[CreateTableAs].table_name)";
+                            println!("{}{} -> {}", prefix, path_table, table_name);
+                            let path_if_not_exists = "(PlanNode::DDL.op[CreateTableAs].if_not_exists)";
+                            println!("{}{} -> {}", prefix, path_if_not_exists, if_not_exists);
+                        }
+                        DdlOperation::DryRun { op } => {
+                            print_plan_expr_paths(&PlanNode::DDL { op: (**op).clone() }, prefix, _plan_path);
+                        }
                     }
                 }
                 // ---- Add pretty print for CreateUser ----
@@ -1525,6 +2117,10 @@ impl Plan {
                     let p2 = "(PlanNode::Revoke.user_name)";
                     println!("{}{} -> {}", prefix, p2, user_name);
                 }
+                PlanNode::Kill { tnx_id } => {
+                    let p = "(PlanNode::Kill.tnx_id)";
+                    println!("{}{} -> {}", prefix, p, tnx_id);
+                }
                 _ => {}
             }
         }
@@ -1641,7 +2237,12 @@ impl Plan {
 
         fn label(plan: &PlanNode) -> String {
             match plan {
-                PlanNode::TableScan { table } => format!("TableScan [{}]", table),
+                PlanNode::TableScan { table, alias: None } => format!("TableScan [{}]", table),
+                PlanNode::TableScan { table, alias: Some(alias) } => format!("TableScan [{} AS {}]", table, alias),
+                PlanNode::With { ctes, .. } => format!("With [{}]", ctes.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ")),
+                PlanNode::CteScan { name, alias: None } => format!("CteScan [{}]", name),
+                PlanNode::CteScan { name, alias: Some(alias) } => format!("CteScan [{} AS {}]", name, alias),
+                PlanNode::Values { columns, rows } => format!("Values [{}] ({} rows)", columns.join(", "), rows.len()),
                 PlanNode::Subquery { alias, .. } => format!("Subquery{}", alias.as_ref().map(|a| format!(" AS {}", a)).unwrap_or_default()),
                 PlanNode::Apply { apply_type, .. } => format!("Apply [{:?}]", apply_type),
                 PlanNode::Filter { predicate, .. } => format!("Filter [{}]", predicate),
@@ -1649,8 +2250,8 @@ impl Plan {
                     format!("Aggregate [group_by: {}, aggr: {}]", fmt_exprs(group_by), fmt_exprs(aggr_exprs))
                 }
                 PlanNode::Projection { exprs, .. } => format!("Projection [{}]", fmt_exprs(exprs)),
-                PlanNode::Sort { columns, asc, .. } => {
-                    let items = columns
+                PlanNode::Sort { exprs, asc, .. } => {
+                    let items = exprs
                         .iter()
                         .zip(asc.iter())
                         .map(|(c, a)| {
@@ -1664,6 +2265,7 @@ impl Plan {
                         .join(", ");
                     format!("Sort [{}]", items)
                 }
+                PlanNode::Limit { limit, offset, .. } => format!("Limit [{} offset {}]", limit, offset),
                 PlanNode::Join { join_type, on, .. } => format!("Join [{:?}, on: {}]", join_type, on.as_ref().map_or("None".to_string(), |e| format!("{}", e))),
                 PlanNode::DDL { op } => match op {
                     DdlOperation::CreateTable { table_name, .. } => {
@@ -1690,8 +2292,33 @@ impl Plan {
                     DdlOperation::DropColumn { table_name, column_name, if_exists } => {
                          format!("AlterTable [{}] DROP COLUMN {} (if_exists={})", table_name, column_name, if_exists)
                     }
+                    DdlOperation::AlterColumnType { table_name, column_name, data_type } => {
+                        format!("AlterTable [{}] ALTER COLUMN {} TYPE {:?}", table_name, column_name, data_type)
+                    }
+                    DdlOperation::AddKey { table_name, column_name, primary } => {
+                        let key = if *primary { "PRIMARY KEY" } else { "UNIQUE" };
+                        format!("AlterTable [{}] ADD {} ({})", table_name, key, column_name)
+                    }
+                    DdlOperation::Reindex { table_name, column } => match column {
+                        Some(column) => format!("Reindex [{}] column {}", table_name, column),
+                        None => format!("Reindex [{}]", table_name),
+                    }
+                    DdlOperation::DebugIndex { table_name, column } => {
+                        format!("DebugIndex [{}] column {}", table_name, column)
+                    }
+                    DdlOperation::CommentOnColumn { table_name, column_name, .. } => {
+                        format!("CommentOnColumn [{}.{}]", table_name, column_name)
+                    }
+                    DdlOperation::Describe { table_name } => format!("Describe [{}]", table_name),
+                    DdlOperation::IndexStats { table_name } => format!("IndexStats [{}]", table_name),
+                    DdlOperation::CreateTableAs { table_name, .. } => {
+                        format!("CreateTableAs [{}]", table_name)
+                    }
+                    DdlOperation::DryRun { op } => {
+                        format!("DryRun {}", label(&PlanNode::DDL { op: (**op).clone() }))
+                    }
                 },
-                PlanNode::Insert { table_name, columns, values, input } => {
+                PlanNode::Insert { table_name, columns, values, input, .. } => {
                     if let Some(_) = input {
                         format!("Insert [{}] cols={:?} [Subquery]", table_name, columns)
                     } else {
@@ -1721,6 +2348,10 @@ impl Plan {
                 PlanNode::Revoke { privilege, user_name, table_name: _ } => {
                     format!("Revoke [{}] FROM {}", privilege, user_name)
                 }
+                PlanNode::Kill { tnx_id } => {
+                    format!("Kill [{}]", tnx_id)
+                }
+                PlanNode::DebugWal => "DebugWal".to_string(),
             }
         }
 
@@ -1728,15 +2359,18 @@ impl Plan {
             match plan {
                 PlanNode::TableScan { .. } => vec![],
                 PlanNode::Subquery { subquery, .. } => vec![subquery],
+                PlanNode::With { ctes, input } => ctes.iter().map(|(_, cte)| cte).chain([&**input]).collect(),
                 PlanNode::Apply { input, subquery, .. } => vec![input, subquery],
                 PlanNode::Filter { input, .. } => vec![input],
                 PlanNode::Aggregate { input, .. } => vec![input],
                 PlanNode::Projection { input, .. } => vec![input],
                 PlanNode::Sort { input, .. } => vec![input],
+                PlanNode::Limit { input, .. } => vec![input],
                 PlanNode::Join { left, right, .. } => vec![left, right],
-                PlanNode::Delete { input } => vec![input],
+                PlanNode::Delete { input, .. } => vec![input],
                 PlanNode::Update { input, .. } => vec![input],
                 PlanNode::Insert { input: Some(sub_plan), .. } => vec![sub_plan],
+                PlanNode::DDL { op: DdlOperation::CreateTableAs { input, .. } } => vec![input],
                 _ => vec![],
             }
         }
@@ -1744,38 +2378,221 @@ impl Plan {
     }
 }
 
+/// Add the position of a sqlparser error to its message, with the offending SQL line and a caret under the column
+/// Splits a request into its statements at top level terminators, ignoring those
+/// inside quotes and comments. Pieces holding only whitespace or comments are
+/// dropped, so repeated, trailing and missing terminators are all accepted.
+fn split_statements(sql: &str, terminator: char) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut has_code = false;
+    let mut quote: Option<char> = None;
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => {
+                quote = Some(c);
+                has_code = true;
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                chars.next();
+                let mut prev = ' ';
+                for (_, c) in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            c if c == terminator => {
+                if has_code {
+                    statements.push(sql[start..i].trim());
+                }
+                start = i + c.len_utf8();
+                has_code = false;
+            }
+            c if !c.is_whitespace() => has_code = true,
+            _ => {}
+        }
+    }
+    if has_code {
+        statements.push(sql[start..].trim());
+    }
+    statements
+}
+
+/// Stands for the empty list of `x IN ()` and `VALUES` without rows, which the generic dialect does not parse
+const EMPTY_LIST: &str = "()";
+
+/// Parses SQL with the generic dialect, after filling empty lists with the `EMPTY_LIST` placeholder:
+/// `x IN ()` is read as `x IN (placeholder)` and a trailing `VALUES` as `VALUES (placeholder)`
+fn parse_statements(sql: &str) -> Result<Vec<Statement>, ParserError> {
+    let dialect = GenericDialect {};
+    let mut tokens = Tokenizer::new(&dialect, sql).tokenize_with_location()?;
+    let significant: Vec<usize> = (0..tokens.len())
+        .filter(|&i| !matches!(tokens[i].token, Token::Whitespace(_)))
+        .collect();
+    let is_keyword = |i: usize, keyword: Keyword| matches!(&tokens[i].token, Token::Word(word) if word.keyword == keyword);
+    let mut fills = vec![];
+    for (pos, &i) in significant.iter().enumerate() {
+        let next = |n: usize| significant.get(pos + n).map(|&j| &tokens[j].token);
+        if is_keyword(i, Keyword::IN) && next(1) == Some(&Token::LParen) && next(2) == Some(&Token::RParen) {
+            fills.push((significant[pos + 2], false));
+        } else if is_keyword(i, Keyword::VALUES)
+            && !(pos > 0 && is_keyword(significant[pos - 1], Keyword::DEFAULT))
+            && matches!(next(1), None | Some(Token::SemiColon | Token::EOF)) {
+            fills.push((i + 1, true));
+        }
+    }
+    for (at, with_parens) in fills.into_iter().rev() {
+        let span = tokens[at.min(tokens.len() - 1)].span;
+        let mut fill = vec![Token::Placeholder(EMPTY_LIST.to_string())];
+        if with_parens {
+            fill = vec![Token::LParen, fill.remove(0), Token::RParen];
+        }
+        tokens.splice(at..at, fill.into_iter().map(|token| TokenWithSpan::new(token, span)));
+    }
+    Parser::new(&dialect).with_tokens_with_locations(tokens).parse_statements()
+}
+
+/// Whether a parsed list is the `EMPTY_LIST` placeholder `parse_statements` filled an empty list with
+fn is_empty_list(list: &[Expr]) -> bool {
+    matches!(list, [Expr::Value(ValueWithSpan { value: Value::Placeholder(p), .. })] if p == EMPTY_LIST)
+}
+
+fn parser_error_with_context(sql: &str, message: &str) -> RsqlError {
+    let position = regex::Regex::new(r"Line: (\d+), Column: (\d+)")
+        .unwrap()
+        .captures(message)
+        .and_then(|caps| Some((caps[1].parse::<usize>().ok()?, caps[2].parse::<usize>().ok()?)))
+        // errors at the end of input carry no position, point after the last character
+        .or_else(|| message.ends_with("found: EOF").then(|| {
+            let last_line = sql.trim_end().lines().count().max(1);
+            let last_len = sql.trim_end().lines().last().map_or(0, |l| l.chars().count());
+            (last_line, last_len + 1)
+        }));
+    let Some((line, column)) = position else {
+        return RsqlError::ParserError(message.to_string());
+    };
+    let Some(line_text) = sql.lines().nth(line.saturating_sub(1)) else {
+        return RsqlError::ParserError(message.to_string());
+    };
+    // byte offset of the error in the whole statement
+    let line_start: usize = sql.split_inclusive('\n').take(line.saturating_sub(1)).map(str::len).sum();
+    let column_bytes: usize = line_text.chars().take(column.saturating_sub(1)).map(char::len_utf8).sum();
+    let prefix = format!("LINE {}: ", line);
+    let caret_pad = prefix.chars().count() + line_text.chars().take(column.saturating_sub(1)).count();
+    RsqlError::ParserError(format!(
+        "{} (byte {})\n{}{}\n{}^",
+        message,
+        line_start + column_bytes,
+        prefix,
+        line_text,
+        " ".repeat(caret_pad)
+    ))
+}
+
+/// Column name kept for the primary key generated for tables without one
+const RESERVED_COLUMN_NAMES: &[&str] = &["rowid"];
+/// Prefix of the system catalog tables
+const RESERVED_TABLE_PREFIX: &str = "sys_";
+
+/// Check a table name given by the user: not empty, fits its catalog slot and not a system name
+fn validate_table_name(name: &str) -> RsqlResult<()> {
+    if name.is_empty() {
+        return Err(RsqlError::ParserError("Table name cannot be empty".to_string()));
+    }
+    if name.len() > MAX_TABLE_NAME_SIZE {
+        return Err(RsqlError::ParserError(format!(
+            "Table name {} exceeds max length {}", name, MAX_TABLE_NAME_SIZE
+        )));
+    }
+    if name.to_lowercase().starts_with(RESERVED_TABLE_PREFIX) {
+        return Err(RsqlError::ParserError(format!(
+            "Table name {} is reserved, the {} prefix is used by system tables", name, RESERVED_TABLE_PREFIX
+        )));
+    }
+    Ok(())
+}
+
+/// Check a column name given by the user: not empty, fits its header slot and not reserved
+fn validate_column_name(name: &str) -> RsqlResult<()> {
+    if name.is_empty() {
+        return Err(RsqlError::ParserError("Column name cannot be empty".to_string()));
+    }
+    if name.len() > MAX_COL_NAME_SIZE {
+        return Err(RsqlError::ParserError(format!(
+            "Column name {} exceeds max length {}", name, MAX_COL_NAME_SIZE
+        )));
+    }
+    if RESERVED_COLUMN_NAMES.contains(&name.to_lowercase().as_str()) {
+        return Err(RsqlError::ParserError(format!("Column name {} is reserved", name)));
+    }
+    Ok(())
+}
+
+/// Map a column's AST data type to the storage column type
+pub(crate) fn data_type_to_col_type(
+    data_type: &sqlparser::ast::DataType,
+    name: &str,
+) -> crate::common::RsqlResult<ColType> {
+    let col_type = match data_type {
+        sqlparser::ast::DataType::Int(_) | sqlparser::ast::DataType::Integer(_) => ColType::Integer,
+        sqlparser::ast::DataType::Float(_) | sqlparser::ast::DataType::Real => ColType::Float,
+        sqlparser::ast::DataType::Double { .. } => ColType::Float,
+
+        sqlparser::ast::DataType::Char(opt_len) => {
+            let size = match opt_len {
+                Some(sqlparser::ast::CharacterLength::IntegerLength { length, .. }) => *length as usize,
+                Some(sqlparser::ast::CharacterLength::Max) | None => 1,
+            };
+            ColType::Chars(size)
+        }
+
+        sqlparser::ast::DataType::Varchar(opt_len) => {
+            let size = match opt_len {
+                Some(sqlparser::ast::CharacterLength::IntegerLength { length, .. }) => *length as usize,
+                Some(sqlparser::ast::CharacterLength::Max) | None => 255,
+            };
+            ColType::VarChar(size)
+        }
+
+        sqlparser::ast::DataType::Bool => ColType::Bool,
+
+        // stored as seconds since the Unix epoch
+        sqlparser::ast::DataType::Timestamp(..) => ColType::Integer,
+
+        _ => return Err(RsqlError::ParserError(format!("Unsupported data type for column {}", name))),
+    };
+    Ok(col_type)
+}
+
 pub(crate) fn columns_ast_to_schema(
     columns: &[ColumnDef],
+    constraints: &[TableConstraint],
 ) -> crate::common::RsqlResult<TableSchema> {
     let mut table_columns = Vec::new();
+    let mut checks = Vec::new();
+    let mut defaults = std::collections::HashMap::new();
+    let mut collations = std::collections::HashMap::new();
+    let mut names = std::collections::HashSet::new();
     for col in columns.iter() {
         let name = col.name.to_string();
+        validate_column_name(&name)?;
+        if !names.insert(name.clone()) {
+            return Err(RsqlError::ParserError(format!("Duplicate column name {}", name)));
+        }
 
-        let data_type = match &col.data_type {
-            sqlparser::ast::DataType::Int(_) | sqlparser::ast::DataType::Integer(_) => ColType::Integer,
-            sqlparser::ast::DataType::Float(_) | sqlparser::ast::DataType::Real => ColType::Float,
-            sqlparser::ast::DataType::Double { .. } => ColType::Float,
-
-            sqlparser::ast::DataType::Char(opt_len) => {
-                let size = match opt_len {
-                    Some(sqlparser::ast::CharacterLength::IntegerLength { length, .. }) => *length as usize,
-                    Some(sqlparser::ast::CharacterLength::Max) | None => 1,
-                };
-                ColType::Chars(size)
-            }
-
-            sqlparser::ast::DataType::Varchar(opt_len) => {
-                let size = match opt_len {
-                    Some(sqlparser::ast::CharacterLength::IntegerLength { length, .. }) => *length as usize,
-                    Some(sqlparser::ast::CharacterLength::Max) | None => 255,
-                };
-                ColType::VarChar(size)
-            }
-
-            sqlparser::ast::DataType::Bool => ColType::Bool,
-        
-            _ => return Err(RsqlError::ParserError(format!("Unsupported data type for column {}", name))),
-        };
+        let data_type = data_type_to_col_type(&col.data_type, &name)?;
 
         let mut pk = false;
         let mut nullable = true;
@@ -1789,19 +2606,73 @@ pub(crate) fn columns_ast_to_schema(
                 ColumnOption::Unique { .. } => { unique = true; index = true; },
                 ColumnOption::NotNull => { nullable = false; },
                 ColumnOption::Null => { nullable = true; },
+                ColumnOption::Check(check) => checks.push(CheckConstraint::new(*check.expr.clone())),
+                ColumnOption::Default(expr) => {
+                    defaults.insert(name.clone(), Plan::fold_signed_number(expr).unwrap_or_else(|| expr.clone()));
+                },
+                ColumnOption::Collation(collation) => {
+                    if !matches!(data_type, ColType::Chars(_) | ColType::VarChar(_)) {
+                        return Err(RsqlError::ParserError(format!("COLLATE {} on non-string column {}", collation, name)));
+                    }
+                    collations.insert(name.clone(), Collation::from_name(&collation.to_string())?);
+                },
                 _ => {}
             }
         }
 
         table_columns.push(TableColumn { name, data_type, pk, nullable, unique, index, is_dropped: false });
     }
+    let mut primary_key = None;
+    for constraint in constraints {
+        match constraint {
+            TableConstraint::Check(check) => checks.push(CheckConstraint::new(*check.expr.clone())),
+            TableConstraint::PrimaryKey(pk) => {
+                if primary_key.is_some() || table_columns.iter().any(|col| col.pk) {
+                    return Err(RsqlError::ParserError("A table can only have one primary key".to_string()));
+                }
+                let mut key = vec![];
+                for column in &pk.columns {
+                    let Expr::Identifier(ident) = &column.column.expr else {
+                        return Err(RsqlError::ParserError(format!("Unsupported key column {}", column.column.expr)));
+                    };
+                    if key.contains(&ident.value) {
+                        return Err(RsqlError::ParserError(format!("Duplicate primary key column {}", ident.value)));
+                    }
+                    key.push(ident.value.clone());
+                }
+                // a single column key is the same as the column option
+                let single = key.len() == 1;
+                for name in &key {
+                    let col = table_columns.iter_mut().find(|col| &col.name == name)
+                        .ok_or(RsqlError::ParserError(format!("Primary key column {} does not exist", name)))?;
+                    col.pk = true;
+                    col.nullable = false;
+                    if single {
+                        col.index = true;
+                        col.unique = true;
+                    }
+                }
+                primary_key = Some(key);
+            },
+            _ => {},
+        }
+    }
 
-    TableSchema::new(table_columns)
+    let schema = TableSchema::new(table_columns)?;
+    let schema = match primary_key {
+        Some(key) => schema.with_primary_key(key)?,
+        None => schema,
+    };
+    Ok(schema.with_checks(checks).with_defaults(defaults).with_collations(collations))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::execution::executor::execute;
+    use crate::execution::test_utils::{query_rows, TestConn};
+    use crate::common::data_item::DataItem;
+
     #[test]
     fn test_plan_pretty_print_pro() {
         // let sql = "\
@@ -1846,4 +2717,311 @@ mod tests {
         // Plan::pretty_print_pro(plan_node);
         // assert!(false);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parser_error_position() {
+        let err = Plan::build_plan("SELECT id, name\nFROM users WHERE age > > 3").err().unwrap();
+        let RsqlError::ParserError(msg) = err else {
+            panic!("expected a parser error, got {:?}", err);
+        };
+        assert!(msg.contains("Line: 2, Column: 24"), "{msg}");
+        assert!(msg.contains("(byte 39)"), "{msg}");
+        let lines: Vec<&str> = msg.lines().collect();
+        assert_eq!(lines[1], "LINE 2: FROM users WHERE age > > 3");
+        assert_eq!(lines[2].find('^'), Some("LINE 2: ".len() + 23));
+
+        // a statement cut short points after its last character
+        let err = Plan::build_plan("SELECT * FROM users WHERE").err().unwrap();
+        let msg = err.to_string();
+        assert!(msg.contains("(byte 25)\nLINE 1: SELECT * FROM users WHERE\n"), "{msg}");
+
+        // manual DCL parsers name the unexpected token
+        let err = Plan::build_plan("GRANT select ON users FOR alice").err().unwrap();
+        assert!(err.to_string().contains("unexpected token: FOR"), "{err}");
+        let err = Plan::build_plan("DROP USER alice bob").err().unwrap();
+        assert!(err.to_string().contains("Unexpected token after DROP USER: bob"), "{err}");
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let plan = Plan::build_plan("CREATE USER a PASSWORD 'x';\nCREATE USER b PASSWORD 'y';").unwrap();
+        let users: Vec<&str> = plan.items.iter().map(|item| match item {
+            PlanItem::DCL(PlanNode::CreateUser { user_name, .. }) => user_name.as_str(),
+            other => panic!("expected CREATE USER, got {:?}", other),
+        }).collect();
+        assert_eq!(users, vec!["a", "b"]);
+
+        // terminators inside quotes or comments do not split, empty pieces are dropped
+        let sql = "SELECT ';' FROM t; -- a; b\n;; /* c; */ DROP USER x;";
+        assert_eq!(split_statements(sql, ';'), vec!["SELECT ';' FROM t", "/* c; */ DROP USER x"]);
+        assert_eq!(split_statements("  ;\n-- only a comment\n", ';'), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_identifier_validation() {
+        let parser_error = |sql: &str| match Plan::build_plan(sql) {
+            Err(RsqlError::ParserError(msg)) => msg,
+            other => panic!("expected a parser error for {sql}, got {:?}", other.map(|_| ())),
+        };
+        let long_name = "c".repeat(MAX_COL_NAME_SIZE + 1);
+        let msg = parser_error(&format!("CREATE TABLE t (id INTEGER PRIMARY KEY, {} INTEGER)", long_name));
+        assert!(msg.contains("exceeds max length"), "{msg}");
+        let msg = parser_error("CREATE TABLE t (id INTEGER PRIMARY KEY, a INTEGER, a FLOAT)");
+        assert!(msg.contains("Duplicate column name a"), "{msg}");
+        let msg = parser_error("CREATE TABLE t (id INTEGER PRIMARY KEY, rowid INTEGER)");
+        assert!(msg.contains("reserved"), "{msg}");
+        let msg = parser_error("CREATE TABLE sys_shadow (id INTEGER PRIMARY KEY)");
+        assert!(msg.contains("reserved"), "{msg}");
+        let msg = parser_error(&format!("ALTER TABLE t RENAME COLUMN a TO {}", long_name));
+        assert!(msg.contains("exceeds max length"), "{msg}");
+
+        // a name filling the whole slot is accepted
+        let name = "c".repeat(MAX_COL_NAME_SIZE);
+        assert!(Plan::build_plan(&format!("CREATE TABLE t (id INTEGER PRIMARY KEY, {} INTEGER)", name)).is_ok());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_insert_on_conflict() {
+        let test_conn = TestConn::setup(&["upsert_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE upsert_table (id INTEGER PRIMARY KEY, name CHAR(16), hits INTEGER)", conn).unwrap();
+        execute("INSERT INTO upsert_table VALUES (1, 'a', 1)", conn).unwrap();
+        assert!(execute("INSERT INTO upsert_table VALUES (1, 'b', 1)", conn).is_err());
+
+        // DO NOTHING skips the duplicate and inserts new keys
+        execute("INSERT INTO upsert_table VALUES (1, 'b', 5) ON CONFLICT (id) DO NOTHING", conn).unwrap();
+        execute("INSERT INTO upsert_table VALUES (2, 'c', 1) ON CONFLICT DO NOTHING", conn).unwrap();
+        let res = execute("SELECT id, name, hits FROM upsert_table", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![
+            vec![DataItem::Integer(1), DataItem::Chars { len: 16, value: "a".to_string() }, DataItem::Integer(1)],
+            vec![DataItem::Integer(2), DataItem::Chars { len: 16, value: "c".to_string() }, DataItem::Integer(1)],
+        ]);
+
+        // DO UPDATE modifies the existing row
+        execute("INSERT INTO upsert_table VALUES (1, 'd', 1) ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, hits = hits + 1", conn).unwrap();
+        let res = execute("SELECT name, hits FROM upsert_table WHERE id = 1", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![
+            vec![DataItem::Chars { len: 16, value: "d".to_string() }, DataItem::Integer(2)],
+        ]);
+        assert!(execute("INSERT INTO upsert_table VALUES (1, 'e', 1) ON CONFLICT (name) DO NOTHING", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_insert_on_conflict_composite_key() {
+        let test_conn = TestConn::setup(&["upsert_pair"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE upsert_pair (a INTEGER, b INTEGER, hits INTEGER, PRIMARY KEY (a, b))", conn).unwrap();
+        execute("INSERT INTO upsert_pair VALUES (1, 1, 1), (1, 2, 1)", conn).unwrap();
+
+        // the whole key is the target, in either order
+        execute("INSERT INTO upsert_pair VALUES (1, 1, 5) ON CONFLICT (a, b) DO NOTHING", conn).unwrap();
+        execute("INSERT INTO upsert_pair VALUES (1, 2, 5) ON CONFLICT (b, a) DO UPDATE SET hits = hits + 1", conn).unwrap();
+        execute("INSERT INTO upsert_pair VALUES (2, 1, 7) ON CONFLICT (a, b) DO UPDATE SET hits = hits + 1", conn).unwrap();
+        let res = execute("SELECT a, b, hits FROM upsert_pair", conn).unwrap();
+        let mut rows = query_rows(&res[0]).1;
+        rows.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        assert_eq!(rows, [[1, 1, 1], [1, 2, 2], [2, 1, 7]].map(|row| row.map(DataItem::Integer).to_vec()));
+
+        // a part of the key is not a target
+        assert!(execute("INSERT INTO upsert_pair VALUES (1, 1, 1) ON CONFLICT (a) DO NOTHING", conn).is_err());
+        assert!(execute("INSERT INTO upsert_pair VALUES (1, 1, 1) ON CONFLICT (a, hits) DO NOTHING", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_negative_and_zero_primary_keys() {
+        let test_conn = TestConn::setup(&["neg_pk"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE neg_pk (id INTEGER PRIMARY KEY, v INTEGER)", conn).unwrap();
+        for id in [3, -5, 0] {
+            execute(&format!("INSERT INTO neg_pk VALUES ({}, 1)", id), conn).unwrap();
+        }
+        let ids = |sql: &str| -> Vec<DataItem> {
+            let res = execute(sql, conn).unwrap();
+            query_rows(&res[0]).1.into_iter().map(|row| row[0].clone()).collect()
+        };
+        assert_eq!(ids("SELECT * FROM neg_pk ORDER BY id"), [-5, 0, 3].map(DataItem::Integer));
+        assert_eq!(ids("SELECT * FROM neg_pk WHERE id BETWEEN -5 AND 0"), [-5, 0].map(DataItem::Integer));
+        assert_eq!(ids("SELECT * FROM neg_pk WHERE id < 0"), [DataItem::Integer(-5)]);
+        assert!(execute("INSERT INTO neg_pk VALUES (0, 2)", conn).is_err());
+
+        execute("UPDATE neg_pk SET v = -1 WHERE id = -5", conn).unwrap();
+        let res = execute("SELECT * FROM neg_pk WHERE id = -5", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![[-5, -1].map(DataItem::Integer).to_vec()]);
+        execute("DELETE FROM neg_pk WHERE id BETWEEN -10 AND -1", conn).unwrap();
+        assert_eq!(ids("SELECT * FROM neg_pk ORDER BY id"), [0, 3].map(DataItem::Integer));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_limit_stops_table_scan_early() {
+        use crate::storage::ConsistStorageEngine;
+        let test_conn = TestConn::setup(&["big"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE big (id INTEGER PRIMARY KEY, v INTEGER)", conn).unwrap();
+        execute("BEGIN", conn).unwrap();
+        for i in 0..2000 {
+            execute(&format!("INSERT INTO big VALUES ({}, {})", i, i * 2), conn).unwrap();
+        }
+        execute("COMMIT", conn).unwrap();
+        let page_reads = |sql: &str| {
+            let before = ConsistStorageEngine::thread_read_count();
+            let res = execute(sql, conn).unwrap();
+            (ConsistStorageEngine::thread_read_count() - before, query_rows(&res[0]).1)
+        };
+        let (full_reads, all_rows) = page_reads("SELECT * FROM big");
+        assert_eq!(all_rows.len(), 2000);
+        let (limit_reads, rows) = page_reads("SELECT * FROM big LIMIT 10");
+        assert_eq!(rows, all_rows[..10]);
+        assert!(limit_reads * 20 < full_reads, "LIMIT 10 read {} pages, the full scan {}", limit_reads, full_reads);
+
+        let (_, rows) = page_reads("SELECT v FROM big LIMIT 3 OFFSET 5");
+        assert_eq!(rows, [10, 12, 14].map(|v| vec![DataItem::Integer(v)]));
+        let (_, rows) = page_reads("SELECT id FROM big WHERE v > 100 ORDER BY id DESC LIMIT 2");
+        assert_eq!(rows, [1999, 1998].map(|id| vec![DataItem::Integer(id)]));
+        assert!(execute("SELECT * FROM big LIMIT -1", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_with_queries() {
+        let test_conn = TestConn::setup(&["cte_src"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE cte_src (id INTEGER PRIMARY KEY, v INTEGER)", conn).unwrap();
+        for (id, v) in [(1, 5), (2, 1), (3, 8), (4, 3)] {
+            execute(&format!("INSERT INTO cte_src VALUES ({}, {})", id, v), conn).unwrap();
+        }
+
+        let res = execute("WITH big AS (SELECT id, v FROM cte_src WHERE v > 2) SELECT * FROM big", conn).unwrap();
+        let inlined = execute("SELECT id, v FROM cte_src WHERE v > 2", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, query_rows(&inlined[0]).1);
+        assert_eq!(query_rows(&res[0]).1.len(), 3);
+
+        // a WITH query reads an earlier one, and is read twice by the join
+        let res = execute(
+            "WITH big AS (SELECT id, v FROM cte_src WHERE v > 2), top AS (SELECT id FROM big WHERE v > 6) \
+             SELECT big.v FROM big JOIN top ON big.id = top.id",
+            conn,
+        ).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(8)]]);
+
+        // an aliased WITH query is filtered through its alias
+        let res = execute("WITH x AS (SELECT id FROM cte_src) SELECT a.id FROM x a WHERE a.id = 2", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(2)]]);
+        // and joined with itself, the second copy's columns are named by its alias
+        let res = execute("WITH x AS (SELECT id FROM cte_src) SELECT * FROM x a JOIN x b ON a.id = b.id", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["id", "b.id"]);
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|row| row[0] == row[1]));
+
+        assert!(matches!(
+            execute("WITH RECURSIVE r AS (SELECT id FROM cte_src) SELECT * FROM r", conn),
+            Err(RsqlError::ParserError(_))
+        ));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_offset_fetch() {
+        let test_conn = TestConn::setup(&["fetched"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE fetched (id INTEGER PRIMARY KEY, v INTEGER)", conn).unwrap();
+        for id in 0..20 {
+            execute(&format!("INSERT INTO fetched VALUES ({}, {})", id, id * 3), conn).unwrap();
+        }
+        let rows = |sql: &str| query_rows(&execute(sql, conn).unwrap()[0]).1;
+
+        let ansi = rows("SELECT id, v FROM fetched ORDER BY id OFFSET 10 ROWS FETCH NEXT 5 ROWS ONLY");
+        assert_eq!(ansi, rows("SELECT id, v FROM fetched ORDER BY id LIMIT 5 OFFSET 10"));
+        assert_eq!(ansi.len(), 5);
+        assert_eq!(rows("SELECT id, v FROM fetched ORDER BY id FETCH FIRST 3 ROWS ONLY"), rows("SELECT id, v FROM fetched ORDER BY id LIMIT 3"));
+        assert_eq!(rows("SELECT id, v FROM fetched ORDER BY id OFFSET 18 ROWS"), rows("SELECT id, v FROM fetched ORDER BY id LIMIT 100 OFFSET 18"));
+        assert_eq!(rows("SELECT id, v FROM fetched ORDER BY id FETCH FIRST ROW ONLY"), vec![vec![DataItem::Integer(0), DataItem::Integer(0)]]);
+        assert!(execute("SELECT v FROM fetched FETCH FIRST 10 PERCENT ROWS ONLY", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_self_join() {
+        let test_conn = TestConn::setup(&["self_join_emp"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE self_join_emp (id INTEGER PRIMARY KEY, name CHAR(8), mgr INTEGER)", conn).unwrap();
+        for row in ["(1, 'boss', NULL)", "(2, 'ann', 1)", "(3, 'bob', 1)", "(4, 'cy', 2)"] {
+            execute(&format!("INSERT INTO self_join_emp VALUES {}", row), conn).unwrap();
+        }
+        // columns of the second copy are named by its alias
+        let res = execute("SELECT * FROM self_join_emp e1 JOIN self_join_emp e2 ON e1.mgr = e2.id", conn).unwrap();
+        let (cols, _) = query_rows(&res[0]);
+        assert_eq!(cols, ["id", "name", "mgr", "e2.id", "e2.name", "e2.mgr"]);
+
+        let res = execute("SELECT e1.name, e2.name FROM self_join_emp e1 JOIN self_join_emp e2 ON e1.mgr = e2.id", conn).unwrap();
+        let mut pairs: Vec<(String, String)> = query_rows(&res[0]).1.iter()
+            .map(|row| (row[0].display(), row[1].display()))
+            .collect();
+        pairs.sort();
+        let expected = [("ann", "boss"), ("bob", "boss"), ("cy", "ann")];
+        assert_eq!(pairs, expected.map(|(e, m)| (e.to_string(), m.to_string())));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_values_constructor() {
+        let test_conn = TestConn::setup(&["values_items"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE values_items (id INTEGER PRIMARY KEY, name CHAR(8))", conn).unwrap();
+        execute("INSERT INTO values_items VALUES (1, 'pen')", conn).unwrap();
+        execute("INSERT INTO values_items VALUES (2, 'ink')", conn).unwrap();
+
+        // a bare list names its columns column1, column2, ...
+        let res = execute("VALUES (1, 'a'), (2, 'bb')", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["column1", "column2"]);
+        assert_eq!(rows.iter().map(|row| row[1].display()).collect::<Vec<_>>(), ["a", "bb"]);
+        let res = execute("VALUES (1), (2.5) ORDER BY 1 DESC", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Float(2.5)], vec![DataItem::Float(1.0)]]);
+
+        let res = execute("SELECT * FROM (VALUES (1, 'a'), (2, 'b')) AS t(id, name) WHERE id > 1", conn).unwrap();
+        let (cols, rows) = query_rows(&res[0]);
+        assert_eq!(cols, ["id", "name"]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], DataItem::Integer(2));
+
+        let res = execute("SELECT i.name, v.qty FROM values_items i JOIN (VALUES (1, 5), (3, 7)) AS v(item, qty) ON i.id = v.item", conn).unwrap();
+        let rows = query_rows(&res[0]).1;
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0][0].display(), rows[0][1].clone()), ("pen".to_string(), DataItem::Integer(5)));
+
+        assert!(execute("VALUES (1, 'a'), (2)", conn).is_err());
+        assert!(execute("VALUES (1), ('a')", conn).is_err());
+        assert!(execute("SELECT * FROM (VALUES (1)) AS t(a, b)", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_delete_update_limit() {
+        let test_conn = TestConn::setup(&["limited_dml"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE limited_dml (id INTEGER PRIMARY KEY, v INTEGER)", conn).unwrap();
+        execute("INSERT INTO limited_dml VALUES (1, 0), (2, 0), (3, 0), (4, 1), (5, 0), (6, 0)", conn).unwrap();
+        let ids = |sql: &str| {
+            let res = execute(sql, conn).unwrap();
+            query_rows(&res[0]).1.into_iter().map(|row| row[0].clone()).collect::<Vec<_>>()
+        };
+
+        execute("UPDATE limited_dml SET v = 2 WHERE v = 0 LIMIT 2", conn).unwrap();
+        assert_eq!(ids("SELECT id FROM limited_dml WHERE v = 2"), vec![DataItem::Integer(1), DataItem::Integer(2)]);
+        execute("DELETE FROM limited_dml WHERE v = 0 LIMIT 2", conn).unwrap();
+        assert_eq!(
+            ids("SELECT id FROM limited_dml"),
+            vec![DataItem::Integer(1), DataItem::Integer(2), DataItem::Integer(4), DataItem::Integer(6)],
+        );
+        // without WHERE the limit applies to the whole table
+        execute("DELETE FROM limited_dml LIMIT 3", conn).unwrap();
+        assert_eq!(ids("SELECT id FROM limited_dml"), vec![DataItem::Integer(6)]);
+        execute("UPDATE limited_dml SET v = 9 LIMIT 0", conn).unwrap();
+        assert_eq!(ids("SELECT v FROM limited_dml"), vec![DataItem::Integer(0)]);
+    }
+}
@@ -1,12 +1,15 @@
+use std::collections::HashSet;
+
 use super::super::storage;
 use super::heap_utils::{HeapChunk, HeapPage};
 use super::entry_utils::{Bitmap, EntryPage};
-use crate::common::RsqlResult;
+use crate::common::{RsqlResult, RsqlError};
 use crate::storage::consist_storage::ConsistStorageEngine;
 
 /// Help stuct for page management and allocation
 /// This allocator implement a disk page allocater.
 /// It supports fixed size entry allocation and variable size heap allocation.
+#[derive(Clone)]
 pub struct Allocator {
     begin_with: u64, // metadata begin with byte offset in page 0
     // entry pages: for fixed size entries
@@ -95,6 +98,13 @@ impl Allocator {
             first_free_heap_page: 0, // 0 means no free heap chunk
         }
     }
+    /// Number of entries an entry page holds, 0 when a single entry is larger than a page
+    pub fn entries_per_page(&self) -> u64 {
+        self.entries_per_page
+    }
+    pub fn entry_size(&self) -> u64 {
+        self.entry_size
+    }
     pub fn reset_begin_with(&mut self, begin_with: u64) {
         self.begin_with = begin_with;
     }
@@ -168,7 +178,7 @@ impl Allocator {
         let prev_free_page = EntryPage::prev_free_page(&page);
         // update previous next
         if prev_free_page == 0 {
-            self.first_free_entry_page = next_free_page;
+            self.set_first_free_entry_page(next_free_page, storage, tnx_id)?;
         } else {
             let mut prev_page_data = storage.read(prev_free_page)?;
             EntryPage::set_next_free_page(&mut prev_page_data, next_free_page);
@@ -197,6 +207,9 @@ impl Allocator {
         tnx_id: u64,
         storage: &mut ConsistStorageEngine,
     ) -> RsqlResult<(u64, u64)> {
+        if self.entries_per_page == 0 {
+            return Err(RsqlError::InvalidInput(format!("Entries of {} bytes do not fit in a page", self.entry_size)));
+        }
         // 1. find a page with free entry
         // Traverse the linked list of entry pages to find one with an empty slot
         // because the linked list only contains pages with free slots, we can stop at the first one
@@ -269,6 +282,93 @@ impl Allocator {
         }
         Ok(())
     }
+    /// Fraction of the slots in an entry page that are free but sit before its last live entry
+    pub fn entry_page_fragmentation(
+        &self,
+        page_idx: u64,
+        storage: &ConsistStorageEngine,
+    ) -> RsqlResult<f64> {
+        let page = storage.read(page_idx)?;
+        let bitmap = EntryPage::bitmap(&page, self.entries_per_page);
+        let mut holes = 0;
+        let mut pending = 0;
+        for i in 0..self.entries_per_page as usize {
+            if bitmap[i / 8] & (1u8 << (i % 8)) != 0 {
+                holes += pending;
+                pending = 0;
+            } else {
+                pending += 1;
+            }
+        }
+        Ok(holes as f64 / self.entries_per_page as f64)
+    }
+    /// Slide the live entries of an entry page to its front slots
+    /// The number of used slots is unchanged, so the page keeps its place in the free list
+    /// Return: the moved entries as (old_offset, new_offset), in slot order
+    pub fn compact_entry_page(
+        &mut self,
+        tnx_id: u64,
+        page_idx: u64,
+        storage: &mut ConsistStorageEngine,
+    ) -> RsqlResult<Vec<(u64, u64)>> {
+        let mut page = storage.read(page_idx)?;
+        let mut bitmap = EntryPage::bitmap(&page, self.entries_per_page).to_vec();
+        let entry_size = self.entry_size as usize;
+        let mut moves = vec![];
+        for (target, i) in self.live_slots(&bitmap).into_iter().enumerate() {
+            if i != target {
+                let old_offset = EntryPage::entries_offset(i as u64, self.entry_size, self.entries_per_page);
+                let new_offset = EntryPage::entries_offset(target as u64, self.entry_size, self.entries_per_page);
+                let (old_start, new_start) = (old_offset as usize, new_offset as usize);
+                page.data.copy_within(old_start..old_start + entry_size, new_start);
+                page.data[old_start..old_start + entry_size].fill(0);
+                Bitmap::set_bit_false(&mut bitmap, i);
+                Bitmap::set_bit_true(&mut bitmap, target);
+                moves.push((old_offset, new_offset));
+            }
+        }
+        if !moves.is_empty() {
+            EntryPage::set_bitmap(&mut page, self.entries_per_page, &bitmap);
+            storage.write(tnx_id, page_idx, &page)?;
+        }
+        Ok(moves)
+    }
+    /// Slots of the live entries in an entry page bitmap, in slot order
+    fn live_slots(&self, bitmap: &[u8]) -> Vec<usize> {
+        (0..self.entries_per_page as usize)
+            .filter(|i| bitmap[i / 8] & (1u8 << (i % 8)) != 0)
+            .collect()
+    }
+    /// Every live entry of a file without index pages, walking its pages in order
+    /// Heap pages are skipped: those with free space are on the free heap page list,
+    /// the others have their first chunk in use or are empty ones left behind
+    /// since only the last page of a file is released
+    /// Return: the entries as (page_idx, entry_offset)
+    pub fn live_entries(
+        &self,
+        storage: &ConsistStorageEngine,
+    ) -> RsqlResult<Vec<(u64, u64)>> {
+        let mut heap_pages = HashSet::new();
+        let mut current_page = self.first_free_heap_page;
+        while current_page != 0 && heap_pages.insert(current_page) {
+            current_page = HeapPage::next_free_page(&storage.read(current_page)?);
+        }
+        let mut entries = vec![];
+        for page_idx in 1..=storage.max_page_index().unwrap_or(0) {
+            if heap_pages.contains(&page_idx) {
+                continue;
+            }
+            let page = storage.read(page_idx)?;
+            if HeapPage::is_heap_page(&page) {
+                continue;
+            }
+            let bitmap = EntryPage::bitmap(&page, self.entries_per_page);
+            for i in self.live_slots(bitmap) {
+                entries.push((page_idx, EntryPage::entries_offset(i as u64, self.entry_size, self.entries_per_page)));
+            }
+        }
+        Ok(entries)
+    }
     fn heap_page_list_tail(
         &self,
         storage: &ConsistStorageEngine,
@@ -356,6 +456,10 @@ impl Allocator {
         size:u64,
         storage: &mut ConsistStorageEngine,
     ) -> RsqlResult<(u64, u64)> {
+        // no page would ever have room for it, searching would add heap pages forever
+        if size > HeapPage::max_chunk_size() {
+            return Err(RsqlError::InvalidInput(format!("Value of {} bytes does not fit in a heap page of {} bytes", size, HeapPage::max_chunk_size())));
+        }
         let mut current_page;
         if self.first_free_heap_page == 0 {
             current_page = self.new_heap_page(tnx_id, storage)?;
@@ -12,6 +12,11 @@ const MAGIC_NUMBER: u32 = 0x4c515352; // 'RSQL' in little endian hex
 /// - [data...]
 pub struct HeapPage;
 impl HeapPage {
+    const FIRST_CHUNK_OFFSET: u64 = 24; // right after the header
+    /// Size of the chunk filling an empty heap page, the largest value a heap page can hold
+    pub fn max_chunk_size() -> u64 {
+        (storage::Page::max_size() as u64) - Self::FIRST_CHUNK_OFFSET - HeapChunk::header_size()
+    }
     /// Create and initialize a new free heap page
     /// Will initialize both header and first free chunk(include entire page)
     pub fn new_free_page(
@@ -32,13 +37,12 @@ impl HeapPage {
         };
         Self::set_prev_free_page(&mut page, prev);
         // initialize first free chunk offset
-        let first_chunk_offset = 24u64; // right after the header
+        let first_chunk_offset = Self::FIRST_CHUNK_OFFSET;
         Self::set_first_free_chunk_offset(&mut page, first_chunk_offset);
         // initialize the only free chunk in this page
         HeapChunk::set_next_free_chunk(&mut page, first_chunk_offset, 0);
         HeapChunk::set_prev_free_chunk(&mut page, first_chunk_offset, 0);
-        let chunk_size = (storage::Page::max_size() as u64) - first_chunk_offset - HeapChunk::header_size();
-        HeapChunk::set_chunk_size_to_free(&mut page, first_chunk_offset, chunk_size);
+        HeapChunk::set_chunk_size_to_free(&mut page, first_chunk_offset, Self::max_chunk_size());
         page
     }
     pub fn next_free_page(page: &storage::Page) -> u64 {
@@ -68,6 +72,16 @@ impl HeapPage {
         let bytes = &mut page.data;
         bytes[16..24].copy_from_slice(&offset.to_le_bytes());
     }
+    /// Whether a page not on the free heap page list is a heap page: a full one starts
+    /// with a used chunk, an empty one is a single free chunk
+    pub fn is_heap_page(page: &storage::Page) -> bool {
+        let first = Self::FIRST_CHUNK_OFFSET as usize;
+        let magic_number = u32::from_le_bytes(page.data[first + 20..first + 24].try_into().unwrap());
+        if magic_number == MAGIC_NUMBER {
+            return page.data[first..first + 12].iter().all(|b| *b == 0);
+        }
+        Self::check_page_empty(page)
+    }
     pub fn check_page_empty(page: &storage::Page) -> bool {
         // check if first free chunk offset == 0
         let first_chunk_offset = Self::first_free_chunk_offset(page);
@@ -241,7 +255,8 @@ impl HeapChunk {
         let next_free = Self::next_free_chunk(page, offset);
         let prev_free = Self::prev_free_chunk(page, offset);
         // 1. try split the chunk and update freelist links
-        let remaining_size = chunk_size - size - Self::header_size();
+        // a chunk reused for an item of (almost) its size has no room left for another header
+        let remaining_size = chunk_size.saturating_sub(size + Self::header_size());
         if remaining_size >= Self::header_size() + 8 { // make sure the remaining chunk's size >= 8bytes
             // split the chunk
             let new_free_chunk_offset = offset + Self::header_size() + size;
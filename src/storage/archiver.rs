@@ -5,11 +5,11 @@ use tar::{Builder, Archive};
 use chrono::Local;
 
 use crate::common::{RsqlResult, RsqlError};
-use crate::config::DB_DIR;
+use crate::config;
 
 /// Single file mode: Packs data/sys and data/tables into data/rsql.db and deletes originals
 pub fn archive_single_file() -> RsqlResult<()> {
-    let db_path = Path::new(DB_DIR);
+    let db_path = config::get().db_dir.as_path();
     let archive_path = db_path.join("rsql.db");
     
     debug!("Archiving to single file: {}", archive_path.display());
@@ -49,7 +49,7 @@ pub fn archive_single_file() -> RsqlResult<()> {
 
 /// Single file mode: Unpacks data/rsql.db into data/sys and data/tables
 pub fn init_single_file() -> RsqlResult<()> {
-    let db_path = Path::new(DB_DIR);
+    let db_path = config::get().db_dir.as_path();
     let archive_path = db_path.join("rsql.db");
 
     if !archive_path.exists() {
@@ -72,11 +72,13 @@ pub fn init_single_file() -> RsqlResult<()> {
 
 /// Backup: Packs data/sys, data/tables, and data/wal.log into data/backup/{TIMESTAMP}.bak
 pub fn backup() -> RsqlResult<String> {
-    let db_path = Path::new(DB_DIR);
-    let backup_dir = db_path.join("backup");
-    
+    let db_path = config::get().db_dir.as_path();
+    backup_dir_to(db_path, &db_path.join("backup"))
+}
+
+fn backup_dir_to(db_path: &Path, backup_dir: &Path) -> RsqlResult<String> {
     if !backup_dir.exists() {
-        fs::create_dir_all(&backup_dir)
+        fs::create_dir_all(backup_dir)
             .map_err(|e| RsqlError::StorageError(format!("Failed to create backup dir: {}", e)))?;
     }
 
@@ -121,7 +123,7 @@ pub fn restore_backup(backup_path: &str) -> RsqlResult<()> {
         return Err(RsqlError::StorageError(format!("Backup file not found: {}", backup_path)));
     }
 
-    let db_path = Path::new(DB_DIR);
+    let db_path = config::get().db_dir.as_path();
     debug!("Restoring from backup: {}", path.display());
 
     if !db_path.exists() {
@@ -139,9 +141,51 @@ pub fn restore_backup(backup_path: &str) -> RsqlResult<()> {
     Ok(())
 }
 
+/// Replace data/sys, data/tables and data/wal.log with the content of a .bak file,
+/// files created after the backup are removed
+pub fn replace_with_backup(backup_path: &str) -> RsqlResult<()> {
+    replace_dir_with_backup(Path::new(backup_path), config::get().db_dir.as_path())
+}
+
+fn replace_dir_with_backup(backup_path: &Path, db_path: &Path) -> RsqlResult<()> {
+    if !backup_path.exists() {
+        return Err(RsqlError::StorageError(format!("Backup file not found: {}", backup_path.display())));
+    }
+    // check the archive before touching the current files
+    let file = File::open(backup_path)
+        .map_err(|e| RsqlError::StorageError(format!("Failed to open backup: {}", e)))?;
+    Archive::new(file).entries()
+        .map_err(|e| RsqlError::StorageError(format!("Invalid backup {}: {}", backup_path.display(), e)))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| RsqlError::StorageError(format!("Invalid backup {}: {}", backup_path.display(), e)))?;
+
+    debug!("Replacing {} with backup {}", db_path.display(), backup_path.display());
+    for dir in ["sys", "tables"] {
+        let dir_path = db_path.join(dir);
+        if dir_path.exists() {
+            fs::remove_dir_all(&dir_path)
+                .map_err(|e| RsqlError::StorageError(format!("Failed to remove {} dir: {}", dir, e)))?;
+        }
+    }
+    let wal_path = db_path.join("wal.log");
+    if wal_path.exists() {
+        fs::remove_file(&wal_path)
+            .map_err(|e| RsqlError::StorageError(format!("Failed to remove wal.log: {}", e)))?;
+    }
+    fs::create_dir_all(db_path)?;
+
+    let file = File::open(backup_path)
+        .map_err(|e| RsqlError::StorageError(format!("Failed to open backup: {}", e)))?;
+    Archive::new(file).unpack(db_path)
+        .map_err(|e| RsqlError::StorageError(format!("Failed to unpack backup: {}", e)))?;
+
+    debug!("Replace from backup completed.");
+    Ok(())
+}
+
 /// Get the path of the latest backup file in data/backup
 pub fn get_latest_backup() -> Option<String> {
-    let db_path = Path::new(DB_DIR);
+    let db_path = config::get().db_dir.as_path();
     let backup_dir = db_path.join("backup");
 
     if !backup_dir.exists() {
@@ -165,3 +209,36 @@ pub fn get_latest_backup() -> Option<String> {
     backups.sort();
     backups.pop().map(|p| p.to_string_lossy().into_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_replaces_data_with_backup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("data");
+        fs::create_dir_all(db_path.join("sys")).unwrap();
+        fs::create_dir_all(db_path.join("tables")).unwrap();
+        fs::write(db_path.join("sys").join("0.dbs"), b"catalog v1").unwrap();
+        fs::write(db_path.join("tables").join("5.dbt"), b"rows v1").unwrap();
+        fs::write(db_path.join("wal.log"), b"log v1").unwrap();
+        let backup_path = backup_dir_to(&db_path, &tmp.path().join("backup")).unwrap();
+
+        // mutate after the backup: change files and add a new table
+        fs::write(db_path.join("sys").join("0.dbs"), b"catalog v2").unwrap();
+        fs::write(db_path.join("tables").join("5.dbt"), b"rows v2").unwrap();
+        fs::write(db_path.join("tables").join("6.dbt"), b"new table").unwrap();
+        fs::write(db_path.join("wal.log"), b"log v2").unwrap();
+
+        replace_dir_with_backup(Path::new(&backup_path), &db_path).unwrap();
+        assert_eq!(fs::read(db_path.join("sys").join("0.dbs")).unwrap(), b"catalog v1");
+        assert_eq!(fs::read(db_path.join("tables").join("5.dbt")).unwrap(), b"rows v1");
+        assert_eq!(fs::read(db_path.join("wal.log")).unwrap(), b"log v1");
+        assert!(!db_path.join("tables").join("6.dbt").exists());
+
+        // a missing backup leaves the data untouched
+        assert!(replace_dir_with_backup(&tmp.path().join("missing.bak"), &db_path).is_err());
+        assert!(db_path.join("wal.log").exists());
+    }
+}
@@ -55,6 +55,7 @@ impl<'a> Iterator for RangeIterator<'a>
 }
 
 /// The B-Tree index of sql table in database.
+#[derive(Clone)]
 pub struct BTreeIndex {
     root: u64,
 }
@@ -75,6 +76,90 @@ impl BTreeIndex {
     ) -> RsqlResult<Self> {
         Ok(Self { root: root_page_num})
     }
+    /// Build a new B-Tree bottom-up from entries sorted by key.
+    /// Leaves are packed full and linked left to right, then each internal level
+    /// is built over the level below, so no node is ever split.
+    /// Entries: (key, page_num, page_offset)
+    pub fn bulk_load(
+        entries: Vec<(data_item::DataItem, u64, u64)>,
+        storage: &mut ConsistStorageEngine,
+        tnx_id: u64,
+    ) -> RsqlResult<Self> {
+        if entries.is_empty() {
+            return Self::new(storage, tnx_id);
+        }
+        if entries.windows(2).any(|pair| pair[1].0 < pair[0].0) {
+            return Err(RsqlError::InvalidInput("Bulk load entries must be sorted by key".to_string()));
+        }
+        let max_size = super::super::storage::Page::max_size();
+        let empty_node_size = 8 + 1 + 8; // [node_length][node_type][next_page_num]
+
+        // 1. pack leaves
+        let mut leaves: Vec<Vec<btree_node::LeafItem>> = vec![vec![]];
+        let mut leaf_size = empty_node_size;
+        for (key, page_num, page_offset) in entries {
+            let item = btree_node::LeafItem { key, child_page_num: page_num, page_offset };
+            if leaf_size + item.size() > max_size {
+                leaves.push(vec![]);
+                leaf_size = empty_node_size;
+            }
+            leaf_size += item.size();
+            leaves.last_mut().unwrap().push(item);
+        }
+        let mut leaf_pages = vec![];
+        for _ in 0..leaves.len() {
+            leaf_pages.push(storage.new_page(tnx_id)?);
+        }
+        // level entries: (smallest key, page_num) of every node
+        let mut level = vec![];
+        for (i, items) in leaves.into_iter().enumerate() {
+            // link leaves left to right
+            let next_page_num = leaf_pages.get(i + 1).map_or(0, |(page_num, _)| *page_num);
+            let (page_num, page) = &mut leaf_pages[i];
+            level.push((items[0].key.clone(), *page_num));
+            let node = btree_node::BTreeNode::Leaf { items, next_page_num };
+            node.to_page(page)?;
+            storage.write(tnx_id, *page_num, page)?;
+        }
+
+        // 2. build internal levels until a single root remains
+        // an internal node over children c0..ck holds items (min(c1), c0)..(min(ck), ck-1)
+        // and ck as next_page_num
+        while level.len() > 1 {
+            let mut groups: Vec<Vec<(data_item::DataItem, u64)>> = vec![vec![]];
+            let mut node_size = empty_node_size;
+            for child in level {
+                let item_size = child.0.size() + 8;
+                let group = groups.last_mut().unwrap();
+                // the first child of a node only becomes an item when a second one follows
+                if !group.is_empty() && node_size + item_size > max_size {
+                    groups.push(vec![]);
+                    node_size = empty_node_size;
+                }
+                let group = groups.last_mut().unwrap();
+                if !group.is_empty() {
+                    node_size += item_size;
+                }
+                group.push(child);
+            }
+            let mut next_level = vec![];
+            for group in groups {
+                let min_key = group[0].0.clone();
+                let mut items = vec![];
+                for pair in group.windows(2) {
+                    items.push(btree_node::IndexItem { key: pair[1].0.clone(), child_page_num: pair[0].1 });
+                }
+                let next_page_num = group.last().unwrap().1;
+                let (page_num, mut page) = storage.new_page(tnx_id)?;
+                let node = btree_node::BTreeNode::Internal { items, next_page_num };
+                node.to_page(&mut page)?;
+                storage.write(tnx_id, page_num, &page)?;
+                next_level.push((min_key, page_num));
+            }
+            level = next_level;
+        }
+        Ok(Self { root: level[0].1 })
+    }
     pub fn drop(
         &self,
         tnx_id: u64,
@@ -214,6 +299,49 @@ impl BTreeIndex {
         }
     } 
 
+    /// Render the tree as text for diagnosing index bugs, one node or item per line,
+    /// children indented below the internal item pointing to them:
+    /// `internal page <n> (next <n>)`, `key <key> -> page <n>`,
+    /// `leaf page <n> (next <n>)`, `<key> -> (<page>, <offset>)`
+    pub fn debug_dump(
+        &self,
+        storage: &ConsistStorageEngine,
+    ) -> RsqlResult<String> {
+        let mut out = String::new();
+        self.debug_dump_node(self.root, 0, storage, &mut out)?;
+        Ok(out)
+    }
+    fn debug_dump_node(
+        &self,
+        page_num: u64,
+        depth: usize,
+        storage: &ConsistStorageEngine,
+        out: &mut String,
+    ) -> RsqlResult<()> {
+        let indent = "  ".repeat(depth);
+        let page = storage.read(page_num)?;
+        match btree_node::BTreeNode::from_page(&page)? {
+            btree_node::BTreeNode::Internal { items, next_page_num } => {
+                out.push_str(&format!("{}internal page {} (next {})\n", indent, page_num, next_page_num));
+                for item in items {
+                    out.push_str(&format!("{}  key {:?} -> page {}\n", indent, item.key, item.child_page_num));
+                    self.debug_dump_node(item.child_page_num, depth + 2, storage, out)?;
+                }
+                if next_page_num != 0 {
+                    out.push_str(&format!("{}  next -> page {}\n", indent, next_page_num));
+                    self.debug_dump_node(next_page_num, depth + 2, storage, out)?;
+                }
+            }
+            btree_node::BTreeNode::Leaf { items, next_page_num } => {
+                out.push_str(&format!("{}leaf page {} (next {})\n", indent, page_num, next_page_num));
+                for item in items {
+                    out.push_str(&format!("{}  {:?} -> ({}, {})\n", indent, item.key, item.child_page_num, item.page_offset));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Check if the given index already exists in the B-Tree.
     pub fn check_exists(
         &self,
@@ -396,42 +524,25 @@ impl BTreeIndex {
         Ok(())
     }
 
-    pub fn traverse_all_entries(
+    /// Iterate all entries in key order, a leaf is read only once the iteration reaches it
+    pub fn traverse_all_entries<'a>(
         &self,
-        storage: &ConsistStorageEngine,
-    ) -> RsqlResult<impl Iterator<Item = RsqlResult<(u64, u64)>>>
+        storage: &'a ConsistStorageEngine,
+    ) -> RsqlResult<RangeIterator<'a>>
     {
-        let mut results: Vec<RsqlResult<(u64, u64)>> = Vec::new();
-
-        // 1. Find the leftmost leaf node
+        // Find the leftmost leaf node
         let mut page_num = self.root;
         loop {
             let page = storage.read(page_num)?;
             let node = btree_node::BTreeNode::from_page(&page)?;
             match node {
-                btree_node::BTreeNode::Leaf { items, next_page_num } => {
-                    // Collect all entries from this leaf and subsequent leaves
-                    for item in items {
-                        results.push(Ok((item.child_page_num, item.page_offset)));
-                    }
-                    let mut next = next_page_num;
-                    while next != 0 {
-                        let p = storage.read(next)?;
-                        match btree_node::BTreeNode::from_page(&p)? {
-                            btree_node::BTreeNode::Leaf { items: leaf_items, next_page_num } => {
-                                for item in leaf_items {
-                                    results.push(Ok((item.child_page_num, item.page_offset)));
-                                }
-                                next = next_page_num;
-                            }
-                            _ => {
-                                return Err(RsqlError::StorageError(
-                                    "Expected leaf page while traversing leaves".to_string()
-                                ));
-                            }
-                        }
-                    }
-                    break;
+                btree_node::BTreeNode::Leaf { .. } => {
+                    return Ok(RangeIterator {
+                        end_index: None,
+                        storage,
+                        current_leaf_node: node,
+                        current_item_index: 0,
+                    });
                 }
                 btree_node::BTreeNode::Internal { items, next_page_num } => {
                     // Go to the leftmost child
@@ -443,7 +554,34 @@ impl BTreeIndex {
                 }
             }
         }
-        Ok(results.into_iter())
+    }
+
+    /// Number of distinct keys, counted by walking the leaves in key order
+    pub fn distinct_keys(
+        &self,
+        storage: &ConsistStorageEngine,
+    ) -> RsqlResult<u64> {
+        let (mut node, _) = self.find_smallest_leaf(storage)?;
+        let mut count = 0u64;
+        let mut last: Option<data_item::DataItem> = None;
+        loop {
+            let next = match node {
+                btree_node::BTreeNode::Leaf { items, next_page_num } => {
+                    for item in items {
+                        if last.as_ref() != Some(&item.key) {
+                            count += 1;
+                            last = Some(item.key);
+                        }
+                    }
+                    next_page_num
+                }
+                _ => 0,
+            };
+            if next == 0 {
+                return Ok(count);
+            }
+            node = btree_node::BTreeNode::from_page(&storage.read(next)?)?;
+        }
     }
 
     pub fn update_entry(
@@ -640,6 +778,64 @@ mod tests {
         assert_eq!(all, expected);
     }
 
+    #[test]
+    fn test_btree_negative_and_zero_keys() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_btree_negative.db");
+        let file_path_str = file_path.to_str().unwrap();
+
+        let tnx = 1u64;
+        let mut storage = ConsistStorageEngine::new(file_path_str, 1).unwrap();
+        let mut idx = BTreeIndex::new(&mut storage, tnx).unwrap();
+
+        // keys are ordered as signed integers, negatives before zero and positives
+        for (key, page) in [(3i64, 30u64), (-5, 50), (0, 0), (i64::MIN, 1), (i64::MAX, 2)] {
+            idx.insert_entry(tnx, DataItem::Integer(key), page, 7, &mut storage).unwrap();
+        }
+        let all: Vec<_> = idx.traverse_all_entries(&storage).unwrap().map(|r| r.unwrap().0).collect();
+        assert_eq!(all, vec![1, 50, 0, 30, 2]);
+
+        let range: Vec<_> = idx.find_range_entry(Some(DataItem::Integer(-5)), Some(DataItem::Integer(0)), &storage).unwrap()
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(range, vec![50, 0]);
+        let below_zero: Vec<_> = idx.find_range_entry(None, Some(DataItem::Integer(-1)), &storage).unwrap()
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(below_zero, vec![1, 50]);
+    }
+
+    #[test]
+    fn test_btree_debug_dump() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_btree_dump.db");
+        let file_path_str = file_path.to_str().unwrap();
+
+        let tnx = 1u64;
+        let mut storage = ConsistStorageEngine::new(file_path_str, 1).unwrap();
+        let mut idx = BTreeIndex::new(&mut storage, tnx).unwrap();
+        for key in [30i64, 10, 20] {
+            idx.insert_entry(tnx, DataItem::Integer(key), key as u64, 7, &mut storage).unwrap();
+        }
+
+        let dump = idx.debug_dump(&storage).unwrap();
+        assert!(dump.starts_with(&format!("leaf page {} (next 0)", idx.root_page_num())));
+        let keys: Vec<usize> = ["Integer(10) -> (10, 7)", "Integer(20) -> (20, 7)", "Integer(30) -> (30, 7)"]
+            .iter()
+            .map(|item| dump.find(item).unwrap())
+            .collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        // after splits every key is still listed, under internal nodes
+        for key in 40..2000i64 {
+            idx.insert_entry(tnx, DataItem::Integer(key), key as u64, 7, &mut storage).unwrap();
+        }
+        let dump = idx.debug_dump(&storage).unwrap();
+        assert!(dump.starts_with("internal page"));
+        assert!(dump.contains("Integer(1999) -> (1999, 7)"));
+        assert_eq!(dump.lines().filter(|line| line.trim_start().starts_with("Integer(")).count(), 1963);
+    }
+
     #[test]
     fn test_btree_root_split_and_many_inserts() {
         let dir = tempdir().unwrap();
@@ -730,4 +926,54 @@ mod tests {
         let deleted = idx.delete_entry(tnx, DataItem::Integer(1), 9999, 9999, &mut storage).unwrap();
         assert!(!deleted);
     }
+
+    #[test]
+    fn test_btree_bulk_load() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_btree_bulk_load.db");
+        let file_path_str = file_path.to_str().unwrap();
+
+        let tnx = 1u64;
+        let mut storage = ConsistStorageEngine::new(file_path_str, 7).unwrap();
+        let entries: Vec<_> = (0..10_000)
+            .map(|i| (DataItem::Integer(i), i as u64, (i * 2) as u64))
+            .collect();
+        let idx = BTreeIndex::bulk_load(entries, &mut storage, tnx).unwrap();
+
+        // the tree has more than one level
+        let root = btree_node::BTreeNode::from_page(&storage.read(idx.root_page_num()).unwrap()).unwrap();
+        assert!(!root.is_leaf());
+
+        // point lookups
+        for i in [0i64, 1, 4999, 5000, 9999] {
+            let found = idx.find_entry(DataItem::Integer(i), &storage).unwrap();
+            assert_eq!(found, Some((i as u64, (i * 2) as u64)));
+        }
+        assert!(idx.find_entry(DataItem::Integer(10_000), &storage).unwrap().is_none());
+
+        // range lookups
+        let range: Vec<_> = idx.find_range_entry(Some(DataItem::Integer(1234)), Some(DataItem::Integer(5678)), &storage)
+            .unwrap()
+            .collect::<RsqlResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(range.len(), 5678 - 1234 + 1);
+        assert_eq!(range[0], (1234, 2468));
+        assert_eq!(range[range.len() - 1], (5678, 11356));
+
+        // full traversal is in key order
+        let all: Vec<_> = idx.traverse_all_entries(&storage).unwrap()
+            .collect::<RsqlResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(all.len(), 10_000);
+        assert!(all.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        // the loaded tree accepts further inserts
+        let mut idx = idx;
+        idx.insert_entry(tnx, DataItem::Integer(10_000), 10_000, 0, &mut storage).unwrap();
+        assert!(idx.check_exists(DataItem::Integer(10_000), &storage).unwrap());
+
+        // unsorted input is rejected
+        let unsorted = vec![(DataItem::Integer(2), 0, 0), (DataItem::Integer(1), 0, 0)];
+        assert!(BTreeIndex::bulk_load(unsorted, &mut storage, tnx).is_err());
+    }
 }
@@ -1,37 +1,71 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
 
 use crate::common::RsqlResult;
 
 use super::storage::{StorageManager, Page};
 use super::wal::WAL;
+
+#[cfg(test)]
+thread_local! {
+    // page reads issued by the current thread, tests running in parallel don't disturb it
+    static THREAD_READS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
 /// This struct implements a consistent storage engine for the database.
+#[derive(Clone)]
 pub struct ConsistStorageEngine {
     table_id: u64,
-    storage_manager: Arc<Mutex<StorageManager>>,
+    storage_manager: Arc<RwLock<StorageManager>>,
     wal: Arc<WAL>,
+    // log flushes are deferred until `end_batch`
+    batch: bool,
 }
 
 impl ConsistStorageEngine {
     pub fn new(file_path: &str, table_id: u64) -> RsqlResult<Self> {
         let storage_manager = StorageManager::new(file_path)?;
-        let wal = WAL::global();
+        let wal = WAL::global()?;
         Ok(ConsistStorageEngine {
             table_id,
             storage_manager,
             wal,
+            batch: false,
         })
     }
+    /// Defer the log flush of every write until `end_batch`, for operations writing many pages.
+    /// Log entries are still appended before the pages they cover are written
+    pub fn begin_batch(&mut self) {
+        self.batch = true;
+    }
+    /// Flush the log once for all writes since `begin_batch`
+    pub fn end_batch(&mut self) -> RsqlResult<()> {
+        self.batch = false;
+        self.wal.flush()
+    }
+    fn flush_wal(&self) -> RsqlResult<()> {
+        if self.batch {
+            return Ok(());
+        }
+        self.wal.flush()
+    }
     pub fn read(&self, page_id: u64) -> RsqlResult<Page> {
-        self.storage_manager.lock().unwrap().read_page(page_id)
+        #[cfg(test)]
+        THREAD_READS.with(|reads| reads.set(reads.get() + 1));
+        self.storage_manager.read().unwrap().read_page(page_id)
+    }
+    /// Pages read through any engine by the current thread
+    #[cfg(test)]
+    pub fn thread_read_count() -> u64 {
+        THREAD_READS.with(|reads| reads.get())
     }
     pub fn read_bytes(&self, page_id: u64, offset: usize, size: usize) -> RsqlResult<Vec<u8>> {
-        let page = self.storage_manager.lock().unwrap().read_page(page_id)?;
+        let page = self.storage_manager.read().unwrap().read_page(page_id)?;
         Ok(page.data[offset..offset + size].to_vec())
     }
     pub fn write(&mut self, tnx_id: u64, page_id: u64, page: &Page) -> RsqlResult<()> {
         // analyze the differences, to find out continuous byte ranges
         // this will significantly reduce the WAL size
-        let old_page = self.storage_manager.lock().unwrap().read_page(page_id)?;
+        let old_page = self.storage_manager.read().unwrap().read_page(page_id)?;
         let mut start = None;
         for (i, byte) in page.data.iter().enumerate() {
             if *byte != old_page.data[i] {
@@ -57,26 +91,26 @@ impl ConsistStorageEngine {
     }
     pub fn write_bytes(&mut self, tnx_id: u64, page_id: u64, offset: usize, data: &[u8]) -> RsqlResult<()> {
         // read old data for WAL
-        let mut old_page = self.storage_manager.lock().unwrap().read_page(page_id)?;
+        let mut old_page = self.storage_manager.read().unwrap().read_page(page_id)?;
         let old_data = &old_page.data[offset..offset + data.len()];
         // write to WAL first
         self.wal.update_page(tnx_id, self.table_id, page_id, offset as u64, old_data, data)?;
-        self.wal.flush()?;
+        self.flush_wal()?;
         // then write to storage
         old_page.data[offset..offset + data.len()].copy_from_slice(data);
-        self.storage_manager.lock().unwrap().write_page(&old_page, page_id)?;
+        self.storage_manager.write().unwrap().write_page(&old_page, page_id)?;
         Ok(())
     }
     pub fn new_page(&mut self, tnx_id: u64) -> RsqlResult<(u64, Page)> {
-        let (page_id, page) = self.storage_manager.lock().unwrap().new_page()?;
+        let (page_id, page) = self.storage_manager.write().unwrap().new_page()?;
         // log the new page creation in WAL
         self.wal.new_page(tnx_id, self.table_id, page_id, &page.data)?;
-        self.wal.flush()?;
+        self.flush_wal()?;
         Ok((page_id, page))
     }
     pub fn free_page(&mut self, tnx_id: u64, page_id: u64) -> RsqlResult<()> {
         let check_page_id = self.storage_manager
-            .lock()
+            .read()
             .unwrap()
             .max_page_index()
             .unwrap(); // None means no page exists, so cannot free any page
@@ -84,17 +118,17 @@ impl ConsistStorageEngine {
             panic!("can only free the last page, 
                     try to free page_id: {}, max_page_id: {}", page_id, check_page_id);
         };
-        let freed_page = self.storage_manager.lock().unwrap().read_page(page_id)?;
+        let freed_page = self.storage_manager.read().unwrap().read_page(page_id)?;
         // log the page deletion in WAL
         self.wal.delete_page(tnx_id, self.table_id, page_id, &freed_page.data)?;
-        self.wal.flush()?;
-        self.storage_manager.lock().unwrap().free()?;
+        self.flush_wal()?;
+        self.storage_manager.write().unwrap().free()?;
         Ok(())
     }
     pub fn max_page_index(&self) -> Option<u64> {
-        self.storage_manager.lock().unwrap().max_page_index()
+        self.storage_manager.read().unwrap().max_page_index()
     }
-    pub fn get_storage(&mut self) -> Arc<Mutex<StorageManager>> {
+    pub fn get_storage(&mut self) -> Arc<RwLock<StorageManager>> {
         Arc::clone(&self.storage_manager)
     }
 }
@@ -127,4 +161,32 @@ mod tests {
             assert_eq!(p.data[0], 99);
         }
     }
+
+    #[test]
+    fn test_concurrent_readers_share_storage() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test_concurrent_readers.db");
+        let mut engine = ConsistStorageEngine::new(file_path.to_str().unwrap(), 778).unwrap();
+        let tnx = 1u64;
+        for i in 0..8u8 {
+            let (pid, mut page) = engine.new_page(tnx).unwrap();
+            page.data[0] = i;
+            engine.write(tnx, pid, &page).unwrap();
+        }
+
+        // a reader holding the storage doesn't block other readers
+        let storage = engine.get_storage();
+        let _held = storage.read().unwrap();
+        std::thread::scope(|s| {
+            for t in 0..4u64 {
+                let engine = engine.clone();
+                s.spawn(move || {
+                    for round in 0..200u64 {
+                        let pid = (t + round) % 8;
+                        assert_eq!(engine.read(pid).unwrap().data[0], pid as u8);
+                    }
+                });
+            }
+        });
+    }
 }
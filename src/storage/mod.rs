@@ -1,6 +1,8 @@
 mod btree_index;
 mod allocator;
 mod consist_storage;
+#[cfg(test)]
+pub(crate) use consist_storage::ConsistStorageEngine;
 pub mod storage;
 
 pub mod table;
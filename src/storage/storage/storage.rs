@@ -1,9 +1,11 @@
 use crate::config::{PAGE_SIZE_BYTES, MAX_PAGE_CACHE_BYTES};
 use crate::common::{RsqlError, RsqlResult};
 use super::cache::LRUCache;
+use crate::storage::wal::WAL;
 use std::sync::{RwLock, Mutex, Arc, OnceLock, Weak};
 use std::fs::{self, OpenOptions, File};
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
 use std::path::Path;
 use std::collections::HashMap;
 
@@ -26,13 +28,15 @@ impl Page {
     }
 }
 
-static STORAGE_REGISTRY: OnceLock<RwLock<HashMap<String, Weak<Mutex<StorageManager>>>>> = OnceLock::new(); // global single instance registry
+static STORAGE_REGISTRY: OnceLock<RwLock<HashMap<String, Weak<RwLock<StorageManager>>>>> = OnceLock::new(); // global single instance registry
 
 pub struct StorageManager {
-    file: Mutex<File>, // file handle
+    file: File, // file handle, only accessed with positioned reads and writes
     file_path: String,
     file_page_num: Mutex<u64>, // number of pages in file
     pages: Mutex<LRUCache>,  // cache of pages which has the latest data
+    // pages evicted from the cache and not yet written back, readers must not load them from the file
+    evicting: Mutex<HashMap<u64, Arc<RwLock<Page>>>>,
 }
 
 // implement Drop trait so that StorageManager will be unregistered when it is dropped
@@ -47,7 +51,7 @@ impl Drop for StorageManager {
 }
 
 impl StorageManager {
-    fn get_registry() -> &'static RwLock<HashMap<String, Weak<Mutex<StorageManager>>>> {
+    fn get_registry() -> &'static RwLock<HashMap<String, Weak<RwLock<StorageManager>>>> {
         STORAGE_REGISTRY.get_or_init(|| RwLock::new(HashMap::new()))
     }
 
@@ -90,42 +94,70 @@ impl StorageManager {
         }
     }
 
-    fn write_back_evicted_page(&self, evicted: Option<(u64, Arc<RwLock<Page>>)>) -> RsqlResult<()> {
-        if let Some((evicted_page_index, evicted_page)) = evicted {
-            // write the evicted_page to the file
-            let mut evicted_page = evicted_page.write().map_err(|_| RsqlError::StorageError(
-                "Poisoned RwLock in page cache".to_string()
-            ))?;
-            if evicted_page.need_flush {
+    /// Insert a page into the cache and write back the page it evicts.
+    /// The evicted page stays visible in `evicting` until it is on disk
+    fn cache_insert(&self, page_index: u64, page: Arc<RwLock<Page>>) -> RsqlResult<()> {
+        let evicted = {
+            let mut pages = self.pages.lock().unwrap();
+            let evicted = pages.insert(page_index, page);
+            if let Some((evicted_index, evicted_page)) = &evicted {
+                self.evicting.lock().unwrap().insert(*evicted_index, Arc::clone(evicted_page));
+            }
+            evicted
+        };
+        let Some((evicted_index, evicted_page)) = evicted else {
+            return Ok(());
+        };
+        let res = self.write_back_evicted_page(evicted_index, &evicted_page);
+        self.evicting.lock().unwrap().remove(&evicted_index);
+        res
+    }
+
+    fn write_back_evicted_page(&self, evicted_page_index: u64, evicted_page: &RwLock<Page>) -> RsqlResult<()> {
+        // write the evicted_page to the file
+        let mut evicted_page = evicted_page.write().map_err(|_| RsqlError::StorageError(
+            "Poisoned RwLock in page cache".to_string()
+        ))?;
+        if evicted_page.need_flush {
+            // the log must reach the disk before the pages it covers
+            if let Some(wal) = WAL::try_global() {
+                wal.flush_if_pending()?;
+            }
+            {
                 let mut file_page_num = self.file_page_num.lock().unwrap();
                 if evicted_page_index >= *file_page_num {
                     let required_file_size = (evicted_page_index + 1) * PAGE_SIZE_BYTES as u64;
-                    self.file.lock().unwrap().set_len(required_file_size)?; // extend file (fills with zeros)
+                    self.file.set_len(required_file_size)?; // extend file (fills with zeros)
                     *file_page_num = evicted_page_index + 1;
                 }
-                let page_data = &evicted_page.data;
-                let offset = evicted_page_index * PAGE_SIZE_BYTES as u64;
-                self.file.lock().unwrap().seek(SeekFrom::Start(offset))?;
-                self.file.lock().unwrap().write_all(page_data)?; // write page data to the file
-                self.file.lock().unwrap().sync_data()?; // ensure data is written to the disk
-                evicted_page.need_flush = false;
             }
+            let offset = evicted_page_index * PAGE_SIZE_BYTES as u64;
+            self.file.write_all_at(&evicted_page.data, offset)?; // write page data to the file
+            self.file.sync_data()?; // ensure data is written to the disk
+            evicted_page.need_flush = false;
         }
         Ok(())
     }
 
-    pub fn new(file_path: &str) -> RsqlResult<Arc<Mutex<Self>>> {
+    pub fn new(file_path: &str) -> RsqlResult<Arc<RwLock<Self>>> {
         let registry = Self::get_registry();
         
         // 1. check if already exists
-        {
-            let read_guard = registry.read().unwrap();
-            if let Some(weak_ref) = read_guard.get(file_path) {
-                if weak_ref.strong_count() > 0 {
-                    panic!("StorageManager for file {} already exists!", file_path);
+        // Tables hold their file exclusively, so a live manager here is only kept by a
+        // concurrent flush; share it rather than opening the file twice.
+        // A manager whose last holder is dropping it still has pages to flush,
+        // wait until it is unregistered instead of reading a stale file
+        let mut write_guard = loop {
+            let write_guard = registry.write().unwrap();
+            match write_guard.get(file_path).map(Weak::upgrade) {
+                Some(Some(manager)) => return Ok(manager),
+                Some(None) => {
+                    drop(write_guard);
+                    std::thread::yield_now();
                 }
+                None => break write_guard,
             }
-        }
+        };
 
         // 2. create new StorageManager
         Self::create_file(file_path)?;
@@ -133,15 +165,16 @@ impl StorageManager {
         let metadata = fs::metadata(file_path)?;
         let file_page_num = metadata.len() / PAGE_SIZE_BYTES as u64;
 
-        let manager = Arc::new(Mutex::new(Self {
-            file: Mutex::new(file),
+        let manager = Arc::new(RwLock::new(Self {
+            file,
             file_path: file_path.to_string(),
             file_page_num: Mutex::new(file_page_num),
             pages: Mutex::new(LRUCache::new(MAX_PAGE_CACHE_BYTES / PAGE_SIZE_BYTES)),
+            evicting: Mutex::new(HashMap::new()),
         }));
 
-        // 3. register the new StorageManager
-        registry.write().unwrap().insert(
+        // 3. register the new StorageManager, under the same lock as the check
+        write_guard.insert(
             file_path.to_string(),
             Arc::downgrade(&manager),
         );
@@ -159,39 +192,41 @@ impl StorageManager {
         self.pages.lock().unwrap().remove(&page_idx);
         // 2. truncate file
         let new_file_size = (page_idx) * PAGE_SIZE_BYTES as u64;
-        self.file.lock().unwrap().set_len(new_file_size)?;
+        self.file.set_len(new_file_size)?;
         *self.file_page_num.lock().unwrap() = page_idx;
         Ok(page_idx)
     }
 
+    /// Read a page, callers only need shared access so readers of one file run concurrently
     pub fn read_page(&self, page_index: u64) -> RsqlResult<Page> {
         self.is_page_index_valid(page_index)?;
 
-        if let Some(page_arc) = self.pages.lock().unwrap().get(&page_index) {
+        let cached = {
+            let mut pages = self.pages.lock().unwrap();
+            match pages.get(&page_index) {
+                Some(page_arc) => Some(Arc::clone(page_arc)),
+                None => self.evicting.lock().unwrap().get(&page_index).cloned(),
+            }
+        };
+        if let Some(page_arc) = cached {
             let page = page_arc.read().unwrap().clone();
-            Ok(page)
-        } else {
-            self.file.lock().unwrap().seek(SeekFrom::Start(page_index * PAGE_SIZE_BYTES as u64))?; // go to the start position of the page with page_index
-            let mut buffer = vec![0u8; PAGE_SIZE_BYTES];
-            self.file.lock().unwrap().read_exact(&mut buffer)?;
-            let page = Page {
-                data: buffer,
-                need_flush: false,
-            };
-            let evicted = self.pages.lock().unwrap().insert(page_index, Arc::new(RwLock::new(page.clone()))); // insert the page into cache
-            self.write_back_evicted_page(evicted)?;
-            Ok(page)
+            return Ok(page);
         }
+        let mut buffer = vec![0u8; PAGE_SIZE_BYTES];
+        self.file.read_exact_at(&mut buffer, page_index * PAGE_SIZE_BYTES as u64)?; // read the page with page_index
+        let page = Page {
+            data: buffer,
+            need_flush: false,
+        };
+        self.cache_insert(page_index, Arc::new(RwLock::new(page.clone())))?; // insert the page into cache
+        Ok(page)
     }
 
     pub fn write_page(&mut self, page: &Page, page_index: u64) -> RsqlResult<()> {
         self.is_page_index_valid(page_index)?;
         let mut page = page.clone();
         page.need_flush = true;
-        let page_arc = Arc::new(RwLock::new(page));
-        let evicted = self.pages.lock().unwrap().insert(page_index, Arc::clone(&page_arc)); // write the page into cache
-        self.write_back_evicted_page(evicted)?;
-        Ok(())
+        self.cache_insert(page_index, Arc::new(RwLock::new(page))) // write the page into cache
     }
 
     pub fn new_page(&mut self) -> RsqlResult<(u64, Page)> {
@@ -200,8 +235,7 @@ impl StorageManager {
             None => 0,
         };
         let new_page = Page::new();
-        let evicted = self.pages.lock().unwrap().insert(new_page_index, Arc::new(RwLock::new(new_page.clone())));
-        self.write_back_evicted_page(evicted)?;
+        self.cache_insert(new_page_index, Arc::new(RwLock::new(new_page.clone())))?;
         Ok((new_page_index, new_page))
     }
 
@@ -220,7 +254,7 @@ impl StorageManager {
 
         let current_file_size = *self.file_page_num.lock().unwrap() * PAGE_SIZE_BYTES as u64;
         if current_file_size < required_file_size {
-            self.file.lock().unwrap().set_len(required_file_size)?; // extend file (fills with zeros)
+            self.file.set_len(required_file_size)?; // extend file (fills with zeros)
         }
         for (page_index, page_arc) in &self.pages.lock().unwrap().map {
             let mut page = page_arc.write().map_err(|_| RsqlError::StorageError(
@@ -229,25 +263,26 @@ impl StorageManager {
             if !page.need_flush {
                 continue;
             }
-            let page_data = &page.data;
             let offset = page_index * PAGE_SIZE_BYTES as u64;
-            self.file.lock().unwrap().seek(SeekFrom::Start(offset))?;
-            self.file.lock().unwrap().write_all(page_data)?; // write page data to the file
+            self.file.write_all_at(&page.data, offset)?; // write page data to the file
             page.need_flush = false;
         }
-        self.file.lock().unwrap().flush()?;
-        self.file.lock().unwrap().sync_data()?; // ensure data is written to the disk
+        (&self.file).flush()?;
+        self.file.sync_data()?; // ensure data is written to the disk
         *self.file_page_num.lock().unwrap() = file_page_num; // update pages number in the file
         Ok(())
     }
     pub fn flush_all() -> RsqlResult<()> {
         let registry = Self::get_registry();
-        let read_guard = registry.read().unwrap();
-        for weak_ref in read_guard.values() {
-            if let Some(strong_ref) = weak_ref.upgrade() {
-                let mut sm = strong_ref.lock().unwrap();
-                sm.flush()?;
-            }
+        // release the registry before flushing: dropping the last reference
+        // of a manager unregisters it, which needs the write lock
+        let managers: Vec<_> = registry.read().unwrap()
+            .values()
+            .filter_map(|weak_ref| weak_ref.upgrade())
+            .collect();
+        for strong_ref in managers {
+            let mut sm = strong_ref.write().unwrap();
+            sm.flush()?;
         }
         Ok(())
     }
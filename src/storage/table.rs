@@ -1,26 +1,179 @@
-use std::sync::OnceLock;
+use std::sync::{Condvar, OnceLock};
 use std::sync::Mutex;
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+use linked_hash_map::LinkedHashMap;
 
 use super::storage::Page;
 use crate::config;
+use crate::config::{LOCK_TIMEOUT_MS, OPEN_TABLE_CACHE_SIZE, STATS_REFRESH_RATIO, COMPOSITE_PK_INDEX};
 use crate::common::{RsqlError, RsqlResult};
 use crate::common::{DataItem, VarCharHead};
 use super::btree_index;
 use super::consist_storage::ConsistStorageEngine;
 use crate::catalog::TableSchema;
 use crate::utils;
+use crate::transaction::{TnxManager, check_cancelled};
+use super::wal::WAL;
+use tracing::info;
 
 use super::allocator::Allocator;
 
 
 const HEADER_MAGIC: u32 = 0x4c515352; // 'RSQL' in little endian hex
+const HEADER_VERSION: u32 = 2; // older files are migrated to this version when opened
+const ROW_COUNT_OFFSET: usize = 8;
+const TIME_OUT: Duration = Duration::from_millis(LOCK_TIMEOUT_MS);
+
+/// Per-table lock registry: table id -> threads holding the table file.
+/// Reading threads share a table, a writing thread holds it alone.
+/// A table file is backed by a single storage manager, so the open tables of one file share its pages.
+struct TableLocks {
+    holders: Mutex<HashMap<u64, TableHolders>>,
+    released: Condvar,
+}
+static TABLE_LOCKS: OnceLock<TableLocks> = OnceLock::new();
+fn get_table_locks() -> &'static TableLocks {
+    TABLE_LOCKS.get_or_init(|| TableLocks {
+        holders: Mutex::new(HashMap::new()),
+        released: Condvar::new(),
+    })
+}
+
+/// Threads holding one table: either readers, each with the number of its opens, or a single writer
+#[derive(Default)]
+struct TableHolders {
+    readers: HashMap<ThreadId, usize>,
+    writer: Option<ThreadId>,
+}
+
+impl TableHolders {
+    fn is_empty(&self) -> bool {
+        self.readers.is_empty() && self.writer.is_none()
+    }
+}
+
+/// Hold on a table file, released when dropped
+struct TableLockGuard {
+    id: u64,
+    holder: ThreadId,
+    exclusive: bool,
+}
+
+impl TableLockGuard {
+    /// Wait until the table can be held: shared with other readers, or alone for a write.
+    /// A thread may open a table for reading again, any other reopen in the same thread
+    /// would wait for itself forever, so it fails instead.
+    fn acquire(id: u64, exclusive: bool) -> RsqlResult<Self> {
+        let locks = get_table_locks();
+        let mut holders = locks.holders.lock().unwrap();
+        let current = thread::current().id();
+        loop {
+            let table = holders.entry(id).or_default();
+            let held_here = table.writer == Some(current) || table.readers.contains_key(&current);
+            if held_here && (exclusive || table.writer.is_some()) {
+                return Err(RsqlError::ExecutionError(format!(
+                    "Table {} is already open in this thread and cannot be opened again for {}",
+                    id, if exclusive { "writing" } else { "reading" }
+                )));
+            }
+            let free = if exclusive { table.is_empty() } else { table.writer.is_none() };
+            if free {
+                if exclusive {
+                    table.writer = Some(current);
+                } else {
+                    *table.readers.entry(current).or_insert(0) += 1;
+                }
+                return Ok(TableLockGuard { id, holder: current, exclusive });
+            }
+            let (new_holders, wait_res) = locks.released.wait_timeout(holders, TIME_OUT).unwrap();
+            holders = new_holders;
+            if wait_res.timed_out() {
+                let table = holders.entry(id).or_default();
+                let still_held = if exclusive { !table.is_empty() } else { table.writer.is_some() };
+                if still_held {
+                    return Err(RsqlError::LockError(format!("Timeout waiting for table {}", id)));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TableLockGuard {
+    fn drop(&mut self) {
+        let locks = get_table_locks();
+        let mut holders = locks.holders.lock().unwrap();
+        if let Some(table) = holders.get_mut(&self.id) {
+            if self.exclusive {
+                table.writer = None;
+            } else if let Some(count) = table.readers.get_mut(&self.holder) {
+                *count -= 1;
+                if *count == 0 {
+                    table.readers.remove(&self.holder);
+                }
+            }
+            if table.is_empty() {
+                holders.remove(&self.id);
+            }
+        }
+        drop(holders);
+        locks.released.notify_all();
+    }
+}
+
+/// Parsed state of a closed table, reused by the next open instead of parsing the header page again.
+/// The storage engine keeps the table file open while the entry lives.
+#[derive(Clone)]
+struct CachedTable {
+    is_sys: bool,
+    indexes: HashMap<String, btree_index::BTreeIndex>,
+    allocator: Allocator,
+    row_count: u64,
+    stats: HashMap<String, ColumnStats>,
+    storage: ConsistStorageEngine,
+}
 
-// Guard to avoid instantiating Table on same table file multiple times
-static TABLE_GUARD: OnceLock<Mutex<HashSet<u64>>> = OnceLock::new();
-fn get_table_guard() -> &'static Mutex<HashSet<u64>> {
-    TABLE_GUARD.get_or_init(|| Mutex::new(HashSet::new()))
+/// LRU of closed tables: table id -> parsed state.
+/// An entry is taken out while its table is open for writing and put back when it is closed,
+/// both under the table lock, so a written table never shares its state with the cache.
+/// Readers copy the entry, it cannot change while they hold the table.
+struct OpenTableCache {
+    tables: LinkedHashMap<u64, CachedTable>,
+    header_parses: HashMap<u64, u64>, // table id -> times its header page was parsed
+    index_lookups: HashMap<u64, u64>, // table id -> lookups through a secondary index
+    full_scans: HashMap<u64, u64>, // table id -> scans of all rows
+    index_uses: HashMap<(u64, String), u64>, // (table id, column) -> reads through the column index
+}
+static OPEN_TABLES: OnceLock<Mutex<OpenTableCache>> = OnceLock::new();
+fn get_open_tables() -> &'static Mutex<OpenTableCache> {
+    OPEN_TABLES.get_or_init(|| Mutex::new(OpenTableCache {
+        tables: LinkedHashMap::new(),
+        header_parses: HashMap::new(),
+        index_lookups: HashMap::new(),
+        full_scans: HashMap::new(),
+        index_uses: HashMap::new(),
+    }))
+}
+
+/// Row counts changed by transactions but not yet written to their table headers:
+/// table id -> (whether a system table, transaction id -> rows added).
+/// A transaction writes its changes to each header once when it commits, or when the header is synced
+struct PendingRowCounts {
+    is_sys: bool,
+    deltas: HashMap<u64, i64>,
+}
+static PENDING_ROW_COUNTS: OnceLock<Mutex<HashMap<u64, PendingRowCounts>>> = OnceLock::new();
+fn get_pending_row_counts() -> &'static Mutex<HashMap<u64, PendingRowCounts>> {
+    PENDING_ROW_COUNTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Planner statistics of an indexed column
+#[derive(Debug, Clone, Copy)]
+pub struct ColumnStats {
+    pub row_count: u64, // rows of the table when the column was counted
+    pub distinct: u64, // distinct keys in the column index
 }
 
 /// Data sturcture manage a table in database
@@ -28,57 +181,146 @@ fn get_table_guard() -> &'static Mutex<HashSet<u64>> {
 /// The metadata stored in the first page in the table file:
 /// - magic number: 4 bytes
 /// - version: 4 bytes
+/// - row count: 8 bytes (since version 2)
 /// - indexes count: 8 bytes
 /// - each index [column_name: 64bytes][root_page: 8bytes]
 /// - allocator metadata: rest of the page
 pub struct Table {
     id: u64,
     schema: TableSchema,
+    row_layout: Vec<(usize, usize)>, // (offset in an entry, size) of each live column
     indexes: HashMap<String, btree_index::BTreeIndex>, // column name -> index
     allocator: Allocator,
+    row_count: u64,
+    stats: Mutex<HashMap<String, ColumnStats>>, // indexed column name -> its last counted stats
 
     storage: ConsistStorageEngine,
+    is_sys: bool,
+    cacheable: bool, // false once the table file is dropped
+    // must be dropped after storage, so the next holder opens a released file
+    lock: TableLockGuard,
 }
 
 impl Drop for Table {
+    /// Keep the parsed state for the next open, the table lock is still held here
     fn drop(&mut self) {
-        let guard = get_table_guard();
-        let mut guard = guard.lock().unwrap();
-        guard.remove(&self.id);
+        if !self.cacheable {
+            return;
+        }
+        let entry = CachedTable {
+            is_sys: self.is_sys,
+            indexes: std::mem::take(&mut self.indexes),
+            allocator: self.allocator.clone(),
+            row_count: self.row_count,
+            stats: std::mem::take(self.stats.get_mut().unwrap()),
+            storage: self.storage.clone(),
+        };
+        let evicted = {
+            let mut cache = get_open_tables().lock().unwrap();
+            cache.tables.insert(self.id, entry);
+            if cache.tables.len() > OPEN_TABLE_CACHE_SIZE {
+                cache.tables.pop_front()
+            } else {
+                None
+            }
+        };
+        // the evicted table file is flushed and closed outside the cache lock
+        drop(evicted);
+    }
+}
+
+
+/// Low bits of a heap pointer holding the offset, enough for any offset in a page of `page_size` bytes.
+/// Never below 16, the width used by files written with pages of up to 64 KB
+const fn ptr_offset_bits(page_size: usize) -> u32 {
+    let needed = usize::BITS - page_size.saturating_sub(1).leading_zeros();
+    if needed > 16 { needed } else { 16 }
+}
+
+/// Pack a heap location of a page of `page_size` bytes into one pointer, failing instead of truncating it
+fn pack_ptr_sized(page_idx: u64, offset: u64, page_size: usize) -> RsqlResult<u64> {
+    let bits = ptr_offset_bits(page_size);
+    if offset >= page_size as u64 || page_idx >> (u64::BITS - bits) != 0 {
+        return Err(RsqlError::StorageError(format!(
+            "Heap location (page {}, offset {}) does not fit in a pointer", page_idx, offset
+        )));
     }
+    Ok((page_idx << bits) | offset)
 }
 
+fn unpack_ptr_sized(ptr: u64, page_size: usize) -> (u64, u64) {
+    let bits = ptr_offset_bits(page_size);
+    (ptr >> bits, ptr & ((1 << bits) - 1))
+}
 
-fn pack_ptr(page_idx: u64, offset: u64) -> u64 {
-    (page_idx << 16) | (offset & 0xFFFF)
+fn pack_ptr(page_idx: u64, offset: u64) -> RsqlResult<u64> {
+    pack_ptr_sized(page_idx, offset, Page::max_size())
 }
 
 fn unpack_ptr(ptr: u64) -> (u64, u64) {
-    (ptr >> 16, ptr & 0xFFFF)
+    unpack_ptr_sized(ptr, Page::max_size())
+}
+
+/// Whether an updated value is stored the same as the old one.
+/// Strings compare by value, a loaded VarChar also carries its heap pointer and CHAR values their padding
+fn same_stored_value(old: &DataItem, new: &DataItem) -> bool {
+    match (old, new) {
+        (DataItem::VarChar { value: old, .. }, DataItem::VarChar { value: new, .. }) => old == new,
+        (DataItem::Chars { value: old, .. }, DataItem::Chars { value: new, .. }) => {
+            old.trim_end_matches('\0') == new.trim_end_matches('\0')
+        },
+        _ => old == new,
+    }
+}
+
+/// Whether `len` bytes starting at `offset` stay inside a page
+fn fits_in_page(offset: u64, len: usize) -> bool {
+    (offset as usize).checked_add(len).is_some_and(|end| end <= Page::max_size())
 }
 
 impl Table {
+    /// Offset and size of each live column in an entry, dropped columns keep their bytes
+    fn row_layout(schema: &TableSchema) -> Vec<(usize, usize)> {
+        let mut layout = vec![];
+        let mut offset = 0;
+        for (col, size) in schema.get_columns().iter().zip(schema.get_sizes()) {
+            if !col.is_dropped {
+                layout.push((offset, size));
+            }
+            offset += size;
+        }
+        layout
+    }
+
+    fn set_schema(&mut self, schema: TableSchema) {
+        self.row_layout = Self::row_layout(&schema);
+        self.schema = schema;
+    }
+
+    /// (index name, key) of every index entry of a row of the live columns
+    fn index_keys(&self, row: &[DataItem]) -> RsqlResult<Vec<(String, DataItem)>> {
+        let mut keys: Vec<(String, DataItem)> = self.schema.get_columns().iter()
+            .filter(|col| !col.is_dropped)
+            .zip(row)
+            .filter(|(col, _)| col.index)
+            .map(|(col, item)| (col.name.clone(), self.schema.collation_key(&col.name, item)))
+            .collect();
+        if self.schema.get_primary_key().len() > 1 {
+            keys.push((COMPOSITE_PK_INDEX.to_string(), self.schema.pk_key(row)?));
+        }
+        Ok(keys)
+    }
+
     fn read_row_at(&self, page_idx: u64, offset: u64) -> RsqlResult<Vec<DataItem>> {
         let data_page = self.storage.read(page_idx)?;
-        let mut row = vec![];
-        let mut curr_offset = offset as usize;
-        // let sizes = self.schema.get_sizes();
-        
-        for col in self.schema.get_columns() {
-            let size = DataItem::cal_size_from_coltype(&col.data_type);
-            if col.is_dropped {
-                curr_offset += size;
-                continue;
-            }
-            let item_bytes = &data_page.data[curr_offset..curr_offset+size];
-            match DataItem::from_bytes(item_bytes, None)? {
-                DataItem::VarChar { head, value } => {
-                    let varchar = self.load_varchar(&DataItem::VarChar { head, value })?;
-                    row.push(varchar);
-                },
-                item => row.push(item),
+        let entry = data_page.data.get(offset as usize..).ok_or_else(|| RsqlError::StorageError(format!(
+            "Entry offset {} is out of page {}", offset, page_idx
+        )))?;
+        let mut row = DataItem::decode_row(entry, &self.row_layout)?;
+        for item in row.iter_mut() {
+            if matches!(item, DataItem::VarChar { .. }) {
+                *item = self.load_varchar(item)?;
             }
-            curr_offset += size;
         }
         Ok(row)
     }
@@ -93,13 +335,16 @@ impl Table {
         }
         let heap_len = value.len() as u64;
         let (heap_page_idx, heap_offset) = self.allocator.alloc_heap(tnx_id, heap_len, &mut self.storage)?;
+        if !fits_in_page(heap_offset, value.len()) {
+            return Err(RsqlError::StorageError(format!("Heap chunk at offset {} of page {} overruns the page", heap_offset, heap_page_idx)));
+        }
         // write heap data
         self.storage.write_bytes(tnx_id, heap_page_idx, heap_offset as usize, value.as_bytes())?;
         // write ptr in head
         let new_head = VarCharHead {
             max_len: max_len,
             len: heap_len,
-            page_ptr: Some(pack_ptr(heap_page_idx, heap_offset)),
+            page_ptr: Some(pack_ptr(heap_page_idx, heap_offset)?),
         };
         Ok(DataItem::VarChar { head: new_head, value })
     }
@@ -116,6 +361,12 @@ impl Table {
             return Err(RsqlError::StorageError("Cannot load varchar with empty pointer".to_string()));
         };
         let (heap_page_idx, heap_offset) = unpack_ptr(varchar_head.page_ptr.unwrap());
+        // a corrupted or stale pointer must fail the read, not panic the thread
+        let in_file = self.storage.max_page_index().is_some_and(|max_idx| heap_page_idx <= max_idx);
+        let in_page = fits_in_page(heap_offset, varchar_head.len as usize);
+        if !in_file || !in_page {
+            return Err(RsqlError::StorageError("invalid heap pointer for varchar".to_string()));
+        }
         let heap_data = self.storage.read_bytes(heap_page_idx, heap_offset as usize, varchar_head.len as usize)?;
         Ok(DataItem::VarChar {
             head: varchar_head.clone(),
@@ -152,15 +403,44 @@ impl Table {
         Ok(entry_data)
     }
 
+    /// Open a table for writing, waiting until no other thread holds it
     pub fn from(id: u64, schema: TableSchema, is_sys: bool) -> RsqlResult<Self> {
-        // 1. check if table already opened
-        let guard = get_table_guard();
-        let mut guard = guard.lock().unwrap();
-        #[cfg(not(test))]
-        if guard.contains(&id) {
-            panic!("Table {} already opened in this process", id);
-        }
-        guard.insert(id);
+        Self::open(id, schema, is_sys, true)
+    }
+    /// Open a table for reading only, shared with the other readers of the table.
+    /// Writing through it fails
+    pub fn from_shared(id: u64, schema: TableSchema, is_sys: bool) -> RsqlResult<Self> {
+        Self::open(id, schema, is_sys, false)
+    }
+    fn open(id: u64, schema: TableSchema, is_sys: bool, exclusive: bool) -> RsqlResult<Self> {
+        // 1. wait until the table can be held
+        let lock = TableLockGuard::acquire(id, exclusive)?;
+        // reuse the parsed state of the last open if its indexes still match the schema
+        let cached = {
+            let mut cache = get_open_tables().lock().unwrap();
+            if exclusive { cache.tables.remove(&id) } else { cache.tables.get(&id).cloned() }
+        };
+        if let Some(cached) = cached {
+            let index_cols: HashSet<String> = schema.index_names().into_iter().collect();
+            if cached.is_sys == is_sys
+                && cached.indexes.len() == index_cols.len()
+                && cached.indexes.keys().all(|name| index_cols.contains(name)) {
+                return Ok(Table {
+                    id,
+                    row_layout: Self::row_layout(&schema),
+                    schema,
+                    storage: cached.storage,
+                    indexes: cached.indexes,
+                    allocator: cached.allocator,
+                    row_count: cached.row_count,
+                    stats: Mutex::new(cached.stats),
+                    is_sys,
+                    cacheable: true,
+                    lock,
+                });
+            }
+        }
+        *get_open_tables().lock().unwrap().header_parses.entry(id).or_insert(0) += 1;
         // 2. open table file
         let path = get_table_path(id, is_sys);
         let path_str = path.to_str().unwrap();
@@ -174,12 +454,26 @@ impl Table {
         if magic != HEADER_MAGIC {
             return Err(RsqlError::StorageError("Invalid table file, has wrong magic number".to_string()));
         }
-        let version = u32::from_le_bytes(header_page.data[4..8].try_into().unwrap()); // unused for now
-        if version != 1 {
-            panic!("Unsupported table file version: {}", version);
+        let version = u32::from_le_bytes(header_page.data[4..8].try_into().unwrap());
+        if version == 0 || version > HEADER_VERSION {
+            return Err(RsqlError::StorageError(format!("Unsupported table file version {} of table {}", version, id)));
+        }
+        if version < HEADER_VERSION && !exclusive {
+            // the upgrade writes, so it runs under a write open before the table is shared
+            drop((storage, lock));
+            drop(Self::from(id, schema.clone(), is_sys)?);
+            return Self::open(id, schema, is_sys, false);
         }
-        // 4. read indexes
         let mut offset = 4 + 4;
+        let row_count = if version == 1 {
+            0 // counted by the migration
+        } else {
+            offset += 8;
+            let header_count = u64::from_le_bytes(header_page.data[ROW_COUNT_OFFSET..ROW_COUNT_OFFSET+8].try_into().unwrap());
+            // rows of open transactions are not in the header yet
+            header_count.saturating_add_signed(Self::pending_row_delta(id, None))
+        };
+        // 4. read indexes
         let indexes_count_bytes = &header_page.data[offset..offset+8];
         let indexes_count = u64::from_le_bytes(indexes_count_bytes.try_into().unwrap());
         offset += 8;
@@ -196,29 +490,122 @@ impl Table {
             indexes.insert(col_name, btree_index);
         };
         // 5. check if indexes compatible with schema
-        if indexes.len() != schema.get_columns().iter().filter(|col| col.index).count() {
+        if indexes.len() != schema.index_names().len() {
             panic!("Incompatible index count between schema and table file {:?}", path);
         }
         // 6. construct allocator
         let allocator = Allocator::from(&header_page, offset as u64)?;
-        Ok(Table {
+        let mut table = Table {
             id,
+            row_layout: Self::row_layout(&schema),
             schema,
             storage,
             indexes,
             allocator,
-        })
+            row_count,
+            stats: Mutex::new(HashMap::new()),
+            is_sys,
+            cacheable: true,
+            lock,
+        };
+        // 7. upgrade files written by older versions
+        if version < HEADER_VERSION {
+            table.migrate(version)?;
+        }
+        Ok(table)
+    }
+    /// Rewrite a table file of an older format version to the current one, one version at a time.
+    /// Runs in a transaction of its own, a crash before it commits leaves the old file after recovery
+    fn migrate(&mut self, from_version: u32) -> RsqlResult<()> {
+        info!("Migrating table {} file from version {} to {}", self.id, from_version, HEADER_VERSION);
+        let tnx_id = TnxManager::global().new_tnx_id();
+        let wal = WAL::global()?;
+        wal.open_tnx(tnx_id)?;
+        for version in from_version..HEADER_VERSION {
+            match version {
+                // version 2 keeps the row count in the header
+                1 => {
+                    let mut count = 0;
+                    for row in self.get_all_rows()? {
+                        row?;
+                        count += 1;
+                    }
+                    self.row_count = count;
+                },
+                _ => unreachable!("no migration from table file version {}", version),
+            }
+        }
+        self.sync_header(tnx_id)?;
+        wal.commit_tnx(tnx_id)
+    }
+    /// Forget the parsed state of a closed table, the next open reads its header page again
+    pub fn evict_cached(id: u64) {
+        let evicted = get_open_tables().lock().unwrap().tables.remove(&id);
+        drop(evicted);
+    }
+    /// Forget all closed tables, needed after their pages were changed behind them, e.g. by a rollback
+    pub fn clear_cache() {
+        let evicted: Vec<CachedTable> = {
+            let mut cache = get_open_tables().lock().unwrap();
+            let mut evicted = vec![];
+            while let Some((_, entry)) = cache.tables.pop_front() {
+                evicted.push(entry);
+            }
+            evicted
+        };
+        drop(evicted);
+    }
+    /// Wait until no thread holds a table, e.g. before the table files are archived.
+    /// Fails naming the held tables if they are not released within the lock timeout
+    pub fn wait_all_released() -> RsqlResult<()> {
+        let locks = get_table_locks();
+        let mut holders = locks.holders.lock().unwrap();
+        while !holders.is_empty() {
+            let (new_holders, wait_res) = locks.released.wait_timeout(holders, TIME_OUT).unwrap();
+            holders = new_holders;
+            if wait_res.timed_out() && !holders.is_empty() {
+                let mut ids: Vec<u64> = holders.keys().copied().collect();
+                ids.sort();
+                return Err(RsqlError::LockError(format!("Timeout waiting for tables {:?} to be closed", ids)));
+            }
+        }
+        Ok(())
+    }
+    /// How many times the header page of a table file has been parsed
+    #[cfg(test)]
+    pub fn header_parse_count(id: u64) -> u64 {
+        get_open_tables().lock().unwrap().header_parses.get(&id).copied().unwrap_or(0)
+    }
+    /// How many times rows of a table were looked up through a secondary index and scanned in full
+    #[cfg(test)]
+    pub fn read_counts(id: u64) -> (u64, u64) {
+        let cache = get_open_tables().lock().unwrap();
+        (
+            cache.index_lookups.get(&id).copied().unwrap_or(0),
+            cache.full_scans.get(&id).copied().unwrap_or(0),
+        )
+    }
+    /// How many point lookups and range scans read a table through the index of a column since start
+    pub fn index_use_count(id: u64, col_name: &str) -> u64 {
+        get_open_tables().lock().unwrap().index_uses.get(&(id, col_name.to_string())).copied().unwrap_or(0)
+    }
+    fn count_index_use(&self, col_name: &str) {
+        *get_open_tables().lock().unwrap().index_uses.entry((self.id, col_name.to_string())).or_insert(0) += 1;
     }
     pub fn sync_header(&mut self, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
         let mut page_data: Vec<u8> = vec![0u8; Page::max_size()];
         
-        // 1. Magic & Version
+        // 1. Magic, Version & Row count
         page_data[0..4].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
-        page_data[4..8].copy_from_slice(&1u32.to_le_bytes());
+        page_data[4..8].copy_from_slice(&HEADER_VERSION.to_le_bytes());
+        // the rows of other open transactions are written when they commit
+        let header_count = self.row_count.saturating_add_signed(-Self::pending_row_delta(self.id, Some(tnx_id)));
+        page_data[ROW_COUNT_OFFSET..ROW_COUNT_OFFSET+8].copy_from_slice(&header_count.to_le_bytes());
         
         // 2. Indexes
         let indexes_count = self.indexes.len() as u64;
-        let mut offset = 8;
+        let mut offset = 16;
         page_data[offset..offset+8].copy_from_slice(&indexes_count.to_le_bytes());
         offset += 8;
         
@@ -243,30 +630,24 @@ impl Table {
         let mut page = Page::new();
         page.data = page_data;
         self.storage.write(tnx_id, 0, &page)?;
-        
+        if let Some(pending) = get_pending_row_counts().lock().unwrap().get_mut(&self.id) {
+            pending.deltas.remove(&tnx_id);
+        }
         Ok(())
     }
     /// Create a new table with given schema
     pub fn create(id: u64, schema: TableSchema, tnx_id: u64, is_sys: bool) -> RsqlResult<Self> { 
-        // check if table already opened
-        let guard = get_table_guard();
-        let mut guard = guard.lock().unwrap();
-        #[cfg(not(test))]
-        if guard.contains(&id) {
-            panic!("Table {} already opened in this process", id);
-        }
-        guard.insert(id);
+        // wait until no other thread holds the table
+        let lock = TableLockGuard::acquire(id, true)?;
+        // a table previously stored under this id must not keep the file open, nor its uncommitted rows
+        Self::evict_cached(id);
+        get_pending_row_counts().lock().unwrap().remove(&id);
         // create table file
         let path = get_table_path(id, is_sys);
         let path_str = path.to_str().unwrap();
         let mut storage = ConsistStorageEngine::new(path_str, id)?;
         // 1. collect indexes info
-        let mut index_cols = HashSet::new();
-        for col in schema.get_columns() {
-            if col.index {
-                index_cols.insert(col.name.clone());
-            }
-        }
+        let index_cols: HashSet<String> = schema.index_names().into_iter().collect();
         // 2. new indexes
         let mut indexes = HashMap::new();
         // Reserve page 0 for header
@@ -281,9 +662,10 @@ impl Table {
         // 3. collect header page bytes
         let mut page_data: Vec<u8> = vec![0u8; Page::max_size()];
         page_data[0..4].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
-        page_data[4..8].copy_from_slice(&1u32.to_le_bytes()); // version 1
+        page_data[4..8].copy_from_slice(&HEADER_VERSION.to_le_bytes());
+        // row count starts at 0
         let indexes_count = indexes.len() as u64;
-        let mut offset = 4 + 4;
+        let mut offset = 4 + 4 + 8;
         page_data[offset..offset+8].copy_from_slice(&indexes_count.to_le_bytes());
         offset += 8;
         for (col_name, btree_index) in &indexes {
@@ -313,16 +695,25 @@ impl Table {
         storage.write(tnx_id, 0, &header_page)?;
         Ok(Table {
             id,
+            row_layout: Self::row_layout(&schema),
             schema,
             storage,
             indexes,
             allocator,
+            row_count: 0,
+            stats: Mutex::new(HashMap::new()),
+            is_sys,
+            cacheable: true,
+            lock,
         })
     }
     /// Drop the table
     /// This implements will only set the table file length to 0
     /// TODO: support deleting the table file
     pub fn drop(mut self, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
+        self.cacheable = false;
+        get_pending_row_counts().lock().unwrap().remove(&self.id);
         let page_max_idx = self.storage.max_page_index();
         // truncate the file
         if page_max_idx.is_none() {
@@ -334,25 +725,43 @@ impl Table {
         };
         Ok(())
     }
+    /// Drop the table like `drop`, after overwriting every page with zeros on disk,
+    /// so the released file region keeps no rows. The log holds the old bytes until the next checkpoint,
+    /// for rollback and recovery
+    pub fn drop_zeroed(mut self, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
+        self.zero_fill(tnx_id)?;
+        self.drop(tnx_id)
+    }
+    /// Overwrite every page of the table file with zeros under the transaction and force them to disk
+    fn zero_fill(&mut self, tnx_id: u64) -> RsqlResult<()> {
+        let Some(page_max_idx) = self.storage.max_page_index() else {
+            return Ok(());
+        };
+        let zeros = Page::new();
+        for page_idx in 0..=page_max_idx {
+            self.storage.write(tnx_id, page_idx, &zeros)?;
+        }
+        self.storage.get_storage().write().unwrap().flush()
+    }
     fn get_row_ptr_by_pk(&self, pk: &DataItem) -> RsqlResult<Option<(u64, u64)>> {
-        // find the primary key column
-        let pk_col = self.schema.get_columns().iter().find(|col| col.pk);
-        if pk_col.is_none() {
-            return Err(RsqlError::InvalidInput("Table has no primary key".to_string()));
-        }
-        let pk_col = pk_col.unwrap();
-        // find the index for primary key column
-        let index = self.indexes.get(&pk_col.name);
+        // find the index of the primary key
+        let pk_index = self.schema.pk_index_name()
+            .ok_or(RsqlError::InvalidInput("Table has no primary key".to_string()))?;
+        let index = self.indexes.get(pk_index);
         if index.is_none() {
             return Err(RsqlError::InvalidInput("Primary key column has no index".to_string()));
         }
         let index = index.unwrap();
-        // search the index
-        let pair_opt = index.find_entry(pk.clone(), &self.storage)?;
+        // search the index, a single-column key is looked up under the column collation
+        let pair_opt = index.find_entry(self.schema.collation_key(pk_index, pk), &self.storage)?;
         Ok(pair_opt)
     }
     pub fn get_row_by_pk(&self, pk: &DataItem) -> RsqlResult<Option<Vec<DataItem>>> {
         let pair_opt = self.get_row_ptr_by_pk(pk)?;
+        if let Some(pk_index) = self.schema.pk_index_name() {
+            self.count_index_use(pk_index);
+        }
         let (match_page, match_offset) = match pair_opt {
             Some(pair) => pair,
             None => return Ok(None),
@@ -373,11 +782,15 @@ impl Table {
         let index = self.indexes.get(col_name).ok_or(RsqlError::InvalidInput(
             format!("Column {} is not indexed, cannot search", col_name)
         ))?;
-        // get iterator from index
-        let entry_iter = index.find_range_entry(start.clone(), end.clone(), &self.storage)?;
+        *get_open_tables().lock().unwrap().index_lookups.entry(self.id).or_insert(0) += 1;
+        self.count_index_use(col_name);
+        // get iterator from index, the bounds compare under the column collation
+        let bound = |item: &Option<DataItem>| item.as_ref().map(|item| self.schema.collation_key(col_name, item));
+        let entry_iter = index.find_range_entry(bound(start), bound(end), &self.storage)?;
 
         // map to row iterator
         let iter = entry_iter.map(move |pair_res| {
+            check_cancelled()?;
             let (match_page, match_offset) = pair_res?;
             // read the row from data page
             let row = self.read_row_at(match_page, match_offset)?;
@@ -385,34 +798,58 @@ impl Table {
         });
         Ok(iter)
     }
+    /// All rows of the table in ascending primary key order.
+    /// The rows are read by walking the primary key index, callers may rely on the order
     pub fn get_all_rows(&self) -> RsqlResult<impl Iterator<Item = RsqlResult<Vec<DataItem>>>> {
-        // find primary key column
-        let pk_col = self.schema.get_columns().iter().find(|col| col.pk);
-        if pk_col.is_none() {
-            panic!("Table has no primary key column, cannot get all rows");
-        }
-        let pk_col = pk_col.unwrap();
-        // find index for primary key column
-        let index = self.indexes.get(&pk_col.name);
-        if index.is_none() {
-            panic!("Primary key column has no index, cannot get all rows");
-        }
-        let index = index.unwrap();
+        // find index of the primary key
+        let pk_index = self.schema.pk_index_name()
+            .ok_or(RsqlError::StorageError(format!("Table {} has no primary key column, cannot get all rows", self.id)))?;
+        let index = self.indexes.get(pk_index)
+            .ok_or(RsqlError::StorageError(format!("Primary key column of table {} has no index, cannot get all rows", self.id)))?;
+        *get_open_tables().lock().unwrap().full_scans.entry(self.id).or_insert(0) += 1;
         // get all entries iterator
         let iter = index.traverse_all_entries(&self.storage)?;
         let iter = iter.map(move |pair_res| {
+            check_cancelled()?;
             let (match_page, match_offset) = pair_res?;
             let row = self.read_row_at(match_page, match_offset)?;
             Ok(row)
         });
         Ok(iter)
     }
+    /// All rows of a table without any index, read by walking its data pages in page order.
+    /// Such a table has no other row directory, a table with an index is read through it
+    pub fn get_rows_by_pages(&self) -> RsqlResult<Vec<Vec<DataItem>>> {
+        if !self.indexes.is_empty() {
+            return Err(RsqlError::StorageError(format!("Table {} has indexes, its rows are read through them", self.id)));
+        }
+        *get_open_tables().lock().unwrap().full_scans.entry(self.id).or_insert(0) += 1;
+        self.allocator.live_entries(&self.storage)?.into_iter().map(|(page_idx, offset)| {
+            check_cancelled()?;
+            self.read_row_at(page_idx, offset)
+        }).collect()
+    }
+    /// All rows of the table in ascending primary key order, like get_all_rows but
+    /// failing to start the scan is reported as the first item
+    pub fn scan(&self) -> impl Iterator<Item = RsqlResult<Vec<DataItem>>> + '_ {
+        let (rows, err) = match self.get_all_rows() {
+            Ok(rows) => (Some(rows), None),
+            Err(e) => (None, Some(Err(e))),
+        };
+        err.into_iter().chain(rows.into_iter().flatten())
+    }
     pub fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
     pub fn insert_row(&mut self, data: Vec<DataItem>, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
         // 1. check if data satisfies schema
         self.schema.satisfy(&data)?;
+        if self.allocator.entries_per_page() == 0 {
+            return Err(RsqlError::InvalidInput(format!(
+                "Rows of table {} take {} bytes, more than a page of {} bytes holds", self.id, self.allocator.entry_size(), Page::max_size()
+            )));
+        }
         // check unique constraints separately
         // the pk must be unique, so no need to check again
         let mut visible_col_idx = 0;
@@ -420,7 +857,7 @@ impl Table {
             if col.is_dropped { continue; }
             if col.unique {
                 let index = self.indexes.get(&col.name).unwrap();
-                let existing = index.check_exists(data[visible_col_idx].clone(), &self.storage)?;
+                let existing = index.check_exists(self.schema.collation_key(&col.name, &data[visible_col_idx]), &self.storage)?;
                 if existing {
                     return Err(RsqlError::InvalidInput(
                         format!("Unique constraint violation on column {}", col.name)));
@@ -428,6 +865,15 @@ impl Table {
             }
             visible_col_idx += 1;
         }
+        if self.schema.get_primary_key().len() > 1 {
+            let index = self.indexes.get(COMPOSITE_PK_INDEX).unwrap();
+            if index.check_exists(self.schema.pk_key(&data)?, &self.storage)? {
+                return Err(RsqlError::InvalidInput(format!(
+                    "Unique constraint violation on primary key ({})", self.schema.get_primary_key().join(", ")
+                )));
+            }
+        }
+        let index_keys = self.index_keys(&data)?;
         // 2. allocate entry
         let (entry_page_idx, entry_offset) = self.allocator.alloc_entry(tnx_id, &mut self.storage)?;
         // 3. construct physical data (handling dropped columns and VarChar)
@@ -463,28 +909,186 @@ impl Table {
         }
         // 4. write entry data
         let entry_bytes = Self::row_to_bytes(&physical_data)?;
+        if !fits_in_page(entry_offset, entry_bytes.len()) {
+            return Err(RsqlError::StorageError(format!("Entry at offset {} of page {} overruns the page", entry_offset, entry_page_idx)));
+        }
         self.storage.write_bytes(tnx_id, entry_page_idx, entry_offset as usize, &entry_bytes)?;
         // 5. write index entries
-        for (i, col) in self.schema.get_columns().iter().enumerate() {
-            if col.index && !col.is_dropped {
-                let index = self.indexes.get_mut(&col.name).unwrap();
-                index.insert_entry(
-                    tnx_id,
-                    physical_data[i].clone(),
-                    entry_page_idx,
-                    entry_offset,
-                    &mut self.storage,
-                )?;
+        for (index_name, key) in index_keys {
+            let index = self.indexes.get_mut(&index_name).unwrap();
+            index.insert_entry(
+                tnx_id,
+                key,
+                entry_page_idx,
+                entry_offset,
+                &mut self.storage,
+            )?;
+        }
+        // 6. count the row
+        self.add_row_count(1, tnx_id)
+    }
+    /// Number of rows kept in the header
+    pub fn row_count(&self) -> u64 {
+        self.row_count
+    }
+    /// Row count and distinct-key estimate of an indexed column.
+    /// The keys are counted from the index again once the row count drifted by more than STATS_REFRESH_RATIO
+    pub fn column_stats(&self, col_name: &str) -> RsqlResult<ColumnStats> {
+        let index = self.indexes.get(col_name).ok_or(RsqlError::InvalidInput(
+            format!("Column {} is not indexed, has no stats", col_name)
+        ))?;
+        let mut stats = self.stats.lock().unwrap();
+        if let Some(known) = stats.get(col_name) {
+            let drift = known.row_count.abs_diff(self.row_count) as f64;
+            if drift <= known.row_count as f64 * STATS_REFRESH_RATIO {
+                return Ok(*known);
+            }
+        }
+        let counted = ColumnStats {
+            row_count: self.row_count,
+            distinct: index.distinct_keys(&self.storage)?,
+        };
+        stats.insert(col_name.to_string(), counted);
+        Ok(counted)
+    }
+    /// Count rows added or removed by the transaction, the header is written once when it commits
+    fn add_row_count(&mut self, delta: i64, tnx_id: u64) -> RsqlResult<()> {
+        let count = self.row_count.checked_add_signed(delta)
+            .ok_or(RsqlError::StorageError(format!("Row count of table {} underflows", self.id)))?;
+        let mut pending = get_pending_row_counts().lock().unwrap();
+        let table = pending.entry(self.id).or_insert_with(|| PendingRowCounts { is_sys: self.is_sys, deltas: HashMap::new() });
+        *table.deltas.entry(tnx_id).or_insert(0) += delta;
+        self.row_count = count;
+        Ok(())
+    }
+    /// Rows added to a table by open transactions, except `excluded`
+    fn pending_row_delta(id: u64, excluded: Option<u64>) -> i64 {
+        get_pending_row_counts().lock().unwrap().get(&id).map_or(0, |table| {
+            table.deltas.iter()
+                .filter(|(tnx_id, _)| Some(**tnx_id) != excluded)
+                .map(|(_, delta)| delta)
+                .sum()
+        })
+    }
+    /// Write the rows a committing transaction added or removed to the headers of its tables, under the transaction.
+    /// Must run before the transaction commits, with none of its tables open in this thread
+    pub fn commit_row_counts(tnx_id: u64) -> RsqlResult<()> {
+        let mut tables: Vec<(u64, bool, i64)> = get_pending_row_counts().lock().unwrap().iter()
+            .filter_map(|(id, table)| table.deltas.get(&tnx_id).map(|delta| (*id, table.is_sys, *delta)))
+            .collect();
+        tables.sort();
+        for (id, is_sys, delta) in tables {
+            let _lock = TableLockGuard::acquire(id, true)?;
+            let cached = get_open_tables().lock().unwrap().tables.get(&id).map(|cached| cached.storage.clone());
+            let mut storage = match cached {
+                Some(storage) => storage,
+                None => ConsistStorageEngine::new(get_table_path(id, is_sys).to_str().unwrap(), id)?,
+            };
+            let header_count = u64::from_le_bytes(
+                storage.read_bytes(0, ROW_COUNT_OFFSET, 8)?.try_into().unwrap()
+            ).saturating_add_signed(delta);
+            storage.write_bytes(tnx_id, 0, ROW_COUNT_OFFSET, &header_count.to_le_bytes())?;
+            let mut pending = get_pending_row_counts().lock().unwrap();
+            if let Some(table) = pending.get_mut(&id) {
+                table.deltas.remove(&tnx_id);
+                if table.deltas.is_empty() {
+                    pending.remove(&id);
+                }
             }
         }
         Ok(())
     }
+    /// Forget the rows a rolled back transaction added or removed, its header writes were never made
+    pub fn discard_row_counts(tnx_id: u64) {
+        get_pending_row_counts().lock().unwrap().retain(|_, table| {
+            table.deltas.remove(&tnx_id);
+            !table.deltas.is_empty()
+        });
+    }
+    /// Update a row in place: entries have a fixed size, so the new row keeps the slot of the old one.
+    /// Only the bytes of changed columns are logged and only the indexes of changed keys are touched
     pub fn update_row(&mut self, pk: &DataItem, new_data: Vec<DataItem>, tnx_id: u64) -> RsqlResult<()> {
-        // TODO: optimize update in place if sizes match
-        self.delete_row(pk, tnx_id)?;
-        self.insert_row(new_data, tnx_id)
+        self.check_writable()?;
+        self.schema.satisfy(&new_data)?;
+        let (entry_page_idx, entry_offset) = self.get_row_ptr_by_pk(pk)?.ok_or(
+            RsqlError::InvalidInput("No such row with given primary key".to_string())
+        )?;
+        let old_row = self.read_row_at(entry_page_idx, entry_offset)?;
+        let live_cols: Vec<crate::catalog::table_schema::TableColumn> = self.schema.get_columns().iter()
+            .filter(|col| !col.is_dropped)
+            .cloned()
+            .collect();
+        let changed: Vec<usize> = (0..live_cols.len())
+            .filter(|&i| !same_stored_value(&old_row[i], &new_data[i]))
+            .collect();
+        // 1. check unique constraints of the changed keys before anything is written
+        for &i in &changed {
+            let col = &live_cols[i];
+            let key = self.schema.collation_key(&col.name, &new_data[i]);
+            // a value changed only in what the collation ignores keeps its own key
+            if col.unique && key != self.schema.collation_key(&col.name, &old_row[i]) {
+                let index = self.indexes.get(&col.name).unwrap();
+                if index.check_exists(key, &self.storage)? {
+                    return Err(RsqlError::InvalidInput(
+                        format!("Unique constraint violation on column {}", col.name)));
+                }
+            }
+        }
+        let (old_pk, new_pk) = (self.schema.pk_key(&old_row)?, self.schema.pk_key(&new_data)?);
+        let pk_moved = self.schema.get_primary_key().len() > 1 && old_pk != new_pk;
+        if pk_moved && self.indexes.get(COMPOSITE_PK_INDEX).unwrap().check_exists(new_pk.clone(), &self.storage)? {
+            return Err(RsqlError::InvalidInput(format!(
+                "Unique constraint violation on primary key ({})", self.schema.get_primary_key().join(", ")
+            )));
+        }
+        // 2. patch the changed columns in a copy of the entry
+        let entry_size = self.allocator.entry_size();
+        let old_entry = self.storage.read_bytes(entry_page_idx, entry_offset as usize, entry_size as usize)?;
+        let mut entry = old_entry.clone();
+        let mut new_data = new_data;
+        for &i in &changed {
+            if let DataItem::VarChar { .. } = &old_row[i] {
+                self.del_varchar(&old_row[i], tnx_id)?;
+            }
+            if let DataItem::VarChar { .. } = &new_data[i] {
+                let max_len = match &live_cols[i].data_type {
+                    crate::catalog::table_schema::ColType::VarChar(max_len) => *max_len,
+                    _ => panic!("Column type mismatch for VarChar"),
+                } as u64;
+                new_data[i] = self.store_varchar(new_data[i].clone(), tnx_id, max_len)?;
+            }
+            let (col_offset, size) = self.row_layout[i];
+            let (bytes, _) = new_data[i].to_bytes()?;
+            if bytes.len() != size {
+                return Err(RsqlError::StorageError(format!(
+                    "Column {} takes {} bytes, its slot has {}", live_cols[i].name, bytes.len(), size
+                )));
+            }
+            entry[col_offset..col_offset + size].copy_from_slice(&bytes);
+        }
+        // 3. write the bytes between the first and the last difference
+        if let Some(start) = entry.iter().zip(&old_entry).position(|(new, old)| new != old) {
+            let end = entry.len() - entry.iter().rev().zip(old_entry.iter().rev()).position(|(new, old)| new != old).unwrap();
+            self.storage.write_bytes(tnx_id, entry_page_idx, entry_offset as usize + start, &entry[start..end])?;
+        }
+        // 4. point the changed keys to the same slot
+        for &i in &changed {
+            if let Some(index) = self.indexes.get_mut(&live_cols[i].name) {
+                let old_key = self.schema.collation_key(&live_cols[i].name, &old_row[i]);
+                let new_key = self.schema.collation_key(&live_cols[i].name, &new_data[i]);
+                index.delete_entry(tnx_id, old_key, entry_page_idx, entry_offset, &mut self.storage)?;
+                index.insert_entry(tnx_id, new_key, entry_page_idx, entry_offset, &mut self.storage)?;
+            }
+        }
+        if pk_moved {
+            let index = self.indexes.get_mut(COMPOSITE_PK_INDEX).unwrap();
+            index.delete_entry(tnx_id, old_pk, entry_page_idx, entry_offset, &mut self.storage)?;
+            index.insert_entry(tnx_id, new_pk, entry_page_idx, entry_offset, &mut self.storage)?;
+        }
+        Ok(())
     }
     pub fn delete_row(&mut self, pk: &DataItem, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
         // 1. find the row by primary key
         let pair_opt = self.get_row_ptr_by_pk(pk)?;
         let (match_page, match_offset) = match pair_opt {
@@ -504,26 +1108,79 @@ impl Table {
             }
         }
         // 5. delete from indexes
-        let mut visible_idx = 0;
-        for col in self.schema.get_columns().iter() {
-            if col.is_dropped {
-                continue;
-            }
-            if col.index {
-                let index = self.indexes.get_mut(&col.name).unwrap();
-                index.delete_entry(
+        for (index_name, key) in self.index_keys(&row)? {
+            let index = self.indexes.get_mut(&index_name).unwrap();
+            index.delete_entry(
+                tnx_id,
+                key,
+                match_page,
+                match_offset,
+                &mut self.storage,
+            )?;
+        }
+        self.add_row_count(-1, tnx_id)?;
+        // 6. compact the page if deletes left it too fragmented
+        if self.storage.max_page_index().is_some_and(|max| match_page <= max)
+            && self.allocator.entry_page_fragmentation(match_page, &self.storage)? >= config::PAGE_COMPACT_THRESHOLD {
+            self.compact_page(match_page, tnx_id)?;
+        }
+        Ok(())
+    }
+    /// Delete the rows whose `col_name` value is in [start, end], found through the index of the column.
+    /// The primary keys are collected first, since deletes may compact pages and move rows,
+    /// then the rows are deleted with a single log flush for the whole batch.
+    /// Returns the number of deleted rows
+    pub fn delete_rows_by_range(
+        &mut self,
+        col_name: &str,
+        start: &Option<DataItem>,
+        end: &Option<DataItem>,
+        tnx_id: u64,
+    ) -> RsqlResult<usize> {
+        let pks = self.get_rows_by_range_indexed_col(col_name, start, end)?
+            .map(|row| row.and_then(|row| self.schema.pk_key(&row)))
+            .collect::<RsqlResult<Vec<_>>>()?;
+        self.storage.begin_batch();
+        let deleted = pks.iter().try_for_each(|pk| self.delete_row(pk, tnx_id));
+        self.storage.end_batch()?;
+        deleted?;
+        Ok(pks.len())
+    }
+    /// Slide the rows of a data page to its front and repoint the index entries of moved rows.
+    /// `page_idx` must be a data (entry) page of this table.
+    /// Returns the number of moved rows.
+    pub fn compact_page(&mut self, page_idx: u64, tnx_id: u64) -> RsqlResult<usize> {
+        self.check_writable()?;
+        let moves = self.allocator.compact_entry_page(tnx_id, page_idx, &mut self.storage)?;
+        for &(old_offset, new_offset) in &moves {
+            let row = self.read_row_at(page_idx, new_offset)?;
+            for (index_name, key) in self.index_keys(&row)? {
+                let index = self.indexes.get(&index_name).unwrap();
+                let updated = index.update_entry(
                     tnx_id,
-                    row[visible_idx].clone(),
-                    match_page,
-                    match_offset,
+                    key,
+                    page_idx,
+                    old_offset,
+                    page_idx,
+                    new_offset,
                     &mut self.storage,
                 )?;
+                if !updated {
+                    return Err(RsqlError::StorageError(format!(
+                        "Index on column {} has no entry for row at page {} offset {}",
+                        index_name, page_idx, old_offset
+                    )));
+                }
             }
-            visible_idx += 1;
         }
-        Ok(())
+        Ok(moves.len())
+    }
+    /// Whether a B-tree is maintained for `col_name`, the primary key always has one
+    pub fn has_index(&self, col_name: &str) -> bool {
+        self.indexes.contains_key(col_name)
     }
     pub fn create_index(&mut self, col_name: &str, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
         // check if column exists and is already indexed
         let col = self.schema.get_columns().iter().find(|col| col.name == col_name);
         if col.is_none() {
@@ -547,44 +1204,33 @@ impl Table {
                 columns.push(schema_col.clone());
             }
         };
-        self.schema = TableSchema::new(columns)?;
-        // create new index
-        let mut btree_index = btree_index::BTreeIndex::new(&mut self.storage, tnx_id)?;
+        self.set_schema(TableSchema::new(columns)?
+            .with_primary_key(self.schema.get_primary_key().to_vec())?
+            .with_collations(self.schema.get_collations().clone()));
         // populate index with existing data
-        let mut pk_index = 0;
-        let mut col_index = 0;
-        let mut visible_idx = 0;
-        for col in self.schema.get_columns() {
-            if col.is_dropped { continue; }
-            if col.pk {
-                pk_index = visible_idx;
-            }
-            if col.name == col_name {
-                col_index = visible_idx;
-            }
-            visible_idx += 1;
-        }
+        let col_index = self.schema.get_columns().iter()
+            .filter(|col| !col.is_dropped)
+            .position(|col| col.name == col_name)
+            .unwrap();
 
         let entry_iter = self.get_all_rows()?
             .collect::<RsqlResult<Vec<_>>>()?;
+        let mut entries = vec![];
         for row_res in entry_iter {
             let row = row_res;
-            let pk = row[pk_index].clone();
+            let pk = self.schema.pk_key(&row)?;
             let (entry_page_idx, entry_offset) = self.get_row_ptr_by_pk(&pk)?.unwrap();
-            btree_index.insert_entry(
-                tnx_id,
-                row[col_index].clone(),
-                entry_page_idx,
-                entry_offset,
-                &mut self.storage,
-            )?;
+            entries.push((self.schema.collation_key(col_name, &row[col_index]), entry_page_idx, entry_offset));
         };
+        // create new index
+        let btree_index = Self::bulk_load_index(entries, &mut self.storage, tnx_id)?;
         self.indexes.insert(col_name.to_string(), btree_index);
         // sync header
         self.sync_header(tnx_id)?;
         Ok(())
     }
     pub fn drop_index(&mut self, col_name: &str, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
         // check if column exists and is indexed
         let col = self.schema.get_columns().iter().find(|col| col.name == col_name);
         if col.is_none() {
@@ -609,7 +1255,9 @@ impl Table {
                 columns.push(schema_col.clone());
             }
         };
-        self.schema = TableSchema::new(columns)?;
+        self.set_schema(TableSchema::new(columns)?
+            .with_primary_key(self.schema.get_primary_key().to_vec())?
+            .with_collations(self.schema.get_collations().clone()));
         // remove index
         let index = self.indexes.remove(col_name).unwrap();
         index.drop(tnx_id, &mut self.storage)?;
@@ -617,12 +1265,100 @@ impl Table {
         self.sync_header(tnx_id)?;
         Ok(())
     }
+    /// Build an index over existing rows in one pass instead of inserting keys one by one
+    fn bulk_load_index(
+        mut entries: Vec<(DataItem, u64, u64)>,
+        storage: &mut ConsistStorageEngine,
+        tnx_id: u64,
+    ) -> RsqlResult<btree_index::BTreeIndex> {
+        entries.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        btree_index::BTreeIndex::bulk_load(entries, storage, tnx_id)
+    }
+    /// Text dump of the B-Tree of an indexed column, see `BTreeIndex::debug_dump`
+    pub fn debug_index(&self, col_name: &str) -> RsqlResult<String> {
+        let Some(index) = self.indexes.get(col_name) else {
+            return Err(RsqlError::InvalidInput(format!("Column {} is not indexed", col_name)));
+        };
+        index.debug_dump(&self.storage)
+    }
+    /// Rebuild the index on an indexed column from the live rows of the table.
+    /// Rows are enumerated through the primary key index, which is the only row directory
+    /// of the table file, so rebuilding the primary key index only compacts it.
+    /// The pages of the old tree are not walked, since they may be corrupted,
+    /// and are left unreachable because storage can only reclaim the last page.
+    /// A multi-column primary key index is rebuilt under the name COMPOSITE_PK_INDEX.
+    pub fn rebuild_index(&mut self, col_name: &str, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
+        let composite = col_name == COMPOSITE_PK_INDEX && self.schema.get_primary_key().len() > 1;
+        if !composite {
+            // check if column exists and is indexed
+            let col = self.schema.get_columns().iter().find(|col| col.name == col_name);
+            if col.is_none() {
+                return Err(RsqlError::InvalidInput(format!("Column {} does not exist", col_name)));
+            };
+            let col = col.unwrap();
+            if col.is_dropped {
+                return Err(RsqlError::InvalidInput(format!("Column {} is dropped", col_name)));
+            }
+            if !col.index {
+                return Err(RsqlError::InvalidInput(format!("Column {} is not indexed", col_name)));
+            };
+        }
+        let pk_name = self.schema.pk_index_name()
+            .ok_or(RsqlError::InvalidInput("Table has no primary key".to_string()))?;
+        // find visible index of the column, none for the key of a multi-column primary key
+        let col_index = self.schema.get_columns().iter()
+            .filter(|col| !col.is_dropped)
+            .position(|col| col.name == col_name);
+        // collect (key, entry pointer) of every live row
+        let pk_index = self.indexes.get(pk_name).unwrap();
+        let mut entries = vec![];
+        for pair_res in pk_index.traverse_all_entries(&self.storage)? {
+            let (entry_page_idx, entry_offset) = pair_res?;
+            let row = self.read_row_at(entry_page_idx, entry_offset)?;
+            let key = match col_index {
+                Some(idx) => self.schema.collation_key(col_name, &row[idx]),
+                None => self.schema.pk_key(&row)?,
+            };
+            entries.push((key, entry_page_idx, entry_offset));
+        }
+        // build the new index
+        let btree_index = Self::bulk_load_index(entries, &mut self.storage, tnx_id)?;
+        self.indexes.insert(col_name.to_string(), btree_index);
+        // sync header
+        self.sync_header(tnx_id)?;
+        Ok(())
+    }
+    /// Rebuild all indexes of the table, primary key index first
+    pub fn rebuild_indexes(&mut self, tnx_id: u64) -> RsqlResult<()> {
+        self.check_writable()?;
+        let mut names = self.schema.get_indexed_col();
+        if self.schema.get_primary_key().len() > 1 {
+            names.push(COMPOSITE_PK_INDEX.to_string());
+        }
+        let pk_name = self.schema.pk_index_name();
+        let mut cols: Vec<(bool, String)> = names.into_iter()
+            .map(|name| (Some(name.as_str()) != pk_name, name))
+            .collect();
+        cols.sort();
+        for (_, col_name) in cols {
+            self.rebuild_index(&col_name, tnx_id)?;
+        }
+        Ok(())
+    }
     pub fn get_storage(&mut self) -> &mut ConsistStorageEngine {
         &mut self.storage
     }
     pub fn get_table_id(&self) -> u64 {
         self.id
     }
+    /// Writes need the table opened by `from`, a shared open only reads
+    fn check_writable(&self) -> RsqlResult<()> {
+        if !self.lock.exclusive {
+            return Err(RsqlError::ExecutionError(format!("Table {} is open for reading only", self.id)));
+        }
+        Ok(())
+    }
 }
 
 /// Get the file path for a table given its ID
@@ -632,13 +1368,13 @@ pub fn get_table_path(id: u64, is_sys: bool) -> PathBuf {
         if cfg!(test) {
             utils::test_dir(format!("table_{id}")).join(format!("{}.dbs", id))
         } else {
-            std::path::Path::new(config::DB_DIR).join("sys").join(format!("{}.dbs", id))
+            config::get().sys_dir().join(format!("{}.dbs", id))
         }
     } else {
         if cfg!(test) {
             utils::test_dir(format!("table_{id}")).join(format!("{}.dbt", id))
         } else {
-            std::path::Path::new(config::DB_DIR).join("tables").join(format!("{}.dbt", id))
+            config::get().tables_dir().join(format!("{}.dbt", id))
         }
     }
 }
@@ -646,8 +1382,15 @@ pub fn get_table_path(id: u64, is_sys: bool) -> PathBuf {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::execution::executor::execute;
+    use crate::execution::test_utils::{query_rows, TestConn};
+    use crate::catalog::SysCatalog;
+    use crate::catalog::table_schema::Collation;
     use crate::catalog::table_schema::{TableColumn, ColType};
+    use crate::storage::WAL;
+    use crate::storage::storage::StorageManager;
     use std::fs;
+    use crate::config::MAX_VARCHAR_SIZE;
 
     fn setup_schema() -> TableSchema {
         let columns = vec![
@@ -983,7 +1726,7 @@ mod tests {
         // Drop 'temp' column manually (simulating what SysCatalog::drop_column does)
         let mut new_cols = table.schema.get_columns().clone();
         new_cols[1].is_dropped = true;
-        table.schema = crate::catalog::table_schema::TableSchema::new(new_cols).unwrap();
+        table.set_schema(crate::catalog::table_schema::TableSchema::new(new_cols).unwrap());
 
         // Now create index on 'age' (which is effectively index 1 now, was index 2)
         table.create_index("age", tnx_id).expect("Failed to create index after drop column");
@@ -1003,4 +1746,913 @@ mod tests {
 
         let _ = std::fs::remove_file(&path);
     }
+
+    #[test]
+    fn test_rebuild_corrupted_index() {
+        let table_id = 5000;
+        let columns = vec![
+            TableColumn {
+                name: "id".to_string(),
+                data_type: ColType::Integer,
+                pk: true,
+                nullable: false,
+                unique: true,
+                index: true,
+                is_dropped: false,
+            },
+            TableColumn {
+                name: "age".to_string(),
+                data_type: ColType::Integer,
+                pk: false,
+                nullable: false,
+                unique: false,
+                index: true,
+                is_dropped: false,
+            },
+        ];
+        let schema = TableSchema::new(columns).unwrap();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema.clone(), tnx_id, false).expect("Failed to create table");
+        for i in 1..=5 {
+            table.insert_row(
+                vec![DataItem::Integer(i as i64), DataItem::Integer((i * 10) as i64)],
+                tnx_id,
+            ).expect("Failed to insert row");
+        }
+
+        // corrupt the `age` index by overwriting its root with the primary key root
+        let pk_root = table.indexes.get("id").unwrap().root_page_num();
+        let age_root = table.indexes.get("age").unwrap().root_page_num();
+        let pk_page = table.storage.read(pk_root).unwrap();
+        table.storage.write(tnx_id, age_root, &pk_page).unwrap();
+
+        let start = Some(DataItem::Integer(20));
+        let end = Some(DataItem::Integer(40));
+        let rows: Vec<_> = table.get_rows_by_range_indexed_col("age", &start, &end)
+            .expect("Range scan failed")
+            .collect::<RsqlResult<Vec<_>>>()
+            .expect("Iterator error");
+        assert!(rows.is_empty());
+
+        // rebuild and check lookups are correct again
+        table.rebuild_indexes(tnx_id).expect("Failed to rebuild indexes");
+        let rows: Vec<_> = table.get_rows_by_range_indexed_col("age", &start, &end)
+            .expect("Range scan failed")
+            .collect::<RsqlResult<Vec<_>>>()
+            .expect("Iterator error");
+        let ids: Vec<_> = rows.iter().map(|row| row[0].clone()).collect();
+        assert_eq!(ids, vec![DataItem::Integer(2), DataItem::Integer(3), DataItem::Integer(4)]);
+
+        // the rebuilt roots are persisted in the header
+        drop(table);
+        let table = Table::from(table_id, schema, false).expect("Failed to open table");
+        let rows: Vec<_> = table.get_rows_by_range_indexed_col("age", &start, &end)
+            .expect("Range scan failed")
+            .collect::<RsqlResult<Vec<_>>>()
+            .expect("Iterator error");
+        assert_eq!(rows.len(), 3);
+        assert!(table.get_row_by_pk(&DataItem::Integer(5)).unwrap().is_some());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        let table_id = 5001;
+        let schema = setup_schema();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+        drop(Table::create(table_id, schema.clone(), tnx_id, false).expect("Failed to create table"));
+
+        // every thread reopens the table for each row, so writers contend on the table lock
+        // threads share the test name so they resolve the same test table path
+        let test_name = std::thread::current().name().unwrap().to_string();
+        let threads: Vec<_> = (0..4).map(|t| {
+            let schema = schema.clone();
+            std::thread::Builder::new().name(test_name.clone()).spawn(move || {
+                for i in 0..25 {
+                    let id = t * 100 + i;
+                    let mut table = Table::from(table_id, schema.clone(), false).expect("Failed to open table");
+                    table.insert_row(
+                        vec![
+                            DataItem::Integer(id),
+                            DataItem::Chars { len: 32, value: make_chars(&format!("User{}", id), 32) },
+                        ],
+                        tnx_id,
+                    ).expect("Insert failed");
+                }
+            }).unwrap()
+        }).collect();
+        for handle in threads {
+            handle.join().expect("Insert thread panicked");
+        }
+
+        let table = Table::from(table_id, schema, false).expect("Failed to open table");
+        let rows: Vec<_> = table.get_all_rows().expect("Full scan failed")
+            .collect::<RsqlResult<Vec<_>>>().expect("Iterator error");
+        assert_eq!(rows.len(), 100);
+        for t in 0..4 {
+            for i in 0..25 {
+                assert!(table.get_row_by_pk(&DataItem::Integer(t * 100 + i)).unwrap().is_some());
+            }
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_table_lock_modes() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let table_id = 5010;
+        let schema = setup_schema();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+        drop(Table::create(table_id, schema.clone(), tnx_id, false).expect("Failed to create table"));
+
+        // reopening a written table in the same thread is an error, not a panic
+        {
+            let _table = Table::from(table_id, schema.clone(), false).unwrap();
+            assert!(matches!(Table::from(table_id, schema.clone(), false), Err(RsqlError::ExecutionError(_))));
+            assert!(matches!(Table::from_shared(table_id, schema.clone(), false), Err(RsqlError::ExecutionError(_))));
+        }
+        // a thread may read a table twice, e.g. for a self join, but not write it meanwhile
+        let reader = Table::from_shared(table_id, schema.clone(), false).unwrap();
+        {
+            let mut again = Table::from_shared(table_id, schema.clone(), false).unwrap();
+            assert!(matches!(Table::from(table_id, schema.clone(), false), Err(RsqlError::ExecutionError(_))));
+            let row = vec![
+                DataItem::Integer(1),
+                DataItem::Chars { len: 32, value: make_chars("User1", 32) },
+            ];
+            assert!(matches!(again.insert_row(row, tnx_id), Err(RsqlError::ExecutionError(_))));
+        }
+
+        // other readers do not wait for the reader, a writer does
+        let test_name = std::thread::current().name().unwrap().to_string();
+        let released = Arc::new(AtomicBool::new(false));
+        let other_reader = {
+            let schema = schema.clone();
+            std::thread::Builder::new().name(test_name.clone()).spawn(move || {
+                Table::from_shared(table_id, schema, false).map(|_| ()).expect("Shared open failed");
+            }).unwrap()
+        };
+        other_reader.join().expect("Reader thread panicked");
+        let writer = {
+            let schema = schema.clone();
+            let released = released.clone();
+            std::thread::Builder::new().name(test_name).spawn(move || {
+                let _table = Table::from(table_id, schema, false).expect("Write open failed");
+                assert!(released.load(Ordering::SeqCst), "writer opened the table while it was read");
+            }).unwrap()
+        };
+        std::thread::sleep(Duration::from_millis(200));
+        released.store(true, Ordering::SeqCst);
+        drop(reader);
+        writer.join().expect("Writer thread panicked");
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_row_count_written_at_commit() {
+        let table_id = 5011;
+        let schema = setup_schema();
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+        drop(Table::create(table_id, schema.clone(), 1, false).expect("Failed to create table"));
+        let header_count = |table: &Table| {
+            u64::from_le_bytes(table.storage.read_bytes(0, ROW_COUNT_OFFSET, 8).unwrap().try_into().unwrap())
+        };
+        let insert = |table: &mut Table, id: i64, tnx_id: u64| {
+            table.insert_row(vec![
+                DataItem::Integer(id),
+                DataItem::Chars { len: 32, value: make_chars("User", 32) },
+            ], tnx_id).expect("Insert failed");
+        };
+
+        // rows are counted in memory, the header is not written for each of them
+        let tnx_id = 5011;
+        {
+            let mut table = Table::from(table_id, schema.clone(), false).unwrap();
+            for id in 0..3 {
+                insert(&mut table, id, tnx_id);
+            }
+            assert_eq!(table.row_count(), 3);
+            assert_eq!(header_count(&table), 0);
+        }
+        Table::commit_row_counts(tnx_id).unwrap();
+        {
+            let table = Table::from_shared(table_id, schema.clone(), false).unwrap();
+            assert_eq!(header_count(&table), 3);
+        }
+
+        // a header parsed while a transaction is open still counts its rows
+        let open_tnx = 5012;
+        {
+            let mut table = Table::from(table_id, schema.clone(), false).unwrap();
+            insert(&mut table, 10, open_tnx);
+        }
+        Table::evict_cached(table_id);
+        assert_eq!(Table::from_shared(table_id, schema.clone(), false).unwrap().row_count(), 4);
+        // a rolled back transaction leaves the header as it was
+        Table::discard_row_counts(open_tnx);
+        Table::evict_cached(table_id);
+        assert_eq!(Table::from_shared(table_id, schema, false).unwrap().row_count(), 3);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let table_id = 5002;
+        let columns = vec![
+                TableColumn {
+                    name: "id".to_string(),
+                    data_type: ColType::Integer,
+                    pk: true,
+                    nullable: false,
+                    index: true,
+                    unique: true,
+                    is_dropped: false,
+                },
+                TableColumn {
+                    name: "name".to_string(),
+                    data_type: ColType::Chars(32),
+                    pk: false,
+                    nullable: false,
+                    index: false,
+                    unique: false,
+                    is_dropped: false,
+                },
+                TableColumn {
+                    name: "bio".to_string(),
+                    data_type: ColType::VarChar(256),
+                    pk: false,
+                    nullable: false,
+                    index: false,
+                    unique: false,
+                    is_dropped: false,
+                },
+            ];
+        let schema = TableSchema::new(columns).unwrap();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema, tnx_id, false).expect("Failed to create table");
+        let samples = [
+            ("O'Brien", "it's \"quoted\"\nand spans\r\nlines\twith tabs"),
+            ("ünïcødé", "日本語のテキスト 🎉 — ß"),
+            ("\\back\\slash", "'"),
+        ];
+        for (i, (name, bio)) in samples.iter().enumerate() {
+            let row = vec![
+                DataItem::Integer(i as i64),
+                DataItem::Chars { len: 32, value: name.to_string() },
+                DataItem::VarChar {
+                    head: VarCharHead { max_len: 256, len: bio.len() as u64, page_ptr: Some(0) },
+                    value: bio.to_string(),
+                },
+            ];
+            table.insert_row(row, tnx_id).expect("Failed to insert row");
+        }
+        for (i, (name, bio)) in samples.iter().enumerate() {
+            let row = table.get_row_by_pk(&DataItem::Integer(i as i64)).unwrap().unwrap();
+            match (&row[1], &row[2]) {
+                (DataItem::Chars { value: n, .. }, DataItem::VarChar { value: b, .. }) => {
+                    assert_eq!(n, name);
+                    assert_eq!(b, bio);
+                },
+                _ => panic!("Unexpected row types {:?}", row),
+            }
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_compact_page() {
+        let table_id = 5003;
+        let schema = setup_schema();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema, tnx_id, false).expect("Failed to create table");
+        table.create_index("name", tnx_id).unwrap();
+        for i in 0..20 {
+            let row = vec![
+                DataItem::Integer(i),
+                DataItem::Chars { len: 32, value: format!("user{}", i) },
+            ];
+            table.insert_row(row, tnx_id).unwrap();
+        }
+        // punch holes into the page
+        for i in (0..20).step_by(2) {
+            table.delete_row(&DataItem::Integer(i), tnx_id).unwrap();
+        }
+        let (page_idx, last_offset) = table.get_row_ptr_by_pk(&DataItem::Integer(19)).unwrap().unwrap();
+        assert!(table.allocator.entry_page_fragmentation(page_idx, &table.storage).unwrap() > 0.0);
+
+        let moved = table.compact_page(page_idx, tnx_id).unwrap();
+        assert_eq!(moved, 10);
+        assert_eq!(table.allocator.entry_page_fragmentation(page_idx, &table.storage).unwrap(), 0.0);
+        let (new_page, new_offset) = table.get_row_ptr_by_pk(&DataItem::Integer(19)).unwrap().unwrap();
+        assert_eq!(new_page, page_idx);
+        assert!(new_offset < last_offset);
+
+        // lookups through both indexes still find the moved rows
+        for i in (1..20).step_by(2) {
+            let row = table.get_row_by_pk(&DataItem::Integer(i)).unwrap().unwrap();
+            assert_eq!(row[1], DataItem::Chars { len: 32, value: format!("user{}", i) });
+            let name = Some(DataItem::Chars { len: 32, value: format!("user{}", i) });
+            let rows: Vec<_> = table.get_rows_by_range_indexed_col("name", &name, &name).unwrap()
+                .collect::<RsqlResult<Vec<_>>>()
+                .unwrap();
+            assert_eq!(rows, vec![vec![DataItem::Integer(i), name.clone().unwrap()]]);
+        }
+        assert_eq!(table.get_all_rows().unwrap().count(), 10);
+
+        // freed slots are reusable
+        table.insert_row(vec![DataItem::Integer(100), DataItem::Chars { len: 32, value: "new".to_string() }], tnx_id).unwrap();
+        assert!(table.get_row_by_pk(&DataItem::Integer(100)).unwrap().is_some());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_delete_rows_by_range() {
+        let table_id = 5004;
+        let schema = setup_schema();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema, tnx_id, false).expect("Failed to create table");
+        for i in 0..200 {
+            let row = vec![
+                DataItem::Integer(i),
+                DataItem::Chars { len: 32, value: format!("user{}", i) },
+            ];
+            table.insert_row(row, tnx_id).unwrap();
+        }
+
+        let flushes = WAL::thread_flush_count();
+        let deleted = table.delete_rows_by_range("id", &Some(DataItem::Integer(50)), &Some(DataItem::Integer(149)), tnx_id).unwrap();
+        assert_eq!(deleted, 100);
+        // the whole batch is covered by one flush of the log
+        assert_eq!(WAL::thread_flush_count() - flushes, 1);
+
+        let ids: Vec<_> = table.get_all_rows().unwrap()
+            .map(|row| row.map(|row| row[0].clone()))
+            .collect::<RsqlResult<Vec<_>>>()
+            .unwrap();
+        let expected: Vec<_> = (0..50).chain(150..200).map(DataItem::Integer).collect();
+        assert_eq!(ids, expected);
+        assert!(table.get_row_by_pk(&DataItem::Integer(100)).unwrap().is_none());
+        assert_eq!(table.get_rows_by_range_indexed_col("id", &Some(DataItem::Integer(50)), &Some(DataItem::Integer(149))).unwrap().count(), 0);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_crash_mid_insert_recovers_allocator() {
+        let table_id = 5005;
+        let columns = vec![
+            TableColumn {
+                name: "id".to_string(),
+                data_type: ColType::Integer,
+                pk: true,
+                nullable: false,
+                unique: true,
+                index: true,
+                is_dropped: false,
+            },
+            TableColumn {
+                name: "bio".to_string(),
+                data_type: ColType::VarChar(10000),
+                pk: false,
+                nullable: false,
+                unique: false,
+                index: false,
+                is_dropped: false,
+            },
+            TableColumn {
+                name: "age".to_string(),
+                data_type: ColType::Integer,
+                pk: false,
+                nullable: false,
+                unique: false,
+                index: true,
+                is_dropped: false,
+            },
+        ];
+        let schema = TableSchema::new(columns).unwrap();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+        // large enough that the second row needs a new heap page
+        let bio = |c: char| DataItem::VarChar {
+            head: VarCharHead { max_len: 10000, len: 9000, page_ptr: Some(0) },
+            value: c.to_string().repeat(9000),
+        };
+
+        let mut table = Table::create(table_id, schema.clone(), tnx_id, false).expect("Failed to create table");
+        table.insert_row(vec![DataItem::Integer(1), bio('a'), DataItem::Integer(10)], tnx_id).unwrap();
+        let allocator_before = table.allocator.to_bytes();
+        let max_page_before = table.storage.max_page_index();
+
+        // an insert that allocates its entry and heap chunk, then crashes before the index update
+        let crash_tnx = 5005;
+        let wal = WAL::global().unwrap();
+        wal.open_tnx(crash_tnx).unwrap();
+        let (entry_page, entry_offset) = table.allocator.alloc_entry(crash_tnx, &mut table.storage).unwrap();
+        let stored = table.store_varchar(bio('b'), crash_tnx, 10000).unwrap();
+        let DataItem::VarChar { head, .. } = &stored else { unreachable!() };
+        let heap_ptr = unpack_ptr(head.page_ptr.unwrap());
+        let entry_bytes = Table::row_to_bytes(&vec![DataItem::Integer(2), stored.clone(), DataItem::Integer(20)]).unwrap();
+        table.storage.write_bytes(crash_tnx, entry_page, entry_offset as usize, &entry_bytes).unwrap();
+        assert!(table.storage.max_page_index() > max_page_before);
+        // the dirty pages reach the file, the in-memory allocator is lost
+        table.cacheable = false;
+        drop(table);
+
+        // recovery undoes the unfinished transaction from the log
+        let sm = StorageManager::new(path.to_str().unwrap()).unwrap();
+        wal.rollback_tnx(
+            crash_tnx,
+            &mut |_, page_id, data| {
+                let mut sm = sm.write().unwrap();
+                let mut page = sm.read_page(page_id)?;
+                page.data.copy_from_slice(data);
+                sm.write_page(&page, page_id)
+            },
+            &mut |_, page_id, offset, len, data| {
+                let mut sm = sm.write().unwrap();
+                let mut page = sm.read_page(page_id)?;
+                page.data[offset as usize..(offset + len) as usize].copy_from_slice(data);
+                sm.write_page(&page, page_id)
+            },
+            &mut |_| Ok(sm.write().unwrap().new_page()?.0),
+            &mut |_| { sm.write().unwrap().free()?; Ok(()) },
+            &mut |_| Ok(sm.read().unwrap().max_page_index()),
+        ).unwrap();
+        drop(sm);
+
+        let mut table = Table::from(table_id, schema, false).expect("Failed to open table");
+        assert_eq!(table.allocator.to_bytes(), allocator_before);
+        assert_eq!(table.storage.max_page_index(), max_page_before);
+        let rows = table.get_all_rows().unwrap().collect::<RsqlResult<Vec<_>>>().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], DataItem::Integer(1));
+        // the entry slot and heap chunk of the crashed insert are free again, neither leaked nor in use
+        assert_eq!(table.allocator.alloc_entry(tnx_id, &mut table.storage).unwrap(), (entry_page, entry_offset));
+        assert_eq!(table.allocator.alloc_heap(tnx_id, 9000, &mut table.storage).unwrap(), heap_ptr);
+        table.allocator.free_heap(tnx_id, heap_ptr.0, heap_ptr.1, &mut table.storage).unwrap();
+        table.allocator.free_entry(tnx_id, entry_page, entry_offset, &mut table.storage).unwrap();
+
+        table.insert_row(vec![DataItem::Integer(2), bio('b'), DataItem::Integer(20)], tnx_id).unwrap();
+        let bios: Vec<_> = table.get_all_rows().unwrap()
+            .map(|row| match row.unwrap().remove(1) {
+                DataItem::VarChar { value, .. } => value,
+                other => panic!("Unexpected bio {:?}", other),
+            })
+            .collect();
+        assert_eq!(bios, ["a".repeat(9000), "b".repeat(9000)]);
+        assert_eq!(table.get_rows_by_range_indexed_col("age", &Some(DataItem::Integer(20)), &Some(DataItem::Integer(20))).unwrap().count(), 1);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_get_all_rows_in_pk_order() {
+        TnxManager::init(1);
+        let table_id = 5008;
+        let schema = setup_schema();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema, tnx_id, false).expect("Failed to create table");
+        let keys = [42, -7, 1000, 0, 13, -300, 7, 999];
+        for key in keys {
+            let name = DataItem::Chars { len: 32, value: make_chars(&format!("row{}", key), 32) };
+            table.insert_row(vec![DataItem::Integer(key), name], tnx_id).unwrap();
+        }
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        let scanned = table.get_all_rows().unwrap()
+            .map(|row| row.unwrap()[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(scanned, sorted.into_iter().map(DataItem::Integer).collect::<Vec<_>>());
+
+        // without the primary key index there is no order to scan in
+        table.indexes.clear();
+        assert!(matches!(table.get_all_rows(), Err(RsqlError::StorageError(_))));
+        table.cacheable = false;
+        drop(table);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_nulls_read_back_distinct_from_zero_values() {
+        TnxManager::init(1);
+        let table_id = 5009;
+        let nullable = |name: &str, data_type| TableColumn { name: name.to_string(), data_type, pk: false, nullable: true, index: false, unique: false, is_dropped: false };
+        let columns = vec![
+            TableColumn { name: "id".to_string(), data_type: ColType::Integer, pk: true, nullable: false, index: true, unique: true, is_dropped: false },
+            nullable("n", ColType::Integer),
+            nullable("f", ColType::Float),
+            nullable("c", ColType::Chars(4)),
+            nullable("b", ColType::Bool),
+            nullable("v", ColType::VarChar(16)),
+        ];
+        let schema = TableSchema::new(columns).unwrap();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema, tnx_id, false).expect("Failed to create table");
+        let nulls = vec![DataItem::Integer(1), DataItem::NullInt, DataItem::NullFloat, DataItem::NullChars { len: 4 }, DataItem::NullBool, DataItem::NullVarChar];
+        let zeros = vec![DataItem::Integer(2), DataItem::Integer(0), DataItem::Float(0.0), DataItem::Chars { len: 4, value: String::new() }, DataItem::Bool(false), DataItem::NullVarChar];
+        table.insert_row(nulls.clone(), tnx_id).unwrap();
+        table.insert_row(zeros.clone(), tnx_id).unwrap();
+        // the type tag of each stored item tells a null from a zero filled value
+        let read = |pk| table.get_row_by_pk(&DataItem::Integer(pk)).unwrap().unwrap();
+        for (col, (null, zero)) in read(1).iter().zip(read(2).iter()).enumerate().skip(1).take(4) {
+            assert_eq!(null.display(), crate::config::NULL_DISPLAY, "column {}", col);
+            assert_ne!(zero.display(), crate::config::NULL_DISPLAY, "column {}", col);
+        }
+        assert_eq!(read(1), nulls);
+        assert_eq!(read(2), zeros);
+        table.cacheable = false;
+        drop(table);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_invalid_varchar_pointer_is_an_error() {
+        TnxManager::init(1);
+        let table_id = 5007;
+        let columns = vec![
+            TableColumn { name: "id".to_string(), data_type: ColType::Integer, pk: true, nullable: false, index: true, unique: true, is_dropped: false },
+            TableColumn { name: "bio".to_string(), data_type: ColType::VarChar(64), pk: false, nullable: false, index: false, unique: false, is_dropped: false },
+        ];
+        let schema = TableSchema::new(columns).unwrap();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema, tnx_id, false).expect("Failed to create table");
+        let bio = DataItem::VarChar {
+            head: VarCharHead { max_len: 64, len: 11, page_ptr: Some(0) },
+            value: "Hello World".to_string(),
+        };
+        table.insert_row(vec![DataItem::Integer(1), bio], tnx_id).unwrap();
+        let (entry_page, entry_offset) = table.indexes["id"].traverse_all_entries(&table.storage).unwrap().next().unwrap().unwrap();
+        // the pointer follows the tag, max_len and len of the varchar head
+        let ptr_offset = entry_offset as usize + DataItem::cal_size_from_coltype(&ColType::Integer) + 1 + 8 + 8;
+        let valid_ptr = table.storage.read_bytes(entry_page, ptr_offset, 8).unwrap();
+        let (heap_page, _) = unpack_ptr(u64::from_le_bytes(valid_ptr.try_into().unwrap()));
+
+        // a page past the end of the file, then an offset past the end of the page
+        for bad_ptr in [pack_ptr(heap_page + 1000, 0).unwrap(), pack_ptr(heap_page, Page::max_size() as u64 - 4).unwrap()] {
+            table.storage.write_bytes(tnx_id, entry_page, ptr_offset, &bad_ptr.to_le_bytes()).unwrap();
+            let err = table.get_row_by_pk(&DataItem::Integer(1)).unwrap_err();
+            assert!(matches!(&err, RsqlError::StorageError(msg) if msg == "invalid heap pointer for varchar"), "{:?}", err);
+        }
+        table.cacheable = false;
+        drop(table);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_insert_rejects_row_wider_than_page() {
+        TnxManager::init(1);
+        let table_id = 5011;
+        let width = Page::max_size() / 2 + 1;
+        let columns = vec![
+            TableColumn { name: "id".to_string(), data_type: ColType::Integer, pk: true, nullable: false, index: true, unique: true, is_dropped: false },
+            TableColumn { name: "a".to_string(), data_type: ColType::Chars(width), pk: false, nullable: true, index: false, unique: false, is_dropped: false },
+            TableColumn { name: "b".to_string(), data_type: ColType::Chars(width), pk: false, nullable: true, index: false, unique: false, is_dropped: false },
+        ];
+        let schema = TableSchema::new(columns).unwrap();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema, tnx_id, false).expect("Failed to create table");
+        let row = vec![DataItem::Integer(1), DataItem::NullChars { len: width as u64 }, DataItem::NullChars { len: width as u64 }];
+        let err = table.insert_row(row, tnx_id).unwrap_err();
+        assert!(matches!(&err, RsqlError::InvalidInput(msg) if msg.contains("more than a page")), "{err:?}");
+        table.drop(tnx_id).unwrap();
+        let _ = fs::remove_file(&path);
+
+        // a varchar value larger than a heap page is refused instead of growing the heap forever
+        let table_id = 5012;
+        let columns = vec![
+            TableColumn { name: "id".to_string(), data_type: ColType::Integer, pk: true, nullable: false, index: true, unique: true, is_dropped: false },
+            TableColumn { name: "v".to_string(), data_type: ColType::VarChar(MAX_VARCHAR_SIZE), pk: false, nullable: true, index: false, unique: false, is_dropped: false },
+        ];
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+        let mut table = Table::create(table_id, TableSchema::new(columns).unwrap(), tnx_id, false).expect("Failed to create table");
+        let value = "x".repeat(Page::max_size());
+        let item = DataItem::VarChar {
+            head: VarCharHead { max_len: MAX_VARCHAR_SIZE as u64, len: value.len() as u64, page_ptr: None },
+            value,
+        };
+        let err = table.insert_row(vec![DataItem::Integer(1), item], tnx_id).unwrap_err();
+        assert!(matches!(&err, RsqlError::InvalidInput(msg) if msg.contains("does not fit in a heap page")), "{err:?}");
+        table.drop(tnx_id).unwrap();
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_update_row_in_place() {
+        TnxManager::init(1);
+        let table_id = 5013;
+        let columns = vec![
+            TableColumn { name: "id".to_string(), data_type: ColType::Integer, pk: true, nullable: false, index: true, unique: true, is_dropped: false },
+            TableColumn { name: "k".to_string(), data_type: ColType::Integer, pk: false, nullable: false, index: true, unique: false, is_dropped: false },
+            TableColumn { name: "v".to_string(), data_type: ColType::Integer, pk: false, nullable: false, index: false, unique: false, is_dropped: false },
+        ];
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+        let mut table = Table::create(table_id, TableSchema::new(columns).unwrap(), tnx_id, false).expect("Failed to create table");
+        for i in 0..3 {
+            table.insert_row(vec![DataItem::Integer(i), DataItem::Integer(i), DataItem::Integer(0)], tnx_id).unwrap();
+        }
+        let row = |i: i64| vec![DataItem::Integer(i), DataItem::Integer(i), DataItem::Integer(7)];
+
+        let before = WAL::thread_entry_count();
+        table.update_row(&DataItem::Integer(1), row(1), tnx_id).unwrap();
+        let in_place = WAL::thread_entry_count() - before;
+        let before = WAL::thread_entry_count();
+        table.delete_row(&DataItem::Integer(2), tnx_id).unwrap();
+        table.insert_row(row(2), tnx_id).unwrap();
+        let reinserted = WAL::thread_entry_count() - before;
+        assert_eq!(in_place, 1);
+        assert!(reinserted >= 4 * in_place, "{reinserted} entries to reinsert");
+        assert_eq!(table.get_row_by_pk(&DataItem::Integer(1)).unwrap(), Some(row(1)));
+
+        // a changed key moves only its own index entry
+        table.update_row(&DataItem::Integer(1), vec![DataItem::Integer(1), DataItem::Integer(9), DataItem::Integer(7)], tnx_id).unwrap();
+        let key = |k: i64| Some(DataItem::Integer(k));
+        let found = |k: i64| table.get_rows_by_range_indexed_col("k", &key(k), &key(k)).unwrap().count();
+        assert_eq!((found(1), found(9)), (0, 1));
+        assert_eq!(table.row_count(), 3);
+        table.drop(tnx_id).unwrap();
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_zeroed_drop_overwrites_file() {
+        TnxManager::init(1);
+        let table_id = 5010;
+        let columns = vec![
+            TableColumn { name: "id".to_string(), data_type: ColType::Integer, pk: true, nullable: false, index: true, unique: true, is_dropped: false },
+            TableColumn { name: "secret".to_string(), data_type: ColType::VarChar(64), pk: false, nullable: false, index: false, unique: false, is_dropped: false },
+        ];
+        let schema = TableSchema::new(columns).unwrap();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema, tnx_id, false).expect("Failed to create table");
+        for id in 0..20 {
+            let secret = format!("top-secret-{:02}", id);
+            let item = DataItem::VarChar {
+                head: VarCharHead { max_len: 64, len: secret.len() as u64, page_ptr: None },
+                value: secret,
+            };
+            table.insert_row(vec![DataItem::Integer(id), item], tnx_id).unwrap();
+        }
+        table.storage.get_storage().write().unwrap().flush().unwrap();
+        let written = fs::read(&path).unwrap();
+        assert!(written.windows(10).any(|w| w == b"top-secret"));
+
+        // the pages are zeros on disk before the file is truncated
+        table.zero_fill(tnx_id).unwrap();
+        let zeroed = fs::read(&path).unwrap();
+        assert_eq!(zeroed.len(), written.len());
+        assert!(zeroed.iter().all(|b| *b == 0));
+        table.drop(tnx_id).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_heap_ptr_round_trips_with_large_pages() {
+        // 16 KB pages keep the 16 bit offsets of existing files
+        assert_eq!(pack_ptr_sized(3, 100, 16 * 1024).unwrap(), (3 << 16) | 100);
+        let page_size = 256 * 1024;
+        let ptr = pack_ptr_sized(7, 200_000, page_size).unwrap();
+        assert_eq!(unpack_ptr_sized(ptr, page_size), (7, 200_000));
+        // offsets past the page and pages past the pointer width are refused, not truncated
+        assert!(pack_ptr_sized(7, page_size as u64, page_size).is_err());
+        assert!(pack_ptr_sized(u64::MAX >> 10, 0, page_size).is_err());
+    }
+
+    #[test]
+    fn test_open_migrates_v1_file() {
+        TnxManager::init(1);
+        let table_id = 5006;
+        let schema = setup_schema();
+        let tnx_id = 1;
+        let path = get_table_path(table_id, false);
+        let _ = fs::remove_file(&path);
+
+        let mut table = Table::create(table_id, schema.clone(), tnx_id, false).expect("Failed to create table");
+        for i in 0..3 {
+            let name = DataItem::Chars { len: 32, value: make_chars(&format!("row{}", i), 32) };
+            table.insert_row(vec![DataItem::Integer(i), name], tnx_id).unwrap();
+        }
+        let rows_before = table.get_all_rows().unwrap().collect::<RsqlResult<Vec<_>>>().unwrap();
+        table.cacheable = false;
+        drop(table);
+
+        // rewrite the header in the version 1 layout, which has no row count
+        {
+            let sm = StorageManager::new(path.to_str().unwrap()).unwrap();
+            let mut sm = sm.write().unwrap();
+            let mut page = sm.read_page(0).unwrap();
+            page.data[4..8].copy_from_slice(&1u32.to_le_bytes());
+            page.data.copy_within(ROW_COUNT_OFFSET + 8.., ROW_COUNT_OFFSET);
+            sm.write_page(&page, 0).unwrap();
+        }
+
+        let mut table = Table::from(table_id, schema.clone(), false).expect("Failed to open v1 table");
+        assert_eq!(table.row_count(), 3);
+        assert_eq!(table.get_all_rows().unwrap().collect::<RsqlResult<Vec<_>>>().unwrap(), rows_before);
+        table.insert_row(vec![DataItem::Integer(3), DataItem::Chars { len: 32, value: make_chars("row3", 32) }], tnx_id).unwrap();
+        table.cacheable = false;
+        drop(table);
+
+        // the stored version is bumped, the next open reads the new layout as is
+        let header = StorageManager::new(path.to_str().unwrap()).unwrap().read().unwrap().read_page(0).unwrap();
+        assert_eq!(u32::from_le_bytes(header.data[4..8].try_into().unwrap()), HEADER_VERSION);
+        let table = Table::from(table_id, schema, false).expect("Failed to open migrated table");
+        assert_eq!(table.row_count(), 4);
+        assert_eq!(table.get_all_rows().unwrap().count(), 4);
+        drop(table);
+        Table::evict_cached(table_id);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_open_table_cache() {
+        let test_conn = TestConn::setup(&["cached_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE cached_table (id INTEGER PRIMARY KEY, score INTEGER)", conn).unwrap();
+        execute("INSERT INTO cached_table VALUES (1, 10)", conn).unwrap();
+        let tnx_id = TnxManager::global().begin_transaction(conn);
+        let table_id = SysCatalog::global().get_table_id(tnx_id, "cached_table").unwrap().unwrap();
+        TnxManager::global().end_transaction(conn);
+
+        // repeated selects parse the header once
+        Table::evict_cached(table_id);
+        let parses = Table::header_parse_count(table_id);
+        for _ in 0..3 {
+            let res = execute("SELECT score FROM cached_table WHERE id = 1", conn).unwrap();
+            assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(10)]]);
+        }
+        assert_eq!(Table::header_parse_count(table_id), parses + 1);
+
+        // a schema change evicts the cached table
+        execute("CREATE INDEX cached_table_score ON cached_table (score)", conn).unwrap();
+        let res = execute("SELECT id FROM cached_table WHERE score = 10", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(1)]]);
+        assert_eq!(Table::header_parse_count(table_id), parses + 2);
+
+        // a rolled back write leaves no stale header behind
+        execute("BEGIN", conn).unwrap();
+        execute("INSERT INTO cached_table VALUES (2, 20)", conn).unwrap();
+        execute("ROLLBACK", conn).unwrap();
+        execute("INSERT INTO cached_table VALUES (3, 30)", conn).unwrap();
+        let res = execute("SELECT id FROM cached_table", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![vec![DataItem::Integer(1)], vec![DataItem::Integer(3)]]);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_count_star_row_counter() {
+        let test_conn = TestConn::setup(&["count_table"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE count_table (id INTEGER PRIMARY KEY, name VARCHAR(16))", conn).unwrap();
+        let count = |conn| {
+            let res = execute("SELECT COUNT(*) FROM count_table", conn).unwrap();
+            let (_, rows) = query_rows(&res[0]);
+            let res = execute("SELECT * FROM count_table", conn).unwrap();
+            let scanned = query_rows(&res[0]).1.len() as i64;
+            assert_eq!(rows, vec![vec![DataItem::Integer(scanned)]]);
+            scanned
+        };
+        assert_eq!(count(conn), 0);
+
+        for i in 0..10 {
+            execute(&format!("INSERT INTO count_table VALUES ({}, 'row{}')", i, i), conn).unwrap();
+        }
+        execute("DELETE FROM count_table WHERE id < 3", conn).unwrap();
+        execute("UPDATE count_table SET name = 'updated' WHERE id = 5", conn).unwrap();
+        assert_eq!(count(conn), 7);
+
+        // the counter changes of a rolled back transaction are undone with its rows
+        execute("BEGIN", conn).unwrap();
+        execute("INSERT INTO count_table VALUES (100, 'gone')", conn).unwrap();
+        execute("DELETE FROM count_table WHERE id = 9", conn).unwrap();
+        execute("INSERT INTO count_table VALUES (101, 'gone')", conn).unwrap();
+        assert_eq!(count(conn), 8);
+        execute("ROLLBACK", conn).unwrap();
+        assert_eq!(count(conn), 7);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_composite_primary_key() {
+        let test_conn = TestConn::setup(&["enrollment"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE enrollment (student INTEGER, course CHAR(8), grade FLOAT, PRIMARY KEY (course, student))", conn).unwrap();
+        execute("INSERT INTO enrollment VALUES (2, 'math', 3.0), (1, 'math', 2.0), (1, 'art', 4.0), (-1, 'art', 1.0)", conn).unwrap();
+        // only the whole key is unique
+        assert!(execute("INSERT INTO enrollment VALUES (1, 'math', 1.0)", conn).is_err());
+
+        let tnx_id = TnxManager::global().begin_transaction(conn);
+        let table_id = SysCatalog::global().get_table_id(tnx_id, "enrollment").unwrap().unwrap();
+        let schema = SysCatalog::global().get_table_schema(tnx_id, table_id).unwrap();
+        assert_eq!(schema.get_primary_key(), ["course".to_string(), "student".to_string()]);
+        let table = Table::from(table_id, schema.clone(), false).unwrap();
+        let course = |name: &str| DataItem::Chars { len: 8, value: name.to_string() };
+        let key = schema.pk_key(&[DataItem::Integer(1), course("math"), DataItem::NullFloat]).unwrap();
+        let row = table.get_row_by_pk(&key).unwrap().unwrap();
+        assert_eq!(row[2], DataItem::Float(2.0));
+        // rows come in key order, course first
+        let keys: Vec<(DataItem, DataItem)> = table.get_all_rows().unwrap()
+            .map(|row| row.map(|row| (row[1].clone(), row[0].clone())))
+            .collect::<RsqlResult<_>>()
+            .unwrap();
+        assert_eq!(keys, vec![
+            (course("art"), DataItem::Integer(-1)),
+            (course("art"), DataItem::Integer(1)),
+            (course("math"), DataItem::Integer(1)),
+            (course("math"), DataItem::Integer(2)),
+        ]);
+        drop(table);
+        TnxManager::global().end_transaction(conn);
+
+        execute("UPDATE enrollment SET student = 3 WHERE student = 2", conn).unwrap();
+        execute("DELETE FROM enrollment WHERE course = 'art' AND student = 1", conn).unwrap();
+        let res = execute("SELECT student, grade FROM enrollment", conn).unwrap();
+        assert_eq!(query_rows(&res[0]).1, vec![
+            vec![DataItem::Integer(-1), DataItem::Float(1.0)],
+            vec![DataItem::Integer(1), DataItem::Float(2.0)],
+            vec![DataItem::Integer(3), DataItem::Float(3.0)],
+        ]);
+        assert!(execute("CREATE TABLE bad_key (a INTEGER, PRIMARY KEY (a, missing))", conn).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_nocase_collation() {
+        let test_conn = TestConn::setup(&["tags"]);
+        let conn = test_conn.id;
+        execute("CREATE TABLE tags (id INTEGER PRIMARY KEY, name CHAR(16) COLLATE NOCASE UNIQUE, code VARCHAR(4))", conn).unwrap();
+        assert!(execute("CREATE TABLE bad (id INTEGER PRIMARY KEY, n INTEGER COLLATE NOCASE)", conn).is_err());
+        execute("INSERT INTO tags VALUES (1, 'abc', 'x'), (2, 'Bcd', 'Y')", conn).unwrap();
+        // the unique index sees 'abc' and 'ABC' as the same value
+        let err = execute("INSERT INTO tags VALUES (3, 'ABC', 'z')", conn).unwrap_err();
+        assert!(err.to_string().contains("Unique constraint violation on column name"), "{}", err);
+        // changing only the case of its own value is no conflict
+        execute("UPDATE tags SET name = 'ABC' WHERE id = 1", conn).unwrap();
+        execute("INSERT INTO tags VALUES (3, 'aaa', 'a')", conn).unwrap();
+
+        let tnx_id = TnxManager::global().begin_transaction(conn);
+        let table_id = SysCatalog::global().get_table_id(tnx_id, "tags").unwrap().unwrap();
+        let schema = SysCatalog::global().get_table_schema(tnx_id, table_id).unwrap();
+        TnxManager::global().end_transaction(conn);
+        assert_eq!(schema.get_collation("name"), Collation::NoCase);
+        assert_eq!(schema.get_collation("code"), Collation::Binary);
+
+        let column = |sql: &str| {
+            let (_, rows) = query_rows(&execute(sql, conn).unwrap()[0]);
+            rows.iter().map(|row| match &row[0] {
+                DataItem::VarChar { value, .. } | DataItem::Chars { value, .. } => value.clone(),
+                other => panic!("not a string: {:?}", other),
+            }).collect::<Vec<_>>()
+        };
+        // NOCASE sorts 'Bcd' after 'ABC', the binary column keeps upper case first
+        assert_eq!(column("SELECT name FROM tags ORDER BY name"), ["aaa", "ABC", "Bcd"]);
+        assert_eq!(column("SELECT code FROM tags ORDER BY code"), ["Y", "a", "x"]);
+        assert_eq!(column("SELECT name FROM tags WHERE name = 'bcd'"), ["Bcd"]);
+        execute("DROP TABLE tags", conn).unwrap();
+    }
 }
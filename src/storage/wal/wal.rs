@@ -4,11 +4,11 @@ use std::fs;
 use std::io::Seek;
 use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, OnceLock, atomic::{AtomicU64, Ordering}};
+use std::sync::{Arc, Mutex, OnceLock, atomic::{AtomicBool, AtomicU64, Ordering}};
 
 use tracing::{warn, info};
 
-use crate::config::{DB_DIR, MAX_WAL_SIZE};
+use crate::config::{self, Config, MAX_WAL_SIZE_CEIL, MIN_CHECKPOINT_RECLAIM_RATIO, WAL_BUFFER_SIZE, WAL_OPEN_MAX_RETRY, WAL_OPEN_RETRY_MS};
 use crate::common::{RsqlError, RsqlResult};
 use crate::utils;
 
@@ -22,25 +22,184 @@ fn check_recovered() {
 }
 
 static WAL_INSTANCE: OnceLock<Arc<WAL>> = OnceLock::new();
+// held while the WAL is opened, so concurrent first uses open the file once
+static WAL_OPEN_LOCK: Mutex<()> = Mutex::new(());
+
+#[cfg(test)]
+thread_local! {
+    // flushes issued by the current thread, tests running in parallel don't disturb it
+    static THREAD_FLUSHES: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    // writes to the log file issued by the current thread
+    static THREAD_WRITES: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    // entries appended by the current thread
+    static THREAD_ENTRIES: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
 const HEADER_MAGIC: u32 = 0x4c515352; // 'RSQL' in little endian hex
 
+/// Statistics of a checkpoint, counted over the whole log file
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CheckpointStats {
+    pub entries_before: u64,
+    pub entries_after: u64, // including the checkpoint entry itself
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl CheckpointStats {
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+    /// Fraction of the log removed by the checkpoint
+    pub fn reclaim_ratio(&self) -> f64 {
+        if self.bytes_before == 0 {
+            return 0.0;
+        }
+        self.bytes_reclaimed() as f64 / self.bytes_before as f64
+    }
+}
+
+/// One log entry as shown by DEBUG WAL, without its page data
+#[derive(Debug, Clone, PartialEq)]
+pub struct WALEntrySummary {
+    pub entry_type: &'static str,
+    pub tnx_id: Option<u64>,
+    pub table_id: Option<u64>,
+    pub page_id: Option<u64>,
+    pub detail: String,
+}
+
+impl From<&WALEntry> for WALEntrySummary {
+    fn from(entry: &WALEntry) -> Self {
+        let summary = |entry_type, tnx_id, location: Option<(u64, u64)>, detail| WALEntrySummary {
+            entry_type,
+            tnx_id,
+            table_id: location.map(|(table_id, _)| table_id),
+            page_id: location.map(|(_, page_id)| page_id),
+            detail,
+        };
+        match entry {
+            WALEntry::UpdatePage { tnx_id, table_id, page_id, offset, len, .. } => {
+                summary("UpdatePage", Some(*tnx_id), Some((*table_id, *page_id)), format!("offset {} len {}", offset, len))
+            },
+            WALEntry::NewPage { tnx_id, table_id, page_id, data } => {
+                summary("NewPage", Some(*tnx_id), Some((*table_id, *page_id)), format!("{} bytes", data.len()))
+            },
+            WALEntry::DeletePage { tnx_id, table_id, page_id, old_data } => {
+                summary("DeletePage", Some(*tnx_id), Some((*table_id, *page_id)), format!("{} bytes", old_data.len()))
+            },
+            WALEntry::OpenTnx { tnx_id } => summary("OpenTnx", Some(*tnx_id), None, String::new()),
+            WALEntry::CommitTnx { tnx_id } => summary("CommitTnx", Some(*tnx_id), None, String::new()),
+            WALEntry::RollbackTnx { tnx_id } => summary("RollbackTnx", Some(*tnx_id), None, String::new()),
+            WALEntry::Checkpoint { active_tnx_ids } => {
+                summary("Checkpoint", None, None, format!("active {:?}", active_tnx_ids))
+            },
+        }
+    }
+}
+
+/// The log file with the entry bytes appended but not yet written to it.
+/// Buffered entries are not durable, they reach the file on flush or when the buffer is full
+struct LogFile {
+    file: fs::File,
+    buffer: Vec<u8>,
+}
+
+impl LogFile {
+    fn new(file: fs::File) -> Self {
+        LogFile { file, buffer: Vec::with_capacity(WAL_BUFFER_SIZE) }
+    }
+    fn append(&mut self, bytes: &[u8]) -> RsqlResult<()> {
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() >= WAL_BUFFER_SIZE {
+            self.write_buffered()?;
+        }
+        Ok(())
+    }
+    /// Write the buffered entries to the file in one write, without syncing it
+    fn write_buffered(&mut self) -> RsqlResult<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.file.write_all(&self.buffer)?;
+        self.buffer.clear();
+        #[cfg(test)]
+        THREAD_WRITES.with(|writes| writes.set(writes.get() + 1));
+        Ok(())
+    }
+    /// The whole log including the buffered entries
+    fn read_all(&mut self) -> RsqlResult<Vec<u8>> {
+        self.write_buffered()?;
+        let mut buf = Vec::new();
+        self.file.seek(std::io::SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut buf)?;
+        self.file.seek(std::io::SeekFrom::End(0))?; // Reset cursor
+        Ok(buf)
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_buffered() {
+            warn!("Failed to write buffered WAL entries: {}", e);
+        }
+    }
+}
+
 /// Write-Ahead Log (WAL) structure
 /// A thread safe structure to handle concurrent writes to the log file.
 /// Singleton pattern is used to ensure only one instance of WAL exists.
 /// The Wal log file structure:
 /// [HEADER_MAGIC (4 bytes)][WALEntry 1(not fixed size)][WALEntry 2]...
 pub struct WAL {
-    log_file: Arc<Mutex<fs::File>>,
+    log_file: Arc<Mutex<LogFile>>,
     active_tnx_ids: Arc<Mutex<Vec<u64>>>,
     length: AtomicU64,
     log_path: PathBuf,
+    // log size that triggers a checkpoint, tuned by how much checkpoints reclaim
+    checkpoint_threshold: AtomicU64,
+    // the configured max WAL size, the threshold never drops below it
+    min_checkpoint_threshold: u64,
+    last_checkpoint: Mutex<Option<CheckpointStats>>,
+    // entries were appended since the last flush
+    unflushed: AtomicBool,
 }
 
 impl WAL {
-    pub fn global() -> Arc<Self> {
-        WAL_INSTANCE.get_or_init(|| {
-            Arc::new(Self::new().expect("Failed to init WAL"))
-        }).clone()
+    /// Whether the WAL of this process has been recovered, i.e. the database is open
+    pub fn has_recovered() -> bool {
+        HAS_RECOVERED.get().is_some()
+    }
+    /// The WAL of this process, opened on first use. An open that keeps failing
+    /// returns the error instead of leaving the WAL uninitialized for good
+    pub fn global() -> RsqlResult<Arc<Self>> {
+        if let Some(wal) = WAL_INSTANCE.get() {
+            return Ok(wal.clone());
+        }
+        let _guard = WAL_OPEN_LOCK.lock().unwrap();
+        if let Some(wal) = WAL_INSTANCE.get() {
+            return Ok(wal.clone());
+        }
+        let wal = Arc::new(Self::open_with_retry(Self::new)?);
+        Ok(WAL_INSTANCE.get_or_init(|| wal).clone())
+    }
+    /// Run `open` until it succeeds, at most `WAL_OPEN_MAX_RETRY` more times after a failure
+    fn open_with_retry(mut open: impl FnMut() -> RsqlResult<Self>) -> RsqlResult<Self> {
+        let mut attempt = 0;
+        loop {
+            match open() {
+                Ok(wal) => return Ok(wal),
+                Err(err) if attempt < WAL_OPEN_MAX_RETRY => {
+                    attempt += 1;
+                    warn!("Failed to open WAL: {}, retrying ({}/{})", err, attempt, WAL_OPEN_MAX_RETRY);
+                    std::thread::sleep(std::time::Duration::from_millis(WAL_OPEN_RETRY_MS));
+                },
+                Err(err) => return Err(RsqlError::WalError(format!("Cannot open WAL: {}", err))),
+            }
+        }
+    }
+    /// The WAL instance if it has been initialized, without initializing it
+    pub fn try_global() -> Option<Arc<Self>> {
+        WAL_INSTANCE.get().cloned()
     }
     fn init_header(log_file: &mut fs::File) -> RsqlResult<()> {
         let header = HEADER_MAGIC.to_le_bytes();
@@ -57,13 +216,19 @@ impl WAL {
         Ok(())
     }
     fn new() -> RsqlResult<Self> {
+        Self::with_config(config::get())
+    }
+    fn with_config(config: &Config) -> RsqlResult<Self> {
         // initialize log file
         let log_path = if cfg!(test) {
             // for multi-threaded tests
             utils::test_dir("wal".to_string())
         } else {
-            std::path::Path::new(DB_DIR).join("wal.log")
+            config.wal_path()
         };
+        Self::open_at(log_path, config)
+    }
+    fn open_at(log_path: PathBuf, config: &Config) -> RsqlResult<Self> {
         if !log_path.exists() {
             // not exists, create new file with header
             if let Some(parent) = log_path.parent() {
@@ -93,9 +258,13 @@ impl WAL {
         let length = log_file.metadata()?.len();
         Ok(WAL {
             active_tnx_ids: Arc::new(Mutex::new(Vec::new())),
-            log_file: Arc::new(Mutex::new(log_file)),
+            log_file: Arc::new(Mutex::new(LogFile::new(log_file))),
             length: AtomicU64::new(length),
             log_path,
+            checkpoint_threshold: AtomicU64::new(config.max_wal_size),
+            min_checkpoint_threshold: config.max_wal_size,
+            last_checkpoint: Mutex::new(None),
+            unflushed: AtomicBool::new(false),
         })
     }
     fn align_page_num(
@@ -141,14 +310,7 @@ impl WAL {
         max_page_idx: &mut impl FnMut(u64) -> RsqlResult<Option<u64>>,
     ) -> RsqlResult<u64> {
         info!("Starting WAL recovery");
-        let buf = {
-            let mut file = wal.log_file.lock().unwrap();
-            file.seek(std::io::SeekFrom::Start(0))?;
-            let mut buf = Vec::new();
-            file.read_to_end(&mut buf)?;
-            file.seek(std::io::SeekFrom::End(0))?;
-            buf
-        };
+        let buf = wal.log_file.lock().unwrap().read_all()?;
 
         if buf.len() < 4 {
             panic!("WAL recovery: log file too short to contain header");
@@ -282,32 +444,30 @@ impl WAL {
         trunc_page: &mut impl FnMut(u64) -> RsqlResult<()>,
         max_page_idx: &mut impl FnMut(u64) -> RsqlResult<Option<u64>>,
     ) -> RsqlResult<u64> {
-        Self::recovery_with_instance(WAL::global(), write_page, update_page, append_page, trunc_page, max_page_idx)
+        Self::recovery_with_instance(WAL::global()?, write_page, update_page, append_page, trunc_page, max_page_idx)
     }
 
     pub fn checkpoint(
         &self,
         flush_page: &impl Fn() -> RsqlResult<()>, 
-    ) -> RsqlResult<()> {
+    ) -> RsqlResult<CheckpointStats> {
         check_recovered();
         info!("Starting WAL checkpoint");
-        // 1. flush all dirty pages to storage
+        // 1. flush all dirty pages to storage, after the log covering them
+        self.flush_if_pending()?;
         flush_page()?;
         
         // Hold both locks for the entire duration to ensure atomicity and consistency
         let active_tnx_ids = self.active_tnx_ids.lock().unwrap();
         let mut log_file = self.log_file.lock().unwrap();
 
-        let old_bytes = {
-            let mut buf = Vec::new();
-            log_file.seek(std::io::SeekFrom::Start(0))?;
-            log_file.read_to_end(&mut buf)?;
-            buf
-        };
+        let old_bytes = log_file.read_all()?;
 
         // 2. construct simplified wal log
         let mut new_entrys = Vec::new();
+        let mut entries_before = 0;
         for entry in WALEntry::from_bytes(&old_bytes[4..]) {
+            entries_before += 1;
             match entry {
                 WALEntry::Checkpoint {..} => continue,
                 WALEntry::CommitTnx { tnx_id} 
@@ -337,6 +497,8 @@ impl WAL {
         // 2.5 append checkpoint entry
         new_entrys.push(WALEntry::Checkpoint { active_tnx_ids: active_tnx_ids.clone() });
 
+        let entries_after = new_entrys.len() as u64;
+
         // 3. write new wal log
         let tmp_path = self.log_path.with_extension("log.tmp");
         {
@@ -353,35 +515,103 @@ impl WAL {
         // THIS MUST BE ATOMIC OPERATION
         fs::rename(&tmp_path, &self.log_path)?;
         // 5. update self handle
-        *log_file = fs::OpenOptions::new()
+        log_file.file = fs::OpenOptions::new()
             .read(true)
             .append(true)
             .open(&self.log_path)?;
-        log_file.seek(std::io::SeekFrom::End(0))?; 
-        self.length.store(log_file.metadata()?.len(), Ordering::SeqCst); 
+        log_file.file.seek(std::io::SeekFrom::End(0))?; 
+        let bytes_after = log_file.file.metadata()?.len();
+        self.length.store(bytes_after, Ordering::SeqCst); 
+
+        // 6. record stats and tune the threshold
+        let stats = CheckpointStats {
+            entries_before,
+            entries_after,
+            bytes_before: old_bytes.len() as u64,
+            bytes_after,
+        };
+        let threshold = self.tune_checkpoint_threshold(&stats);
+        *self.last_checkpoint.lock().unwrap() = Some(stats);
         
-        info!("WAL checkpoint completed");
-        Ok(())
+        info!(
+            "WAL checkpoint completed: entries {} -> {}, bytes {} -> {} ({} reclaimed), next threshold {} bytes",
+            stats.entries_before, stats.entries_after, stats.bytes_before, stats.bytes_after,
+            stats.bytes_reclaimed(), threshold,
+        );
+        Ok(stats)
+    }
+    /// Adjust the checkpoint threshold after a checkpoint.
+    /// A checkpoint reclaiming little means most of the log belongs to active transactions,
+    /// so checkpointing again soon would be wasted work: double the threshold up to MAX_WAL_SIZE_CEIL.
+    /// Otherwise halve it back down towards the configured max WAL size.
+    fn tune_checkpoint_threshold(&self, stats: &CheckpointStats) -> u64 {
+        let threshold = self.checkpoint_threshold.load(Ordering::SeqCst);
+        let new_threshold = if stats.reclaim_ratio() < MIN_CHECKPOINT_RECLAIM_RATIO {
+            (threshold * 2).min(MAX_WAL_SIZE_CEIL.max(self.min_checkpoint_threshold))
+        } else {
+            (threshold / 2).max(self.min_checkpoint_threshold)
+        };
+        self.checkpoint_threshold.store(new_threshold, Ordering::SeqCst);
+        new_threshold
+    }
+    /// Stats of the last checkpoint in this process, if any
+    pub fn last_checkpoint_stats(&self) -> Option<CheckpointStats> {
+        *self.last_checkpoint.lock().unwrap()
+    }
+    /// Current log size that triggers a checkpoint
+    pub fn checkpoint_threshold(&self) -> u64 {
+        self.checkpoint_threshold.load(Ordering::SeqCst)
     }
 
     fn append_entry(&self, entry: &WALEntry) -> RsqlResult<()> {
         check_recovered();
         let entry_bytes = entry.to_bytes();
         let mut log_file = self.log_file.lock().unwrap();
-        // 1. write entry bytes
-        log_file.write_all(&entry_bytes)?;
+        // 1. buffer entry bytes, written out on flush or when the buffer is full
+        log_file.append(&entry_bytes)?;
         // 2. update length
         self.length.fetch_add(entry_bytes.len() as u64, Ordering::SeqCst);
+        self.unflushed.store(true, Ordering::SeqCst);
+        #[cfg(test)]
+        THREAD_ENTRIES.with(|entries| entries.set(entries.get() + 1));
         Ok(())
     }
 
     pub fn flush(&self) -> RsqlResult<()> {
         check_recovered();
         let mut log_file = self.log_file.lock().unwrap();
-        log_file.flush()?;
-        log_file.sync_all()?;
+        self.unflushed.store(false, Ordering::SeqCst);
+        log_file.write_buffered()?;
+        log_file.file.flush()?;
+        log_file.file.sync_all()?;
+        #[cfg(test)]
+        THREAD_FLUSHES.with(|flushes| flushes.set(flushes.get() + 1));
+        Ok(())
+    }
+    /// Flush only if entries were appended since the last flush,
+    /// used before dirty pages are written to disk
+    pub fn flush_if_pending(&self) -> RsqlResult<()> {
+        if self.unflushed.load(Ordering::SeqCst) {
+            self.flush()?;
+        }
         Ok(())
     }
+    /// Number of flushes issued by the calling thread
+    #[cfg(test)]
+    pub fn thread_flush_count() -> u64 {
+        THREAD_FLUSHES.with(|flushes| flushes.get())
+    }
+    /// Number of writes to the log file issued by the calling thread
+    #[cfg(test)]
+    pub fn thread_write_count() -> u64 {
+        THREAD_WRITES.with(|writes| writes.get())
+    }
+
+    /// Number of log entries appended by the calling thread
+    #[cfg(test)]
+    pub fn thread_entry_count() -> u64 {
+        THREAD_ENTRIES.with(|entries| entries.get())
+    }
 
     pub fn update_page(
         &self,
@@ -441,6 +671,17 @@ impl WAL {
         self.append_entry(&entry)
     }
 
+    /// Summaries of all entries in the log, in log order
+    pub fn entry_summaries(&self) -> RsqlResult<Vec<WALEntrySummary>> {
+        let buf = self.log_file.lock().unwrap().read_all()?;
+        Ok(WALEntry::from_bytes(&buf[4..]).map(|entry| WALEntrySummary::from(&entry)).collect())
+    }
+
+    /// Transactions opened in the log that have not committed or rolled back yet
+    pub fn active_tnx_ids(&self) -> Vec<u64> {
+        self.active_tnx_ids.lock().unwrap().clone()
+    }
+
     pub fn open_tnx(&self, tnx_id: u64) -> RsqlResult<()> {
         check_recovered();
         let entry = WALEntry::OpenTnx {
@@ -475,12 +716,7 @@ impl WAL {
         check_recovered();
         // 1. find all entries related to this transaction
         let undo_entries = {
-            let mut file = self.log_file.lock().unwrap();
-            let mut buf = Vec::new();
-            file.seek(std::io::SeekFrom::Start(0))?;
-            file.read_to_end(&mut buf)?;
-            file.seek(std::io::SeekFrom::End(0))?; // Reset cursor
-            
+            let buf = self.log_file.lock().unwrap().read_all()?;
             WALEntry::from_bytes(&buf[4..])
                 .filter(|e| match e {
                     WALEntry::UpdatePage { tnx_id: eid, .. }
@@ -525,7 +761,7 @@ impl WAL {
     pub fn need_checkpoint(&self) -> bool {
         check_recovered();
         let length = self.length.load(Ordering::SeqCst);
-        length > MAX_WAL_SIZE
+        length > self.checkpoint_threshold()
     }
 }
 
@@ -533,7 +769,7 @@ impl WAL {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::DB_DIR;
+    use crate::config::{DB_DIR, MAX_WAL_SIZE};
     use std::fs;
     use std::path::Path;
 
@@ -592,7 +828,7 @@ mod tests {
         // mark recovered so test can call WAL methods
         HAS_RECOVERED.get_or_init(|| ());
 
-        let wal = WAL::global();
+        let wal = WAL::global().unwrap();
         wal.open_tnx(100).unwrap();
         wal.update_page(100, 1, 1, 0, &[1], &[2]).unwrap();
         
@@ -600,7 +836,7 @@ mod tests {
         wal.rollback_tnx(100, 
             &mut |_, _, _| Ok(()),
             &mut |_, _, _, _, _| {
-                let wal2 = WAL::global();
+                let wal2 = WAL::global().unwrap();
                 // different tnx id to avoid any other logic issues
                 wal2.open_tnx(101).unwrap();
                 wal2.update_page(101, 1, 1, 0, &[3], &[4]).unwrap();
@@ -632,12 +868,13 @@ mod tests {
         // 3. Checkpoint
         let flushed = Arc::new(Mutex::new(false));
         let flushed_clone = flushed.clone();
-        wal.checkpoint(&|| {
+        let stats = wal.checkpoint(&|| {
             *flushed_clone.lock().unwrap() = true;
             Ok(())
         }).unwrap();
 
         assert!(*flushed.lock().unwrap());
+        assert_eq!(wal.last_checkpoint_stats(), Some(stats));
 
         // 4. Verify log content - should only contain t2 and Checkpoint
         let bytes = fs::read(&wal_path).unwrap();
@@ -680,4 +917,120 @@ mod tests {
         // and its update should be undone (reverting to old data [0u8])
         assert!(updated.contains(&vec![0u8]));
     }
+
+    #[test]
+    fn test_wal_checkpoint_stats() {
+        let _ = HAS_RECOVERED.get_or_init(|| ());
+
+        let wal = Arc::new(WAL::new().expect("Failed to init WAL"));
+        assert_eq!(wal.last_checkpoint_stats(), None);
+
+        // committed t1: 3 entries, dropped by the checkpoint
+        wal.open_tnx(1).unwrap();
+        wal.update_page(1, 10, 0, 0, &[0u8; 64], &[1u8; 64]).unwrap();
+        wal.commit_tnx(1).unwrap();
+        // open t2: 2 entries, kept
+        wal.open_tnx(2).unwrap();
+        wal.update_page(2, 10, 0, 64, &[0u8], &[2u8]).unwrap();
+
+        let kept_bytes: u64 = [
+            WALEntry::OpenTnx { tnx_id: 2 },
+            WALEntry::UpdatePage { tnx_id: 2, table_id: 10, page_id: 0, offset: 64, len: 1, old_data: vec![0u8], new_data: vec![2u8] },
+            WALEntry::Checkpoint { active_tnx_ids: vec![2] },
+        ].iter().map(|e| e.to_bytes().len() as u64).sum();
+        // the open t2 entries are still buffered, write them out to measure the file
+        wal.flush().unwrap();
+        let bytes_before = fs::metadata(&wal.log_path).unwrap().len();
+
+        let stats = wal.checkpoint(&|| Ok(())).unwrap();
+        assert_eq!(stats.entries_before, 5);
+        assert_eq!(stats.entries_after, 3);
+        assert_eq!(stats.bytes_before, bytes_before);
+        assert_eq!(stats.bytes_after, 4 + kept_bytes);
+        assert_eq!(stats.bytes_reclaimed(), bytes_before - 4 - kept_bytes);
+        assert_eq!(wal.last_checkpoint_stats(), Some(stats));
+        // most of the log was reclaimed, so the threshold stays at its floor
+        assert_eq!(wal.checkpoint_threshold(), MAX_WAL_SIZE);
+
+        // nothing left to reclaim but the active t2, the threshold is raised
+        let stats = wal.checkpoint(&|| Ok(())).unwrap();
+        assert_eq!(stats.entries_before, 3);
+        assert_eq!(stats.entries_after, 3);
+        assert_eq!(stats.bytes_reclaimed(), 0);
+        assert_eq!(wal.checkpoint_threshold(), 2 * MAX_WAL_SIZE);
+    }
+
+    #[test]
+    fn test_wal_follows_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config = Config::new()
+            .with_db_dir(tmp.path())
+            .with_max_wal_size(64 * 1024);
+        assert_eq!(config.wal_path(), tmp.path().join("wal.log"));
+        assert_eq!(config.tables_dir(), tmp.path().join("tables"));
+        assert_eq!(Config::default().db_dir, Path::new(DB_DIR));
+
+        let wal = WAL::with_config(&config).expect("Failed to init WAL");
+        assert_eq!(wal.checkpoint_threshold(), 64 * 1024);
+        // a checkpoint reclaiming most of the log keeps the threshold at the configured floor
+        let stats = CheckpointStats { entries_before: 10, entries_after: 1, bytes_before: 1000, bytes_after: 100 };
+        assert_eq!(wal.tune_checkpoint_threshold(&stats), 64 * 1024);
+    }
+
+    #[test]
+    fn test_wal_open_failure_is_an_error() {
+        let tmp = tempfile::tempdir().unwrap();
+        // the log directory is a regular file, so the log can never be created
+        let blocker = tmp.path().join("not_a_dir");
+        fs::write(&blocker, b"").unwrap();
+        let config = Config::new().with_db_dir(tmp.path());
+        let mut attempts = 0;
+        let result = WAL::open_with_retry(|| {
+            attempts += 1;
+            WAL::open_at(blocker.join("wal.log"), &config)
+        });
+        assert!(matches!(result, Err(RsqlError::WalError(ref msg)) if msg.starts_with("Cannot open WAL")));
+        assert_eq!(attempts, WAL_OPEN_MAX_RETRY + 1);
+
+        // a failure that clears up is retried into an open WAL
+        let mut attempts = 0;
+        let wal = WAL::open_with_retry(|| {
+            attempts += 1;
+            if attempts == 1 {
+                return WAL::open_at(blocker.join("wal.log"), &config);
+            }
+            WAL::open_at(tmp.path().join("wal.log"), &config)
+        }).unwrap();
+        assert_eq!(attempts, 2);
+        assert_eq!(wal.log_path, tmp.path().join("wal.log"));
+    }
+
+    #[test]
+    fn test_wal_buffers_entries_until_flush() {
+        let _ = HAS_RECOVERED.get_or_init(|| ());
+
+        let wal = Arc::new(WAL::new().expect("Failed to init WAL"));
+        let len_before = fs::metadata(&wal.log_path).unwrap().len();
+        let writes_before = WAL::thread_write_count();
+        wal.open_tnx(7).unwrap();
+        for i in 0..20 {
+            wal.update_page(7, 10, 0, i, &[0u8], &[1u8]).unwrap();
+        }
+        // nothing reached the file yet, the entries are not durable
+        assert_eq!(WAL::thread_write_count(), writes_before);
+        assert_eq!(fs::metadata(&wal.log_path).unwrap().len(), len_before);
+
+        wal.commit_tnx(7).unwrap();
+        assert_eq!(WAL::thread_write_count(), writes_before + 1);
+        let bytes = fs::read(&wal.log_path).unwrap();
+        let entries: Vec<_> = WALEntry::from_bytes(&bytes[len_before as usize..]).collect();
+        assert_eq!(entries.len(), 22);
+        assert!(matches!(entries[21], WALEntry::CommitTnx { tnx_id: 7 }));
+
+        // a full buffer is written out without waiting for the flush
+        wal.open_tnx(8).unwrap();
+        let data = vec![0u8; WAL_BUFFER_SIZE / 2];
+        wal.update_page(8, 10, 0, 0, &data, &data).unwrap();
+        assert_eq!(WAL::thread_write_count(), writes_before + 2);
+    }
 }
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
-use std::sync::{Mutex, Condvar, OnceLock};
-use std::sync::atomic::{AtomicU64};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, Condvar, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use crate::common::{RsqlResult, RsqlError};
 use crate::config::LOCK_TIMEOUT_MS;
@@ -8,6 +9,41 @@ use crate::config::LOCK_TIMEOUT_MS;
 const TIME_OUT: std::time::Duration = std::time::Duration::from_millis(LOCK_TIMEOUT_MS);
 static TNX_MANAGER: OnceLock<TnxManager> = OnceLock::new();
 
+thread_local! {
+    // cancel flag of the transaction whose statement runs on this thread
+    static CURRENT_CANCEL_FLAG: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Makes a KILL of `tnx_id` visible to `check_cancelled` on this thread until dropped
+pub struct CancelScope {
+    prev: Option<Arc<AtomicBool>>,
+}
+
+impl CancelScope {
+    pub fn enter(tnx_id: u64) -> Self {
+        let flag = TnxManager::global().cancel_flags.lock().unwrap().get(&tnx_id).cloned();
+        let prev = CURRENT_CANCEL_FLAG.with(|current| current.replace(flag));
+        CancelScope { prev }
+    }
+}
+
+impl Drop for CancelScope {
+    fn drop(&mut self) {
+        CURRENT_CANCEL_FLAG.with(|current| *current.borrow_mut() = self.prev.take());
+    }
+}
+
+/// Fails with `Cancelled` if the transaction of the statement running on this thread was killed,
+/// long running loops call it once per row
+pub fn check_cancelled() -> RsqlResult<()> {
+    CURRENT_CANCEL_FLAG.with(|current| match &*current.borrow() {
+        Some(flag) if flag.load(Ordering::SeqCst) => {
+            Err(RsqlError::Cancelled("transaction was killed".to_string()))
+        },
+        _ => Ok(()),
+    })
+}
+
 struct TableState {
     writers: u64,
     readers: u64,
@@ -57,12 +93,17 @@ impl TableState {
 /// Transaction Manager
 /// Singleton struct
 /// Use 2pl protocol for concurrency control
-/// Use timeout and rollback for deadlock handling
+/// A transaction about to wait for a lock is checked against a wait-for graph,
+/// the youngest transaction of a cycle is aborted with a deadlock error. Lock waits still time out
 pub struct TnxManager{
     tnx_counter: AtomicU64,
     tnx_map: Mutex<HashMap<u64, u64>>, // connection_id -> tnx_id
     table_locks: Mutex<HashMap<u64, TableState>>, // table_id -> lock state
     tnx_associated_tables: Mutex<HashMap<u64, (Vec<u64>, Vec<u64>)>>, // tnx_id -> (read_table_ids, write_table_ids)
+    tnx_start_times: Mutex<HashMap<u64, u64>>, // tnx_id -> start time (unix millis)
+    cancel_flags: Mutex<HashMap<u64, Arc<AtomicBool>>>, // tnx_id -> set by KILL
+    waits_for: Mutex<HashMap<u64, Vec<u64>>>, // waiting tnx_id -> tnx_ids holding the lock it waits for
+    deadlock_victims: Mutex<HashSet<u64>>, // waiting tnx_ids chosen to break a cycle, they abort when they wake up
     lock_condvar: Condvar,
 }
 
@@ -76,6 +117,10 @@ impl TnxManager {
             tnx_map: Mutex::new(HashMap::new()),
             table_locks: Mutex::new(HashMap::new()),
             tnx_associated_tables: Mutex::new(HashMap::new()),
+            tnx_start_times: Mutex::new(HashMap::new()),
+            cancel_flags: Mutex::new(HashMap::new()),
+            waits_for: Mutex::new(HashMap::new()),
+            deadlock_victims: Mutex::new(HashSet::new()),
             lock_condvar: Condvar::new(),
         };
         if cfg!(test) {
@@ -87,7 +132,8 @@ impl TnxManager {
             TNX_MANAGER.set(manager).ok().expect("TnxManager already initialized");
         }
     }
-    fn new_tnx_id(&self) -> u64 {
+    /// A fresh transaction id, also used for work the storage runs in a transaction of its own
+    pub fn new_tnx_id(&self) -> u64 {
         self.tnx_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
     }
     /// Begin a new transaction for the given connection ID
@@ -107,6 +153,15 @@ impl TnxManager {
             let mut tnx_associated_tables = self.tnx_associated_tables.lock().unwrap();
             tnx_associated_tables.insert(tnx_id, (Vec::new(), Vec::new()));
         }
+        // record start time
+        {
+            let start = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0);
+            self.tnx_start_times.lock().unwrap().insert(tnx_id, start);
+        }
+        self.cancel_flags.lock().unwrap().insert(tnx_id, Arc::new(AtomicBool::new(false)));
         // return
         tnx_id
     }
@@ -129,30 +184,37 @@ impl TnxManager {
                 }
             }
             while !stats.entry(table_id).or_insert_with(TableState::new).try_read() {
-                let (new_stats, wait_res) = self.lock_condvar.wait_timeout(stats, TIME_OUT).unwrap();
-                stats = new_stats;
-                if wait_res.timed_out() {
-                    // timeout, release all acquired locks
+                let mut res = self.check_deadlock(tnx_id, table_id, false);
+                if res.is_ok() {
+                    let (new_stats, wait_res) = self.lock_condvar.wait_timeout(stats, TIME_OUT).unwrap();
+                    stats = new_stats;
+                    if wait_res.timed_out() {
+                        res = Err(RsqlError::LockError(format!(
+                            "Timeout acquiring read lock on table {}", table_id
+                        )));
+                    }
+                }
+                if let Err(e) = res {
+                    // release all locks acquired by this call
+                    self.stop_waiting(tnx_id);
                     for &t_id in &acquired {
                         if let Some(state) = stats.get_mut(&t_id) {
                             state.release_read();
                         }
                     }
+                    if let Some((reads, _)) = self.tnx_associated_tables.lock().unwrap().get_mut(&tnx_id) {
+                        reads.retain(|t_id| !acquired.contains(t_id));
+                    }
                     self.lock_condvar.notify_all();
-                    return Err(RsqlError::LockError(format!(
-                        "Timeout acquiring read lock on table {}", table_id
-                    )));
+                    return Err(e);
                 }
             }
+            self.stop_waiting(tnx_id);
             acquired.push(table_id);
-        }
-        // record associated tables
-        {
+            // recorded at once, so transactions waiting for this table see who holds it
             let mut tnx_associated_tables = self.tnx_associated_tables.lock().unwrap();
             let entry = tnx_associated_tables.get_mut(&tnx_id).expect("Associated tables not found for transaction");
-            for &table_id in table_ids {
-                entry.0.push(table_id);
-            }
+            entry.0.push(table_id);
         }
         Ok(())
     }
@@ -178,14 +240,20 @@ impl TnxManager {
             // need to upgrade from read lock to write lock
             if has_read {
                 while !stats.entry(table_id).or_insert_with(TableState::new).try_upgrade() {
+                    if let Err(e) = self.check_deadlock(tnx_id, table_id, true) {
+                        self.stop_waiting(tnx_id);
+                        return Err(e);
+                    }
                     let (new_stats, wait_res) = self.lock_condvar.wait_timeout(stats, TIME_OUT).unwrap();
                     stats = new_stats;
                     if wait_res.timed_out() {
+                        self.stop_waiting(tnx_id);
                         return Err(RsqlError::LockError(format!(
                             "Timeout upgrading to write lock on table {}", table_id
                         )));
                     }
                 }
+                self.stop_waiting(tnx_id);
                 // remove from associated read tables, add to write tables
                 let mut assoc = self.tnx_associated_tables.lock().unwrap();
                 if let Some((reads, writes)) = assoc.get_mut(&tnx_id) {
@@ -195,15 +263,21 @@ impl TnxManager {
             } else {
                 // need to acquire write lock directly
                 while !stats.entry(table_id).or_insert_with(TableState::new).try_write() {
+                    if let Err(e) = self.check_deadlock(tnx_id, table_id, true) {
+                        self.stop_waiting(tnx_id);
+                        return Err(e);
+                    }
                     let (new_stats, wait_res) = self.lock_condvar.wait_timeout(stats, TIME_OUT).unwrap();
                     stats = new_stats;
                     
                     if wait_res.timed_out() {
+                        self.stop_waiting(tnx_id);
                         return Err(RsqlError::LockError(format!(
                             "Timeout acquiring write lock on table {}", table_id
                         )));
                     }
                 }
+                self.stop_waiting(tnx_id);
                 
                 let mut assoc = self.tnx_associated_tables.lock().unwrap();
                 if let Some((_, writes)) = assoc.get_mut(&tnx_id) {
@@ -224,6 +298,9 @@ impl TnxManager {
             let mut tnx_associated_tables = self.tnx_associated_tables.lock().unwrap();
             tnx_associated_tables.remove(&tnx_id).expect("Associated tables not found for transaction")
         };
+        self.tnx_start_times.lock().unwrap().remove(&tnx_id);
+        self.cancel_flags.lock().unwrap().remove(&tnx_id);
+        self.stop_waiting(tnx_id);
         // release all locks
         let mut stats = self.table_locks.lock().unwrap();
         for table_id in reads {
@@ -239,10 +316,89 @@ impl TnxManager {
         // notify waiting threads that locks might be available
         self.lock_condvar.notify_all();
     }
+    /// Record that `tnx_id` is about to wait for a lock on `table_id` and look for a cycle of waits.
+    /// The youngest transaction of a cycle is aborted: an error for `tnx_id` itself,
+    /// otherwise the victim is woken up and fails in its own wait loop
+    fn check_deadlock(&self, tnx_id: u64, table_id: u64, exclusive: bool) -> RsqlResult<()> {
+        if self.deadlock_victims.lock().unwrap().remove(&tnx_id) {
+            return Err(RsqlError::Deadlock(format!(
+                "Transaction {} aborted while waiting for table {}", tnx_id, table_id
+            )));
+        }
+        // a shared lock only waits for writers, an exclusive one for every other holder
+        let holders: Vec<u64> = self.tnx_associated_tables.lock().unwrap()
+            .iter()
+            .filter(|&(&id, (reads, writes))| {
+                id != tnx_id && (writes.contains(&table_id) || (exclusive && reads.contains(&table_id)))
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        let mut waits_for = self.waits_for.lock().unwrap();
+        waits_for.insert(tnx_id, holders);
+        let Some(cycle) = find_wait_cycle(&waits_for, tnx_id) else {
+            return Ok(());
+        };
+        let victim = *cycle.iter().max().unwrap();
+        if victim == tnx_id {
+            return Err(RsqlError::Deadlock(format!(
+                "Transaction {} waiting for table {} would deadlock with {:?}", tnx_id, table_id, cycle
+            )));
+        }
+        // the victim stops waiting, so no other cycle is broken through it again
+        waits_for.remove(&victim);
+        self.deadlock_victims.lock().unwrap().insert(victim);
+        self.lock_condvar.notify_all();
+        Ok(())
+    }
+    /// Remove the waits of a transaction that got its lock, gave up or ended
+    fn stop_waiting(&self, tnx_id: u64) {
+        self.waits_for.lock().unwrap().remove(&tnx_id);
+        self.deadlock_victims.lock().unwrap().remove(&tnx_id);
+    }
     pub fn get_transaction_id(&self, connection_id: u64) -> Option<u64> {
         let tnx_map = self.tnx_map.lock().unwrap();
         tnx_map.get(&connection_id).cloned()
     }
+    /// Ask the running statement of a transaction to abort, it is rolled back when it notices.
+    /// Returns false if the transaction is not active
+    pub fn kill(&self, tnx_id: u64) -> bool {
+        match self.cancel_flags.lock().unwrap().get(&tnx_id) {
+            Some(flag) => {
+                flag.store(true, Ordering::SeqCst);
+                true
+            },
+            None => false,
+        }
+    }
+    /// List the active transactions as (tnx_id, start time in unix millis), ordered by id
+    pub fn active_transactions(&self) -> Vec<(u64, u64)> {
+        let start_times = self.tnx_start_times.lock().unwrap();
+        let mut tnxs: Vec<(u64, u64)> = start_times.iter().map(|(&id, &start)| (id, start)).collect();
+        tnxs.sort_by_key(|&(id, _)| id);
+        tnxs
+    }
+}
+
+/// Transactions of a cycle of waits through `start`, None if there is none
+fn find_wait_cycle(waits_for: &HashMap<u64, Vec<u64>>, start: u64) -> Option<Vec<u64>> {
+    fn visit(waits_for: &HashMap<u64, Vec<u64>>, start: u64, path: &mut Vec<u64>, seen: &mut HashSet<u64>) -> bool {
+        let current = *path.last().unwrap();
+        for &next in waits_for.get(&current).into_iter().flatten() {
+            if next == start {
+                return true;
+            }
+            if seen.insert(next) {
+                path.push(next);
+                if visit(waits_for, start, path, seen) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+        false
+    }
+    let mut path = vec![start];
+    visit(waits_for, start, &mut path, &mut HashSet::from([start])).then_some(path)
 }
 
 #[cfg(test)]
@@ -267,6 +423,18 @@ mod tests {
         assert_eq!(mgr.get_transaction_id(1), None);
     }
 
+    #[test]
+    fn test_active_transactions() {
+        setup();
+        let mgr = TnxManager::global();
+        let tid = mgr.begin_transaction(10);
+        let listed = mgr.active_transactions();
+        let (_, start) = listed.iter().find(|(id, _)| *id == tid).expect("transaction not listed");
+        assert!(*start > 0);
+        mgr.end_transaction(10);
+        assert!(mgr.active_transactions().iter().all(|(id, _)| *id != tid));
+    }
+
     #[test]
     fn test_read_sharing() {
         setup();
@@ -361,6 +529,37 @@ mod tests {
         assert!(!(r_a && r_b), "Both transactions acquired conflicting locks; deadlock not detected");
     }
 
+    #[test]
+    fn test_deadlock_aborts_youngest() {
+        setup();
+        let mgr = TnxManager::global();
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let start = std::time::Instant::now();
+        let handles: Vec<_> = [(4000, 4100, 4101), (4001, 4101, 4100)].into_iter().map(|(conn, first, second)| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let tid = mgr.begin_transaction(conn);
+                mgr.acquire_write_locks(tid, &[first]).unwrap();
+                barrier.wait();
+                let res = mgr.acquire_write_locks(tid, &[second]);
+                // the victim rolls back, which lets the other one through
+                mgr.end_transaction(conn);
+                (tid, res)
+            })
+        }).collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        let aborted: Vec<u64> = results.iter()
+            .filter(|(_, res)| matches!(res, Err(RsqlError::Deadlock(_))))
+            .map(|(tid, _)| *tid)
+            .collect();
+        assert_eq!(aborted.len(), 1, "{:?}", results);
+        assert_eq!(aborted[0], results.iter().map(|(tid, _)| *tid).max().unwrap());
+        assert!(results.iter().filter(|(_, res)| res.is_ok()).count() == 1);
+        // broken at once, not by the lock timeout
+        assert!(start.elapsed() < TIME_OUT);
+    }
+
     #[test]
     fn test_complex_deadlocks() {
         setup();
@@ -1,2 +1,2 @@
 pub mod manager;
-pub use manager::TnxManager;
\ No newline at end of file
+pub use manager::{TnxManager, CancelScope, check_cancelled};
\ No newline at end of file
@@ -0,0 +1,79 @@
+use std::sync::OnceLock;
+
+use rsql::{Config, DataItem, Database, ExecutionResult};
+
+/// The database of this test process, a configuration can only be installed once per process
+fn database() -> &'static Database {
+    static DATABASE: OnceLock<Database> = OnceLock::new();
+    DATABASE.get_or_init(|| {
+        let dir = tempfile::tempdir().unwrap().keep();
+        Database::open(Config::new().with_db_dir(&dir).with_log_path(dir.join("rsql.log"))).unwrap()
+    })
+}
+
+fn rows(result: &ExecutionResult) -> Vec<Vec<DataItem>> {
+    match result {
+        ExecutionResult::Query { rows, .. } => rows.clone(),
+        other => panic!("Expected a query result, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_execute_and_transaction() {
+    let conn = database().connect("root").unwrap();
+    conn.execute("CREATE TABLE embedded_items (id INTEGER PRIMARY KEY, qty INTEGER)").unwrap();
+    conn.execute("INSERT INTO embedded_items VALUES (1, 10)").unwrap();
+    conn.transaction(|conn| {
+        conn.execute("INSERT INTO embedded_items VALUES (2, 20)")?;
+        conn.execute("UPDATE embedded_items SET qty = 11 WHERE id = 1")
+    }).unwrap();
+    // a failing transaction leaves nothing behind
+    let failed = conn.transaction(|conn| {
+        conn.execute("INSERT INTO embedded_items VALUES (3, 30)")?;
+        conn.execute("INSERT INTO embedded_items VALUES (3, 31)")
+    });
+    assert!(failed.is_err());
+    let res = conn.execute("SELECT id, qty FROM embedded_items").unwrap();
+    assert_eq!(rows(&res[0]), vec![
+        vec![DataItem::Integer(1), DataItem::Integer(11)],
+        vec![DataItem::Integer(2), DataItem::Integer(20)],
+    ]);
+}
+
+#[test]
+fn test_scan_table() {
+    let conn = database().connect("root").unwrap();
+    conn.execute("CREATE TABLE embedded_scan (id INTEGER PRIMARY KEY, name VARCHAR(8))").unwrap();
+    conn.execute("INSERT INTO embedded_scan VALUES (2, 'b'), (1, 'a')").unwrap();
+    let mut ids = vec![];
+    conn.scan_table("embedded_scan", |row| {
+        ids.push(row[0].clone());
+        Ok(())
+    }).unwrap();
+    assert_eq!(ids, vec![DataItem::Integer(1), DataItem::Integer(2)]);
+    assert!(conn.scan_table("embedded_missing", |_| Ok(())).is_err());
+}
+
+#[test]
+fn test_connect_checks_the_user() {
+    assert!(database().connect("nobody").is_err());
+    let root = database().connect("root").unwrap();
+    root.execute("CREATE USER embedded_reader PASSWORD 'pw'").unwrap();
+    let reader = database().connect("embedded_reader").unwrap();
+    assert_ne!(reader.id(), root.id());
+    // statements run as the user of the connection
+    assert!(reader.execute("CREATE TABLE embedded_denied (id INTEGER PRIMARY KEY)").is_err());
+}
+
+#[test]
+fn test_dropped_connection_rolls_back() {
+    let conn = database().connect("root").unwrap();
+    conn.execute("CREATE TABLE embedded_open (id INTEGER PRIMARY KEY)").unwrap();
+    {
+        let open = database().connect("root").unwrap();
+        open.execute("BEGIN").unwrap();
+        open.execute("INSERT INTO embedded_open VALUES (1)").unwrap();
+    }
+    let res = conn.execute("SELECT * FROM embedded_open").unwrap();
+    assert!(rows(&res[0]).is_empty());
+}
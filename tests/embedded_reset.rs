@@ -0,0 +1,18 @@
+use rsql::{Config, Database, ExecutionResult};
+
+#[test]
+fn test_reset() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Database::open(Config::new().with_db_dir(dir.path()).with_log_path(dir.path().join("rsql.log"))).unwrap();
+    let conn = db.connect("root").unwrap();
+    conn.execute("CREATE TABLE reset_a (id INTEGER PRIMARY KEY)").unwrap();
+    conn.execute("CREATE TABLE reset_b (id INTEGER PRIMARY KEY)").unwrap();
+    match conn.reset().unwrap() {
+        ExecutionResult::Dcl(msg) => assert!(msg.contains("2 table(s) dropped"), "{}", msg),
+        other => panic!("Unexpected reset result {:?}", other),
+    }
+    assert!(conn.execute("SELECT * FROM reset_a").is_err());
+    // a reset inside a transaction is refused
+    conn.execute("BEGIN").unwrap();
+    assert!(conn.reset().is_err());
+}